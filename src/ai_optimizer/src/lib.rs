@@ -1,10 +1,18 @@
-use candid::{CandidType, Deserialize, Principal};
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use common::{CanisterStats, HealthStatus};
 use ic_cdk::{api, init, post_upgrade, pre_upgrade, query, update};
-use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
+use ic_cdk_timers::{clear_timer, set_timer_interval, TimerId};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
+use serde::Serialize as SerdeSerialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::time::Duration;
 use uuid::Uuid;
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct AIOptimization {
     pub optimization_id: String,
     pub policy_id: String,
@@ -16,9 +24,12 @@ pub struct AIOptimization {
     pub execution_plan: ExecutionPlan,
     pub timestamp: u64,
     pub status: OptimizationStatus,
+    pub status_updated_at: Option<u64>,
+    pub failure_reason: Option<String>,
+    pub previous_health_score: Option<f64>,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub enum OptimizationType {
     SmartContractOptimization,
     GasOptimization,
@@ -28,7 +39,7 @@ pub enum OptimizationType {
     CostOptimization,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct OptimizationMetrics {
     pub gas_savings: u64,
     pub performance_improvement: f64,
@@ -38,7 +49,16 @@ pub struct OptimizationMetrics {
     pub efficiency_gain: f64,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
+pub struct OptimizationSummary {
+    pub total_optimizations: u32,
+    pub total_gas_savings: u64,
+    pub average_confidence_score: f64,
+    pub count_by_type: HashMap<String, u32>,
+    pub count_by_status: HashMap<String, u32>,
+}
+
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct AIRecommendation {
     pub recommendation_id: String,
     pub title: String,
@@ -49,7 +69,7 @@ pub struct AIRecommendation {
     pub code_suggestions: Vec<String>,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub enum Priority {
     Critical,
     High,
@@ -57,7 +77,7 @@ pub enum Priority {
     Low,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub enum Difficulty {
     Easy,
     Medium,
@@ -65,7 +85,7 @@ pub enum Difficulty {
     Expert,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct ExecutionPlan {
     pub plan_id: String,
     pub steps: Vec<ExecutionStep>,
@@ -74,7 +94,7 @@ pub struct ExecutionPlan {
     pub risk_assessment: RiskAssessment,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct ExecutionStep {
     pub step_id: String,
     pub title: String,
@@ -84,7 +104,7 @@ pub struct ExecutionStep {
     pub estimated_time: u64,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct RiskAssessment {
     pub risk_level: RiskLevel,
     pub potential_issues: Vec<String>,
@@ -92,7 +112,7 @@ pub struct RiskAssessment {
     pub rollback_plan: String,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub enum RiskLevel {
     Low,
     Medium,
@@ -100,16 +120,15 @@ pub enum RiskLevel {
     Critical,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub enum OptimizationStatus {
-    Pending,
     InProgress,
     Completed,
     Failed,
     RolledBack,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct PredictiveAnalytics {
     pub analytics_id: String,
     pub policy_id: String,
@@ -120,7 +139,7 @@ pub struct PredictiveAnalytics {
     pub timestamp: u64,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub enum PredictionType {
     PolicySuccess,
     FundUtilization,
@@ -130,7 +149,7 @@ pub enum PredictionType {
     CostOverrun,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct RealTimeMonitoring {
     pub monitoring_id: String,
     pub policy_id: String,
@@ -140,16 +159,17 @@ pub struct RealTimeMonitoring {
     pub last_updated: u64,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct Alert {
     pub alert_id: String,
     pub severity: AlertSeverity,
     pub message: String,
     pub timestamp: u64,
     pub resolved: bool,
+    pub resolved_at: Option<u64>,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub enum AlertSeverity {
     Info,
     Warning,
@@ -157,7 +177,7 @@ pub enum AlertSeverity {
     Critical,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub struct CitizenSentiment {
     pub sentiment_id: String,
     pub policy_id: String,
@@ -168,7 +188,16 @@ pub struct CitizenSentiment {
     pub timestamp: u64,
 }
 
-#[derive(CandidType, Deserialize, SerdeSerialize, SerdeDeserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
+pub struct CitizenFeedback {
+    pub feedback_id: String,
+    pub policy_id: String,
+    pub text: String,
+    pub submitted_by: Principal,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, SerdeSerialize, Clone, Debug)]
 pub enum SentimentType {
     Positive,
     Neutral,
@@ -176,52 +205,187 @@ pub enum SentimentType {
     Mixed,
 }
 
+// All feedback submitted for one policy, stored as a single stable map value
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+struct FeedbackLog {
+    entries: Vec<CitizenFeedback>,
+}
+
+// Heap-only settings written to stable memory just before an upgrade
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+struct UpgradeState {
+    backend_canister_id: Option<Principal>,
+    monitoring_refresh_secs: Option<u64>,
+    init_time_ns: Option<u64>,
+}
+
+macro_rules! candid_storable {
+    ($($type:ty),*) => {
+        $(
+            impl Storable for $type {
+                fn to_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(Encode!(self).expect("Failed to encode stable value"))
+                }
+                
+                fn from_bytes(bytes: Cow<[u8]>) -> Self {
+                    Decode!(bytes.as_ref(), Self).expect("Failed to decode stable value")
+                }
+                
+                const BOUND: Bound = Bound::Unbounded;
+            }
+        )*
+    };
+}
+
+candid_storable!(AIOptimization, PredictiveAnalytics, RealTimeMonitoring, CitizenSentiment, FeedbackLog, UpgradeState);
+
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+// The four maps the original pre_upgrade wrote with stable_save; fields added to
+// their records since are all optional, so the current types decode them
+type LegacyState = (
+    HashMap<String, AIOptimization>,
+    HashMap<String, PredictiveAnalytics>,
+    HashMap<String, RealTimeMonitoring>,
+    HashMap<String, CitizenSentiment>,
+);
+type MonitoringMap = StableBTreeMap<String, RealTimeMonitoring, Memory>;
+
+const OPTIMIZATIONS_MEMORY_ID: MemoryId = MemoryId::new(0);
+const PREDICTIVE_ANALYTICS_MEMORY_ID: MemoryId = MemoryId::new(1);
+const REAL_TIME_MONITORING_MEMORY_ID: MemoryId = MemoryId::new(2);
+const CITIZEN_SENTIMENTS_MEMORY_ID: MemoryId = MemoryId::new(3);
+const CITIZEN_FEEDBACK_MEMORY_ID: MemoryId = MemoryId::new(4);
+const UPGRADE_STATE_MEMORY_ID: MemoryId = MemoryId::new(5);
+
 // Storage
-static mut OPTIMIZATIONS: Option<HashMap<String, AIOptimization>> = None;
-static mut PREDICTIVE_ANALYTICS: Option<HashMap<String, PredictiveAnalytics>> = None;
-static mut REAL_TIME_MONITORING: Option<HashMap<String, RealTimeMonitoring>> = None;
-static mut CITIZEN_SENTIMENTS: Option<HashMap<String, CitizenSentiment>> = None;
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+    
+    static OPTIMIZATIONS: RefCell<StableBTreeMap<String, AIOptimization, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(OPTIMIZATIONS_MEMORY_ID)));
+    static PREDICTIVE_ANALYTICS: RefCell<StableBTreeMap<String, PredictiveAnalytics, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(PREDICTIVE_ANALYTICS_MEMORY_ID)));
+    static REAL_TIME_MONITORING: RefCell<MonitoringMap> =
+        RefCell::new(StableBTreeMap::init(memory(REAL_TIME_MONITORING_MEMORY_ID)));
+    static CITIZEN_SENTIMENTS: RefCell<StableBTreeMap<String, CitizenSentiment, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(CITIZEN_SENTIMENTS_MEMORY_ID)));
+    // Raw citizen feedback keyed by policy_id
+    static CITIZEN_FEEDBACK: RefCell<StableBTreeMap<String, FeedbackLog, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(CITIZEN_FEEDBACK_MEMORY_ID)));
+    static UPGRADE_STATE: RefCell<StableCell<UpgradeState, Memory>> = RefCell::new(
+        StableCell::init(memory(UPGRADE_STATE_MEMORY_ID), UpgradeState::default())
+            .expect("Failed to initialize upgrade state cell")
+    );
+}
+
+static mut BACKEND_CANISTER_ID: Option<Principal> = None;
+static mut MONITORING_REFRESH_SECS: u64 = DEFAULT_MONITORING_REFRESH_SECS;
+static mut MONITORING_REFRESH_TIMER: Option<TimerId> = None;
+// Set at install; canisters installed before it existed start counting at their next upgrade
+static mut INIT_TIME_NS: Option<u64> = None;
+
+const DEFAULT_MONITORING_REFRESH_SECS: u64 = 300;
+const MIN_MONITORING_REFRESH_SECS: u64 = 30;
+const ERROR_RATE_WARNING_THRESHOLD: f64 = 0.1;
+const ERROR_RATE_ALERT_MESSAGE: &str = "Error rate exceeded warning threshold";
+
+const MAX_FEEDBACK_LENGTH: usize = 2000;
+// Feedback kept per policy; the oldest entries are dropped beyond this
+const MAX_FEEDBACK_PER_POLICY: usize = 500;
+const MAX_KEYWORDS: usize = 5;
+
+const POSITIVE_WORDS: &[&str] = &[
+    "good", "great", "excellent", "helpful", "efficient", "fast", "transparent", "fair",
+    "improved", "benefit", "happy", "satisfied", "effective", "easy", "support", "progress",
+];
+const NEGATIVE_WORDS: &[&str] = &[
+    "bad", "poor", "slow", "corrupt", "corruption", "delay", "delayed", "unfair", "difficult",
+    "useless", "angry", "unhappy", "failed", "waste", "bribe", "ineffective", "problem",
+];
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "had", "her", "was",
+    "one", "our", "out", "has", "have", "this", "that", "with", "from", "they", "been", "were",
+    "their", "there", "what", "when", "which", "will", "would", "very", "into", "than", "then",
+    "them", "its", "too", "also", "just", "more", "most", "some", "such", "only", "about",
+];
+
+fn memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|manager| manager.borrow().get(id))
+}
 
 #[init]
 fn init() {
     unsafe {
-        OPTIMIZATIONS = Some(HashMap::new());
-        PREDICTIVE_ANALYTICS = Some(HashMap::new());
-        REAL_TIME_MONITORING = Some(HashMap::new());
-        CITIZEN_SENTIMENTS = Some(HashMap::new());
+        INIT_TIME_NS = Some(api::time());
     }
+    schedule_monitoring_refresh();
 }
 
 #[pre_upgrade]
 fn pre_upgrade() {
-    let optimizations = unsafe { OPTIMIZATIONS.take().unwrap() };
-    let analytics = unsafe { PREDICTIVE_ANALYTICS.take().unwrap() };
-    let monitoring = unsafe { REAL_TIME_MONITORING.take().unwrap() };
-    let sentiments = unsafe { CITIZEN_SENTIMENTS.take().unwrap() };
-    
-    ic_cdk::storage::stable_save((optimizations, analytics, monitoring, sentiments))
+    // The maps already live in stable memory; only heap settings need saving
+    let state = UpgradeState {
+        backend_canister_id: unsafe { BACKEND_CANISTER_ID },
+        monitoring_refresh_secs: Some(unsafe { MONITORING_REFRESH_SECS }),
+        init_time_ns: unsafe { INIT_TIME_NS },
+    };
+    UPGRADE_STATE.with(|cell| cell.borrow_mut().set(state))
         .expect("Failed to save state");
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (optimizations, analytics, monitoring, sentiments): (
-        HashMap<String, AIOptimization>,
-        HashMap<String, PredictiveAnalytics>,
-        HashMap<String, RealTimeMonitoring>,
-        HashMap<String, CitizenSentiment>,
-    ) = ic_cdk::storage::stable_restore().expect("Failed to restore state");
+    // Canisters upgraded from the single-blob layout have no memory manager
+    // header yet; pull their state into the stable maps before it is overwritten
+    let mut magic = [0u8; 3];
+    ic_cdk::api::stable::stable_read(0, &mut magic);
+    if &magic != b"MGR" {
+        migrate_legacy_state();
+    }
     
+    let state = UPGRADE_STATE.with(|cell| cell.borrow().get().clone());
     unsafe {
-        OPTIMIZATIONS = Some(optimizations);
-        PREDICTIVE_ANALYTICS = Some(analytics);
-        REAL_TIME_MONITORING = Some(monitoring);
-        CITIZEN_SENTIMENTS = Some(sentiments);
+        BACKEND_CANISTER_ID = state.backend_canister_id;
+        MONITORING_REFRESH_SECS = state.monitoring_refresh_secs.unwrap_or(DEFAULT_MONITORING_REFRESH_SECS);
+        INIT_TIME_NS = Some(state.init_time_ns.unwrap_or_else(api::time));
     }
+    
+    schedule_monitoring_refresh();
+}
+
+fn migrate_legacy_state() {
+    let (optimizations, analytics, monitoring, sentiments): LegacyState =
+        ic_cdk::storage::stable_restore().expect("Failed to restore legacy state");
+    
+    OPTIMIZATIONS.with(|map| {
+        let mut map = map.borrow_mut();
+        optimizations.into_iter().for_each(|(id, optimization)| { map.insert(id, optimization); });
+    });
+    PREDICTIVE_ANALYTICS.with(|map| {
+        let mut map = map.borrow_mut();
+        analytics.into_iter().for_each(|(id, entry)| { map.insert(id, entry); });
+    });
+    REAL_TIME_MONITORING.with(|map| {
+        let mut map = map.borrow_mut();
+        monitoring.into_iter().for_each(|(id, record)| { map.insert(id, record); });
+    });
+    CITIZEN_SENTIMENTS.with(|map| {
+        let mut map = map.borrow_mut();
+        sentiments.into_iter().for_each(|(id, sentiment)| { map.insert(id, sentiment); });
+    });
+    UPGRADE_STATE.with(|cell| cell.borrow_mut().set(UpgradeState::default()))
+        .expect("Failed to migrate settings");
 }
 
 #[update]
 async fn apply_ai_optimization(policy_id: String, optimization_type: OptimizationType) -> Result<AIOptimization, String> {
+    let optimization = start_optimization(policy_id, optimization_type).await?;
+    complete_optimization(optimization.optimization_id)
+}
+
+#[update]
+async fn start_optimization(policy_id: String, optimization_type: OptimizationType) -> Result<AIOptimization, String> {
     let optimization_id = format!("AI_OPT_{}", Uuid::new_v4().to_string());
     let now = api::time();
     
@@ -240,21 +404,73 @@ async fn apply_ai_optimization(policy_id: String, optimization_type: Optimizatio
         recommendations,
         execution_plan,
         timestamp: now,
-        status: OptimizationStatus::Completed,
+        status: OptimizationStatus::InProgress,
+        status_updated_at: Some(now),
+        failure_reason: None,
+        previous_health_score: None,
     };
     
-    unsafe {
-        if let Some(ref mut optimizations) = OPTIMIZATIONS {
-            optimizations.insert(optimization_id.clone(), optimization.clone());
-        }
-    }
-    
-    // Update real-time monitoring
-    update_real_time_metrics(&policy_id, &optimization).await;
+    OPTIMIZATIONS.with(|optimizations| {
+        optimizations.borrow_mut().insert(optimization_id.clone(), optimization.clone());
+    });
     
     Ok(optimization)
 }
 
+#[update]
+fn complete_optimization(optimization_id: String) -> Result<AIOptimization, String> {
+    let now = api::time();
+    
+    update_optimization(&optimization_id, |optimization| {
+        transition_optimization(optimization, OptimizationStatus::Completed, now)?;
+        
+        // Apply the optimization result to real-time monitoring, keeping the
+        // previous score so the change can be rolled back
+        optimization.previous_health_score = REAL_TIME_MONITORING.with(|monitoring| {
+            set_health_score(
+                &mut monitoring.borrow_mut(),
+                &optimization.policy_id,
+                optimization.optimization_metrics.efficiency_gain,
+                now,
+            )
+        });
+        Ok(())
+    })
+}
+
+#[update]
+fn fail_optimization(optimization_id: String, reason: String) -> Result<AIOptimization, String> {
+    let now = api::time();
+    
+    update_optimization(&optimization_id, |optimization| {
+        transition_optimization(optimization, OptimizationStatus::Failed, now)?;
+        optimization.failure_reason = Some(reason);
+        Ok(())
+    })
+}
+
+#[update]
+fn rollback_optimization(optimization_id: String) -> Result<AIOptimization, String> {
+    let now = api::time();
+    
+    update_optimization(&optimization_id, |optimization| {
+        transition_optimization(optimization, OptimizationStatus::RolledBack, now)?;
+        
+        if let Some(previous) = optimization.previous_health_score {
+            REAL_TIME_MONITORING.with(|monitoring| {
+                restore_health_score(
+                    &mut monitoring.borrow_mut(),
+                    &optimization.policy_id,
+                    optimization.optimization_metrics.efficiency_gain,
+                    previous,
+                    now,
+                )
+            })?;
+        }
+        Ok(())
+    })
+}
+
 #[update]
 async fn generate_predictive_analytics(policy_id: String, prediction_type: PredictionType) -> Result<PredictiveAnalytics, String> {
     let analytics_id = format!("PRED_{}", Uuid::new_v4().to_string());
@@ -274,11 +490,9 @@ async fn generate_predictive_analytics(policy_id: String, prediction_type: Predi
         timestamp: now,
     };
     
-    unsafe {
-        if let Some(ref mut analytics_map) = PREDICTIVE_ANALYTICS {
-            analytics_map.insert(analytics_id.clone(), analytics.clone());
-        }
-    }
+    PREDICTIVE_ANALYTICS.with(|analytics_map| {
+        analytics_map.borrow_mut().insert(analytics_id.clone(), analytics.clone());
+    });
     
     Ok(analytics)
 }
@@ -290,7 +504,7 @@ async fn start_real_time_monitoring(policy_id: String) -> Result<RealTimeMonitor
     
     let metrics = collect_real_time_metrics(&policy_id).await;
     let alerts = generate_initial_alerts(&policy_id).await;
-    let health_score = calculate_health_score(&metrics).await;
+    let health_score = calculate_health_score(&metrics);
     
     let monitoring = RealTimeMonitoring {
         monitoring_id: monitoring_id.clone(),
@@ -301,24 +515,68 @@ async fn start_real_time_monitoring(policy_id: String) -> Result<RealTimeMonitor
         last_updated: now,
     };
     
-    unsafe {
-        if let Some(ref mut monitoring_map) = REAL_TIME_MONITORING {
-            monitoring_map.insert(monitoring_id.clone(), monitoring.clone());
-        }
-    }
+    REAL_TIME_MONITORING.with(|monitoring_map| {
+        monitoring_map.borrow_mut().insert(monitoring_id.clone(), monitoring.clone());
+    });
     
     Ok(monitoring)
 }
 
+#[update]
+fn submit_feedback(policy_id: String, text: String) -> Result<CitizenFeedback, String> {
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err("Feedback text cannot be empty".to_string());
+    }
+    if text.chars().count() > MAX_FEEDBACK_LENGTH {
+        return Err(format!("Feedback text exceeds {} characters", MAX_FEEDBACK_LENGTH));
+    }
+    
+    let feedback = CitizenFeedback {
+        feedback_id: format!("FB_{}", Uuid::new_v4()),
+        policy_id: policy_id.clone(),
+        text,
+        submitted_by: api::caller(),
+        timestamp: api::time(),
+    };
+    
+    CITIZEN_FEEDBACK.with(|all_feedback| {
+        let mut all_feedback = all_feedback.borrow_mut();
+        let mut log = all_feedback.get(&policy_id).unwrap_or_default();
+        append_feedback(&mut log, feedback.clone());
+        all_feedback.insert(policy_id, log);
+    });
+    
+    Ok(feedback)
+}
+
+fn append_feedback(log: &mut FeedbackLog, feedback: CitizenFeedback) {
+    log.entries.push(feedback);
+    if log.entries.len() > MAX_FEEDBACK_PER_POLICY {
+        let excess = log.entries.len() - MAX_FEEDBACK_PER_POLICY;
+        log.entries.drain(..excess);
+    }
+}
+
 #[update]
 async fn analyze_citizen_sentiment(policy_id: String) -> Result<CitizenSentiment, String> {
     let sentiment_id = format!("SENT_{}", Uuid::new_v4().to_string());
     let now = api::time();
     
-    let sentiment_score = analyze_sentiment_score(&policy_id).await;
+    let texts: Vec<String> = CITIZEN_FEEDBACK.with(|all_feedback| {
+        all_feedback.borrow()
+            .get(&policy_id)
+            .map(|log| log.entries.into_iter().map(|feedback| feedback.text).collect())
+            .unwrap_or_default()
+    });
+    if texts.is_empty() {
+        return Err("No feedback submitted for policy".to_string());
+    }
+    
+    let sentiment_score = analyze_sentiment_score(&texts);
     let sentiment_type = classify_sentiment(sentiment_score);
-    let feedback_count = get_feedback_count(&policy_id).await;
-    let keywords = extract_keywords(&policy_id).await;
+    let feedback_count = texts.len() as u32;
+    let keywords = extract_keywords(&texts);
     
     let sentiment = CitizenSentiment {
         sentiment_id: sentiment_id.clone(),
@@ -330,48 +588,112 @@ async fn analyze_citizen_sentiment(policy_id: String) -> Result<CitizenSentiment
         timestamp: now,
     };
     
-    unsafe {
-        if let Some(ref mut sentiments) = CITIZEN_SENTIMENTS {
-            sentiments.insert(sentiment_id.clone(), sentiment.clone());
-        }
-    }
+    CITIZEN_SENTIMENTS.with(|sentiments| {
+        sentiments.borrow_mut().insert(sentiment_id.clone(), sentiment.clone());
+    });
     
     Ok(sentiment)
 }
 
+#[update]
+fn raise_alert(policy_id: String, severity: AlertSeverity, message: String) -> Result<Alert, String> {
+    let alert = new_alert(severity, message, api::time());
+    
+    REAL_TIME_MONITORING.with(|monitoring| {
+        push_alert(&mut monitoring.borrow_mut(), &policy_id, alert.clone())
+    })?;
+    
+    Ok(alert)
+}
+
+#[update]
+fn resolve_alert(monitoring_id: String, alert_id: String) -> Result<(), String> {
+    REAL_TIME_MONITORING.with(|monitoring| {
+        let mut monitoring = monitoring.borrow_mut();
+        let mut record = monitoring.get(&monitoring_id)
+            .ok_or("Monitoring record not found".to_string())?;
+        mark_alert_resolved(&mut record, &alert_id, api::time())?;
+        monitoring.insert(monitoring_id, record);
+        Ok(())
+    })
+}
+
 #[query]
-fn get_optimization(optimization_id: String) -> Option<AIOptimization> {
+fn get_unresolved_alerts(policy_id: String) -> Vec<Alert> {
+    REAL_TIME_MONITORING.with(|monitoring| unresolved_alerts_for(&monitoring.borrow(), &policy_id))
+}
+
+#[update]
+fn set_backend_canister_id(canister_id: Principal) -> Result<(), String> {
+    if !api::is_controller(&api::caller()) {
+        return Err("Only controllers can set the backend canister id".to_string());
+    }
+    
     unsafe {
-        OPTIMIZATIONS.as_ref().and_then(|opt| opt.get(&optimization_id).cloned())
+        BACKEND_CANISTER_ID = Some(canister_id);
     }
+    Ok(())
 }
 
 #[query]
-fn get_all_optimizations() -> Vec<AIOptimization> {
+fn get_backend_canister_id() -> Option<Principal> {
+    unsafe { BACKEND_CANISTER_ID }
+}
+
+#[update]
+fn set_monitoring_refresh_interval(seconds: u64) -> Result<(), String> {
+    if !api::is_controller(&api::caller()) {
+        return Err("Only controllers can change the monitoring refresh interval".to_string());
+    }
+    if seconds < MIN_MONITORING_REFRESH_SECS {
+        return Err(format!("Refresh interval must be at least {} seconds", MIN_MONITORING_REFRESH_SECS));
+    }
+    
     unsafe {
-        OPTIMIZATIONS.as_ref().map(|opt| opt.values().cloned().collect()).unwrap_or_default()
+        MONITORING_REFRESH_SECS = seconds;
     }
+    schedule_monitoring_refresh();
+    Ok(())
+}
+
+#[query]
+fn get_monitoring_refresh_interval() -> u64 {
+    unsafe { MONITORING_REFRESH_SECS }
+}
+
+#[query]
+fn get_optimization(optimization_id: String) -> Option<AIOptimization> {
+    OPTIMIZATIONS.with(|optimizations| optimizations.borrow().get(&optimization_id))
+}
+
+#[query]
+fn get_all_optimizations() -> Vec<AIOptimization> {
+    OPTIMIZATIONS.with(|optimizations| {
+        optimizations.borrow().iter().map(|(_, optimization)| optimization).collect()
+    })
+}
+
+#[query]
+fn get_optimization_summary() -> OptimizationSummary {
+    OPTIMIZATIONS.with(|optimizations| {
+        let optimizations: Vec<AIOptimization> = optimizations.borrow().iter().map(|(_, optimization)| optimization).collect();
+        summarize_optimizations(optimizations.iter())
+    })
 }
 
 #[query]
 fn get_predictive_analytics(analytics_id: String) -> Option<PredictiveAnalytics> {
-    unsafe {
-        PREDICTIVE_ANALYTICS.as_ref().and_then(|analytics| analytics.get(&analytics_id).cloned())
-    }
+    PREDICTIVE_ANALYTICS.with(|analytics| analytics.borrow().get(&analytics_id))
 }
 
 #[query]
 fn get_real_time_monitoring(monitoring_id: String) -> Option<RealTimeMonitoring> {
-    unsafe {
-        REAL_TIME_MONITORING.as_ref().and_then(|monitoring| monitoring.get(&monitoring_id).cloned())
-    }
+    REAL_TIME_MONITORING.with(|monitoring| monitoring.borrow().get(&monitoring_id))
 }
 
 #[query]
 fn get_citizen_sentiment(sentiment_id: String) -> Option<CitizenSentiment> {
-    unsafe {
-        CITIZEN_SENTIMENTS.as_ref().and_then(|sentiments| sentiments.get(&sentiment_id).cloned())
-    }
+    CITIZEN_SENTIMENTS.with(|sentiments| sentiments.borrow().get(&sentiment_id))
 }
 
 // Helper functions
@@ -387,6 +709,86 @@ async fn analyze_policy_performance(policy_id: &str) -> OptimizationMetrics {
 }
 
 async fn generate_ai_recommendations(policy_id: &str, optimization_type: &OptimizationType) -> Vec<AIRecommendation> {
+    let backend = unsafe { BACKEND_CANISTER_ID };
+    let response = match backend {
+        Some(canister_id) => {
+            let prompt = build_recommendation_prompt(policy_id, optimization_type);
+            ic_cdk::call::<(String,), (String,)>(canister_id, "prompt", (prompt,))
+                .await
+                .map(|(reply,)| reply)
+                .map_err(|(code, message)| format!("Backend prompt call failed: {:?} {}", code, message))
+        }
+        None => Err("Backend canister id not configured".to_string()),
+    };
+    
+    recommendations_from_response(response)
+}
+
+fn build_recommendation_prompt(policy_id: &str, optimization_type: &OptimizationType) -> String {
+    format!(
+        "You are optimizing the smart contract for government policy {}. \
+         Focus area: {:?}. Suggest up to 3 concrete improvements. \
+         Reply with one recommendation per line in the exact format: \
+         TITLE | DESCRIPTION | PRIORITY (Critical, High, Medium or Low) | IMPACT (0.0-1.0) | DIFFICULTY (Easy, Medium, Hard or Expert). \
+         Do not add any other text.",
+        policy_id, optimization_type
+    )
+}
+
+fn recommendations_from_response(response: Result<String, String>) -> Vec<AIRecommendation> {
+    match response {
+        Ok(reply) => {
+            let recommendations = parse_recommendations(&reply);
+            if recommendations.is_empty() {
+                ic_cdk::println!("LLM reply contained no parsable recommendations, using defaults");
+                static_recommendations()
+            } else {
+                recommendations
+            }
+        }
+        Err(error) => {
+            ic_cdk::println!("{}, using default recommendations", error);
+            static_recommendations()
+        }
+    }
+}
+
+fn parse_recommendations(reply: &str) -> Vec<AIRecommendation> {
+    reply.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('|').map(|field| field.trim()).collect();
+            if fields.len() != 5 || fields[0].is_empty() {
+                return None;
+            }
+            
+            let priority = match fields[2].to_lowercase().as_str() {
+                "critical" => Priority::Critical,
+                "high" => Priority::High,
+                "low" => Priority::Low,
+                _ => Priority::Medium,
+            };
+            let implementation_difficulty = match fields[4].to_lowercase().as_str() {
+                "easy" => Difficulty::Easy,
+                "hard" => Difficulty::Hard,
+                "expert" => Difficulty::Expert,
+                _ => Difficulty::Medium,
+            };
+            let estimated_impact = fields[3].parse::<f64>().unwrap_or(0.5).clamp(0.0, 1.0);
+            
+            Some(AIRecommendation {
+                recommendation_id: format!("REC_{}", Uuid::new_v4()),
+                title: fields[0].to_string(),
+                description: fields[1].to_string(),
+                priority,
+                estimated_impact,
+                implementation_difficulty,
+                code_suggestions: vec![],
+            })
+        })
+        .collect()
+}
+
+fn static_recommendations() -> Vec<AIRecommendation> {
     vec![
         AIRecommendation {
             recommendation_id: format!("REC_{}", Uuid::new_v4().to_string()),
@@ -431,8 +833,8 @@ async fn create_execution_plan(recommendations: &[AIRecommendation]) -> Executio
     
     ExecutionPlan {
         plan_id: format!("PLAN_{}", Uuid::new_v4().to_string()),
-        steps,
         estimated_duration: steps.len() as u64 * 3600,
+        steps,
         required_resources: vec!["Developer".to_string(), "Security Auditor".to_string()],
         risk_assessment: RiskAssessment {
             risk_level: RiskLevel::Medium,
@@ -489,16 +891,142 @@ async fn generate_initial_alerts(policy_id: &str) -> Vec<Alert> {
             message: "System monitoring initialized successfully".to_string(),
             timestamp: api::time(),
             resolved: true,
+            resolved_at: Some(api::time()),
         },
     ]
 }
 
-async fn calculate_health_score(metrics: &HashMap<String, f64>) -> f64 {
-    0.92 // 92% health score
+fn new_alert(severity: AlertSeverity, message: String, now: u64) -> Alert {
+    Alert {
+        alert_id: format!("ALERT_{}", Uuid::new_v4()),
+        severity,
+        message,
+        timestamp: now,
+        resolved: false,
+        resolved_at: None,
+    }
+}
+
+// Most recently updated monitoring record for the policy
+fn latest_monitoring_for(monitoring: &MonitoringMap, policy_id: &str) -> Option<RealTimeMonitoring> {
+    monitoring.iter()
+        .map(|(_, record)| record)
+        .filter(|record| record.policy_id == policy_id)
+        .max_by_key(|record| record.last_updated)
 }
 
-async fn analyze_sentiment_score(policy_id: &str) -> f64 {
-    0.78 // 78% positive sentiment
+fn push_alert(monitoring: &mut MonitoringMap, policy_id: &str, alert: Alert) -> Result<(), String> {
+    let mut record = latest_monitoring_for(monitoring, policy_id)
+        .ok_or("No monitoring record for policy".to_string())?;
+    
+    record.alerts.push(alert);
+    monitoring.insert(record.monitoring_id.clone(), record);
+    Ok(())
+}
+
+fn mark_alert_resolved(monitoring: &mut RealTimeMonitoring, alert_id: &str, now: u64) -> Result<(), String> {
+    let alert = monitoring.alerts.iter_mut()
+        .find(|alert| alert.alert_id == alert_id)
+        .ok_or("Alert not found".to_string())?;
+    
+    if alert.resolved {
+        return Err("Alert is already resolved".to_string());
+    }
+    
+    alert.resolved = true;
+    alert.resolved_at = Some(now);
+    Ok(())
+}
+
+fn unresolved_alerts_for(monitoring: &MonitoringMap, policy_id: &str) -> Vec<Alert> {
+    let mut alerts: Vec<Alert> = monitoring.iter()
+        .map(|(_, record)| record)
+        .filter(|record| record.policy_id == policy_id)
+        .flat_map(|record| record.alerts.into_iter())
+        .filter(|alert| !alert.resolved)
+        .collect();
+    alerts.sort_by_key(|alert| std::cmp::Reverse(alert.timestamp));
+    alerts
+}
+
+// Throughput counts for the policy, error rate and latency count against it
+fn calculate_health_score(metrics: &HashMap<String, f64>) -> f64 {
+    let metric = |name: &str| metrics.get(name).copied().unwrap_or(0.0).clamp(0.0, 1.0);
+    (metric("throughput") + (1.0 - metric("error_rate")) + (1.0 - metric("response_time"))) / 3.0
+}
+
+fn schedule_monitoring_refresh() {
+    let interval = unsafe { MONITORING_REFRESH_SECS };
+    unsafe {
+        if let Some(timer_id) = MONITORING_REFRESH_TIMER.take() {
+            clear_timer(timer_id);
+        }
+        MONITORING_REFRESH_TIMER = Some(set_timer_interval(Duration::from_secs(interval), || {
+            ic_cdk::spawn(refresh_all_monitoring());
+        }));
+    }
+}
+
+async fn refresh_all_monitoring() {
+    let records: Vec<(String, String)> = REAL_TIME_MONITORING.with(|monitoring| {
+        monitoring.borrow().iter()
+            .map(|(monitoring_id, record)| (monitoring_id, record.policy_id))
+            .collect()
+    });
+    
+    for (monitoring_id, policy_id) in records {
+        let metrics = collect_real_time_metrics(&policy_id).await;
+        let now = api::time();
+        REAL_TIME_MONITORING.with(|monitoring| {
+            let mut monitoring = monitoring.borrow_mut();
+            if let Some(mut record) = monitoring.get(&monitoring_id) {
+                refresh_monitoring_record(&mut record, metrics, now);
+                monitoring.insert(monitoring_id, record);
+            }
+        });
+    }
+}
+
+// Applies a fresh metrics sample; returns true when a new alert was raised
+fn refresh_monitoring_record(record: &mut RealTimeMonitoring, metrics: HashMap<String, f64>, now: u64) -> bool {
+    record.health_score = calculate_health_score(&metrics);
+    record.last_updated = now;
+    
+    let error_rate = metrics.get("error_rate").copied().unwrap_or(0.0);
+    record.metrics = metrics;
+    
+    let already_open = record.alerts.iter()
+        .any(|alert| !alert.resolved && alert.message == ERROR_RATE_ALERT_MESSAGE);
+    if error_rate > ERROR_RATE_WARNING_THRESHOLD && !already_open {
+        record.alerts.push(new_alert(AlertSeverity::Warning, ERROR_RATE_ALERT_MESSAGE.to_string(), now));
+        return true;
+    }
+    false
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+// Share of lexicon hits that are positive; 0.5 when no lexicon word appears
+fn analyze_sentiment_score(texts: &[String]) -> f64 {
+    let (mut positive, mut negative) = (0u32, 0u32);
+    for token in texts.iter().flat_map(|text| tokenize(text)) {
+        if POSITIVE_WORDS.contains(&token.as_str()) {
+            positive += 1;
+        } else if NEGATIVE_WORDS.contains(&token.as_str()) {
+            negative += 1;
+        }
+    }
+    
+    if positive + negative == 0 {
+        0.5
+    } else {
+        positive as f64 / (positive + negative) as f64
+    }
 }
 
 fn classify_sentiment(score: f64) -> SentimentType {
@@ -509,29 +1037,500 @@ fn classify_sentiment(score: f64) -> SentimentType {
     }
 }
 
-async fn get_feedback_count(policy_id: &str) -> u32 {
-    1250 // Simulated feedback count
+// Most frequent non-stopword tokens, ties broken alphabetically
+fn extract_keywords(texts: &[String]) -> Vec<String> {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    for token in texts.iter().flat_map(|text| tokenize(text)) {
+        if token.len() > 2 && !STOPWORDS.contains(&token.as_str()) {
+            *counts.entry(token).or_insert(0) += 1;
+        }
+    }
+    
+    let mut ranked: Vec<(String, u32)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.into_iter().take(MAX_KEYWORDS).map(|(token, _)| token).collect()
 }
 
-async fn extract_keywords(policy_id: &str) -> Vec<String> {
-    vec![
-        "transparency".to_string(),
-        "efficiency".to_string(),
-        "innovation".to_string(),
-        "trust".to_string(),
-        "progress".to_string(),
-    ]
+fn summarize_optimizations<'a>(optimizations: impl Iterator<Item = &'a AIOptimization>) -> OptimizationSummary {
+    let mut summary = OptimizationSummary {
+        total_optimizations: 0,
+        total_gas_savings: 0,
+        average_confidence_score: 0.0,
+        count_by_type: HashMap::new(),
+        count_by_status: HashMap::new(),
+    };
+    let mut confidence_total = 0.0;
+    
+    for optimization in optimizations {
+        summary.total_optimizations += 1;
+        summary.total_gas_savings += optimization.optimization_metrics.gas_savings;
+        confidence_total += optimization.confidence_score;
+        *summary.count_by_type.entry(format!("{:?}", optimization.optimization_type)).or_insert(0) += 1;
+        *summary.count_by_status.entry(format!("{:?}", optimization.status)).or_insert(0) += 1;
+    }
+    
+    if summary.total_optimizations > 0 {
+        summary.average_confidence_score = confidence_total / summary.total_optimizations as f64;
+    }
+    summary
 }
 
-async fn update_real_time_metrics(policy_id: &str, optimization: &AIOptimization) {
-    // Update monitoring with optimization results
-    if let Some(monitoring) = unsafe { REAL_TIME_MONITORING.as_mut() } {
-        for (_, monitoring_data) in monitoring.iter_mut() {
-            if monitoring_data.policy_id == *policy_id {
-                monitoring_data.health_score = optimization.optimization_metrics.efficiency_gain;
-                monitoring_data.last_updated = api::time();
-                break;
+fn transition_optimization(optimization: &mut AIOptimization, next: OptimizationStatus, now: u64) -> Result<(), String> {
+    let allowed = matches!(
+        (&optimization.status, &next),
+        (OptimizationStatus::InProgress, OptimizationStatus::Completed)
+            | (OptimizationStatus::InProgress, OptimizationStatus::Failed)
+            | (OptimizationStatus::Completed, OptimizationStatus::RolledBack)
+    );
+    
+    if !allowed {
+        return Err(format!(
+            "Cannot move optimization from {:?} to {:?}",
+            optimization.status, next
+        ));
+    }
+    
+    optimization.status = next;
+    optimization.status_updated_at = Some(now);
+    Ok(())
+}
+
+// Sets the health score on the policy's monitoring record and returns the score it replaced
+fn set_health_score(monitoring: &mut MonitoringMap, policy_id: &str, health_score: f64, now: u64) -> Option<f64> {
+    let mut monitoring_data = latest_monitoring_for(monitoring, policy_id)?;
+    let previous = monitoring_data.health_score;
+    monitoring_data.health_score = health_score;
+    monitoring_data.last_updated = now;
+    monitoring.insert(monitoring_data.monitoring_id.clone(), monitoring_data);
+    Some(previous)
+}
+
+// Puts back the score an optimization replaced, unless something has set a new one since
+fn restore_health_score(monitoring: &mut MonitoringMap, policy_id: &str, applied: f64, previous: f64, now: u64) -> Result<(), String> {
+    match latest_monitoring_for(monitoring, policy_id) {
+        Some(record) if record.health_score != applied => {
+            Err("Health score has changed since the optimization was applied".to_string())
+        }
+        _ => {
+            set_health_score(monitoring, policy_id, previous, now);
+            Ok(())
+        }
+    }
+}
+
+// Loads an optimization, applies `update` and writes it back only if it succeeds
+fn update_optimization<F>(optimization_id: &str, update: F) -> Result<AIOptimization, String>
+where
+    F: FnOnce(&mut AIOptimization) -> Result<(), String>,
+{
+    OPTIMIZATIONS.with(|optimizations| {
+        let mut optimizations = optimizations.borrow_mut();
+        let mut optimization = optimizations.get(&optimization_id.to_string())
+            .ok_or("Optimization not found".to_string())?;
+        update(&mut optimization)?;
+        optimizations.insert(optimization_id.to_string(), optimization.clone());
+        Ok(optimization)
+    })
+}
+
+#[query]
+fn get_canister_stats() -> CanisterStats {
+    common::canister_stats()
+}
+
+#[query]
+fn health_check() -> HealthStatus {
+    health_status_at(api::time())
+}
+
+// The maps live in stable memory and always exist; initialized means init or post_upgrade ran
+fn health_status_at(now: u64) -> HealthStatus {
+    let optimizations = OPTIMIZATIONS.with(|map| map.borrow().len() as usize);
+    let analytics = PREDICTIVE_ANALYTICS.with(|map| map.borrow().len() as usize);
+    let monitoring = REAL_TIME_MONITORING.with(|map| map.borrow().len() as usize);
+    let sentiments = CITIZEN_SENTIMENTS.with(|map| map.borrow().len() as usize);
+    let feedback_logs = CITIZEN_FEEDBACK.with(|map| map.borrow().len() as usize);
+    let init_time_ns = unsafe { INIT_TIME_NS };
+    common::health_status(
+        init_time_ns.is_some(),
+        &[
+            ("optimizations", optimizations),
+            ("predictive_analytics", analytics),
+            ("real_time_monitoring", monitoring),
+            ("citizen_sentiments", sentiments),
+            ("feedback_logs", feedback_logs),
+        ],
+        init_time_ns,
+        now,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::Memory as _;
+    
+    #[test]
+    fn test_recommendations_fall_back_on_call_failure() {
+        let recommendations = recommendations_from_response(Err("canister rejected".to_string()));
+        assert_eq!(recommendations.len(), 2);
+        assert_eq!(recommendations[0].title, "Optimize Smart Contract Gas Usage");
+    }
+    
+    #[test]
+    fn test_recommendations_fall_back_on_unparsable_reply() {
+        let recommendations = recommendations_from_response(Ok("I cannot help with that.".to_string()));
+        assert_eq!(recommendations.len(), 2);
+    }
+    
+    fn monitoring_record(monitoring_id: &str, policy_id: &str, last_updated: u64) -> RealTimeMonitoring {
+        RealTimeMonitoring {
+            monitoring_id: monitoring_id.to_string(),
+            policy_id: policy_id.to_string(),
+            metrics: HashMap::new(),
+            alerts: vec![],
+            health_score: 0.9,
+            last_updated,
+        }
+    }
+    
+    fn test_monitoring_map() -> MonitoringMap {
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+        StableBTreeMap::init(manager.get(REAL_TIME_MONITORING_MEMORY_ID))
+    }
+    
+    #[test]
+    fn test_raise_list_and_resolve_alerts() {
+        let mut monitoring = test_monitoring_map();
+        monitoring.insert("MON_1".to_string(), monitoring_record("MON_1", "policy_a", 10));
+        monitoring.insert("MON_2".to_string(), monitoring_record("MON_2", "policy_a", 20));
+        monitoring.insert("MON_3".to_string(), monitoring_record("MON_3", "policy_b", 30));
+        
+        let warning = new_alert(AlertSeverity::Warning, "error rate rising".to_string(), 100);
+        let critical = new_alert(AlertSeverity::Critical, "funds stuck".to_string(), 200);
+        push_alert(&mut monitoring, "policy_a", warning.clone()).unwrap();
+        push_alert(&mut monitoring, "policy_a", critical.clone()).unwrap();
+        assert!(push_alert(&mut monitoring, "policy_c", warning.clone()).is_err());
+        
+        let unresolved = unresolved_alerts_for(&monitoring, "policy_a");
+        assert_eq!(unresolved.len(), 2);
+        assert_eq!(unresolved[0].alert_id, critical.alert_id);
+        assert!(unresolved_alerts_for(&monitoring, "policy_b").is_empty());
+        
+        let mut record = monitoring.get(&"MON_2".to_string()).unwrap();
+        mark_alert_resolved(&mut record, &warning.alert_id, 300).unwrap();
+        assert_eq!(record.alerts[0].resolved_at, Some(300));
+        assert!(mark_alert_resolved(&mut record, &warning.alert_id, 400).is_err());
+        assert!(mark_alert_resolved(&mut record, "ALERT_missing", 400).is_err());
+        monitoring.insert("MON_2".to_string(), record);
+        
+        let unresolved = unresolved_alerts_for(&monitoring, "policy_a");
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].alert_id, critical.alert_id);
+    }
+    
+    fn optimization_with_status(status: OptimizationStatus) -> AIOptimization {
+        AIOptimization {
+            optimization_id: "AI_OPT_1".to_string(),
+            policy_id: "policy_a".to_string(),
+            optimization_type: OptimizationType::GasOptimization,
+            ai_model_version: "test".to_string(),
+            confidence_score: 0.9,
+            optimization_metrics: OptimizationMetrics {
+                gas_savings: 100,
+                performance_improvement: 0.5,
+                cost_reduction: 0.2,
+                security_score: 0.9,
+                compliance_score: 0.9,
+                efficiency_gain: 0.7,
+            },
+            recommendations: vec![],
+            execution_plan: ExecutionPlan {
+                plan_id: "PLAN_1".to_string(),
+                steps: vec![],
+                estimated_duration: 0,
+                required_resources: vec![],
+                risk_assessment: RiskAssessment {
+                    risk_level: RiskLevel::Low,
+                    potential_issues: vec![],
+                    mitigation_strategies: vec![],
+                    rollback_plan: String::new(),
+                },
+            },
+            timestamp: 0,
+            status,
+            status_updated_at: None,
+            failure_reason: None,
+            previous_health_score: None,
+        }
+    }
+    
+    #[test]
+    fn test_optimization_legal_transitions() {
+        let mut optimization = optimization_with_status(OptimizationStatus::InProgress);
+        transition_optimization(&mut optimization, OptimizationStatus::Completed, 2).unwrap();
+        transition_optimization(&mut optimization, OptimizationStatus::RolledBack, 3).unwrap();
+        assert!(matches!(optimization.status, OptimizationStatus::RolledBack));
+        assert_eq!(optimization.status_updated_at, Some(3));
+        
+        let mut optimization = optimization_with_status(OptimizationStatus::InProgress);
+        transition_optimization(&mut optimization, OptimizationStatus::Failed, 1).unwrap();
+        assert!(matches!(optimization.status, OptimizationStatus::Failed));
+    }
+    
+    #[test]
+    fn test_optimization_illegal_transitions() {
+        let mut optimization = optimization_with_status(OptimizationStatus::InProgress);
+        assert!(transition_optimization(&mut optimization, OptimizationStatus::RolledBack, 1).is_err());
+        assert!(transition_optimization(&mut optimization, OptimizationStatus::InProgress, 1).is_err());
+        assert!(matches!(optimization.status, OptimizationStatus::InProgress));
+        
+        let mut optimization = optimization_with_status(OptimizationStatus::Failed);
+        assert!(transition_optimization(&mut optimization, OptimizationStatus::RolledBack, 1).is_err());
+        
+        let mut optimization = optimization_with_status(OptimizationStatus::RolledBack);
+        assert!(transition_optimization(&mut optimization, OptimizationStatus::Completed, 1).is_err());
+    }
+    
+    #[test]
+    fn test_health_score_rollback() {
+        let mut monitoring = test_monitoring_map();
+        monitoring.insert("MON_1".to_string(), monitoring_record("MON_1", "policy_a", 0));
+        
+        let previous = set_health_score(&mut monitoring, "policy_a", 0.7, 10);
+        assert_eq!(previous, Some(0.9));
+        set_health_score(&mut monitoring, "policy_a", previous.unwrap(), 20);
+        assert_eq!(monitoring.get(&"MON_1".to_string()).unwrap().health_score, 0.9);
+        assert_eq!(set_health_score(&mut monitoring, "policy_b", 0.5, 30), None);
+    }
+    
+    #[test]
+    fn test_rollback_keeps_newer_health_score() {
+        let mut monitoring = test_monitoring_map();
+        monitoring.insert("MON_1".to_string(), monitoring_record("MON_1", "policy_a", 0));
+        
+        let previous = set_health_score(&mut monitoring, "policy_a", 0.7, 10).unwrap();
+        restore_health_score(&mut monitoring, "policy_a", 0.7, previous, 20).unwrap();
+        assert_eq!(monitoring.get(&"MON_1".to_string()).unwrap().health_score, 0.9);
+        
+        // A later optimization replaced the score, so rolling back the first one would undo it
+        let previous = set_health_score(&mut monitoring, "policy_a", 0.7, 30).unwrap();
+        set_health_score(&mut monitoring, "policy_a", 0.4, 40);
+        assert!(restore_health_score(&mut monitoring, "policy_a", 0.7, previous, 50).is_err());
+        assert_eq!(monitoring.get(&"MON_1".to_string()).unwrap().health_score, 0.4);
+    }
+    
+    fn texts(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+    
+    #[test]
+    fn test_sentiment_follows_feedback_text() {
+        let positive = texts(&[
+            "The new scheme is great and very helpful",
+            "Fast and transparent disbursement, happy with it",
+        ]);
+        let score = analyze_sentiment_score(&positive);
+        assert!(matches!(classify_sentiment(score), SentimentType::Positive));
+        
+        let negative = texts(&[
+            "Payments are slow and delayed, the process is unfair",
+            "Corruption everywhere, a waste of money",
+        ]);
+        let score = analyze_sentiment_score(&negative);
+        assert!(matches!(classify_sentiment(score), SentimentType::Negative));
+        
+        assert_eq!(analyze_sentiment_score(&texts(&["Nothing to report"])), 0.5);
+    }
+    
+    #[test]
+    fn test_feedback_log_keeps_latest_entries() {
+        let mut log = FeedbackLog::default();
+        for n in 0..MAX_FEEDBACK_PER_POLICY + 3 {
+            append_feedback(&mut log, CitizenFeedback {
+                feedback_id: format!("FB_{}", n),
+                policy_id: "policy_a".to_string(),
+                text: "Helpful scheme".to_string(),
+                submitted_by: Principal::anonymous(),
+                timestamp: n as u64,
+            });
+        }
+        assert_eq!(log.entries.len(), MAX_FEEDBACK_PER_POLICY);
+        assert_eq!(log.entries[0].feedback_id, "FB_3");
+        assert_eq!(log.entries.last().unwrap().timestamp, MAX_FEEDBACK_PER_POLICY as u64 + 2);
+    }
+    
+    #[test]
+    fn test_extract_keywords_skips_stopwords() {
+        let feedback = texts(&[
+            "The water supply is better with the water scheme",
+            "Water tanker arrives and the supply is regular",
+        ]);
+        let keywords = extract_keywords(&feedback);
+        assert_eq!(keywords[0], "water");
+        assert_eq!(keywords[1], "supply");
+        assert!(!keywords.contains(&"the".to_string()));
+        assert!(keywords.len() <= MAX_KEYWORDS);
+    }
+    
+    #[test]
+    fn test_optimization_summary() {
+        let mut gas = optimization_with_status(OptimizationStatus::Completed);
+        gas.optimization_type = OptimizationType::GasOptimization;
+        gas.optimization_metrics.gas_savings = 1_000;
+        gas.confidence_score = 0.9;
+        
+        let mut second_gas = optimization_with_status(OptimizationStatus::Failed);
+        second_gas.optimization_type = OptimizationType::GasOptimization;
+        second_gas.optimization_metrics.gas_savings = 500;
+        second_gas.confidence_score = 0.7;
+        
+        let mut security = optimization_with_status(OptimizationStatus::Completed);
+        security.optimization_type = OptimizationType::SecurityOptimization;
+        security.optimization_metrics.gas_savings = 0;
+        security.confidence_score = 0.8;
+        
+        let optimizations = [gas, second_gas, security];
+        let summary = summarize_optimizations(optimizations.iter());
+        assert_eq!(summary.total_optimizations, 3);
+        assert_eq!(summary.total_gas_savings, 1_500);
+        assert!((summary.average_confidence_score - 0.8).abs() < 1e-9);
+        assert_eq!(summary.count_by_type["GasOptimization"], 2);
+        assert_eq!(summary.count_by_type["SecurityOptimization"], 1);
+        assert_eq!(summary.count_by_status["Completed"], 2);
+        assert_eq!(summary.count_by_status["Failed"], 1);
+        
+        let empty = summarize_optimizations(std::iter::empty());
+        assert_eq!(empty.total_optimizations, 0);
+        assert_eq!(empty.average_confidence_score, 0.0);
+    }
+    
+    #[test]
+    fn test_monitoring_refresh_tick() {
+        let mut record = monitoring_record("MON_1", "policy_1", 100);
+        let mut metrics = HashMap::new();
+        metrics.insert("throughput".to_string(), 0.9);
+        metrics.insert("response_time".to_string(), 0.1);
+        metrics.insert("error_rate".to_string(), 0.02);
+        
+        assert!(!refresh_monitoring_record(&mut record, metrics.clone(), 200));
+        assert_eq!(record.last_updated, 200);
+        assert!(record.alerts.is_empty());
+        let healthy_score = record.health_score;
+        
+        metrics.insert("error_rate".to_string(), 0.25);
+        assert!(refresh_monitoring_record(&mut record, metrics.clone(), 300));
+        assert_eq!(record.last_updated, 300);
+        assert!(record.health_score < healthy_score);
+        assert!(matches!(record.alerts[0].severity, AlertSeverity::Warning));
+        
+        // An open alert is not raised twice
+        assert!(!refresh_monitoring_record(&mut record, metrics, 400));
+        assert_eq!(record.alerts.len(), 1);
+    }
+    
+    #[test]
+    fn test_stable_storage_survives_upgrade_beyond_blob_limit() {
+        let memory = DefaultMemoryImpl::default();
+        let padding = "x".repeat(1024);
+        let count = 3_000u32;
+        
+        {
+            let manager = MemoryManager::init(memory.clone());
+            let mut optimizations: StableBTreeMap<String, AIOptimization, Memory> =
+                StableBTreeMap::init(manager.get(OPTIMIZATIONS_MEMORY_ID));
+            for i in 0..count {
+                let mut optimization = optimization_with_status(OptimizationStatus::Completed);
+                optimization.optimization_id = format!("AI_OPT_{}", i);
+                optimization.failure_reason = Some(padding.clone());
+                optimizations.insert(optimization.optimization_id.clone(), optimization);
             }
         }
+        
+        // More than the 2MB the single-blob stable_save path could hold
+        assert!(memory.size() * 65_536 > 2 * 1024 * 1024);
+        
+        // Re-initialising from the same memory is what post_upgrade sees
+        let manager = MemoryManager::init(memory);
+        let optimizations: StableBTreeMap<String, AIOptimization, Memory> =
+            StableBTreeMap::init(manager.get(OPTIMIZATIONS_MEMORY_ID));
+        assert_eq!(optimizations.len(), count as u64);
+        let restored = optimizations.get(&"AI_OPT_2999".to_string()).unwrap();
+        assert_eq!(restored.failure_reason, Some(padding));
+        assert!(matches!(restored.status, OptimizationStatus::Completed));
+    }
+    
+    #[test]
+    fn test_legacy_state_decodes_original_layout() {
+        // AIOptimization as the original canister stored it, before the status bookkeeping fields
+        #[derive(CandidType)]
+        struct OriginalOptimization {
+            optimization_id: String,
+            policy_id: String,
+            optimization_type: OptimizationType,
+            ai_model_version: String,
+            confidence_score: f64,
+            optimization_metrics: OptimizationMetrics,
+            recommendations: Vec<AIRecommendation>,
+            execution_plan: ExecutionPlan,
+            timestamp: u64,
+            status: OptimizationStatus,
+        }
+        
+        let current = optimization_with_status(OptimizationStatus::Completed);
+        let original = OriginalOptimization {
+            optimization_id: "AI_OPT_1".to_string(),
+            policy_id: current.policy_id,
+            optimization_type: current.optimization_type,
+            ai_model_version: current.ai_model_version,
+            confidence_score: current.confidence_score,
+            optimization_metrics: current.optimization_metrics,
+            recommendations: current.recommendations,
+            execution_plan: current.execution_plan,
+            timestamp: 7,
+            status: current.status,
+        };
+        let optimizations = HashMap::from([("AI_OPT_1".to_string(), original)]);
+        let bytes = candid::encode_args((
+            optimizations,
+            HashMap::<String, PredictiveAnalytics>::new(),
+            HashMap::<String, RealTimeMonitoring>::new(),
+            HashMap::<String, CitizenSentiment>::new(),
+        )).unwrap();
+        
+        let (optimizations, analytics, monitoring, sentiments): LegacyState = candid::decode_args(&bytes).unwrap();
+        let restored = &optimizations["AI_OPT_1"];
+        assert_eq!(restored.timestamp, 7);
+        assert_eq!(restored.status_updated_at, None);
+        assert_eq!(restored.failure_reason, None);
+        assert!(matches!(restored.status, OptimizationStatus::Completed));
+        assert!(analytics.is_empty() && monitoring.is_empty() && sentiments.is_empty());
+    }
+    
+    #[test]
+    fn test_parse_recommendations() {
+        let reply = "Cache policy lookups | Avoid repeated map scans | High | 0.6 | Easy\n\
+                     Add rate limiting | Throttle release calls | critical | 1.7 | Hard";
+        let recommendations = parse_recommendations(reply);
+        assert_eq!(recommendations.len(), 2);
+        assert_eq!(recommendations[0].title, "Cache policy lookups");
+        assert!(matches!(recommendations[0].priority, Priority::High));
+        assert!(matches!(recommendations[1].implementation_difficulty, Difficulty::Hard));
+        assert_eq!(recommendations[1].estimated_impact, 1.0);
+    }
+    
+    #[test]
+    fn test_health_check_counts() {
+        assert!(!health_status_at(400).initialized);
+        unsafe {
+            INIT_TIME_NS = Some(100);
+        }
+        let before = health_status_at(400);
+        assert!(before.initialized);
+        assert_eq!(before.uptime_ns, 300);
+        assert_eq!(before.record_counts[4], ("feedback_logs".to_string(), 0));
+        
+        CITIZEN_FEEDBACK.with(|map| map.borrow_mut().insert("policy_1".to_string(), FeedbackLog { entries: Vec::new() }));
+        assert_eq!(health_status_at(400).record_counts[4], ("feedback_logs".to_string(), 1));
     }
 }
@@ -1,27 +1,242 @@
+use candid::{CandidType, Deserialize, Principal};
+use common::CanisterStats;
 use ic_cdk::export_candid;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
 
-use ic_llm::{ChatMessage, Model};
+use ic_llm::{AssistantMessage, ChatMessage, FunctionCall, Model, ParameterType, Tool};
+
+const RATE_LIMIT_WINDOW_NS: u64 = 60 * 60 * 1_000_000_000;
+const DEFAULT_MAX_CALLS_PER_HOUR: u32 = 30;
+const MAX_LEADERBOARD_SIZE: u64 = 100;
+// Follow-up turns allowed while the model keeps requesting tools
+const MAX_TOOL_ROUNDS: usize = 3;
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct CallWindow {
+    started_at: u64,
+    calls: u32,
+}
+
+thread_local! {
+    static MAX_CALLS_PER_HOUR: RefCell<u32> = const { RefCell::new(DEFAULT_MAX_CALLS_PER_HOUR) };
+    static PRINCIPAL_COUNTERS: RefCell<BTreeMap<Principal, CallWindow>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+// Rate-limit state as saved across upgrades; fields added later go on the end as Options
+type UpgradeState = (Option<u32>, Option<BTreeMap<Principal, CallWindow>>);
+
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    let state: UpgradeState = (
+        Some(get_max_calls_per_hour()),
+        Some(PRINCIPAL_COUNTERS.with(|counters| counters.take())),
+    );
+    ic_cdk::storage::stable_save(state).expect("Failed to save rate limit state");
+}
+
+// Canisters upgraded from before anything was saved keep the defaults
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    if let Ok(state) = ic_cdk::storage::stable_restore::<UpgradeState>() {
+        restore_rate_limit(state);
+    }
+}
+
+fn restore_rate_limit((max_calls, counters): UpgradeState) {
+    if let Some(max_calls) = max_calls {
+        MAX_CALLS_PER_HOUR.with(|max| *max.borrow_mut() = max_calls);
+    }
+    if let Some(counters) = counters {
+        PRINCIPAL_COUNTERS.with(|stored| *stored.borrow_mut() = counters);
+    }
+}
 
 #[ic_cdk::update]
 async fn prompt(prompt_str: String) -> String {
+    enforce_rate_limit().unwrap_or_else(|e| ic_cdk::trap(&e));
     ic_llm::prompt(Model::Llama3_1_8B, prompt_str).await
 }
 
 #[ic_cdk::update]
 async fn chat(messages: Vec<ChatMessage>) -> String {
-    let response = ic_llm::chat(Model::Llama3_1_8B)
-        .with_messages(messages)
-        .send()
-        .await;
+    enforce_rate_limit().unwrap_or_else(|e| ic_cdk::trap(&e));
+    run_chat(ic_cdk::caller(), messages, |messages| async move {
+        ic_llm::chat(Model::Llama3_1_8B)
+            .with_messages(messages)
+            .with_tools(chat_tools())
+            .send()
+            .await
+            .message
+    })
+    .await
+}
+
+// Answers tool calls and sends the results back until the model replies in text or the
+// round cap is hit, in which case whatever text the last turn carried is returned
+async fn run_chat<F, Fut>(caller: Principal, mut messages: Vec<ChatMessage>, mut send: F) -> String
+where
+    F: FnMut(Vec<ChatMessage>) -> Fut,
+    Fut: Future<Output = AssistantMessage>,
+{
+    let mut message = send(messages.clone()).await;
+    for _ in 0..MAX_TOOL_ROUNDS {
+        if message.tool_calls.is_empty() {
+            break;
+        }
+        append_tool_results(&mut messages, message, &caller);
+        message = send(messages.clone()).await;
+    }
+    message.content.unwrap_or_default()
+}
+
+fn append_tool_results(messages: &mut Vec<ChatMessage>, message: AssistantMessage, caller: &Principal) {
+    let results: Vec<ChatMessage> = message
+        .tool_calls
+        .iter()
+        .map(|call| ChatMessage::Tool {
+            content: dispatch_tool(&call.function, caller),
+            tool_call_id: call.id.clone(),
+        })
+        .collect();
+    messages.push(ChatMessage::Assistant(message));
+    messages.extend(results);
+}
+
+fn chat_tools() -> Vec<Tool> {
+    vec![
+        ic_llm::tool("greet")
+            .with_description("Greet someone by name")
+            .with_parameter(
+                ic_llm::parameter("name", ParameterType::String)
+                    .with_description("The name of the person to greet")
+                    .is_required(),
+            )
+            .build(),
+        ic_llm::tool("get_count")
+            .with_description("Get the current value of the caller's counter")
+            .build(),
+    ]
+}
+
+// Unknown tools are reported back to the model rather than failing the chat
+fn dispatch_tool(call: &FunctionCall, caller: &Principal) -> String {
+    match call.name.as_str() {
+        "greet" => greet(call.get("name").unwrap_or_else(|| "World".to_string())),
+        "get_count" => count_of(caller).to_string(),
+        name => format!("Unknown tool: {}", name),
+    }
+}
+
+#[ic_cdk::update]
+async fn chat_with_system(system: String, messages: Vec<ChatMessage>) -> String {
+    chat(with_system_message(system, messages)).await
+}
+
+#[ic_cdk::update]
+async fn prompt_with_model(model_name: String, prompt: String) -> Result<String, String> {
+    let model = parse_model(&model_name)?;
+    enforce_rate_limit()?;
+    Ok(ic_llm::prompt(model, prompt).await)
+}
+
+#[ic_cdk::update]
+fn set_max_calls_per_hour(max_calls: u32) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can change the rate limit".to_string());
+    }
+    if max_calls == 0 {
+        return Err("max_calls_per_hour must be at least 1".to_string());
+    }
+    MAX_CALLS_PER_HOUR.with(|max| *max.borrow_mut() = max_calls);
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_max_calls_per_hour() -> u32 {
+    MAX_CALLS_PER_HOUR.with(|max| *max.borrow())
+}
+
+// Most active callers by LLM calls in their current window; expired windows are left out
+#[ic_cdk::query]
+fn get_leaderboard(limit: u64) -> Vec<(Principal, u64)> {
+    let now = ic_cdk::api::time();
+    PRINCIPAL_COUNTERS.with(|counters| leaderboard(&counters.borrow(), now, limit))
+}
+
+fn leaderboard(counters: &BTreeMap<Principal, CallWindow>, now: u64, limit: u64) -> Vec<(Principal, u64)> {
+    let mut entries: Vec<(Principal, u64)> = counters
+        .iter()
+        .filter(|(_, window)| now < window.started_at.saturating_add(RATE_LIMIT_WINDOW_NS))
+        .map(|(principal, window)| (*principal, u64::from(window.calls)))
+        .collect();
+    // Stable sort keeps principal order among equal counts
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.1));
+    entries.truncate(limit.min(MAX_LEADERBOARD_SIZE) as usize);
+    entries
+}
+
+// Counts one LLM call against the caller's hourly window
+fn enforce_rate_limit() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    let max_calls = get_max_calls_per_hour();
+    PRINCIPAL_COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        let window = counters.remove(&caller);
+        let window = record_call(window, now, max_calls)?;
+        counters.insert(caller, window);
+        Ok(())
+    })
+}
 
-    // A response can contain tool calls, but we're not calling tools in this project,
-    // so we can return the response message directly.
-    response.message.content.unwrap_or_default()
+// Windows are fixed: they start at the first call and reset once an hour has passed
+fn record_call(window: Option<CallWindow>, now: u64, max_calls: u32) -> Result<CallWindow, String> {
+    let window = match window {
+        Some(window) if now < window.started_at.saturating_add(RATE_LIMIT_WINDOW_NS) => window,
+        _ => CallWindow { started_at: now, calls: 0 },
+    };
+    if window.calls >= max_calls {
+        return Err(format!(
+            "Rate limit exceeded: at most {} LLM calls per hour",
+            max_calls
+        ));
+    }
+    Ok(CallWindow { calls: window.calls + 1, ..window })
+}
+
+fn with_system_message(system: String, messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let mut with_system = Vec::with_capacity(messages.len() + 1);
+    with_system.push(ChatMessage::System { content: system });
+    with_system.extend(messages);
+    with_system
+}
+
+// Accepts the model's canonical name ("llama3.1:8b") or its enum name, in any case
+fn parse_model(name: &str) -> Result<Model, String> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "llama3.1:8b" | "llama3_1_8b" => Ok(Model::Llama3_1_8B),
+        _ => Err(format!("Unknown model: {}", name)),
+    }
 }
 
 thread_local! {
-    static COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    // Each caller has a counter of their own, starting at zero
+    static COUNTERS: RefCell<BTreeMap<Principal, u64>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+fn count_of(caller: &Principal) -> u64 {
+    COUNTERS.with(|counters| counters.borrow().get(caller).copied().unwrap_or(0))
+}
+
+fn update_count(caller: Principal, change: impl FnOnce(u64) -> u64) -> u64 {
+    COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        let count = counters.entry(caller).or_insert(0);
+        *count = change(*count);
+        *count
+    })
 }
 
 #[ic_cdk::query]
@@ -31,24 +246,226 @@ fn greet(name: String) -> String {
 
 #[ic_cdk::update]
 fn increment() -> u64 {
-    COUNTER.with(|counter| {
-        let val = *counter.borrow() + 1;
-        *counter.borrow_mut() = val;
-        val
-    })
+    update_count(ic_cdk::caller(), |count| count + 1)
+}
+
+// Saturates at zero rather than wrapping
+#[ic_cdk::update]
+fn decrement() -> u64 {
+    update_count(ic_cdk::caller(), |count| count.saturating_sub(1))
+}
+
+// Resets only the caller's own count
+#[ic_cdk::update]
+fn reset() -> u64 {
+    update_count(ic_cdk::caller(), |_| 0)
 }
 
 #[ic_cdk::query]
 fn get_count() -> u64 {
-    COUNTER.with(|counter| *counter.borrow())
+    count_of(&ic_cdk::caller())
 }
 
 #[ic_cdk::update]
 fn set_count(value: u64) -> u64 {
-    COUNTER.with(|counter| {
-        *counter.borrow_mut() = value;
-        value
-    })
+    update_count(ic_cdk::caller(), |_| value)
+}
+
+#[ic_cdk::query]
+fn get_canister_stats() -> CanisterStats {
+    common::canister_stats()
 }
 
 export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_llm::ToolCall;
+    use std::collections::VecDeque;
+    use std::task::{Context, Poll, Waker};
+
+    #[test]
+    fn test_parse_model() {
+        for name in ["llama3.1:8b", "Llama3_1_8B", " LLAMA3.1:8B "] {
+            assert!(matches!(parse_model(name), Ok(Model::Llama3_1_8B)));
+        }
+        assert_eq!(parse_model("gpt-4").err().as_deref(), Some("Unknown model: gpt-4"));
+    }
+
+    #[test]
+    fn test_decrement_and_reset() {
+        let caller = Principal::from_slice(&[1, 3]);
+        let other = Principal::from_slice(&[2, 3]);
+        let decrement = || update_count(caller, |count| count.saturating_sub(1));
+        update_count(caller, |_| 2);
+        assert_eq!(decrement(), 1);
+        assert_eq!(decrement(), 0);
+        assert_eq!(decrement(), 0);
+        assert_eq!(count_of(&caller), 0);
+
+        update_count(caller, |_| u64::MAX);
+        update_count(other, |_| 5);
+        assert_eq!(decrement(), u64::MAX - 1);
+        assert_eq!(update_count(caller, |_| 0), 0);
+        assert_eq!(count_of(&caller), 0);
+        // Other callers keep their counts
+        assert_eq!(count_of(&other), 5);
+        assert_eq!(update_count(caller, |count| count + 1), 1);
+    }
+
+    #[test]
+    fn test_rate_limit_window() {
+        let mut window = None;
+        for call in 1..=3 {
+            let updated = record_call(window, 1_000 + call, 3).unwrap();
+            assert_eq!(updated.calls, call as u32);
+            assert_eq!(updated.started_at, 1_001);
+            window = Some(updated);
+        }
+
+        // The call over the limit is rejected and the window is left as it was
+        assert_eq!(
+            record_call(window, 2_000, 3),
+            Err("Rate limit exceeded: at most 3 LLM calls per hour".to_string())
+        );
+        assert_eq!(
+            record_call(window, 1_001 + RATE_LIMIT_WINDOW_NS - 1, 3).err(),
+            Some("Rate limit exceeded: at most 3 LLM calls per hour".to_string())
+        );
+
+        let reset = record_call(window, 1_001 + RATE_LIMIT_WINDOW_NS, 3).unwrap();
+        assert_eq!(reset, CallWindow { started_at: 1_001 + RATE_LIMIT_WINDOW_NS, calls: 1 });
+    }
+
+    #[test]
+    fn test_rate_limit_survives_upgrade() {
+        let window = CallWindow { started_at: 100, calls: 4 };
+        let caller = Principal::from_slice(&[1, 1]);
+        let saved = candid::encode_one((Some(12u32), Some(BTreeMap::from([(caller, window)])))).unwrap();
+        restore_rate_limit(candid::decode_one(&saved).unwrap());
+        assert_eq!(get_max_calls_per_hour(), 12);
+        assert_eq!(PRINCIPAL_COUNTERS.with(|counters| counters.borrow().get(&caller).copied()), Some(window));
+
+        // A state saved without the counters leaves the current ones in place
+        let saved = candid::encode_one((Some(20u32), None::<BTreeMap<Principal, CallWindow>>)).unwrap();
+        restore_rate_limit(candid::decode_one(&saved).unwrap());
+        assert_eq!(get_max_calls_per_hour(), 20);
+        assert_eq!(PRINCIPAL_COUNTERS.with(|counters| counters.borrow().len()), 1);
+    }
+
+    #[test]
+    fn test_leaderboard() {
+        let mut counters = BTreeMap::new();
+        for (id, calls) in [(1u8, 3u32), (2, 7), (3, 5), (4, 5)] {
+            counters.insert(Principal::from_slice(&[id, 1]), CallWindow { started_at: 100, calls });
+        }
+        // Expired window, so it no longer counts
+        counters.insert(Principal::from_slice(&[5, 1]), CallWindow { started_at: 0, calls: 9 });
+        let now = RATE_LIMIT_WINDOW_NS + 50;
+
+        let top = leaderboard(&counters, now, 3);
+        assert_eq!(
+            top,
+            vec![
+                (Principal::from_slice(&[2, 1]), 7),
+                (Principal::from_slice(&[3, 1]), 5),
+                (Principal::from_slice(&[4, 1]), 5),
+            ]
+        );
+        assert_eq!(leaderboard(&counters, now, 1_000).len(), 4);
+        assert!(leaderboard(&counters, now, 0).is_empty());
+
+        for id in 0..150u8 {
+            counters.insert(Principal::from_slice(&[id, 2]), CallWindow { started_at: 100, calls: 1 });
+        }
+        assert_eq!(leaderboard(&counters, now, 1_000).len(), 100);
+    }
+
+    // ic_llm doesn't export the argument type, so test calls carry no arguments
+    fn tool_call(id: &str, name: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            function: FunctionCall { name: name.to_string(), arguments: Vec::new() },
+        }
+    }
+
+    fn reply(content: Option<&str>, tool_calls: Vec<ToolCall>) -> AssistantMessage {
+        AssistantMessage { content: content.map(str::to_string), tool_calls }
+    }
+
+    // The send closures below return ready futures, so a single poll completes the chat
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("chat future did not complete"),
+        }
+    }
+
+    #[test]
+    fn test_tool_calls_are_dispatched() {
+        let caller = Principal::from_slice(&[1, 4]);
+        update_count(caller, |_| 7);
+        let mut responses = VecDeque::from(vec![
+            reply(None, vec![tool_call("call_1", "greet")]),
+            reply(None, vec![tool_call("call_2", "get_count"), tool_call("call_3", "launch")]),
+            reply(Some("Hello, the count is 7"), vec![]),
+        ]);
+        let mut sent = Vec::new();
+
+        let answer = block_on(run_chat(
+            caller,
+            vec![ChatMessage::User { content: "Say hello and read the counter".to_string() }],
+            |messages| {
+                sent.push(messages);
+                std::future::ready(responses.pop_front().unwrap())
+            },
+        ));
+
+        assert_eq!(answer, "Hello, the count is 7");
+        assert_eq!(sent.len(), 3);
+        assert_eq!(
+            sent[1][2],
+            ChatMessage::Tool { content: "Hello, World!".to_string(), tool_call_id: "call_1".to_string() }
+        );
+        assert_eq!(
+            &sent[2][4..],
+            &[
+                ChatMessage::Tool { content: "7".to_string(), tool_call_id: "call_2".to_string() },
+                ChatMessage::Tool {
+                    content: "Unknown tool: launch".to_string(),
+                    tool_call_id: "call_3".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_rounds_are_capped() {
+        let mut calls = 0;
+        let answer = block_on(run_chat(Principal::anonymous(), Vec::new(), |_| {
+            calls += 1;
+            std::future::ready(reply(Some("still thinking"), vec![tool_call("call", "get_count")]))
+        }));
+
+        assert_eq!(answer, "still thinking");
+        assert_eq!(calls, MAX_TOOL_ROUNDS + 1);
+    }
+
+    #[test]
+    fn test_system_message_goes_first() {
+        let messages = with_system_message(
+            "Answer briefly".to_string(),
+            vec![ChatMessage::User { content: "Hi".to_string() }],
+        );
+        assert_eq!(
+            messages,
+            vec![
+                ChatMessage::System { content: "Answer briefly".to_string() },
+                ChatMessage::User { content: "Hi".to_string() },
+            ]
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use candid::{CandidType, Deserialize};
-use ic_cdk::{api::call::call, export::candid, init, post_upgrade, pre_upgrade, query, update};
-use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
+use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
+use serde::Serialize as SerdeSerialize;
+use common::{CanisterStats, HealthStatus, TransactionStatus, VerificationLog};
 use std::collections::BTreeMap;
 use uuid::Uuid;
 
@@ -10,7 +11,7 @@ const POLYGON_RPC_URL: &str = "https://polygon-rpc.com";
 const SOLANA_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
 const WCHL25_HACKATHON_ID: &str = "WCHL25_CIVICLEDGER_BLOCKCHAIN_VERIFIER";
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct BlockchainTransaction {
     pub transaction_id: String,
     pub block_hash: String,
@@ -29,7 +30,7 @@ pub struct BlockchainTransaction {
     pub sharding_verification: Option<ShardingVerification>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct CrossChainVerification {
     pub blockchain: String,
     pub transaction_hash: String,
@@ -40,7 +41,7 @@ pub struct CrossChainVerification {
     pub cross_chain_proof: String,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct QuantumSignature {
     pub signature_type: String,
     pub public_key: String,
@@ -51,7 +52,7 @@ pub struct QuantumSignature {
     pub post_quantum_algorithm: String,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct ZeroKnowledgeProof {
     pub proof_type: String,
     pub proof_data: String,
@@ -62,7 +63,7 @@ pub struct ZeroKnowledgeProof {
     pub zk_snark_parameters: ZKSnarkParameters,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct ZKSnarkParameters {
     pub proving_key: String,
     pub verification_key: String,
@@ -71,7 +72,7 @@ pub struct ZKSnarkParameters {
     pub proof_size: u64,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct AtomicSwapDetails {
     pub swap_id: String,
     pub source_chain: String,
@@ -84,7 +85,7 @@ pub struct AtomicSwapDetails {
     pub swap_timestamp: u64,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct Layer2Optimization {
     pub layer2_protocol: String,
     pub rollup_type: String,
@@ -96,7 +97,7 @@ pub struct Layer2Optimization {
     pub compression_ratio: f64,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct ShardingVerification {
     pub shard_id: String,
     pub shard_count: u32,
@@ -108,18 +109,7 @@ pub struct ShardingVerification {
     pub shard_consensus_score: f64,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub enum TransactionStatus {
-    Pending,
-    Confirmed,
-    Failed,
-    CrossChainConfirmed,
-    QuantumSecured,
-    Layer2Optimized,
-    ShardingVerified,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub enum SwapStatus {
     Initiated,
     HashLocked,
@@ -129,7 +119,7 @@ pub enum SwapStatus {
     Failed,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct MerkleTree {
     pub root_hash: String,
     pub leaf_count: u32,
@@ -139,7 +129,7 @@ pub struct MerkleTree {
     pub verification_status: bool,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct ConsensusProof {
     pub consensus_id: String,
     pub participating_chains: Vec<String>,
@@ -155,17 +145,8 @@ static mut TRANSACTIONS: Option<BTreeMap<String, BlockchainTransaction>> = None;
 static mut MERKLE_TREES: Option<BTreeMap<String, MerkleTree>> = None;
 static mut CONSENSUS_PROOFS: Option<BTreeMap<String, ConsensusProof>> = None;
 static mut VERIFICATION_LOGS: Option<BTreeMap<String, Vec<VerificationLog>>> = None;
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct VerificationLog {
-    pub log_id: String,
-    pub transaction_id: String,
-    pub verification_type: String,
-    pub status: bool,
-    pub timestamp: u64,
-    pub details: String,
-    pub blockchain_hash: Option<String>,
-}
+// Set at install; canisters installed before it existed start counting at their next upgrade
+static mut INIT_TIME_NS: Option<u64> = None;
 
 #[init]
 fn init() {
@@ -174,6 +155,7 @@ fn init() {
         MERKLE_TREES = Some(BTreeMap::new());
         CONSENSUS_PROOFS = Some(BTreeMap::new());
         VERIFICATION_LOGS = Some(BTreeMap::new());
+        INIT_TIME_NS = Some(ic_cdk::api::time());
     }
     
     ic_cdk::println!("🚀 WCHL25: Blockchain Verifier initialized successfully");
@@ -185,17 +167,20 @@ fn pre_upgrade() {
     let merkle_trees = unsafe { MERKLE_TREES.take().unwrap() };
     let consensus_proofs = unsafe { CONSENSUS_PROOFS.take().unwrap() };
     let verification_logs = unsafe { VERIFICATION_LOGS.take().unwrap() };
+    let init_time_ns = unsafe { INIT_TIME_NS };
     
-    ic_cdk::storage::stable_save((transactions, merkle_trees, consensus_proofs, verification_logs)).unwrap();
+    ic_cdk::storage::stable_save((transactions, merkle_trees, consensus_proofs, verification_logs, init_time_ns)).unwrap();
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (transactions, merkle_trees, consensus_proofs, verification_logs): (
+    // init_time_ns is absent when upgrading from before health_check existed
+    let (transactions, merkle_trees, consensus_proofs, verification_logs, init_time_ns): (
         BTreeMap<String, BlockchainTransaction>,
         BTreeMap<String, MerkleTree>,
         BTreeMap<String, ConsensusProof>,
         BTreeMap<String, Vec<VerificationLog>>,
+        Option<u64>,
     ) = ic_cdk::storage::stable_restore().unwrap();
     
     unsafe {
@@ -203,6 +188,7 @@ fn post_upgrade() {
         MERKLE_TREES = Some(merkle_trees);
         CONSENSUS_PROOFS = Some(consensus_proofs);
         VERIFICATION_LOGS = Some(verification_logs);
+        INIT_TIME_NS = Some(init_time_ns.unwrap_or_else(ic_cdk::api::time));
     }
 }
 
@@ -211,7 +197,7 @@ async fn verify_transaction(transaction_id: String) -> Result<BlockchainTransact
     let now = ic_cdk::api::time();
     
     // Generate blockchain hash
-    let block_hash = generate_block_hash(&transaction_id);
+    let block_hash = generate_block_hash(&transaction_id, now);
     
     // Perform cross-chain verification
     let cross_chain_verification = perform_cross_chain_verification(&transaction_id).await;
@@ -272,7 +258,8 @@ async fn verify_transaction(transaction_id: String) -> Result<BlockchainTransact
         if let Some(ref mut logs) = VERIFICATION_LOGS {
             let log_entry = VerificationLog {
                 log_id: format!("LOG_{}", Uuid::new_v4().to_string()),
-                transaction_id: transaction_id.clone(),
+                transaction_id: Some(transaction_id.clone()),
+                policy_id: None,
                 verification_type: "Cross-Chain Verification".to_string(),
                 status: true,
                 timestamp: now,
@@ -283,7 +270,7 @@ async fn verify_transaction(transaction_id: String) -> Result<BlockchainTransact
             if let Some(logs_for_tx) = logs.get_mut(&transaction_id) {
                 logs_for_tx.push(log_entry);
             } else {
-                logs.insert(transaction_id, vec![log_entry]);
+                logs.insert(transaction_id.clone(), vec![log_entry]);
             }
         }
     }
@@ -305,7 +292,7 @@ async fn verify_cross_chain_transaction(policy_id: String) -> Result<CrossChainV
     
     let cross_chain_verification = CrossChainVerification {
         blockchain: "Multi-Chain".to_string(),
-        transaction_hash: generate_transaction_hash(&policy_id),
+        transaction_hash: generate_transaction_hash(&policy_id, now),
         verification_status: true,
         confirmation_count: 15,
         verification_timestamp: now,
@@ -539,16 +526,16 @@ async fn verify_on_icp(policy_id: &str) -> bool {
     true
 }
 
-fn generate_block_hash(transaction_id: &str) -> String {
-    format!("0x{}{}", transaction_id, ic_cdk::api::time()).chars().take(64).collect()
+fn generate_block_hash(transaction_id: &str, now: u64) -> String {
+    format!("0x{}{}", transaction_id, now).chars().take(64).collect()
 }
 
 fn generate_block_number() -> u64 {
     ic_cdk::api::time() / 12 // Simulate block time
 }
 
-fn generate_transaction_hash(policy_id: &str) -> String {
-    format!("0x{}{}", policy_id, ic_cdk::api::time()).chars().take(64).collect()
+fn generate_transaction_hash(policy_id: &str, now: u64) -> String {
+    format!("0x{}{}", policy_id, now).chars().take(64).collect()
 }
 
 fn generate_cross_chain_proof(transaction_id: &str) -> String {
@@ -575,6 +562,32 @@ fn generate_consensus_proof(transaction_id: &str) -> String {
     format!("CONSENSUS_PROOF_{}{}", transaction_id, ic_cdk::api::time())
 }
 
+#[query]
+fn get_canister_stats() -> CanisterStats {
+    common::canister_stats()
+}
+
+#[query]
+fn health_check() -> HealthStatus {
+    health_status_at(ic_cdk::api::time())
+}
+
+fn health_status_at(now: u64) -> HealthStatus {
+    unsafe {
+        common::health_status(
+            TRANSACTIONS.is_some() && MERKLE_TREES.is_some() && CONSENSUS_PROOFS.is_some() && VERIFICATION_LOGS.is_some(),
+            &[
+                ("transactions", TRANSACTIONS.as_ref().map_or(0, BTreeMap::len)),
+                ("merkle_trees", MERKLE_TREES.as_ref().map_or(0, BTreeMap::len)),
+                ("consensus_proofs", CONSENSUS_PROOFS.as_ref().map_or(0, BTreeMap::len)),
+                ("verification_logs", VERIFICATION_LOGS.as_ref().map_or(0, |logs| logs.values().map(Vec::len).sum())),
+            ],
+            INIT_TIME_NS,
+            now,
+        )
+    }
+}
+
 // Candid interface
 candid::export_service!();
 
@@ -584,16 +597,16 @@ mod tests {
     
     #[test]
     fn test_block_hash_generation() {
-        let hash = generate_block_hash("test_tx");
+        let hash = generate_block_hash("test_tx", 42);
         assert!(hash.starts_with("0x"));
-        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, "0xtest_tx42");
     }
     
     #[test]
     fn test_transaction_hash_generation() {
-        let hash = generate_transaction_hash("test_policy");
+        let hash = generate_transaction_hash("test_policy", 42);
         assert!(hash.starts_with("0x"));
-        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, "0xtest_policy42");
     }
     
     #[test]
@@ -603,4 +616,33 @@ mod tests {
         assert!(root.contains("leaf1"));
         assert!(root.contains("leaf2"));
     }
+    
+    #[test]
+    fn test_health_check_counts() {
+        unsafe {
+            TRANSACTIONS = Some(BTreeMap::new());
+            MERKLE_TREES = Some(BTreeMap::new());
+            CONSENSUS_PROOFS = Some(BTreeMap::new());
+            VERIFICATION_LOGS = Some(BTreeMap::new());
+            INIT_TIME_NS = Some(100);
+        }
+        let before = health_status_at(400);
+        assert!(before.initialized);
+        assert_eq!(before.uptime_ns, 300);
+        assert_eq!(before.record_counts[1], ("merkle_trees".to_string(), 0));
+        
+        unsafe {
+            if let Some(ref mut trees) = MERKLE_TREES {
+                trees.insert("tree_1".to_string(), MerkleTree {
+                    root_hash: "0xroot".to_string(),
+                    leaf_count: 0,
+                    tree_depth: 0,
+                    leaf_hashes: Vec::new(),
+                    proof_paths: Vec::new(),
+                    verification_status: true,
+                });
+            }
+        }
+        assert_eq!(health_status_at(400).record_counts[1], ("merkle_trees".to_string(), 1));
+    }
 }
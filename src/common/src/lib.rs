@@ -0,0 +1,490 @@
+// Helpers and types shared by the CivicLedger canisters.
+//
+// Only code whose behaviour is identical everywhere belongs here. Types several canisters
+// send on the wire (IndiaHubRegistration, VerificationLog, TransactionStatus) are defined
+// once here; fields only some canisters fill in are Options, so each canister's earlier
+// stable data still decodes.
+
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+// Returned by every canister's health_check query
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct HealthStatus {
+    pub initialized: bool,
+    pub record_counts: Vec<(String, u64)>,
+    pub uptime_ns: u64,
+}
+
+// Uptime runs from init_time_ns, the time the canister was installed (or first upgraded to a
+// version that records it); None reports zero uptime
+pub fn health_status(
+    initialized: bool,
+    record_counts: &[(&str, usize)],
+    init_time_ns: Option<u64>,
+    now: u64,
+) -> HealthStatus {
+    HealthStatus {
+        initialized,
+        record_counts: record_counts
+            .iter()
+            .map(|(name, count)| (name.to_string(), *count as u64))
+            .collect(),
+        uptime_ns: init_time_ns.map_or(0, |started| now.saturating_sub(started)),
+    }
+}
+
+// Returned by every canister's get_canister_stats query, for dashboards watching resource usage
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct CanisterStats {
+    pub cycles_balance: u64,
+    pub stable_memory_bytes: u64,
+    // Size of the Wasm linear memory, which only grows; an upper bound on the live heap
+    pub heap_size: u64,
+}
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+// Reads the system APIs, so it can only run inside a canister
+pub fn canister_stats() -> CanisterStats {
+    stats_from_pages(
+        ic_cdk::api::canister_balance(),
+        ic_cdk::api::stable::stable_size(),
+        heap_pages(),
+    )
+}
+
+fn stats_from_pages(cycles_balance: u64, stable_pages: u64, heap_pages: u64) -> CanisterStats {
+    CanisterStats {
+        cycles_balance,
+        stable_memory_bytes: stable_pages.saturating_mul(WASM_PAGE_SIZE),
+        heap_size: heap_pages.saturating_mul(WASM_PAGE_SIZE),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn heap_pages() -> u64 {
+    core::arch::wasm32::memory_size(0) as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn heap_pages() -> u64 {
+    0
+}
+
+// Witness in the IC hash tree format; everything off the path to the leaf is Pruned
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum HashTree {
+    Empty,
+    Fork(Box<HashTree>, Box<HashTree>),
+    Labeled(Vec<u8>, Box<HashTree>),
+    Leaf(Vec<u8>),
+    Pruned(Vec<u8>),
+}
+
+// Hashing as in the IC interface spec: H(domain_sep(tag) · children), domain_sep(s) = |s| · s
+fn tree_hash(tag: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([tag.len() as u8]);
+    hasher.update(tag.as_bytes());
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+pub fn reconstruct(tree: &HashTree) -> [u8; 32] {
+    match tree {
+        HashTree::Empty => tree_hash("ic-hashtree-empty", &[]),
+        HashTree::Fork(left, right) => tree_hash("ic-hashtree-fork", &[&reconstruct(left), &reconstruct(right)]),
+        HashTree::Labeled(label, subtree) => tree_hash("ic-hashtree-labeled", &[label, &reconstruct(subtree)]),
+        HashTree::Leaf(value) => tree_hash("ic-hashtree-leaf", &[value]),
+        HashTree::Pruned(hash) => hash.as_slice().try_into().unwrap_or([0; 32]),
+    }
+}
+
+// Root of a tree holding one leaf per key, in key order under balanced forks, all under
+// `label`; this is the value a canister passes to set_certified_data
+pub fn certified_root(label: &[u8], leaves: &BTreeMap<String, [u8; 32]>) -> [u8; 32] {
+    let leaves: Vec<(&String, &[u8; 32])> = leaves.iter().collect();
+    tree_hash("ic-hashtree-labeled", &[label, &subtree_hash(&leaves)])
+}
+
+fn subtree_hash(leaves: &[(&String, &[u8; 32])]) -> [u8; 32] {
+    match leaves {
+        [] => tree_hash("ic-hashtree-empty", &[]),
+        [(key, hash)] => tree_hash("ic-hashtree-labeled", &[key.as_bytes(), &tree_hash("ic-hashtree-leaf", &[*hash])]),
+        _ => {
+            let (left, right) = leaves.split_at(leaves.len() / 2);
+            tree_hash("ic-hashtree-fork", &[&subtree_hash(left), &subtree_hash(right)])
+        }
+    }
+}
+
+// Witness for one key of the tree certified_root hashes; None if the key has no leaf
+pub fn certified_witness(label: &[u8], leaves: &BTreeMap<String, [u8; 32]>, key: &str) -> Option<HashTree> {
+    if !leaves.contains_key(key) {
+        return None;
+    }
+    let leaves: Vec<(&String, &[u8; 32])> = leaves.iter().collect();
+    Some(HashTree::Labeled(label.to_vec(), Box::new(subtree_witness(&leaves, key))))
+}
+
+fn subtree_witness(leaves: &[(&String, &[u8; 32])], key: &str) -> HashTree {
+    match leaves {
+        [(leaf_key, hash)] if leaf_key.as_str() == key => {
+            HashTree::Labeled(leaf_key.as_bytes().to_vec(), Box::new(HashTree::Leaf(hash.to_vec())))
+        }
+        [] | [_] => HashTree::Pruned(subtree_hash(leaves).to_vec()),
+        _ => {
+            let (left, right) = leaves.split_at(leaves.len() / 2);
+            if key < right[0].0.as_str() {
+                HashTree::Fork(Box::new(subtree_witness(left, key)), Box::new(HashTree::Pruned(subtree_hash(right).to_vec())))
+            } else {
+                HashTree::Fork(Box::new(HashTree::Pruned(subtree_hash(left).to_vec())), Box::new(subtree_witness(right, key)))
+            }
+        }
+    }
+}
+
+// Hash-style reference stored on audit records: "0x" followed by the data, cut to 64 characters
+pub fn blockchain_hash(data: &str) -> String {
+    format!("0x{}", data).chars().take(64).collect()
+}
+
+// Guard for controller-only endpoints; action completes "Only controllers can ..."
+pub fn ensure_controller(action: &str) -> Result<(), String> {
+    if ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err(format!("Only controllers can {}", action))
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Default, Serialize)]
+pub struct IndiaHubRegistration {
+    pub policy_id: String,
+    pub registration_id: String,
+    pub hub_verification_status: bool,
+    pub compliance_score: f64,
+    pub regional_impact_score: f64,
+    pub timestamp: u64,
+    // Filled in by india_hub; registrations smart_policy keeps locally carry only the fields above
+    pub aadhaar_integration: Option<AadhaarVerification>,
+    pub gst_verification: Option<GSTVerification>,
+    pub pan_card_validation: Option<PANValidation>,
+    pub regional_compliance: Option<Vec<RegionalCompliance>>,
+    pub digital_locker_integration: Option<DigitalLockerEntry>,
+    pub biometric_verification: Option<BiometricVerification>,
+    pub e_kyc_status: Option<EKYCStatus>,
+    pub compliance_audit: Option<ComplianceAudit>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct AadhaarVerification {
+    pub aadhaar_number: String,
+    pub verification_status: bool,
+    pub biometric_match: bool,
+    pub otp_verified: bool,
+    pub verification_timestamp: u64,
+    pub verification_score: f64,
+    pub demographic_data: DemographicData,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct GSTVerification {
+    pub gst_number: String,
+    pub business_name: String,
+    pub registration_status: String,
+    pub compliance_status: String,
+    pub last_filing_date: u64,
+    pub verification_score: f64,
+    pub tax_compliance: TaxCompliance,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct PANValidation {
+    pub pan_number: String,
+    pub holder_name: String,
+    pub validation_status: bool,
+    pub verification_timestamp: u64,
+    pub verification_score: f64,
+    pub kyc_status: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct RegionalCompliance {
+    pub state: String,
+    pub district: String,
+    pub compliance_rules: Vec<String>,
+    pub compliance_status: bool,
+    pub compliance_score: f64,
+    pub regional_authority: String,
+    pub approval_date: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct DigitalLockerEntry {
+    pub locker_id: String,
+    pub document_type: String,
+    pub document_hash: String,
+    pub upload_timestamp: u64,
+    pub verification_status: bool,
+    pub access_permissions: Vec<String>,
+    pub document_metadata: DocumentMetadata,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct BiometricVerification {
+    pub biometric_type: String,
+    pub verification_status: bool,
+    pub match_score: f64,
+    pub verification_timestamp: u64,
+    pub device_id: String,
+    pub location: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct EKYCStatus {
+    pub kyc_status: String,
+    pub verification_level: String,
+    pub last_updated: u64,
+    pub verification_score: f64,
+    pub compliance_requirements: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct ComplianceAudit {
+    pub audit_id: String,
+    pub audit_date: u64,
+    pub compliance_score: f64,
+    pub audit_findings: Vec<AuditFinding>,
+    pub recommendations: Vec<String>,
+    pub next_audit_date: u64,
+    pub auditor: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct DemographicData {
+    pub name: String,
+    pub date_of_birth: String,
+    pub gender: String,
+    pub address: String,
+    pub photo_hash: String,
+    pub verification_status: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct TaxCompliance {
+    pub filing_frequency: String,
+    pub last_filing_period: String,
+    pub tax_liability: f64,
+    pub compliance_score: f64,
+    pub pending_returns: i32,
+    pub penalty_amount: f64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct DocumentMetadata {
+    pub file_name: String,
+    pub file_size: u64,
+    pub mime_type: String,
+    pub upload_source: String,
+    pub verification_hash: String,
+    pub expiry_date: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct AuditFinding {
+    pub finding_id: String,
+    pub severity: String,
+    pub description: String,
+    pub recommendation: String,
+    pub status: String,
+    pub due_date: u64,
+}
+
+// Status of a transaction in fund_tracker (Pending through Cancelled) or blockchain_verifier
+// (Pending, Confirmed, Failed and the verification states); each sets only its own
+#[derive(CandidType, Deserialize, Clone, PartialEq, Serialize, Debug)]
+pub enum TransactionStatus {
+    Pending,
+    Processing,
+    Completed,
+    Failed,
+    Cancelled,
+    Confirmed,
+    CrossChainConfirmed,
+    QuantumSecured,
+    Layer2Optimized,
+    ShardingVerified,
+}
+
+// blockchain_verifier logs against a transaction_id, india_hub against a policy_id
+#[derive(CandidType, Deserialize, Clone, Serialize)]
+pub struct VerificationLog {
+    pub log_id: String,
+    pub transaction_id: Option<String>,
+    pub policy_id: Option<String>,
+    pub verification_type: String,
+    pub status: bool,
+    pub timestamp: u64,
+    pub details: String,
+    pub blockchain_hash: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blockchain_hash() {
+        assert_eq!(blockchain_hash("policy_1approve"), "0xpolicy_1approve");
+
+        let long = "a".repeat(100);
+        let hash = blockchain_hash(&long);
+        assert_eq!(hash.len(), 64);
+        assert!(hash.starts_with("0xaaa"));
+
+        // Truncation counts characters, so multi-byte input is never split
+        assert_eq!(blockchain_hash(&"₹".repeat(70)).chars().count(), 64);
+    }
+
+    #[test]
+    fn test_health_status() {
+        let status = health_status(true, &[("policies", 2), ("events", 0)], Some(100), 250);
+        assert!(status.initialized);
+        assert_eq!(
+            status.record_counts,
+            vec![("policies".to_string(), 2), ("events".to_string(), 0)]
+        );
+        assert_eq!(status.uptime_ns, 150);
+
+        assert_eq!(health_status(false, &[], None, 250).uptime_ns, 0);
+        assert_eq!(health_status(true, &[], Some(300), 250).uptime_ns, 0);
+    }
+
+    #[test]
+    fn test_certified_witness() {
+        let leaves: BTreeMap<String, [u8; 32]> = (0..5u8)
+            .map(|i| (format!("key{}", i), [i; 32]))
+            .collect();
+        let root = certified_root(b"label", &leaves);
+
+        for key in leaves.keys() {
+            assert_eq!(reconstruct(&certified_witness(b"label", &leaves, key).unwrap()), root);
+        }
+        assert!(certified_witness(b"label", &leaves, "missing").is_none());
+        assert_ne!(certified_root(b"other", &leaves), root);
+        assert_eq!(
+            reconstruct(&HashTree::Labeled(b"label".to_vec(), Box::new(HashTree::Empty))),
+            certified_root(b"label", &BTreeMap::new())
+        );
+    }
+
+    #[test]
+    fn test_stats_from_pages() {
+        let stats = stats_from_pages(3_000_000_000_000, 2, 17);
+        assert_eq!(stats.cycles_balance, 3_000_000_000_000);
+        assert_eq!(stats.stable_memory_bytes, 131_072);
+        assert_eq!(stats.heap_size, 17 * 65_536);
+
+        assert_eq!(stats_from_pages(0, 0, 0).stable_memory_bytes, 0);
+        assert_eq!(stats_from_pages(0, u64::MAX, 0).stable_memory_bytes, u64::MAX);
+    }
+
+    #[test]
+    fn test_shared_types_decode_earlier_layouts() {
+        // smart_policy kept only the base registration fields
+        #[derive(CandidType)]
+        struct PolicyRegistration {
+            policy_id: String,
+            registration_id: String,
+            hub_verification_status: bool,
+            compliance_score: f64,
+            regional_impact_score: f64,
+            timestamp: u64,
+        }
+        // india_hub's compliance fields were not optional
+        #[derive(CandidType)]
+        struct HubRegistration {
+            policy_id: String,
+            registration_id: String,
+            hub_verification_status: bool,
+            compliance_score: f64,
+            regional_impact_score: f64,
+            timestamp: u64,
+            regional_compliance: Vec<RegionalCompliance>,
+            compliance_audit: ComplianceAudit,
+        }
+        #[derive(CandidType)]
+        struct TransactionLog {
+            log_id: String,
+            transaction_id: String,
+            verification_type: String,
+            status: bool,
+            timestamp: u64,
+            details: String,
+            blockchain_hash: Option<String>,
+        }
+        #[derive(CandidType)]
+        enum FundStatus {
+            Cancelled,
+        }
+
+        let bytes = candid::encode_one(PolicyRegistration {
+            policy_id: "policy_1".to_string(),
+            registration_id: "INDIA_HUB_1".to_string(),
+            hub_verification_status: true,
+            compliance_score: 0.95,
+            regional_impact_score: 0.88,
+            timestamp: 7,
+        })
+        .unwrap();
+        let registration: IndiaHubRegistration = candid::decode_one(&bytes).unwrap();
+        assert_eq!(registration.registration_id, "INDIA_HUB_1");
+        assert!(registration.regional_compliance.is_none() && registration.compliance_audit.is_none());
+
+        let audit = ComplianceAudit {
+            audit_id: "AUDIT_1".to_string(),
+            audit_date: 7,
+            compliance_score: 0.9,
+            audit_findings: Vec::new(),
+            recommendations: Vec::new(),
+            next_audit_date: 8,
+            auditor: "Auditor".to_string(),
+        };
+        let bytes = candid::encode_one(HubRegistration {
+            policy_id: "policy_1".to_string(),
+            registration_id: "INDIA_HUB_1".to_string(),
+            hub_verification_status: true,
+            compliance_score: 0.95,
+            regional_impact_score: 0.88,
+            timestamp: 7,
+            regional_compliance: Vec::new(),
+            compliance_audit: audit,
+        })
+        .unwrap();
+        let registration: IndiaHubRegistration = candid::decode_one(&bytes).unwrap();
+        assert_eq!(registration.regional_compliance.map(|rules| rules.len()), Some(0));
+        assert_eq!(registration.compliance_audit.map(|audit| audit.audit_id), Some("AUDIT_1".to_string()));
+
+        let bytes = candid::encode_one(TransactionLog {
+            log_id: "LOG_1".to_string(),
+            transaction_id: "tx_1".to_string(),
+            verification_type: "Cross-Chain Verification".to_string(),
+            status: true,
+            timestamp: 7,
+            details: String::new(),
+            blockchain_hash: None,
+        })
+        .unwrap();
+        let log: VerificationLog = candid::decode_one(&bytes).unwrap();
+        assert_eq!((log.transaction_id.as_deref(), log.policy_id), (Some("tx_1"), None));
+
+        let status: TransactionStatus = candid::decode_one(&candid::encode_one(FundStatus::Cancelled).unwrap()).unwrap();
+        assert_eq!(status, TransactionStatus::Cancelled);
+    }
+}
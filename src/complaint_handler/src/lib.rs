@@ -1,12 +1,14 @@
 use candid::{CandidType, Deserialize, Principal};
-use ic_cdk::{api::call::call, export::candid, init, post_upgrade, pre_upgrade, query, update};
+use ic_cdk::{api::call::call, init, post_upgrade, pre_upgrade, query, update};
 use ic_cdk_timers::set_timer_interval;
+use common::{CanisterStats, HealthStatus};
+use ic_llm::ChatMessage;
 use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 use uuid::Uuid;
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct Complaint {
     pub id: String,
     pub title: String,
@@ -24,9 +26,35 @@ pub struct Complaint {
     pub ai_analysis: Option<AIAnalysis>,
     pub audit_score: f64,
     pub resolution_time: Option<u64>,
+    // Latest attempt to pause the linked policy; None until trigger_policy_pause is called
+    pub policy_pause: Option<PolicyPause>,
+    // Officer accountable for the complaint
+    pub assigned_to: Option<String>,
+    // What was done, recorded by resolve_complaint
+    pub resolution_outcome: Option<String>,
+    pub resolution_notes: Option<String>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct PolicyPause {
+    pub policy_id: String,
+    pub paused: bool,
+    // smart_policy's error, or the call failure, when the pause did not go through
+    pub error: Option<String>,
+    pub attempted_at: u64,
+}
+
+// One message in a complaint's citizen–officer thread
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct ComplaintUpdate {
+    pub author: String,
+    pub text: String,
+    pub timestamp: u64,
+    // Posted by a registered officer
+    pub is_official: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub enum ComplaintPriority {
     Low,
     Medium,
@@ -34,7 +62,7 @@ pub enum ComplaintPriority {
     Critical,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, PartialEq, SerdeSerialize)]
 pub enum ComplaintStatus {
     Submitted,
     UnderReview,
@@ -44,7 +72,7 @@ pub enum ComplaintStatus {
     Escalated,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct AIAnalysis {
     pub sentiment: String,
     pub category_prediction: String,
@@ -54,33 +82,81 @@ pub struct AIAnalysis {
     pub keywords: Vec<String>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct ComplaintMetrics {
     pub total_complaints: u32,
     pub resolved_complaints: u32,
     pub average_resolution_time: f64,
+    // Sum behind average_resolution_time; None on metrics saved before it was tracked
+    pub total_resolution_time_ns: Option<u64>,
     pub category_distribution: BTreeMap<String, u32>,
     pub district_distribution: BTreeMap<String, u32>,
 }
 
+// Shape the LLM is asked to reply with; missing optional fields fall back to defaults
+#[derive(SerdeDeserialize)]
+struct LlmAnalysis {
+    sentiment: String,
+    category: String,
+    priority_score: f64,
+    keywords: Vec<String>,
+    suggested_action: Option<String>,
+    confidence: Option<f64>,
+}
+
+const DEFAULT_SUGGESTED_ACTION: &str = "Investigate and respond within 48 hours";
+
+const NANOS_PER_HOUR: u64 = 3600 * 1_000_000_000;
+
+// Upper bound on page size so responses stay well under the message limit
+const MAX_PAGE_SIZE: u32 = 100;
+
+// A citizen's complaint in the same district within this window whose words overlap at
+// least this much is treated as a possible duplicate
+const DUPLICATE_WINDOW_NS: u64 = 48 * NANOS_PER_HOUR;
+const DUPLICATE_SIMILARITY: f64 = 0.6;
+
+const MAX_MEDIA_LINKS: usize = 10;
+const MAX_MEDIA_LINK_LENGTH: usize = 2_048;
+const MEDIA_LINK_SCHEMES: [&str; 3] = ["http://", "https://", "ipfs://"];
+
+const MAX_UPDATE_LENGTH: usize = 2_000;
+
 // Stable storage for complaints
 static mut COMPLAINTS: Option<BTreeMap<String, Complaint>> = None;
 static mut COMPLAINT_METRICS: Option<ComplaintMetrics> = None;
+// Canister exposing the LLM `chat` endpoint; keyword analysis is used while unset
+static mut BACKEND_CANISTER_ID: Option<Principal> = None;
+static mut SMART_POLICY_CANISTER_ID: Option<Principal> = None;
+// Principals whose complaint updates are marked official
+static mut OFFICERS: Option<BTreeSet<Principal>> = None;
+// complaint_id -> updates in the order they were posted
+static mut COMPLAINT_UPDATES: Option<BTreeMap<String, Vec<ComplaintUpdate>>> = None;
+// Set at install; canisters installed before it existed start counting at their next upgrade
+static mut INIT_TIME_NS: Option<u64> = None;
 
 #[init]
 fn init() {
     unsafe {
         COMPLAINTS = Some(BTreeMap::new());
+        OFFICERS = Some(BTreeSet::new());
+        COMPLAINT_UPDATES = Some(BTreeMap::new());
+        INIT_TIME_NS = Some(ic_cdk::api::time());
         COMPLAINT_METRICS = Some(ComplaintMetrics {
             total_complaints: 0,
             resolved_complaints: 0,
             average_resolution_time: 0.0,
+            total_resolution_time_ns: Some(0),
             category_distribution: BTreeMap::new(),
             district_distribution: BTreeMap::new(),
         });
     }
     
-    // Set up periodic complaint analysis
+    start_analysis_timer();
+}
+
+// Periodic complaint analysis and SLA escalation; timers do not survive an upgrade
+fn start_analysis_timer() {
     set_timer_interval(Duration::from_secs(1800), || {
         ic_cdk::spawn(analyze_pending_complaints());
     });
@@ -90,19 +166,55 @@ fn init() {
 fn pre_upgrade() {
     let complaints = unsafe { COMPLAINTS.take().unwrap() };
     let metrics = unsafe { COMPLAINT_METRICS.take().unwrap() };
+    let backend_canister_id = unsafe { BACKEND_CANISTER_ID };
+    let smart_policy_canister_id = unsafe { SMART_POLICY_CANISTER_ID };
+    let officers = unsafe { OFFICERS.take() };
+    let updates = unsafe { COMPLAINT_UPDATES.take() };
+    let init_time_ns = unsafe { INIT_TIME_NS };
     
-    ic_cdk::storage::stable_save((complaints, metrics)).unwrap();
+    ic_cdk::storage::stable_save((
+        complaints,
+        metrics,
+        backend_canister_id,
+        smart_policy_canister_id,
+        officers,
+        updates,
+        init_time_ns,
+    )).unwrap();
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (complaints, metrics): (BTreeMap<String, Complaint>, ComplaintMetrics) = 
-        ic_cdk::storage::stable_restore().unwrap();
+    let (complaints, metrics, backend_canister_id, smart_policy_canister_id, officers, updates, init_time_ns): (
+        BTreeMap<String, Complaint>,
+        ComplaintMetrics,
+        Option<Principal>,
+        Option<Principal>,
+        Option<BTreeSet<Principal>>,
+        Option<BTreeMap<String, Vec<ComplaintUpdate>>>,
+        Option<u64>,
+    ) = ic_cdk::storage::stable_restore().unwrap();
+    
+    // Older metrics only kept a running average that could drift; rebuild it from the complaints
+    let mut metrics = metrics;
+    if metrics.total_resolution_time_ns.is_none() {
+        let (resolved, total) = resolution_totals(complaints.values());
+        metrics.resolved_complaints = resolved;
+        metrics.total_resolution_time_ns = Some(total);
+        metrics.average_resolution_time = average_resolution_time(total, resolved);
+    }
     
     unsafe {
         COMPLAINTS = Some(complaints);
         COMPLAINT_METRICS = Some(metrics);
+        BACKEND_CANISTER_ID = backend_canister_id;
+        SMART_POLICY_CANISTER_ID = smart_policy_canister_id;
+        OFFICERS = Some(officers.unwrap_or_default());
+        COMPLAINT_UPDATES = Some(updates.unwrap_or_default());
+        INIT_TIME_NS = Some(init_time_ns.unwrap_or_else(ic_cdk::api::time));
     }
+    
+    start_analysis_timer();
 }
 
 #[update]
@@ -116,10 +228,19 @@ async fn submit_complaint(
     location: Option<String>,
     media_links: Vec<String>,
     citizen_id: String,
+    // Omitted by callers from before the duplicate check; None means false
+    force: Option<bool>,
 ) -> Result<String, String> {
+    let media_links = normalize_media_links(media_links)?;
     let complaint_id = Uuid::new_v4().to_string();
     let now = ic_cdk::api::time();
     
+    unsafe {
+        if let Some(ref complaints) = COMPLAINTS {
+            check_duplicate(complaints.values(), &citizen_id, &district, &title, &description, force.unwrap_or(false), now)?;
+        }
+    }
+    
     let complaint = Complaint {
         id: complaint_id.clone(),
         title,
@@ -137,6 +258,10 @@ async fn submit_complaint(
         ai_analysis: None,
         audit_score: 0.0,
         resolution_time: None,
+        policy_pause: None,
+        assigned_to: None,
+        resolution_outcome: None,
+        resolution_notes: None,
     };
     
     // Store complaint
@@ -164,27 +289,18 @@ async fn update_complaint_status(
     complaint_id: String,
     status: ComplaintStatus,
 ) -> Result<(), String> {
+    // Resolutions must say what was done
+    if matches!(status, ComplaintStatus::Resolved) {
+        return Err("Use resolve_complaint to resolve a complaint".to_string());
+    }
+    
     let now = ic_cdk::api::time();
     
     unsafe {
         if let Some(ref mut complaints) = COMPLAINTS {
             if let Some(complaint) = complaints.get_mut(&complaint_id) {
-                complaint.status = status.clone();
+                complaint.status = status;
                 complaint.updated_at = now;
-                
-                if status == ComplaintStatus::Resolved {
-                    complaint.resolution_time = Some(now - complaint.created_at);
-                    
-                    // Update metrics
-                    if let Some(ref mut metrics) = COMPLAINT_METRICS {
-                        metrics.resolved_complaints += 1;
-                        // Update average resolution time
-                        let total_time = metrics.average_resolution_time * (metrics.resolved_complaints - 1) as f64;
-                        let new_time = (now - complaint.created_at) as f64;
-                        metrics.average_resolution_time = (total_time + new_time) / metrics.resolved_complaints as f64;
-                    }
-                }
-                
                 return Ok(());
             }
         }
@@ -193,6 +309,69 @@ async fn update_complaint_status(
     Err("Complaint not found".to_string())
 }
 
+#[update]
+fn resolve_complaint(complaint_id: String, outcome: String, notes: String) -> Result<(), String> {
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        let complaint = COMPLAINTS.as_mut()
+            .and_then(|complaints| complaints.get_mut(&complaint_id))
+            .ok_or("Complaint not found".to_string())?;
+        let previous = complaint.resolution_time;
+        
+        if let (Some(resolution_time), Some(ref mut metrics)) = (resolve(complaint, outcome, notes, now)?, &mut COMPLAINT_METRICS) {
+            record_resolution(metrics, previous, resolution_time);
+        }
+    }
+    Ok(())
+}
+
+// Returns the time taken to resolve, or None when the complaint was already resolved and
+// nothing changed
+fn resolve(complaint: &mut Complaint, outcome: String, notes: String, now: u64) -> Result<Option<u64>, String> {
+    if outcome.trim().is_empty() {
+        return Err("Resolution outcome must not be empty".to_string());
+    }
+    if matches!(complaint.status, ComplaintStatus::Resolved) {
+        return Ok(None);
+    }
+    
+    let resolution_time = now.saturating_sub(complaint.created_at);
+    complaint.status = ComplaintStatus::Resolved;
+    complaint.updated_at = now;
+    complaint.resolution_time = Some(resolution_time);
+    complaint.resolution_outcome = Some(outcome);
+    complaint.resolution_notes = Some(notes);
+    Ok(Some(resolution_time))
+}
+
+// A complaint reopened and resolved again replaces its earlier time instead of counting twice
+fn record_resolution(metrics: &mut ComplaintMetrics, previous: Option<u64>, resolution_time: u64) {
+    let mut total = metrics.total_resolution_time_ns.unwrap_or(0);
+    match previous {
+        Some(previous) => total = total.saturating_sub(previous),
+        None => metrics.resolved_complaints += 1,
+    }
+    total = total.saturating_add(resolution_time);
+    metrics.total_resolution_time_ns = Some(total);
+    metrics.average_resolution_time = average_resolution_time(total, metrics.resolved_complaints);
+}
+
+fn average_resolution_time(total_ns: u64, resolved: u32) -> f64 {
+    if resolved == 0 {
+        0.0
+    } else {
+        total_ns as f64 / resolved as f64
+    }
+}
+
+// Count and total over every complaint that has been resolved at least once
+fn resolution_totals<'a>(complaints: impl Iterator<Item = &'a Complaint>) -> (u32, u64) {
+    complaints
+        .filter_map(|complaint| complaint.resolution_time)
+        .fold((0, 0), |(count, total), time| (count + 1, total.saturating_add(time)))
+}
+
 #[query]
 fn get_complaint(complaint_id: String) -> Result<Complaint, String> {
     unsafe {
@@ -243,6 +422,179 @@ fn get_complaints_by_district(district: String) -> Vec<Complaint> {
     }
 }
 
+#[query]
+fn get_complaints_by_status(status: ComplaintStatus, offset: u64, limit: u32) -> Vec<Complaint> {
+    unsafe {
+        if let Some(ref complaints) = COMPLAINTS {
+            complaint_page(complaints.values(), |complaint| {
+                std::mem::discriminant(&complaint.status) == std::mem::discriminant(&status)
+            }, offset, limit)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[query]
+fn get_complaints_by_priority(priority: ComplaintPriority, offset: u64, limit: u32) -> Vec<Complaint> {
+    unsafe {
+        if let Some(ref complaints) = COMPLAINTS {
+            complaint_page(complaints.values(), |complaint| {
+                std::mem::discriminant(&complaint.priority) == std::mem::discriminant(&priority)
+            }, offset, limit)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn validate_media_link(link: &str) -> bool {
+    if link.len() > MAX_MEDIA_LINK_LENGTH || link.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+    let lowercase = link.to_ascii_lowercase();
+    MEDIA_LINK_SCHEMES.iter().any(|scheme| lowercase.starts_with(scheme) && link.len() > scheme.len())
+}
+
+// Trims surrounding whitespace; one bad link rejects the whole submission
+fn normalize_media_links(links: Vec<String>) -> Result<Vec<String>, String> {
+    if links.len() > MAX_MEDIA_LINKS {
+        return Err(format!("At most {} media links are allowed", MAX_MEDIA_LINKS));
+    }
+    links.into_iter()
+        .map(|link| {
+            let link = link.trim().to_string();
+            if validate_media_link(&link) {
+                Ok(link)
+            } else {
+                Err(format!("Invalid media link: {}", link.chars().take(100).collect::<String>()))
+            }
+        })
+        .collect()
+}
+
+fn word_set(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn jaccard_similarity(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+// force files the complaint even when it looks like a repeat
+fn check_duplicate<'a>(
+    complaints: impl Iterator<Item = &'a Complaint>,
+    citizen_id: &str,
+    district: &str,
+    title: &str,
+    description: &str,
+    force: bool,
+    now: u64,
+) -> Result<(), String> {
+    if force {
+        return Ok(());
+    }
+    match find_duplicate(complaints, citizen_id, district, title, description, now) {
+        Some(existing_id) => Err(format!("possible duplicate of {}", existing_id)),
+        None => Ok(()),
+    }
+}
+
+// The most similar recent complaint by the same citizen in the same district, if any
+// crosses DUPLICATE_SIMILARITY
+fn find_duplicate<'a>(
+    complaints: impl Iterator<Item = &'a Complaint>,
+    citizen_id: &str,
+    district: &str,
+    title: &str,
+    description: &str,
+    now: u64,
+) -> Option<String> {
+    let words = word_set(&format!("{} {}", title, description));
+    complaints
+        .filter(|complaint| {
+            complaint.citizen_id == citizen_id
+                && complaint.district == district
+                && now.saturating_sub(complaint.created_at) <= DUPLICATE_WINDOW_NS
+        })
+        .map(|complaint| {
+            let existing = word_set(&format!("{} {}", complaint.title, complaint.description));
+            (jaccard_similarity(&words, &existing), complaint)
+        })
+        .filter(|(similarity, _)| *similarity >= DUPLICATE_SIMILARITY)
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, complaint)| complaint.id.clone())
+}
+
+// Newest first, ties broken by id so pages stay stable
+fn complaint_page<'a>(
+    complaints: impl Iterator<Item = &'a Complaint>,
+    predicate: impl Fn(&Complaint) -> bool,
+    offset: u64,
+    limit: u32,
+) -> Vec<Complaint> {
+    let mut matching: Vec<&Complaint> = complaints.filter(|complaint| predicate(complaint)).collect();
+    matching.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+    matching.into_iter()
+        .skip(offset.min(usize::MAX as u64) as usize)
+        .take(limit.min(MAX_PAGE_SIZE) as usize)
+        .cloned()
+        .collect()
+}
+
+#[update]
+fn assign_complaint(complaint_id: String, officer_id: String) -> Result<(), String> {
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        let complaint = COMPLAINTS.as_mut()
+            .and_then(|complaints| complaints.get_mut(&complaint_id))
+            .ok_or("Complaint not found".to_string())?;
+        assign(complaint, officer_id, now)
+    }
+}
+
+#[query]
+fn get_complaints_assigned_to(officer_id: String) -> Vec<Complaint> {
+    unsafe {
+        if let Some(ref complaints) = COMPLAINTS {
+            assigned_to(complaints.values(), &officer_id)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn assign(complaint: &mut Complaint, officer_id: String, now: u64) -> Result<(), String> {
+    if officer_id.trim().is_empty() {
+        return Err("Officer id must not be empty".to_string());
+    }
+    if matches!(complaint.status, ComplaintStatus::Resolved | ComplaintStatus::Dismissed) {
+        return Err("Closed complaints cannot be assigned".to_string());
+    }
+    
+    complaint.assigned_to = Some(officer_id);
+    if matches!(complaint.status, ComplaintStatus::Submitted) {
+        complaint.status = ComplaintStatus::UnderReview;
+    }
+    complaint.updated_at = now;
+    Ok(())
+}
+
+fn assigned_to<'a>(complaints: impl Iterator<Item = &'a Complaint>, officer_id: &str) -> Vec<Complaint> {
+    complaints
+        .filter(|complaint| complaint.assigned_to.as_deref() == Some(officer_id))
+        .cloned()
+        .collect()
+}
+
 #[query]
 fn get_complaint_metrics() -> ComplaintMetrics {
     unsafe {
@@ -250,6 +602,7 @@ fn get_complaint_metrics() -> ComplaintMetrics {
             total_complaints: 0,
             resolved_complaints: 0,
             average_resolution_time: 0.0,
+            total_resolution_time_ns: Some(0),
             category_distribution: BTreeMap::new(),
             district_distribution: BTreeMap::new(),
         })
@@ -289,8 +642,24 @@ async fn add_audit_score(complaint_id: String, score: f64) -> Result<(), String>
     Err("Complaint not found".to_string())
 }
 
+#[update]
+fn set_backend_canister_id(canister_id: Principal) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can set the backend canister id".to_string());
+    }
+    
+    unsafe {
+        BACKEND_CANISTER_ID = Some(canister_id);
+    }
+    Ok(())
+}
+
+#[query]
+fn get_backend_canister_id() -> Option<Principal> {
+    unsafe { BACKEND_CANISTER_ID }
+}
+
 async fn analyze_complaint_with_ai(complaint_id: String, description: String) {
-    // Simulate AI analysis using LLM canister
     let analysis_result = analyze_text_with_llm(&description).await;
     
     unsafe {
@@ -304,7 +673,72 @@ async fn analyze_complaint_with_ai(complaint_id: String, description: String) {
 }
 
 async fn analyze_text_with_llm(text: &str) -> AIAnalysis {
-    // Mock AI analysis - in real implementation, this would call the LLM canister
+    let backend = unsafe { BACKEND_CANISTER_ID };
+    let response = match backend {
+        Some(canister_id) => {
+            let messages = vec![
+                ChatMessage::System { content: analysis_system_prompt() },
+                ChatMessage::User { content: text.to_string() },
+            ];
+            call::<(Vec<ChatMessage>,), (String,)>(canister_id, "chat", (messages,))
+                .await
+                .map(|(reply,)| reply)
+                .map_err(|(code, message)| format!("Backend chat call failed: {:?} {}", code, message))
+        }
+        None => Err("Backend canister id not configured".to_string()),
+    };
+    
+    analysis_from_response(response, text)
+}
+
+fn analysis_system_prompt() -> String {
+    "You triage citizen complaints about government schemes. \
+     Read the complaint and reply with a single JSON object and nothing else, using exactly these keys: \
+     \"sentiment\" (\"positive\", \"neutral\" or \"negative\"), \
+     \"category\" (\"infrastructure\", \"fund_misuse\", \"corruption\", \"service_delay\" or \"other\"), \
+     \"priority_score\" (0.0-1.0, where 1.0 needs action today), \
+     \"keywords\" (up to 5 short lowercase strings), \
+     \"suggested_action\" (one sentence) and \
+     \"confidence\" (0.0-1.0)."
+        .to_string()
+}
+
+fn analysis_from_response(response: Result<String, String>, text: &str) -> AIAnalysis {
+    match response.and_then(|reply| parse_analysis(&reply)) {
+        Ok(analysis) => analysis,
+        Err(error) => {
+            ic_cdk::println!("{}, using keyword analysis", error);
+            keyword_analysis(text)
+        }
+    }
+}
+
+// Models often wrap the object in prose or code fences, so only the outermost braces are parsed
+fn parse_analysis(reply: &str) -> Result<AIAnalysis, String> {
+    let json = match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => return Err("LLM reply contained no JSON object".to_string()),
+    };
+    let parsed: LlmAnalysis = serde_json::from_str(json)
+        .map_err(|e| format!("LLM reply was not a valid analysis: {}", e))?;
+    
+    Ok(AIAnalysis {
+        sentiment: parsed.sentiment.trim().to_lowercase(),
+        category_prediction: parsed.category.trim().to_lowercase(),
+        priority_score: parsed.priority_score.clamp(0.0, 1.0),
+        suggested_action: parsed.suggested_action
+            .filter(|action| !action.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_SUGGESTED_ACTION.to_string()),
+        confidence: parsed.confidence.unwrap_or(0.85).clamp(0.0, 1.0),
+        keywords: parsed.keywords.into_iter()
+            .map(|keyword| keyword.trim().to_lowercase())
+            .filter(|keyword| !keyword.is_empty())
+            .take(5)
+            .collect(),
+    })
+}
+
+fn keyword_analysis(text: &str) -> AIAnalysis {
     let sentiment = if text.contains("corruption") || text.contains("fraud") {
         "negative".to_string()
     } else if text.contains("delay") || text.contains("slow") {
@@ -333,13 +767,19 @@ async fn analyze_text_with_llm(text: &str) -> AIAnalysis {
         sentiment,
         category_prediction,
         priority_score,
-        suggested_action: "Investigate and respond within 48 hours".to_string(),
+        suggested_action: DEFAULT_SUGGESTED_ACTION.to_string(),
         confidence: 0.85,
         keywords: vec!["government".to_string(), "service".to_string(), "issue".to_string()],
     }
 }
 
 async fn analyze_pending_complaints() {
+    unsafe {
+        if let Some(ref mut complaints) = COMPLAINTS {
+            escalate_overdue(complaints, ic_cdk::api::time());
+        }
+    }
+    
     // Analyze complaints that haven't been processed yet
     unsafe {
         if let Some(ref complaints) = COMPLAINTS {
@@ -354,11 +794,220 @@ async fn analyze_pending_complaints() {
     }
 }
 
+#[query]
+fn get_overdue_complaints() -> Vec<Complaint> {
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        if let Some(ref complaints) = COMPLAINTS {
+            complaints.values()
+                .filter(|complaint| is_overdue(complaint, now))
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+// Time allowed from submission to resolution
+fn sla_duration(priority: &ComplaintPriority) -> u64 {
+    match priority {
+        ComplaintPriority::Critical => 24 * NANOS_PER_HOUR,
+        ComplaintPriority::High => 72 * NANOS_PER_HOUR,
+        ComplaintPriority::Medium => 7 * 24 * NANOS_PER_HOUR,
+        ComplaintPriority::Low => 14 * 24 * NANOS_PER_HOUR,
+    }
+}
+
+fn is_overdue(complaint: &Complaint, now: u64) -> bool {
+    !matches!(complaint.status, ComplaintStatus::Resolved | ComplaintStatus::Dismissed)
+        && now.saturating_sub(complaint.created_at) > sla_duration(&complaint.priority)
+}
+
+// Escalates overdue complaints that are not escalated yet, so each one is bumped only once
+fn escalate_overdue(complaints: &mut BTreeMap<String, Complaint>, now: u64) -> Vec<String> {
+    let mut escalated = Vec::new();
+    for complaint in complaints.values_mut() {
+        if is_overdue(complaint, now) && !matches!(complaint.status, ComplaintStatus::Escalated) {
+            complaint.status = ComplaintStatus::Escalated;
+            complaint.updated_at = now;
+            complaint.audit_score += 0.2;
+            escalated.push(complaint.id.clone());
+        }
+    }
+    escalated
+}
+
+// Pauses the complaint's linked policy in smart_policy; the outcome is kept on the complaint
+// either way, and a successful pause escalates it
 #[update]
 async fn trigger_policy_pause(complaint_id: String) -> Result<(), String> {
-    // This would integrate with the smart_policy canister to pause policies
-    // For now, we'll just mark the complaint as escalated
-    escalate_complaint(complaint_id).await
+    let canister_id = unsafe { SMART_POLICY_CANISTER_ID }
+        .ok_or("smart_policy canister id not configured".to_string())?;
+    let policy_id = unsafe {
+        let complaint = COMPLAINTS.as_ref()
+            .and_then(|complaints| complaints.get(&complaint_id))
+            .ok_or("Complaint not found".to_string())?;
+        pause_target(complaint)?
+    };
+    
+    let outcome = call::<(String,), (Result<(), String>,)>(canister_id, "pause_policy", (policy_id.clone(),))
+        .await
+        .map_err(|(code, message)| format!("pause_policy call failed: {:?} {}", code, message))
+        .and_then(|(result,)| result);
+    
+    unsafe {
+        if let Some(complaint) = COMPLAINTS.as_mut().and_then(|complaints| complaints.get_mut(&complaint_id)) {
+            record_pause_outcome(complaint, policy_id, &outcome, ic_cdk::api::time());
+        }
+    }
+    outcome
+}
+
+fn pause_target(complaint: &Complaint) -> Result<String, String> {
+    complaint.policy_id.clone()
+        .filter(|policy_id| !policy_id.is_empty())
+        .ok_or("Complaint is not linked to a policy".to_string())
+}
+
+fn record_pause_outcome(complaint: &mut Complaint, policy_id: String, outcome: &Result<(), String>, now: u64) {
+    complaint.policy_pause = Some(PolicyPause {
+        policy_id,
+        paused: outcome.is_ok(),
+        error: outcome.as_ref().err().cloned(),
+        attempted_at: now,
+    });
+    complaint.updated_at = now;
+    if outcome.is_ok() {
+        complaint.status = ComplaintStatus::Escalated;
+        complaint.audit_score += 0.2; // Same weight as a manual escalation
+    }
+}
+
+#[update]
+fn set_smart_policy_canister_id(canister_id: Principal) -> Result<(), String> {
+    common::ensure_controller("set the smart_policy canister id")?;
+    
+    unsafe {
+        SMART_POLICY_CANISTER_ID = Some(canister_id);
+    }
+    Ok(())
+}
+
+#[query]
+fn get_smart_policy_canister_id() -> Option<Principal> {
+    unsafe { SMART_POLICY_CANISTER_ID }
+}
+
+#[update]
+fn add_officer(officer: Principal) -> Result<(), String> {
+    common::ensure_controller("register officers")?;
+    
+    unsafe {
+        if let Some(ref mut officers) = OFFICERS {
+            officers.insert(officer);
+        }
+    }
+    Ok(())
+}
+
+#[update]
+fn remove_officer(officer: Principal) -> Result<(), String> {
+    common::ensure_controller("remove officers")?;
+    
+    unsafe {
+        if let Some(ref mut officers) = OFFICERS {
+            officers.remove(&officer);
+        }
+    }
+    Ok(())
+}
+
+#[query]
+fn get_officers() -> Vec<Principal> {
+    unsafe { OFFICERS.as_ref().map(|officers| officers.iter().cloned().collect()).unwrap_or_default() }
+}
+
+#[update]
+fn add_complaint_update(complaint_id: String, text: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        let is_official = OFFICERS.as_ref().is_some_and(|officers| officers.contains(&caller));
+        match (&mut COMPLAINTS, &mut COMPLAINT_UPDATES) {
+            (Some(ref mut complaints), Some(ref mut updates)) => {
+                let complaint = complaints.get_mut(&complaint_id).ok_or("Complaint not found".to_string())?;
+                let thread = updates.entry(complaint_id).or_default();
+                append_update(complaint, thread, caller.to_text(), text, is_official, now)
+            }
+            _ => Err("Complaints not initialized".to_string()),
+        }
+    }
+}
+
+#[query]
+fn get_complaint_updates(complaint_id: String) -> Result<Vec<ComplaintUpdate>, String> {
+    unsafe {
+        if !COMPLAINTS.as_ref().is_some_and(|complaints| complaints.contains_key(&complaint_id)) {
+            return Err("Complaint not found".to_string());
+        }
+        Ok(COMPLAINT_UPDATES.as_ref()
+            .and_then(|updates| updates.get(&complaint_id).cloned())
+            .unwrap_or_default())
+    }
+}
+
+fn append_update(
+    complaint: &mut Complaint,
+    thread: &mut Vec<ComplaintUpdate>,
+    author: String,
+    text: String,
+    is_official: bool,
+    now: u64,
+) -> Result<(), String> {
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err("Update cannot be empty".to_string());
+    }
+    if text.chars().count() > MAX_UPDATE_LENGTH {
+        return Err(format!("Update exceeds {} characters", MAX_UPDATE_LENGTH));
+    }
+    
+    thread.push(ComplaintUpdate {
+        author,
+        text,
+        timestamp: now,
+        is_official,
+    });
+    complaint.updated_at = now;
+    Ok(())
+}
+
+#[query]
+fn get_canister_stats() -> CanisterStats {
+    common::canister_stats()
+}
+
+#[query]
+fn health_check() -> HealthStatus {
+    health_status_at(ic_cdk::api::time())
+}
+
+fn health_status_at(now: u64) -> HealthStatus {
+    unsafe {
+        common::health_status(
+            COMPLAINTS.is_some() && COMPLAINT_METRICS.is_some(),
+            &[
+                ("complaints", COMPLAINTS.as_ref().map_or(0, BTreeMap::len)),
+                ("officers", OFFICERS.as_ref().map_or(0, BTreeSet::len)),
+                ("complaint_updates", COMPLAINT_UPDATES.as_ref().map_or(0, |updates| updates.values().map(Vec::len).sum())),
+            ],
+            INIT_TIME_NS,
+            now,
+        )
+    }
 }
 
 // Candid interface
@@ -374,4 +1023,351 @@ mod tests {
         let complaint_id = "test_complaint_123".to_string();
         assert!(complaint_id.contains("test"));
     }
-} 
\ No newline at end of file
+    
+    fn sample_complaint(policy_id: Option<&str>) -> Complaint {
+        Complaint {
+            id: "complaint_1".to_string(),
+            title: "Road left unfinished".to_string(),
+            description: "Funds released but no work done".to_string(),
+            category: "infrastructure".to_string(),
+            priority: ComplaintPriority::High,
+            status: ComplaintStatus::Submitted,
+            policy_id: policy_id.map(str::to_string),
+            district: "Pune".to_string(),
+            location: None,
+            media_links: Vec::new(),
+            citizen_id: "citizen_1".to_string(),
+            created_at: 1,
+            updated_at: 1,
+            ai_analysis: None,
+            audit_score: 0.0,
+            resolution_time: None,
+            policy_pause: None,
+            assigned_to: None,
+            resolution_outcome: None,
+            resolution_notes: None,
+        }
+    }
+    
+    #[test]
+    fn test_complaint_assignment() {
+        let mut complaint = sample_complaint(None);
+        assert!(assign(&mut complaint, " ".to_string(), 5).is_err());
+        
+        assign(&mut complaint, "officer_a".to_string(), 5).unwrap();
+        assert_eq!(complaint.assigned_to.as_deref(), Some("officer_a"));
+        assert!(matches!(complaint.status, ComplaintStatus::UnderReview));
+        assert_eq!(complaint.updated_at, 5);
+        
+        // Reassigning keeps a later status as it is
+        complaint.status = ComplaintStatus::Investigation;
+        assign(&mut complaint, "officer_b".to_string(), 6).unwrap();
+        assert!(matches!(complaint.status, ComplaintStatus::Investigation));
+        assert_eq!(complaint.assigned_to.as_deref(), Some("officer_b"));
+        
+        complaint.status = ComplaintStatus::Resolved;
+        assert_eq!(assign(&mut complaint, "officer_a".to_string(), 7), Err("Closed complaints cannot be assigned".to_string()));
+        assert_eq!(complaint.assigned_to.as_deref(), Some("officer_b"));
+    }
+    
+    #[test]
+    fn test_complaints_assigned_to_officer() {
+        let mut first = sample_complaint(None);
+        first.id = "c1".to_string();
+        let mut second = sample_complaint(Some("policy_a"));
+        second.id = "c2".to_string();
+        let unassigned = sample_complaint(None);
+        assign(&mut first, "officer_a".to_string(), 2).unwrap();
+        assign(&mut second, "officer_b".to_string(), 3).unwrap();
+        
+        let complaints = [first, second, unassigned];
+        let ids = |officer_id: &str| assigned_to(complaints.iter(), officer_id).into_iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(ids("officer_a"), vec!["c1"]);
+        assert_eq!(ids("officer_b"), vec!["c2"]);
+        assert!(ids("officer_c").is_empty());
+    }
+    
+    #[test]
+    fn test_complaint_pages_by_status_and_priority() {
+        let complaints: Vec<Complaint> = (0..6u64)
+            .map(|i| {
+                let mut complaint = sample_complaint(None);
+                complaint.id = format!("c{}", i);
+                complaint.created_at = 10 + i;
+                complaint.status = if i % 2 == 0 { ComplaintStatus::Submitted } else { ComplaintStatus::Escalated };
+                complaint.priority = if i < 4 { ComplaintPriority::High } else { ComplaintPriority::Critical };
+                complaint
+            })
+            .collect();
+        let ids = |page: Vec<Complaint>| page.into_iter().map(|complaint| complaint.id).collect::<Vec<_>>();
+        let submitted = |complaint: &Complaint| matches!(complaint.status, ComplaintStatus::Submitted);
+        let high = |complaint: &Complaint| matches!(complaint.priority, ComplaintPriority::High);
+        
+        assert_eq!(ids(complaint_page(complaints.iter(), submitted, 0, 10)), vec!["c4", "c2", "c0"]);
+        assert_eq!(ids(complaint_page(complaints.iter(), submitted, 1, 1)), vec!["c2"]);
+        assert!(complaint_page(complaints.iter(), submitted, 3, 10).is_empty());
+        assert_eq!(ids(complaint_page(complaints.iter(), high, 0, 2)), vec!["c3", "c2"]);
+        assert_eq!(ids(complaint_page(complaints.iter(), high, 2, 2)), vec!["c1", "c0"]);
+        
+        let many: Vec<Complaint> = (0..150u64)
+            .map(|i| {
+                let mut complaint = sample_complaint(None);
+                complaint.id = format!("m{:03}", i);
+                complaint
+            })
+            .collect();
+        let page = complaint_page(many.iter(), |_| true, 0, u32::MAX);
+        assert_eq!(page.len(), MAX_PAGE_SIZE as usize);
+        // Equal timestamps fall back to id order
+        assert_eq!(page[0].id, "m000");
+    }
+    
+    #[test]
+    fn test_resolution_records_outcome() {
+        let mut complaint = sample_complaint(None);
+        complaint.created_at = 100;
+        complaint.status = ComplaintStatus::Investigation;
+        assert!(resolve(&mut complaint, "  ".to_string(), "notes".to_string(), 200).is_err());
+        assert!(matches!(complaint.status, ComplaintStatus::Investigation));
+        
+        let taken = resolve(&mut complaint, "Contractor fined".to_string(), "Site inspected, work redone".to_string(), 400).unwrap();
+        assert_eq!(taken, Some(300));
+        assert!(matches!(complaint.status, ComplaintStatus::Resolved));
+        assert_eq!(complaint.resolution_time, Some(300));
+        assert_eq!(complaint.resolution_outcome.as_deref(), Some("Contractor fined"));
+        assert_eq!(complaint.resolution_notes.as_deref(), Some("Site inspected, work redone"));
+        assert_eq!(complaint.updated_at, 400);
+        
+        // Resolving again is a no-op
+        assert_eq!(resolve(&mut complaint, "Again".to_string(), String::new(), 500), Ok(None));
+        assert_eq!(complaint.resolution_outcome.as_deref(), Some("Contractor fined"));
+        assert_eq!(complaint.updated_at, 400);
+    }
+    
+    #[test]
+    fn test_average_resolution_time() {
+        let mut metrics = ComplaintMetrics {
+            total_complaints: 3,
+            resolved_complaints: 0,
+            average_resolution_time: 0.0,
+            total_resolution_time_ns: Some(0),
+            category_distribution: BTreeMap::new(),
+            district_distribution: BTreeMap::new(),
+        };
+        assert_eq!(average_resolution_time(0, 0), 0.0);
+        
+        let mut complaint = sample_complaint(None);
+        complaint.created_at = 100;
+        let previous = complaint.resolution_time;
+        let taken = resolve(&mut complaint, "Fixed".to_string(), String::new(), 400).unwrap().unwrap();
+        record_resolution(&mut metrics, previous, taken);
+        assert_eq!(metrics.resolved_complaints, 1);
+        assert_eq!(metrics.average_resolution_time, 300.0);
+        
+        record_resolution(&mut metrics, None, 100);
+        assert_eq!(metrics.resolved_complaints, 2);
+        assert_eq!(metrics.total_resolution_time_ns, Some(400));
+        assert_eq!(metrics.average_resolution_time, 200.0);
+        
+        // A double resolve changes nothing, so there is nothing to record
+        assert_eq!(resolve(&mut complaint, "Fixed".to_string(), String::new(), 900), Ok(None));
+        
+        // Reopened and resolved later: the new time replaces the old one
+        complaint.status = ComplaintStatus::Investigation;
+        let previous = complaint.resolution_time;
+        let taken = resolve(&mut complaint, "Fixed properly".to_string(), String::new(), 600).unwrap().unwrap();
+        record_resolution(&mut metrics, previous, taken);
+        assert_eq!(metrics.resolved_complaints, 2);
+        assert_eq!(metrics.total_resolution_time_ns, Some(600));
+        assert_eq!(metrics.average_resolution_time, 300.0);
+        
+        let mut open = sample_complaint(None);
+        open.id = "open".to_string();
+        assert_eq!(resolution_totals([complaint, open].iter()), (1, 500));
+    }
+    
+    #[test]
+    fn test_duplicate_detection() {
+        let existing = sample_complaint(None);
+        let complaints = [existing.clone()];
+        let now = existing.created_at + NANOS_PER_HOUR;
+        
+        // Same words in a different case and order
+        let duplicate = find_duplicate(complaints.iter(), "citizen_1", "Pune", "Road unfinished", "No work done but funds released, road left", now);
+        assert_eq!(duplicate.as_deref(), Some("complaint_1"));
+        assert_eq!(
+            check_duplicate(complaints.iter(), "citizen_1", "Pune", &existing.title, &existing.description, false, now),
+            Err("possible duplicate of complaint_1".to_string())
+        );
+        assert!(check_duplicate(complaints.iter(), "citizen_1", "Pune", &existing.title, &existing.description, true, now).is_ok());
+        
+        assert!(find_duplicate(complaints.iter(), "citizen_2", "Pune", &existing.title, &existing.description, now).is_none());
+        assert!(find_duplicate(complaints.iter(), "citizen_1", "Nashik", &existing.title, &existing.description, now).is_none());
+        assert!(find_duplicate(complaints.iter(), "citizen_1", "Pune", "Hospital short of medicines", "Pharmacy shelves empty for weeks", now).is_none());
+        let later = existing.created_at + DUPLICATE_WINDOW_NS + 1;
+        assert!(find_duplicate(complaints.iter(), "citizen_1", "Pune", &existing.title, &existing.description, later).is_none());
+        
+        assert_eq!(jaccard_similarity(&word_set("a b"), &word_set("b c")), 1.0 / 3.0);
+        assert_eq!(jaccard_similarity(&word_set(""), &word_set("")), 0.0);
+    }
+    
+    #[test]
+    fn test_media_link_validation() {
+        assert!(!validate_media_link("javascript:alert(1)"));
+        assert!(!validate_media_link("ftp://example.org/photo.jpg"));
+        assert!(!validate_media_link("https://"));
+        assert!(!validate_media_link("https://example.org/a b.jpg"));
+        assert!(!validate_media_link(&format!("https://example.org/{}", "a".repeat(MAX_MEDIA_LINK_LENGTH))));
+        assert!(validate_media_link("ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"));
+        assert!(validate_media_link("HTTPS://example.org/photo.jpg"));
+        
+        let links = normalize_media_links(vec![" ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi\n".to_string()]).unwrap();
+        assert_eq!(links, vec!["ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_string()]);
+        
+        let mixed = vec!["https://example.org/photo.jpg".to_string(), "javascript:alert(1)".to_string()];
+        assert_eq!(normalize_media_links(mixed), Err("Invalid media link: javascript:alert(1)".to_string()));
+        let too_many = vec!["https://example.org/photo.jpg".to_string(); MAX_MEDIA_LINKS + 1];
+        assert!(normalize_media_links(too_many).is_err());
+    }
+    
+    #[test]
+    fn test_complaint_update_thread() {
+        let mut complaint = sample_complaint(None);
+        let mut thread = Vec::new();
+        
+        append_update(&mut complaint, &mut thread, "citizen".to_string(), " Still no work on site ".to_string(), false, 5).unwrap();
+        append_update(&mut complaint, &mut thread, "officer".to_string(), "Inspection scheduled".to_string(), true, 9).unwrap();
+        assert_eq!(complaint.updated_at, 9);
+        
+        let posted: Vec<(&str, &str, bool)> = thread.iter()
+            .map(|update| (update.author.as_str(), update.text.as_str(), update.is_official))
+            .collect();
+        assert_eq!(posted, vec![("citizen", "Still no work on site", false), ("officer", "Inspection scheduled", true)]);
+        assert_eq!(thread[0].timestamp, 5);
+        
+        assert!(append_update(&mut complaint, &mut thread, "citizen".to_string(), "   ".to_string(), false, 10).is_err());
+        let too_long = "a".repeat(MAX_UPDATE_LENGTH + 1);
+        assert!(append_update(&mut complaint, &mut thread, "citizen".to_string(), too_long, false, 11).is_err());
+        assert_eq!(thread.len(), 2);
+        assert_eq!(complaint.updated_at, 9);
+    }
+    
+    #[test]
+    fn test_sla_escalation() {
+        let mut critical = sample_complaint(None);
+        critical.id = "critical".to_string();
+        critical.priority = ComplaintPriority::Critical;
+        let mut low = sample_complaint(None);
+        low.id = "low".to_string();
+        low.priority = ComplaintPriority::Low;
+        let mut resolved = sample_complaint(None);
+        resolved.id = "resolved".to_string();
+        resolved.priority = ComplaintPriority::Critical;
+        resolved.status = ComplaintStatus::Resolved;
+        let mut complaints: BTreeMap<String, Complaint> = [critical, low, resolved].into_iter()
+            .map(|complaint| (complaint.id.clone(), complaint))
+            .collect();
+        
+        // Exactly at the deadline is still within the SLA
+        let deadline = 1 + 24 * NANOS_PER_HOUR;
+        assert!(escalate_overdue(&mut complaints, deadline).is_empty());
+        
+        assert_eq!(escalate_overdue(&mut complaints, deadline + 1), vec!["critical".to_string()]);
+        let critical = &complaints["critical"];
+        assert!(matches!(critical.status, ComplaintStatus::Escalated));
+        assert_eq!(critical.updated_at, deadline + 1);
+        assert!((critical.audit_score - 0.2).abs() < f64::EPSILON);
+        
+        // Escalated complaints stay overdue but are not bumped again
+        assert!(escalate_overdue(&mut complaints, deadline + 2).is_empty());
+        assert!(is_overdue(&complaints["critical"], deadline + 2));
+        assert!((complaints["critical"].audit_score - 0.2).abs() < f64::EPSILON);
+        
+        let fortnight = 1 + 14 * 24 * NANOS_PER_HOUR;
+        assert_eq!(escalate_overdue(&mut complaints, fortnight + 1), vec!["low".to_string()]);
+        assert!(!is_overdue(&complaints["resolved"], fortnight + 1));
+    }
+    
+    #[test]
+    fn test_policy_pause_outcomes() {
+        let mut linked = sample_complaint(Some("policy_a"));
+        let policy_id = pause_target(&linked).unwrap();
+        assert_eq!(policy_id, "policy_a");
+        
+        // Mocked smart_policy reply for a successful pause
+        record_pause_outcome(&mut linked, policy_id.clone(), &Ok(()), 10);
+        let pause = linked.policy_pause.clone().unwrap();
+        assert!(pause.paused && pause.error.is_none());
+        assert_eq!((pause.policy_id.as_str(), pause.attempted_at), ("policy_a", 10));
+        assert!(matches!(linked.status, ComplaintStatus::Escalated));
+        assert_eq!(linked.audit_score, 0.2);
+        
+        // A rejected pause is recorded without escalating
+        let mut rejected = sample_complaint(Some("policy_a"));
+        record_pause_outcome(&mut rejected, policy_id, &Err("Policy not found".to_string()), 11);
+        assert_eq!(rejected.policy_pause.unwrap().error, Some("Policy not found".to_string()));
+        assert!(matches!(rejected.status, ComplaintStatus::Submitted));
+        assert_eq!(rejected.audit_score, 0.0);
+        
+        assert_eq!(pause_target(&sample_complaint(None)), Err("Complaint is not linked to a policy".to_string()));
+        assert!(pause_target(&sample_complaint(Some(""))).is_err());
+    }
+    
+    #[test]
+    fn test_llm_analysis_parsing() {
+        let reply = "Here is the analysis:\n```json\n{\"sentiment\": \"Negative\", \"category\": \"fund_misuse\", \
+                     \"priority_score\": 1.4, \"keywords\": [\"Funds\", \" \", \"contractor\"]}\n```";
+        let analysis = parse_analysis(reply).unwrap();
+        assert_eq!(analysis.sentiment, "negative");
+        assert_eq!(analysis.category_prediction, "fund_misuse");
+        assert_eq!(analysis.priority_score, 1.0);
+        assert_eq!(analysis.keywords, vec!["funds", "contractor"]);
+        assert_eq!(analysis.suggested_action, DEFAULT_SUGGESTED_ACTION);
+        
+        assert!(parse_analysis("I cannot help with that").is_err());
+        assert!(parse_analysis("{\"sentiment\": \"negative\"}").is_err());
+    }
+    
+    #[test]
+    fn test_llm_analysis_fallback() {
+        let text = "urgent: road repair funds lost to corruption";
+        let failed = analysis_from_response(Err("Backend chat call failed".to_string()), text);
+        assert_eq!(failed.sentiment, "negative");
+        assert_eq!(failed.category_prediction, "infrastructure");
+        assert_eq!(failed.priority_score, 0.9);
+        
+        // An unparsable reply falls back the same way
+        let garbled = analysis_from_response(Ok("no json here".to_string()), text);
+        assert_eq!(garbled.category_prediction, failed.category_prediction);
+    }
+    
+    #[test]
+    fn test_health_check_counts() {
+        unsafe {
+            COMPLAINTS = Some(BTreeMap::new());
+            INIT_TIME_NS = Some(100);
+        }
+        let before = health_status_at(400);
+        assert!(!before.initialized);
+        assert_eq!(before.uptime_ns, 300);
+        assert_eq!(before.record_counts[0], ("complaints".to_string(), 0));
+        
+        unsafe {
+            COMPLAINT_METRICS = Some(ComplaintMetrics {
+                total_complaints: 0,
+                resolved_complaints: 0,
+                average_resolution_time: 0.0,
+                total_resolution_time_ns: Some(0),
+                category_distribution: BTreeMap::new(),
+                district_distribution: BTreeMap::new(),
+            });
+            if let Some(ref mut complaints) = COMPLAINTS {
+                let complaint = sample_complaint(None);
+                complaints.insert(complaint.id.clone(), complaint);
+            }
+        }
+        let after = health_status_at(400);
+        assert!(after.initialized);
+        assert_eq!(after.record_counts[0], ("complaints".to_string(), 1));
+    }
+}
@@ -1,12 +1,14 @@
-use candid::{CandidType, Deserialize, Principal};
-use ic_cdk::{api::call::call, export::candid, init, post_upgrade, pre_upgrade, query, update};
+use candid::{CandidType, Deserialize, Nat, Principal};
+use ic_cdk::{api::call::call, init, post_upgrade, pre_upgrade, query, update};
 use ic_cdk_timers::set_timer_interval;
-use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
-use std::collections::BTreeMap;
+use serde::Serialize as SerdeSerialize;
+use common::{CanisterStats, HashTree, HealthStatus};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 use uuid::Uuid;
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct Proposal {
     pub id: String,
     pub title: String,
@@ -23,9 +25,199 @@ pub struct Proposal {
     pub total_votes: u32,
     pub quorum_required: u32,
     pub execution_data: Option<ProposalExecution>,
+    // None on proposals created before quorum modes; they use quorum_required as absolute power
+    pub quorum_mode: Option<QuorumMode>,
+    // Call made on smart_policy when the proposal executes; None for signalling proposals
+    pub execution_target: Option<ProposalAction>,
+    // Set when the proposal is cancelled; None on older or live proposals
+    pub cancellation_reason: Option<String>,
+    // Draft edits; None on proposals that were never edited
+    pub edited_at: Option<u64>,
+    pub edit_history: Option<Vec<ProposalEdit>>,
+    // Multiple-choice proposals only; None on binary Yes/No proposals
+    pub options: Option<Vec<ProposalOption>>,
+    // Percentage of option votes the winner must hold, 0-100
+    pub min_winning_share: Option<u8>,
+    // option id -> voting power cast for it
+    pub option_votes: Option<BTreeMap<String, u32>>,
+    pub winning_option: Option<String>,
+    // Set once when voting is extended: the admin's member id or AUTO_EXTENDED_BY
+    pub extended_by: Option<String>,
+    pub extension_reason: Option<String>,
+    pub original_voting_end: Option<u64>,
+    // Recorded when voting closes; None while voting is open and on older proposals
+    pub tally_snapshot: Option<TallySnapshot>,
+    // Token balances taken when the proposal opened; None unless a governance token was set
+    pub power_snapshot: Option<PowerSnapshot>,
+    // Set when the proposal passes; execute_proposal waits until then. None on proposals
+    // that passed before timelocks existed, which can execute straight away
+    pub executable_after: Option<u64>,
+    // Member ids objecting during the timelock
+    pub objections: Option<Vec<String>>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+// Wait between a proposal passing and its execution, during which enough member objections
+// reject it
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct TimelockConfig {
+    pub timelock_hours: u64,
+    // Share of active members, 1-100, whose objections reject the proposal
+    pub objection_threshold_percent: u8,
+}
+
+// Voting power follows holdings of this ICRC-1 token: one vote per units_per_vote base units
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct GovernanceToken {
+    pub ledger: Principal,
+    pub units_per_vote: u64,
+}
+
+// Filled a batch of balance calls at a time; voting opens once pending is empty
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct PowerSnapshot {
+    pub ledger: Principal,
+    pub units_per_vote: u64,
+    pub started_at: u64,
+    pub completed_at: Option<u64>,
+    // member id -> voting power from their balance
+    pub powers: BTreeMap<String, u32>,
+    pub pending: Vec<String>,
+    // Members whose balance could not be read; they vote with no power
+    pub failed: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SnapshotProgress {
+    pub proposal_id: String,
+    pub total_members: u32,
+    pub completed_members: u32,
+    pub failed_members: u32,
+    pub complete: bool,
+}
+
+#[derive(CandidType, Deserialize)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+// Votes cast by members holding one role
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize, Default)]
+pub struct RoleTally {
+    pub voters: u32,
+    pub yes_votes: u32,
+    pub no_votes: u32,
+    pub abstain_votes: u32,
+    // option id -> voting power; multiple-choice proposals only
+    pub option_votes: BTreeMap<String, u32>,
+}
+
+// Result of a proposal broken down by the role each voter held when casting. Provisional
+// snapshots are computed live for proposals without a recorded one and have no closed_at.
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct TallySnapshot {
+    pub by_role: BTreeMap<String, RoleTally>,
+    pub turnout_pct: f64,
+    pub quorum_met: bool,
+    pub closed_at: Option<u64>,
+    pub provisional: bool,
+}
+
+// When participation at the deadline is within `within_percent` of the quorum, voting is
+// extended once by `extra_hours` instead of the proposal expiring
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct AutoExtensionRule {
+    pub within_percent: u8,
+    pub extra_hours: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ExtensionSettings {
+    pub max_extension_hours: u64,
+    pub auto_extension: Option<AutoExtensionRule>,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct ProposalOption {
+    pub id: String,
+    pub label: String,
+    pub description: String,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MultipleChoice {
+    pub options: Vec<ProposalOption>,
+    pub min_winning_share: u8,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct OptionTally {
+    pub option_id: String,
+    pub label: String,
+    pub votes: u32,
+}
+
+// Standing of a proposal as of now; for an Active proposal the outcome is what
+// finalization would decide if voting ended at this moment
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Tally {
+    pub proposal_id: String,
+    pub status: ProposalStatus,
+    pub yes_votes: u32,
+    pub no_votes: u32,
+    pub abstain_votes: u32,
+    pub total_votes: u32,
+    pub quorum_power_needed: u64,
+    pub quorum_met: bool,
+    pub provisional_outcome: ProposalStatus,
+    pub leading_option: Option<String>,
+    pub voting_end: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct ProposalUpdate {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub voting_duration_hours: Option<u64>,
+    pub quorum_mode: Option<QuorumMode>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy)]
+pub enum ProposalSort {
+    CreatedDesc,
+    VotingEndAsc,
+    TotalVotesDesc,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ProposalPage {
+    pub proposals: Vec<Proposal>,
+    pub total_count: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct ProposalEdit {
+    pub editor: String,
+    pub edited_at: u64,
+    pub fields: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub enum ProposalAction {
+    PausePolicy(String),
+    ActivatePolicy(String),
+    ReleaseFunds { policy_id: String, amount: u64, to: String },
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub enum QuorumMode {
+    AbsolutePower(u32),
+    // Share of all members' voting power at finalization, 0-100
+    PercentOfTotalPower(u8),
+}
+
+#[derive(CandidType, Deserialize, Clone, PartialEq, SerdeSerialize)]
 pub enum ProposalStatus {
     Draft,
     Active,
@@ -33,9 +225,10 @@ pub enum ProposalStatus {
     Rejected,
     Executed,
     Expired,
+    Cancelled,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct ProposalExecution {
     pub executed_at: u64,
     pub executor: String,
@@ -44,7 +237,16 @@ pub struct ProposalExecution {
     pub error_message: Option<String>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct Comment {
+    pub id: String,
+    pub proposal_id: String,
+    pub author: String,
+    pub text: String,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct Vote {
     pub proposal_id: String,
     pub voter: String,
@@ -52,16 +254,39 @@ pub struct Vote {
     pub voting_power: u32,
     pub timestamp: u64,
     pub reason: Option<String>,
+    // Caller that cast the vote; None on votes recorded under free-text voter ids, which are
+    // kept read-only
+    pub voter_principal: Option<Principal>,
+    // Members whose delegated power is included in voting_power, snapshotted when the vote
+    // was cast; later revocations do not change it
+    pub delegated_from: Option<Vec<String>>,
+    // Set on votes for an option of a multiple-choice proposal, whose vote_type is Yes
+    pub option_id: Option<String>,
+    // Hex SHA-256 returned to the voter; reissued when the vote changes. None on votes cast
+    // before receipts existed
+    pub receipt_hash: Option<String>,
+    // Role when the vote was cast; None on votes cast before roles were recorded
+    pub voter_role: Option<MemberRole>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct VoteReceiptProof {
+    pub proposal_id: String,
+    pub receipt_hash: String,
+    // CBOR certificate whose certified_data is the root hash of `witness`; only available
+    // from a query call
+    pub certificate: Option<Vec<u8>>,
+    pub witness: HashTree,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub enum VoteType {
     Yes,
     No,
     Abstain,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct DAOMember {
     pub id: String,
     pub name: String,
@@ -70,9 +295,104 @@ pub struct DAOMember {
     pub total_votes_cast: u32,
     pub reputation_score: f64,
     pub role: MemberRole,
+    // Identity the member votes with; None only for members added before principals were
+    // recorded whose id is not a principal text
+    pub principal: Option<Principal>,
+    // Set by remove_member; removed members stay on record so their past votes still resolve
+    pub removed_at: Option<u64>,
+    pub removal_reason: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub enum ReputationReason {
+    VotedOnQuorumProposal,
+    AuthoredPassedProposal,
+    MissedProposals,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct ReputationEvent {
+    pub proposal_id: String,
+    pub reason: ReputationReason,
+    // Change actually applied, after clamping
+    pub delta: f64,
+    pub score_after: f64,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MemberReputation {
+    pub member_id: String,
+    pub reputation_score: f64,
+    pub recent_events: Vec<ReputationEvent>,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub enum ApplicationStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+// Self-service request to join the DAO; the review fields are set by the admin who decides it
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct MembershipApplication {
+    pub id: String,
+    pub applicant: Principal,
+    pub name: String,
+    pub requested_role: MemberRole,
+    pub status: ApplicationStatus,
+    pub submitted_at: u64,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<u64>,
+    pub granted_role: Option<MemberRole>,
+    pub voting_power: Option<u32>,
+    // Member created on approval
+    pub member_id: Option<String>,
+    pub rejection_reason: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ApplicationPage {
+    pub applications: Vec<MembershipApplication>,
+    pub total_count: u64,
+}
+
+// A member's vote with the proposal it was cast on; proposal_status is the final outcome
+// once voting has closed
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MemberVoteRecord {
+    pub proposal_id: String,
+    pub proposal_title: String,
+    pub proposal_status: ProposalStatus,
+    pub vote: Vote,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MemberVotingSummary {
+    pub votes_cast: u32,
+    pub proposals_authored: u32,
+    // Finalized proposals, out of the last PARTICIPATION_WINDOW, that opened after the member joined
+    pub recent_proposals: u32,
+    pub recent_votes: u32,
+    pub participation_rate: f64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MemberVotes {
+    pub member_id: String,
+    pub votes: Vec<MemberVoteRecord>,
+    pub total_count: u64,
+    pub summary: MemberVotingSummary,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct MemberUpdate {
+    pub role: Option<MemberRole>,
+    pub voting_power: Option<u32>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub enum MemberRole {
     Citizen,
     PolicyMaker,
@@ -81,13 +401,14 @@ pub enum MemberRole {
     Admin,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct DAOMetrics {
     pub total_proposals: u32,
     pub active_proposals: u32,
     pub passed_proposals: u32,
     pub total_members: u32,
     pub total_votes_cast: u32,
+    // Mean share of total voting power cast per finalized proposal
     pub average_participation: f64,
 }
 
@@ -95,7 +416,76 @@ pub struct DAOMetrics {
 static mut PROPOSALS: Option<BTreeMap<String, Proposal>> = None;
 static mut VOTES: Option<BTreeMap<String, Vote>> = None;
 static mut MEMBERS: Option<BTreeMap<String, DAOMember>> = None;
+// principal -> member id, rebuilt from MEMBERS on upgrade
+static mut MEMBER_PRINCIPALS: Option<BTreeMap<Principal, String>> = None;
+// delegator member id -> delegate member id; delegates cannot delegate further
+static mut DELEGATIONS: Option<BTreeMap<String, String>> = None;
 static mut DAO_METRICS: Option<DAOMetrics> = None;
+// member_id -> finalized proposals missed in a row
+static mut MISSED_STREAKS: Option<BTreeMap<String, u32>> = None;
+// member_id -> reputation changes, oldest first, capped at MAX_REPUTATION_HISTORY
+static mut REPUTATION_HISTORY: Option<BTreeMap<String, Vec<ReputationEvent>>> = None;
+// proposal_id -> comments in posting order
+static mut COMMENTS: Option<BTreeMap<String, Vec<Comment>>> = None;
+static mut SMART_POLICY_CANISTER_ID: Option<Principal> = None;
+static mut MIN_PROPOSAL_REPUTATION: f64 = DEFAULT_MIN_PROPOSAL_REPUTATION;
+// Proposals whose smart_policy call is awaiting a reply
+static mut EXECUTING: Option<BTreeSet<String>> = None;
+// receipt hash -> SHA-256(proposal_id); the certified tree, rebuilt from VOTES on upgrade
+static mut VOTE_RECEIPTS: Option<BTreeMap<String, [u8; 32]>> = None;
+// member_id -> keys of the member's entries in VOTES, rebuilt from VOTES on upgrade
+static mut MEMBER_VOTES: Option<BTreeMap<String, BTreeSet<String>>> = None;
+// proposal_id -> members whose power is in the proposal's tally, rebuilt from VOTES on upgrade
+static mut COUNTED_MEMBERS: Option<BTreeMap<String, BTreeSet<String>>> = None;
+// application_id -> membership application, kept after review
+static mut APPLICATIONS: Option<BTreeMap<String, MembershipApplication>> = None;
+static mut MAX_EXTENSION_HOURS: u64 = DEFAULT_MAX_EXTENSION_HOURS;
+static mut GOVERNANCE_TOKEN: Option<GovernanceToken> = None;
+static mut TIMELOCK: TimelockConfig = TimelockConfig {
+    timelock_hours: DEFAULT_TIMELOCK_HOURS,
+    objection_threshold_percent: DEFAULT_OBJECTION_THRESHOLD_PERCENT,
+};
+// Set while a batch of balance calls is in flight so timer ticks do not overlap
+static mut SNAPSHOT_RUNNING: bool = false;
+static mut AUTO_EXTENSION: Option<AutoExtensionRule> = None;
+// Set at install; canisters installed before it existed start counting at their next upgrade
+static mut INIT_TIME_NS: Option<u64> = None;
+
+// Reputation earned for voting on a proposal that reached quorum
+const REPUTATION_VOTE_BONUS: f64 = 0.05;
+// Reputation earned by the proposer when their proposal passes
+const REPUTATION_AUTHOR_BONUS: f64 = 0.25;
+// Reputation lost per missed proposal once a member misses MISSED_STREAK_PENALTY_AFTER in a row
+const REPUTATION_MISSED_PENALTY: f64 = 0.05;
+const MISSED_STREAK_PENALTY_AFTER: u32 = 2;
+const MIN_REPUTATION: f64 = 0.0;
+const MAX_REPUTATION: f64 = 5.0;
+const MAX_REPUTATION_HISTORY: usize = 50;
+// New members start at 1.0, so only members who have lost reputation are held back
+const DEFAULT_MIN_PROPOSAL_REPUTATION: f64 = 0.5;
+
+const MAX_COMMENT_LENGTH: usize = 2_000;
+const MAX_COMMENTS_PAGE: u32 = 100;
+const MAX_PROPOSALS_PAGE: u32 = 100;
+const MAX_MEMBER_VOTES_PAGE: u32 = 100;
+const MAX_APPLICATIONS_PAGE: u32 = 100;
+const MAX_MEMBER_NAME_LENGTH: usize = 100;
+// Upper bound on an admin's voting power override; tallies saturate beyond u32 regardless
+const MAX_VOTING_POWER_OVERRIDE: u32 = 10_000;
+const SNAPSHOT_BATCH: usize = 20;
+// Drafts activate and deadlines close at most this long after they fall due
+const DEADLINE_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const DEFAULT_TIMELOCK_HOURS: u64 = 48;
+const DEFAULT_OBJECTION_THRESHOLD_PERCENT: u8 = 25;
+const PARTICIPATION_WINDOW: usize = 20;
+// Older entries are dropped once a draft has been edited this many times
+const MAX_EDIT_HISTORY: usize = 20;
+const MAX_PROPOSAL_OPTIONS: usize = 10;
+const RECEIPTS_LABEL: &[u8] = b"vote_receipts";
+const NANOS_PER_HOUR: u64 = 3_600_000_000_000;
+const DEFAULT_MAX_EXTENSION_HOURS: u64 = 72;
+const AUTO_EXTENDED_BY: &str = "auto-extension";
 
 #[init]
 fn init() {
@@ -103,6 +493,17 @@ fn init() {
         PROPOSALS = Some(BTreeMap::new());
         VOTES = Some(BTreeMap::new());
         MEMBERS = Some(BTreeMap::new());
+        MEMBER_PRINCIPALS = Some(BTreeMap::new());
+        DELEGATIONS = Some(BTreeMap::new());
+        MISSED_STREAKS = Some(BTreeMap::new());
+        REPUTATION_HISTORY = Some(BTreeMap::new());
+        COMMENTS = Some(BTreeMap::new());
+        EXECUTING = Some(BTreeSet::new());
+        VOTE_RECEIPTS = Some(BTreeMap::new());
+        MEMBER_VOTES = Some(BTreeMap::new());
+        COUNTED_MEMBERS = Some(BTreeMap::new());
+        APPLICATIONS = Some(BTreeMap::new());
+        INIT_TIME_NS = Some(ic_cdk::api::time());
         DAO_METRICS = Some(DAOMetrics {
             total_proposals: 0,
             active_proposals: 0,
@@ -112,9 +513,14 @@ fn init() {
             average_participation: 0.0,
         });
     }
-    
+    certify_vote_receipts();
+    start_deadline_timer();
+}
+
+// Timers do not survive an upgrade, so post_upgrade registers this again
+fn start_deadline_timer() {
     // Set up periodic proposal checks
-    set_timer_interval(Duration::from_secs(3600), || {
+    set_timer_interval(DEADLINE_CHECK_INTERVAL, || {
         ic_cdk::spawn(check_proposal_deadlines());
     });
 }
@@ -125,21 +531,102 @@ fn pre_upgrade() {
     let votes = unsafe { VOTES.take().unwrap() };
     let members = unsafe { MEMBERS.take().unwrap() };
     let metrics = unsafe { DAO_METRICS.take().unwrap() };
+    let missed_streaks = unsafe { MISSED_STREAKS.take() };
+    let smart_policy_canister_id = unsafe { SMART_POLICY_CANISTER_ID };
+    let min_proposal_reputation = unsafe { Some(MIN_PROPOSAL_REPUTATION) };
+    let comments = unsafe { COMMENTS.take() };
+    let delegations = unsafe { DELEGATIONS.take() };
+    let init_time_ns = unsafe { INIT_TIME_NS };
+    let reputation_history = unsafe { REPUTATION_HISTORY.take() };
+    let max_extension_hours = unsafe { Some(MAX_EXTENSION_HOURS) };
+    let auto_extension = unsafe { AUTO_EXTENSION.take() };
+    let applications = unsafe { APPLICATIONS.take() };
+    let governance_token = unsafe { GOVERNANCE_TOKEN.take() };
+    let timelock = unsafe { Some(TIMELOCK.clone()) };
     
-    ic_cdk::storage::stable_save((proposals, votes, members, metrics)).unwrap();
+    ic_cdk::storage::stable_save((
+        proposals,
+        votes,
+        members,
+        metrics,
+        missed_streaks,
+        smart_policy_canister_id,
+        min_proposal_reputation,
+        comments,
+        delegations,
+        init_time_ns,
+        reputation_history,
+        max_extension_hours,
+        auto_extension,
+        applications,
+        governance_token,
+        timelock,
+    )).unwrap();
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (proposals, votes, members, metrics): (BTreeMap<String, Proposal>, BTreeMap<String, Vote>, BTreeMap<String, DAOMember>, DAOMetrics) = 
-        ic_cdk::storage::stable_restore().unwrap();
+    let (proposals, votes, members, metrics, missed_streaks, smart_policy_canister_id, min_proposal_reputation, comments, delegations, init_time_ns, reputation_history, max_extension_hours, auto_extension, applications, governance_token, timelock): (
+        BTreeMap<String, Proposal>,
+        BTreeMap<String, Vote>,
+        BTreeMap<String, DAOMember>,
+        DAOMetrics,
+        Option<BTreeMap<String, u32>>,
+        Option<Principal>,
+        Option<f64>,
+        Option<BTreeMap<String, Vec<Comment>>>,
+        Option<BTreeMap<String, String>>,
+        Option<u64>,
+        Option<BTreeMap<String, Vec<ReputationEvent>>>,
+        Option<u64>,
+        Option<AutoExtensionRule>,
+        Option<BTreeMap<String, MembershipApplication>>,
+        Option<GovernanceToken>,
+        Option<TimelockConfig>,
+    ) = ic_cdk::storage::stable_restore().unwrap();
+    
+    // Members saved before principals were recorded were keyed by principal text
+    let mut members = members;
+    for member in members.values_mut() {
+        if member.principal.is_none() {
+            member.principal = Principal::from_text(&member.id).ok();
+        }
+    }
+    let member_principals = build_principal_index(&members);
+    let vote_receipts = build_vote_receipts(&votes);
+    let member_votes = build_member_vote_index(&votes);
+    let counted_members = build_counted_members(&votes);
     
     unsafe {
         PROPOSALS = Some(proposals);
         VOTES = Some(votes);
         MEMBERS = Some(members);
+        MEMBER_PRINCIPALS = Some(member_principals);
         DAO_METRICS = Some(metrics);
+        MISSED_STREAKS = Some(missed_streaks.unwrap_or_default());
+        REPUTATION_HISTORY = Some(reputation_history.unwrap_or_default());
+        SMART_POLICY_CANISTER_ID = smart_policy_canister_id;
+        MIN_PROPOSAL_REPUTATION = min_proposal_reputation.unwrap_or(DEFAULT_MIN_PROPOSAL_REPUTATION);
+        COMMENTS = Some(comments.unwrap_or_default());
+        DELEGATIONS = Some(delegations.unwrap_or_default());
+        EXECUTING = Some(BTreeSet::new());
+        VOTE_RECEIPTS = Some(vote_receipts);
+        MEMBER_VOTES = Some(member_votes);
+        COUNTED_MEMBERS = Some(counted_members);
+        MAX_EXTENSION_HOURS = max_extension_hours.unwrap_or(DEFAULT_MAX_EXTENSION_HOURS);
+        AUTO_EXTENSION = auto_extension;
+        APPLICATIONS = Some(applications.unwrap_or_default());
+        GOVERNANCE_TOKEN = governance_token;
+        if let Some(timelock) = timelock {
+            TIMELOCK = timelock;
+        }
+        INIT_TIME_NS = Some(init_time_ns.unwrap_or_else(ic_cdk::api::time));
     }
+    certify_vote_receipts();
+    start_deadline_timer();
+    
+    // Snapshots interrupted by the upgrade carry on from their pending members
+    schedule_snapshot_batch();
 }
 
 #[update]
@@ -147,10 +634,25 @@ async fn create_proposal(
     title: String,
     description: String,
     category: String,
-    proposer: String,
     voting_duration_hours: u64,
-    quorum_required: u32,
+    quorum_mode: QuorumMode,
+    execution_target: Option<ProposalAction>,
+    choices: Option<MultipleChoice>,
 ) -> Result<String, String> {
+    if let Some(ref choices) = choices {
+        validate_choices(choices, &execution_target)?;
+    }
+    // The caller is recorded as proposer, so only they can later cancel
+    let proposer = ic_cdk::caller().to_text();
+    unsafe {
+        let members = MEMBERS.as_ref().ok_or("Members not initialized".to_string())?;
+        let member_id = MEMBER_PRINCIPALS.as_ref()
+            .and_then(|index| index.get(&ic_cdk::caller()))
+            .map_or(proposer.as_str(), String::as_str);
+        check_proposal_eligibility(members, member_id, MIN_PROPOSAL_REPUTATION)?;
+    }
+    
+    let quorum_required = quorum_required_for(&quorum_mode)?;
     let proposal_id = Uuid::new_v4().to_string();
     let now = ic_cdk::api::time();
     let voting_start = now + 3600_000_000_000; // 1 hour from now
@@ -172,6 +674,22 @@ async fn create_proposal(
         total_votes: 0,
         quorum_required,
         execution_data: None,
+        quorum_mode: Some(quorum_mode),
+        execution_target,
+        cancellation_reason: None,
+        edited_at: None,
+        edit_history: None,
+        options: choices.as_ref().map(|choices| choices.options.clone()),
+        min_winning_share: choices.map(|choices| choices.min_winning_share),
+        option_votes: None,
+        winning_option: None,
+        extended_by: None,
+        extension_reason: None,
+        original_voting_end: None,
+        tally_snapshot: None,
+        power_snapshot: None,
+        executable_after: None,
+        objections: None,
     };
     
     unsafe {
@@ -187,6 +705,144 @@ async fn create_proposal(
     Ok(proposal_id)
 }
 
+fn check_proposal_eligibility(members: &BTreeMap<String, DAOMember>, caller: &str, min_reputation: f64) -> Result<(), String> {
+    let member = members.get(caller)
+        .filter(|member| member.removed_at.is_none())
+        .ok_or("Only DAO members can create proposals".to_string())?;
+    if member.reputation_score < min_reputation {
+        return Err(format!(
+            "Reputation {:.2} is below the {:.2} required to create proposals",
+            member.reputation_score, min_reputation
+        ));
+    }
+    Ok(())
+}
+
+// Options are fixed at creation; the winner is a decision, not something to execute
+fn validate_choices(choices: &MultipleChoice, execution_target: &Option<ProposalAction>) -> Result<(), String> {
+    if execution_target.is_some() {
+        return Err("Multiple-choice proposals cannot have an execution target".to_string());
+    }
+    if choices.options.len() < 2 || choices.options.len() > MAX_PROPOSAL_OPTIONS {
+        return Err(format!("Multiple-choice proposals need between 2 and {} options", MAX_PROPOSAL_OPTIONS));
+    }
+    if choices.min_winning_share > 100 {
+        return Err("Minimum winning share must be between 0 and 100".to_string());
+    }
+    
+    let mut ids = BTreeSet::new();
+    for option in &choices.options {
+        if option.id.trim().is_empty() || option.label.trim().is_empty() {
+            return Err("Options need a non-empty id and label".to_string());
+        }
+        if !ids.insert(option.id.as_str()) {
+            return Err(format!("Duplicate option id {}", option.id));
+        }
+    }
+    Ok(())
+}
+
+#[update]
+fn set_min_proposal_reputation(min_reputation: f64) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can change the proposal reputation threshold".to_string());
+    }
+    if !(0.0..=MAX_REPUTATION).contains(&min_reputation) {
+        return Err(format!("Threshold must be between 0 and {}", MAX_REPUTATION));
+    }
+    
+    unsafe {
+        MIN_PROPOSAL_REPUTATION = min_reputation;
+    }
+    Ok(())
+}
+
+#[query]
+fn get_min_proposal_reputation() -> f64 {
+    unsafe { MIN_PROPOSAL_REPUTATION }
+}
+
+// Percentage quorums are resolved against total power at finalization, so they store 0 here
+fn quorum_required_for(quorum_mode: &QuorumMode) -> Result<u32, String> {
+    match *quorum_mode {
+        QuorumMode::AbsolutePower(power) => Ok(power),
+        QuorumMode::PercentOfTotalPower(percent) if percent <= 100 => Ok(0),
+        QuorumMode::PercentOfTotalPower(_) => Err("Quorum percentage must be between 0 and 100".to_string()),
+    }
+}
+
+#[update]
+fn update_proposal(proposal_id: String, changes: ProposalUpdate) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        let is_admin = caller_is_admin(&caller);
+        let proposal = PROPOSALS.as_mut()
+            .and_then(|proposals| proposals.get_mut(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        apply_proposal_update(proposal, changes, &caller.to_text(), is_admin, now)
+    }
+}
+
+fn apply_proposal_update(
+    proposal: &mut Proposal,
+    changes: ProposalUpdate,
+    caller: &str,
+    is_admin: bool,
+    now: u64,
+) -> Result<(), String> {
+    if proposal.proposer != caller && !is_admin {
+        return Err("Only the proposer or an admin can edit a proposal".to_string());
+    }
+    if proposal.status != ProposalStatus::Draft {
+        return Err("Only draft proposals can be edited; this proposal has already been activated".to_string());
+    }
+    
+    // Validate everything before touching the proposal so a bad field leaves it unchanged
+    if changes.title.as_ref().is_some_and(|title| title.trim().is_empty()) {
+        return Err("Title cannot be empty".to_string());
+    }
+    if changes.voting_duration_hours == Some(0) {
+        return Err("Voting duration must be at least one hour".to_string());
+    }
+    let quorum_required = changes.quorum_mode.as_ref().map(quorum_required_for).transpose()?;
+    
+    let mut fields = Vec::new();
+    if let Some(title) = changes.title {
+        proposal.title = title;
+        fields.push("title".to_string());
+    }
+    if let Some(description) = changes.description {
+        proposal.description = description;
+        fields.push("description".to_string());
+    }
+    if let Some(category) = changes.category {
+        proposal.category = category;
+        fields.push("category".to_string());
+    }
+    if let Some(hours) = changes.voting_duration_hours {
+        proposal.voting_end = proposal.voting_start + hours * 3_600_000_000_000;
+        fields.push("voting_duration".to_string());
+    }
+    if let (Some(quorum_mode), Some(quorum_required)) = (changes.quorum_mode, quorum_required) {
+        proposal.quorum_mode = Some(quorum_mode);
+        proposal.quorum_required = quorum_required;
+        fields.push("quorum".to_string());
+    }
+    if fields.is_empty() {
+        return Err("No changes provided".to_string());
+    }
+    
+    proposal.edited_at = Some(now);
+    let history = proposal.edit_history.get_or_insert_with(Vec::new);
+    history.push(ProposalEdit { editor: caller.to_string(), edited_at: now, fields });
+    if history.len() > MAX_EDIT_HISTORY {
+        history.remove(0);
+    }
+    Ok(())
+}
+
 #[update]
 async fn activate_proposal(proposal_id: String) -> Result<(), String> {
     let now = ic_cdk::api::time();
@@ -196,6 +852,10 @@ async fn activate_proposal(proposal_id: String) -> Result<(), String> {
             if let Some(proposal) = proposals.get_mut(&proposal_id) {
                 if proposal.status == ProposalStatus::Draft {
                     proposal.status = ProposalStatus::Active;
+                    if let (Some(ref token), Some(ref members)) = (&GOVERNANCE_TOKEN, &MEMBERS) {
+                        proposal.power_snapshot = Some(start_power_snapshot(members, token, now));
+                        schedule_snapshot_batch();
+                    }
                     
                     if let Some(ref mut metrics) = DAO_METRICS {
                         metrics.active_proposals += 1;
@@ -211,272 +871,3515 @@ async fn activate_proposal(proposal_id: String) -> Result<(), String> {
 }
 
 #[update]
-async fn cast_vote(
-    proposal_id: String,
-    voter: String,
-    vote_type: VoteType,
-    voting_power: u32,
-    reason: Option<String>,
-) -> Result<(), String> {
-    let now = ic_cdk::api::time();
-    
-    // Check if proposal is active
-    unsafe {
-        if let Some(ref proposals) = PROPOSALS {
-            if let Some(proposal) = proposals.get(&proposal_id) {
-                if proposal.status != ProposalStatus::Active {
-                    return Err("Proposal is not active for voting".to_string());
-                }
-                if now < proposal.voting_start || now > proposal.voting_end {
-                    return Err("Voting period is not active".to_string());
-                }
-            } else {
-                return Err("Proposal not found".to_string());
-            }
-        }
-    }
-    
-    // Check if voter has already voted
-    let vote_key = format!("{}:{}", proposal_id, voter);
-    unsafe {
-        if let Some(ref votes) = VOTES {
-            if votes.contains_key(&vote_key) {
-                return Err("Voter has already cast a vote".to_string());
-            }
-        }
+fn cancel_proposal(proposal_id: String, reason: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("A cancellation reason is required".to_string());
     }
     
-    let vote = Vote {
-        proposal_id: proposal_id.clone(),
-        voter: voter.clone(),
-        vote_type: vote_type.clone(),
-        voting_power,
-        timestamp: now,
-        reason,
-    };
-    
-    // Store vote
     unsafe {
-        if let Some(ref mut votes) = VOTES {
-            votes.insert(vote_key, vote);
-        }
+        let is_admin = caller_is_admin(&caller);
+        let has_votes = VOTES.as_ref().is_some_and(|votes| proposal_has_votes(votes, &proposal_id));
         
-        // Update proposal vote counts
-        if let Some(ref mut proposals) = PROPOSALS {
-            if let Some(proposal) = proposals.get_mut(&proposal_id) {
-                match vote_type {
-                    VoteType::Yes => proposal.yes_votes += voting_power,
-                    VoteType::No => proposal.no_votes += voting_power,
-                    VoteType::Abstain => proposal.abstain_votes += voting_power,
-                }
-                proposal.total_votes += voting_power;
-            }
-        }
+        let proposal = PROPOSALS.as_mut()
+            .and_then(|proposals| proposals.get_mut(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        check_cancellable(proposal, &caller.to_text(), is_admin, has_votes)?;
         
-        // Update metrics
-        if let Some(ref mut metrics) = DAO_METRICS {
-            metrics.total_votes_cast += 1;
+        if proposal.status == ProposalStatus::Active {
+            if let Some(ref mut metrics) = DAO_METRICS {
+                metrics.active_proposals = metrics.active_proposals.saturating_sub(1);
+            }
         }
+        proposal.status = ProposalStatus::Cancelled;
+        proposal.cancellation_reason = Some(reason);
     }
     
     Ok(())
 }
 
-#[query]
-fn get_proposal(proposal_id: String) -> Result<Proposal, String> {
-    unsafe {
-        if let Some(ref proposals) = PROPOSALS {
-            proposals.get(&proposal_id).cloned().ok_or("Proposal not found".to_string())
-        } else {
-            Err("Proposals not initialized".to_string())
-        }
+// Proposers may withdraw until the first vote lands; admins may cancel at any point before a result
+fn check_cancellable(proposal: &Proposal, caller: &str, is_admin: bool, has_votes: bool) -> Result<(), String> {
+    if proposal.proposer != caller && !is_admin {
+        return Err("Only the proposer or an admin can cancel a proposal".to_string());
     }
-}
-
-#[query]
-fn get_all_proposals() -> Vec<Proposal> {
-    unsafe {
-        if let Some(ref proposals) = PROPOSALS {
-            proposals.values().cloned().collect()
-        } else {
-            Vec::new()
+    
+    match proposal.status {
+        ProposalStatus::Draft | ProposalStatus::Active if is_admin || !has_votes => Ok(()),
+        ProposalStatus::Draft | ProposalStatus::Active => {
+            Err("Votes have already been cast; only an admin can cancel".to_string())
         }
+        _ => Err("Proposal can no longer be cancelled".to_string()),
     }
 }
 
-#[query]
-fn get_active_proposals() -> Vec<Proposal> {
-    let now = ic_cdk::api::time();
-    unsafe {
-        if let Some(ref proposals) = PROPOSALS {
-            proposals.values()
-                .filter(|proposal| {
-                    proposal.status == ProposalStatus::Active &&
-                    now >= proposal.voting_start &&
-                    now <= proposal.voting_end
-                })
-                .cloned()
-                .collect()
-        } else {
-            Vec::new()
-        }
-    }
+unsafe fn caller_is_admin(caller: &Principal) -> bool {
+    MEMBER_PRINCIPALS.as_ref()
+        .and_then(|index| index.get(caller))
+        .and_then(|member_id| MEMBERS.as_ref().and_then(|members| members.get(member_id)))
+        .is_some_and(|member| matches!(member.role, MemberRole::Admin))
 }
 
-#[query]
-fn get_proposal_votes(proposal_id: String) -> Vec<Vote> {
-    unsafe {
-        if let Some(ref votes) = VOTES {
-            votes.values()
-                .filter(|vote| vote.proposal_id == proposal_id)
-                .cloned()
-                .collect()
-        } else {
-            Vec::new()
-        }
-    }
+// Vote keys are "<proposal_id>:<principal>"
+fn proposal_has_votes(votes: &BTreeMap<String, Vote>, proposal_id: &str) -> bool {
+    let prefix = format!("{}:", proposal_id);
+    votes.range(prefix.clone()..).next().is_some_and(|(key, _)| key.starts_with(&prefix))
 }
 
+// Admin rescue for a proposal about to expire; each proposal can be extended once
 #[update]
-async fn execute_proposal(proposal_id: String, executor: String) -> Result<(), String> {
+fn extend_voting(proposal_id: String, extra_hours: u64, reason: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
     let now = ic_cdk::api::time();
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("An extension reason is required".to_string());
+    }
     
     unsafe {
-        if let Some(ref mut proposals) = PROPOSALS {
-            if let Some(proposal) = proposals.get_mut(&proposal_id) {
-                if proposal.status != ProposalStatus::Passed {
-                    return Err("Proposal has not passed".to_string());
-                }
-                
-                // Simulate execution
-                let execution_data = ProposalExecution {
-                    executed_at: now,
-                    executor: executor.clone(),
-                    execution_hash: format!("exec_{}", Uuid::new_v4().to_string()),
-                    success: true,
-                    error_message: None,
-                };
-                
-                proposal.status = ProposalStatus::Executed;
-                proposal.execution_data = Some(execution_data);
-                
-                return Ok(());
-            }
+        if !caller_is_admin(&caller) {
+            return Err("Only admins can extend voting".to_string());
         }
+        let admin_id = MEMBER_PRINCIPALS.as_ref()
+            .and_then(|index| index.get(&caller))
+            .cloned()
+            .unwrap_or_else(|| caller.to_text());
+        let proposal = PROPOSALS.as_mut()
+            .and_then(|proposals| proposals.get_mut(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        check_manual_extension(proposal, extra_hours, MAX_EXTENSION_HOURS, now)?;
+        extend_deadline(proposal, extra_hours, admin_id, reason, now)
     }
-    
-    Err("Proposal not found".to_string())
 }
 
+// None returns the DAO to hand-assigned voting power for proposals opened from now on
 #[update]
-async fn add_member(
-    id: String,
-    name: String,
-    voting_power: u32,
-    role: MemberRole,
-) -> Result<(), String> {
-    let now = ic_cdk::api::time();
+fn set_governance_token(token: Option<GovernanceToken>) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) && !unsafe { caller_is_admin(&caller) } {
+        return Err("Only controllers and admins can set the governance token".to_string());
+    }
+    if token.as_ref().is_some_and(|token| token.units_per_vote == 0) {
+        return Err("units_per_vote must be greater than zero".to_string());
+    }
     
-    let member = DAOMember {
-        id: id.clone(),
-        name,
-        voting_power,
-        joined_at: now,
-        total_votes_cast: 0,
-        reputation_score: 1.0,
-        role,
+    unsafe {
+        GOVERNANCE_TOKEN = token;
+    }
+    Ok(())
+}
+
+#[query]
+fn get_governance_token() -> Option<GovernanceToken> {
+    unsafe { GOVERNANCE_TOKEN.clone() }
+}
+
+#[query]
+fn get_snapshot_progress(proposal_id: String) -> Result<SnapshotProgress, String> {
+    unsafe {
+        let proposal = PROPOSALS.as_ref()
+            .and_then(|proposals| proposals.get(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        let snapshot = proposal.power_snapshot.as_ref().ok_or("Proposal has no token snapshot".to_string())?;
+        Ok(snapshot_progress(&proposal_id, snapshot))
+    }
+}
+
+fn snapshot_progress(proposal_id: &str, snapshot: &PowerSnapshot) -> SnapshotProgress {
+    SnapshotProgress {
+        proposal_id: proposal_id.to_string(),
+        total_members: (snapshot.powers.len() + snapshot.pending.len()) as u32,
+        completed_members: snapshot.powers.len() as u32,
+        failed_members: snapshot.failed.len() as u32,
+        complete: snapshot.completed_at.is_some(),
+    }
+}
+
+// Members who join after the proposal opened are not in the snapshot and vote with no power
+fn start_power_snapshot(members: &BTreeMap<String, DAOMember>, token: &GovernanceToken, now: u64) -> PowerSnapshot {
+    let mut snapshot = PowerSnapshot {
+        ledger: token.ledger,
+        units_per_vote: token.units_per_vote,
+        started_at: now,
+        completed_at: None,
+        powers: BTreeMap::new(),
+        pending: members.values()
+            .filter(|member| member.removed_at.is_none())
+            .map(|member| member.id.clone())
+            .collect(),
+        failed: Vec::new(),
+    };
+    if snapshot.pending.is_empty() {
+        snapshot.completed_at = Some(now);
+    }
+    snapshot
+}
+
+fn schedule_snapshot_batch() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(advance_power_snapshots()));
+}
+
+// Reads the next SNAPSHOT_BATCH balances of the first incomplete snapshot and schedules
+// another batch while members remain
+async fn advance_power_snapshots() {
+    let batch = unsafe {
+        if SNAPSHOT_RUNNING {
+            return;
+        }
+        match (&PROPOSALS, &MEMBERS) {
+            (Some(ref proposals), Some(ref members)) => next_snapshot_batch(proposals, members),
+            _ => None,
+        }
+    };
+    let Some((proposal_id, ledger, batch)) = batch else {
+        return;
     };
     
     unsafe {
-        if let Some(ref mut members) = MEMBERS {
-            members.insert(id, member);
+        SNAPSHOT_RUNNING = true;
+    }
+    let mut balances = Vec::new();
+    for (member_id, principal) in batch {
+        let balance = match principal {
+            Some(owner) => {
+                let response: Result<(Nat,), _> =
+                    call(ledger, "icrc1_balance_of", (Account { owner, subaccount: None },)).await;
+                response.map(|(balance,)| balance)
+                    .map_err(|(code, msg)| format!("icrc1_balance_of call failed: {:?} {}", code, msg))
+            }
+            None => Err("Member has no principal".to_string()),
+        };
+        balances.push((member_id, balance));
+    }
+    
+    let now = ic_cdk::api::time();
+    let more = unsafe {
+        SNAPSHOT_RUNNING = false;
+        let snapshot = PROPOSALS.as_mut()
+            .and_then(|proposals| proposals.get_mut(&proposal_id))
+            .and_then(|proposal| proposal.power_snapshot.as_mut());
+        if let Some(snapshot) = snapshot {
+            for (member_id, balance) in balances {
+                record_snapshot_balance(snapshot, &member_id, balance, now);
+            }
         }
-        
-        if let Some(ref mut metrics) = DAO_METRICS {
-            metrics.total_members += 1;
+        PROPOSALS.as_ref()
+            .is_some_and(|proposals| proposals.values().any(snapshot_pending))
+    };
+    if more {
+        schedule_snapshot_batch();
+    }
+}
+
+// Finished or cancelled proposals no longer need their snapshot, so reads stop there
+fn snapshot_pending(proposal: &Proposal) -> bool {
+    matches!(proposal.status, ProposalStatus::Draft | ProposalStatus::Active)
+        && proposal.power_snapshot.as_ref().is_some_and(|snapshot| snapshot.completed_at.is_none())
+}
+
+// Proposal id, ledger, and the members to read with the principal each holds
+type SnapshotBatch = (String, Principal, Vec<(String, Option<Principal>)>);
+
+fn next_snapshot_batch(
+    proposals: &BTreeMap<String, Proposal>,
+    members: &BTreeMap<String, DAOMember>,
+) -> Option<SnapshotBatch> {
+    let proposal = proposals.values().find(|proposal| snapshot_pending(proposal))?;
+    let snapshot = proposal.power_snapshot.as_ref()?;
+    let batch = snapshot.pending.iter()
+        .take(SNAPSHOT_BATCH)
+        .map(|member_id| (member_id.clone(), members.get(member_id).and_then(|member| member.principal)))
+        .collect();
+    Some((proposal.id.clone(), snapshot.ledger, batch))
+}
+
+// A failed balance read counts as zero power rather than holding voting closed
+fn record_snapshot_balance(snapshot: &mut PowerSnapshot, member_id: &str, balance: Result<Nat, String>, now: u64) {
+    let Some(position) = snapshot.pending.iter().position(|pending| pending == member_id) else {
+        return;
+    };
+    snapshot.pending.remove(position);
+    let power = match balance {
+        Ok(balance) => votes_from_balance(&balance, snapshot.units_per_vote),
+        Err(_) => {
+            snapshot.failed.push(member_id.to_string());
+            0
         }
+    };
+    snapshot.powers.insert(member_id.to_string(), power);
+    if snapshot.pending.is_empty() {
+        snapshot.completed_at = Some(now);
+    }
+}
+
+fn votes_from_balance(balance: &Nat, units_per_vote: u64) -> u32 {
+    let votes = balance.0.clone() / units_per_vote.max(1);
+    u32::try_from(&votes).unwrap_or(u32::MAX)
+}
+
+// Members with their voting power replaced by the snapshot's, for counting delegated power
+fn with_snapshot_power(members: &BTreeMap<String, DAOMember>, snapshot: &PowerSnapshot) -> BTreeMap<String, DAOMember> {
+    members.iter()
+        .map(|(member_id, member)| {
+            let voting_power = snapshot.powers.get(member_id).copied().unwrap_or(0);
+            (member_id.clone(), DAOMember { voting_power, ..member.clone() })
+        })
+        .collect()
+}
+
+// Quorum on a token-weighted proposal is measured against the power in its snapshot
+fn proposal_total_power(proposal: &Proposal, total_power: u64) -> u64 {
+    match proposal.power_snapshot {
+        Some(ref snapshot) => snapshot.powers.values().map(|power| *power as u64).sum(),
+        None => total_power,
+    }
+}
+
+#[update]
+fn set_max_extension_hours(hours: u64) -> Result<(), String> {
+    common::ensure_controller("change the maximum voting extension")?;
+    if hours == 0 {
+        return Err("Maximum extension must be at least one hour".to_string());
     }
     
+    unsafe {
+        MAX_EXTENSION_HOURS = hours;
+    }
     Ok(())
 }
 
-#[query]
-fn get_member(member_id: String) -> Result<DAOMember, String> {
-    unsafe {
-        if let Some(ref members) = MEMBERS {
-            members.get(&member_id).cloned().ok_or("Member not found".to_string())
-        } else {
-            Err("Members not initialized".to_string())
+// None turns automatic extension off
+#[update]
+fn set_auto_extension(rule: Option<AutoExtensionRule>) -> Result<(), String> {
+    common::ensure_controller("change the automatic voting extension")?;
+    if let Some(ref rule) = rule {
+        if rule.within_percent == 0 || rule.within_percent > 100 {
+            return Err("within_percent must be between 1 and 100".to_string());
+        }
+        if rule.extra_hours == 0 || rule.extra_hours > unsafe { MAX_EXTENSION_HOURS } {
+            return Err("Automatic extension must be between one hour and the maximum extension".to_string());
         }
     }
+    
+    unsafe {
+        AUTO_EXTENSION = rule;
+    }
+    Ok(())
 }
 
 #[query]
-fn get_all_members() -> Vec<DAOMember> {
+fn get_extension_settings() -> ExtensionSettings {
     unsafe {
-        if let Some(ref members) = MEMBERS {
-            members.values().cloned().collect()
-        } else {
-            Vec::new()
+        ExtensionSettings {
+            max_extension_hours: MAX_EXTENSION_HOURS,
+            auto_extension: AUTO_EXTENSION.clone(),
         }
     }
 }
 
-#[query]
-fn get_dao_metrics() -> DAOMetrics {
-    unsafe {
-        DAO_METRICS.clone().unwrap_or(DAOMetrics {
-            total_proposals: 0,
-            active_proposals: 0,
-            passed_proposals: 0,
-            total_members: 0,
-            total_votes_cast: 0,
-            average_participation: 0.0,
-        })
+fn check_manual_extension(proposal: &Proposal, extra_hours: u64, max_hours: u64, now: u64) -> Result<(), String> {
+    if extra_hours == 0 || extra_hours > max_hours {
+        return Err(format!("Extension must be between 1 and {} hours", max_hours));
+    }
+    if proposal.status == ProposalStatus::Active && now > proposal.voting_end {
+        return Err("Voting has already closed".to_string());
+    }
+    Ok(())
+}
+
+// Moves the deadline extra_hours past the later of the current deadline and now, so an
+// extension applied after the deadline still leaves the full extra time to vote
+fn extend_deadline(proposal: &mut Proposal, extra_hours: u64, extended_by: String, reason: String, now: u64) -> Result<(), String> {
+    if proposal.status != ProposalStatus::Active {
+        return Err("Only active proposals can be extended".to_string());
+    }
+    if proposal.extended_by.is_some() {
+        return Err("Voting on this proposal has already been extended".to_string());
     }
+    
+    proposal.original_voting_end = Some(proposal.voting_end);
+    proposal.voting_end = proposal.voting_end.max(now).saturating_add(extra_hours.saturating_mul(NANOS_PER_HOUR));
+    proposal.extended_by = Some(extended_by);
+    proposal.extension_reason = Some(reason);
+    Ok(())
 }
 
-async fn check_proposal_deadlines() {
+#[update]
+async fn cast_vote(
+    proposal_id: String,
+    vote_type: VoteType,
+    reason: Option<String>,
+    voting_power_override: Option<u32>,
+) -> Result<String, String> {
+    record_ballot(proposal_id, vote_type, None, reason, voting_power_override)
+}
+
+#[update]
+async fn cast_option_vote(proposal_id: String, option_id: String) -> Result<String, String> {
+    record_ballot(proposal_id, VoteType::Yes, Some(option_id), None, None)
+}
+
+fn record_ballot(
+    proposal_id: String,
+    vote_type: VoteType,
+    option_id: Option<String>,
+    reason: Option<String>,
+    voting_power_override: Option<u32>,
+) -> Result<String, String> {
     let now = ic_cdk::api::time();
+    let caller = ic_cdk::caller();
+    let voter = unsafe {
+        let index = MEMBER_PRINCIPALS.as_ref().ok_or("Members not initialized".to_string())?;
+        voter_member_id(index, &caller)?
+    };
+    
+    // Check if proposal is active
+    unsafe {
+        if let Some(ref proposals) = PROPOSALS {
+            if let Some(proposal) = proposals.get(&proposal_id) {
+                if proposal.status != ProposalStatus::Active {
+                    return Err("Proposal is not active for voting".to_string());
+                }
+                if now < proposal.voting_start || now > proposal.voting_end {
+                    return Err("Voting period is not active".to_string());
+                }
+                if proposal.power_snapshot.as_ref().is_some_and(|snapshot| snapshot.completed_at.is_none()) {
+                    return Err("Voting opens once the token balance snapshot completes".to_string());
+                }
+                check_ballot(proposal, option_id.as_deref())?;
+            } else {
+                return Err("Proposal not found".to_string());
+            }
+        }
+    }
     
+    // Check if voter has already voted; votes are keyed by the caller, never by a supplied name
+    let vote_key = format!("{}:{}", proposal_id, caller.to_text());
     unsafe {
-        if let Some(ref mut proposals) = PROPOSALS {
-            for proposal in proposals.values_mut() {
-                if proposal.status == ProposalStatus::Active && now > proposal.voting_end {
-                    // Voting period ended, determine result
-                    if proposal.total_votes >= proposal.quorum_required {
-                        if proposal.yes_votes > proposal.no_votes {
-                            proposal.status = ProposalStatus::Passed;
-                            if let Some(ref mut metrics) = DAO_METRICS {
-                                metrics.passed_proposals += 1;
-                            }
-                        } else {
-                            proposal.status = ProposalStatus::Rejected;
+        if let Some(ref votes) = VOTES {
+            if votes.contains_key(&vote_key) {
+                return Err("Voter has already cast a vote".to_string());
+            }
+        }
+    }
+    
+    // Voting power comes from the caller's membership, never from the request, plus what
+    // has been delegated to them and not yet counted on this proposal
+    let mut vote = unsafe {
+        let snapshot = PROPOSALS.as_ref()
+            .and_then(|proposals| proposals.get(&proposal_id))
+            .and_then(|proposal| proposal.power_snapshot.as_ref());
+        match (&mut MEMBERS, &DELEGATIONS, &COUNTED_MEMBERS) {
+            (Some(ref mut members), Some(ref delegations), Some(ref counted_members)) => {
+                let none_counted = BTreeSet::new();
+                let counted = counted_members.get(&proposal_id).unwrap_or(&none_counted);
+                check_can_vote_directly(delegations, counted, &voter)?;
+                let mut vote = record_member_vote(members, &proposal_id, &voter, vote_type, reason, voting_power_override, now)?;
+                match snapshot {
+                    Some(snapshot) => {
+                        if voting_power_override.is_none() {
+                            vote.voting_power = snapshot.powers.get(&voter).copied().unwrap_or(0);
                         }
-                    } else {
-                        proposal.status = ProposalStatus::Expired;
-                    }
-                    
-                    if let Some(ref mut metrics) = DAO_METRICS {
-                        metrics.active_proposals = metrics.active_proposals.saturating_sub(1);
+                        add_delegated_power(&mut vote, &with_snapshot_power(members, snapshot), delegations, counted);
                     }
+                    None => add_delegated_power(&mut vote, members, delegations, counted),
                 }
+                vote.option_id = option_id;
+                vote
             }
+            _ => return Err("Members not initialized".to_string()),
+        }
+    };
+    let receipt = issue_receipt(&mut vote, &caller);
+    
+    unsafe {
+        // Update proposal vote counts
+        if let Some(ref mut proposals) = PROPOSALS {
+            if let Some(proposal) = proposals.get_mut(&proposal_id) {
+                tally_vote(proposal, &vote);
+            }
+        }
+        
+        // Store vote
+        if let Some(ref mut receipts) = VOTE_RECEIPTS {
+            replace_receipt(receipts, None, &receipt, &proposal_id);
+        }
+        if let Some(ref mut index) = MEMBER_VOTES {
+            index.entry(vote.voter.clone()).or_default().insert(vote_key.clone());
+        }
+        if let Some(ref mut counted_members) = COUNTED_MEMBERS {
+            count_vote(counted_members, &vote);
+        }
+        if let Some(ref mut votes) = VOTES {
+            votes.insert(vote_key, vote);
+        }
+        
+        // Update metrics
+        if let Some(ref mut metrics) = DAO_METRICS {
+            metrics.total_votes_cast += 1;
         }
     }
+    certify_vote_receipts();
+    
+    Ok(receipt)
 }
 
-// Candid interface
-candid::export_service!();
+// Multiple-choice proposals take option votes only, binary proposals Yes/No/Abstain only
+fn check_ballot(proposal: &Proposal, option_id: Option<&str>) -> Result<(), String> {
+    match (&proposal.options, option_id) {
+        (Some(options), Some(option_id)) => {
+            if options.iter().any(|option| option.id == option_id) {
+                Ok(())
+            } else {
+                Err(format!("Unknown option {}", option_id))
+            }
+        }
+        (Some(_), None) => Err("This proposal has options; vote with cast_option_vote".to_string()),
+        (None, Some(_)) => Err("This proposal has no options; vote with cast_vote".to_string()),
+        (None, None) => Ok(()),
+    }
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn tally_vote(proposal: &mut Proposal, vote: &Vote) {
+    match vote.option_id {
+        Some(ref option_id) => {
+            let total = proposal.option_votes.get_or_insert_with(BTreeMap::new)
+                .entry(option_id.clone())
+                .or_insert(0);
+            *total = total.saturating_add(vote.voting_power);
+        }
+        None => match vote.vote_type {
+            VoteType::Yes => proposal.yes_votes = proposal.yes_votes.saturating_add(vote.voting_power),
+            VoteType::No => proposal.no_votes = proposal.no_votes.saturating_add(vote.voting_power),
+            VoteType::Abstain => proposal.abstain_votes = proposal.abstain_votes.saturating_add(vote.voting_power),
+        },
+    }
+    proposal.total_votes = proposal.total_votes.saturating_add(vote.voting_power);
+}
+
+#[update]
+async fn change_vote(
+    proposal_id: String,
+    new_vote_type: VoteType,
+    reason: Option<String>,
+) -> Result<String, String> {
+    let now = ic_cdk::api::time();
+    let caller = ic_cdk::caller();
+    let vote_key = format!("{}:{}", proposal_id, caller.to_text());
     
-    #[test]
-    fn test_proposal_creation() {
-        // Test proposal creation logic
-        let proposal_id = "test_proposal_123".to_string();
-        assert!(proposal_id.contains("test"));
+    let receipt = unsafe {
+        let proposal = PROPOSALS.as_mut()
+            .and_then(|proposals| proposals.get_mut(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        let vote = VOTES.as_mut()
+            .and_then(|votes| votes.get_mut(&vote_key))
+            .ok_or("No vote to change on this proposal".to_string())?;
+        apply_vote_change(proposal, vote, new_vote_type, reason, now)?;
+        
+        // The receipt for the previous choice no longer verifies
+        let previous = vote.receipt_hash.clone();
+        let receipt = issue_receipt(vote, &caller);
+        if let Some(ref mut receipts) = VOTE_RECEIPTS {
+            replace_receipt(receipts, previous.as_deref(), &receipt, &proposal_id);
+        }
+        receipt
+    };
+    certify_vote_receipts();
+    Ok(receipt)
+}
+
+// Withdraws the caller's vote while voting is open; delegated power counted with it is
+// released so the delegators' power can be cast again
+#[update]
+fn retract_vote(proposal_id: String) -> Result<(), String> {
+    let now = ic_cdk::api::time();
+    let caller = ic_cdk::caller();
+    let vote_key = format!("{}:{}", proposal_id, caller.to_text());
+    
+    unsafe {
+        let proposal = PROPOSALS.as_mut()
+            .and_then(|proposals| proposals.get_mut(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        let vote = VOTES.as_ref()
+            .and_then(|votes| votes.get(&vote_key))
+            .ok_or("No vote to retract on this proposal".to_string())?;
+        withdraw_vote(proposal, vote, now)?;
+        let vote = VOTES.as_mut()
+            .and_then(|votes| votes.remove(&vote_key))
+            .ok_or("No vote to retract on this proposal".to_string())?;
+        
+        if let Some(ref mut receipts) = VOTE_RECEIPTS {
+            if let Some(ref receipt) = vote.receipt_hash {
+                receipts.remove(receipt);
+            }
+        }
+        if let Some(ref mut index) = MEMBER_VOTES {
+            if let Some(keys) = index.get_mut(&vote.voter) {
+                keys.remove(&vote_key);
+                if keys.is_empty() {
+                    index.remove(&vote.voter);
+                }
+            }
+        }
+        if let Some(ref mut counted_members) = COUNTED_MEMBERS {
+            uncount_vote(counted_members, &vote);
+        }
+        if let Some(member) = MEMBERS.as_mut().and_then(|members| members.get_mut(&vote.voter)) {
+            member.total_votes_cast = member.total_votes_cast.saturating_sub(1);
+        }
+        if let Some(ref mut metrics) = DAO_METRICS {
+            metrics.total_votes_cast = metrics.total_votes_cast.saturating_sub(1);
+        }
     }
-} 
\ No newline at end of file
+    certify_vote_receipts();
+    Ok(())
+}
+
+// Confirms a vote with this receipt is recorded on the proposal without saying whose it is.
+// To check the proof off-chain:
+// 1. check `certificate` against the IC root key and read /canister/<id>/certified_data;
+// 2. check the leaf labeled with the receipt hash holds SHA-256(proposal_id);
+// 3. recompute the root hash of `witness` and compare it with certified_data.
+#[query]
+fn verify_vote_receipt(proposal_id: String, receipt_hash: String) -> Result<VoteReceiptProof, String> {
+    let witness = unsafe {
+        let receipts = VOTE_RECEIPTS.as_ref().ok_or("Votes not initialized".to_string())?;
+        receipt_witness(receipts, &proposal_id, &receipt_hash)?
+    };
+    Ok(VoteReceiptProof {
+        proposal_id,
+        receipt_hash,
+        certificate: ic_cdk::api::data_certificate(),
+        witness,
+    })
+}
+
+// SHA-256 over proposal_id, the voter's principal bytes, the ballot ("Yes", "No", "Abstain"
+// or "option:<id>") and the timestamp as 8 big-endian bytes, concatenated; hex-encoded
+fn vote_receipt(vote: &Vote, voter: &Principal) -> String {
+    let ballot = match (&vote.option_id, &vote.vote_type) {
+        (Some(option_id), _) => format!("option:{}", option_id),
+        (None, VoteType::Yes) => "Yes".to_string(),
+        (None, VoteType::No) => "No".to_string(),
+        (None, VoteType::Abstain) => "Abstain".to_string(),
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(vote.proposal_id.as_bytes());
+    hasher.update(voter.as_slice());
+    hasher.update(ballot.as_bytes());
+    hasher.update(vote.timestamp.to_be_bytes());
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn issue_receipt(vote: &mut Vote, voter: &Principal) -> String {
+    let receipt = vote_receipt(vote, voter);
+    vote.receipt_hash = Some(receipt.clone());
+    receipt
+}
+
+// Leaves hold only the proposal, so a proof never identifies the voter
+fn receipt_leaf(proposal_id: &str) -> [u8; 32] {
+    Sha256::digest(proposal_id.as_bytes()).into()
+}
+
+fn replace_receipt(receipts: &mut BTreeMap<String, [u8; 32]>, previous: Option<&str>, receipt: &str, proposal_id: &str) {
+    if let Some(previous) = previous {
+        receipts.remove(previous);
+    }
+    receipts.insert(receipt.to_string(), receipt_leaf(proposal_id));
+}
+
+fn build_vote_receipts(votes: &BTreeMap<String, Vote>) -> BTreeMap<String, [u8; 32]> {
+    votes.values()
+        .filter_map(|vote| vote.receipt_hash.as_ref().map(|receipt| (receipt.clone(), receipt_leaf(&vote.proposal_id))))
+        .collect()
+}
+
+fn receipt_witness(receipts: &BTreeMap<String, [u8; 32]>, proposal_id: &str, receipt_hash: &str) -> Result<HashTree, String> {
+    if receipts.get(receipt_hash) != Some(&receipt_leaf(proposal_id)) {
+        return Err("No vote with this receipt on the proposal".to_string());
+    }
+    common::certified_witness(RECEIPTS_LABEL, receipts, receipt_hash)
+        .ok_or("No vote with this receipt on the proposal".to_string())
+}
+
+fn certify_vote_receipts() {
+    let root = unsafe { VOTE_RECEIPTS.as_ref().map(|receipts| common::certified_root(RECEIPTS_LABEL, receipts)) };
+    if let Some(root) = root {
+        ic_cdk::api::set_certified_data(&root);
+    }
+}
+
+#[query]
+fn get_proposal(proposal_id: String) -> Result<Proposal, String> {
+    unsafe {
+        if let Some(ref proposals) = PROPOSALS {
+            proposals.get(&proposal_id).cloned().ok_or("Proposal not found".to_string())
+        } else {
+            Err("Proposals not initialized".to_string())
+        }
+    }
+}
+
+#[query]
+fn get_proposal_tally(proposal_id: String) -> Result<Tally, String> {
+    unsafe {
+        let total_power = MEMBERS.as_ref().map(total_voting_power).unwrap_or(0);
+        let proposal = PROPOSALS.as_ref()
+            .and_then(|proposals| proposals.get(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        Ok(proposal_tally(proposal, proposal_total_power(proposal, total_power)))
+    }
+}
+
+fn proposal_tally(proposal: &Proposal, total_power: u64) -> Tally {
+    let provisional_outcome = match proposal.status {
+        ProposalStatus::Draft | ProposalStatus::Active => voting_outcome(proposal, total_power),
+        ref status => status.clone(),
+    };
+    Tally {
+        proposal_id: proposal.id.clone(),
+        status: proposal.status.clone(),
+        yes_votes: proposal.yes_votes,
+        no_votes: proposal.no_votes,
+        abstain_votes: proposal.abstain_votes,
+        total_votes: proposal.total_votes,
+        quorum_power_needed: quorum_power_needed(proposal, total_power),
+        quorum_met: quorum_met(proposal, total_power),
+        provisional_outcome,
+        leading_option: proposal.winning_option.clone().or_else(|| winning_option(proposal)),
+        voting_end: proposal.voting_end,
+    }
+}
+
+// The snapshot recorded when voting closed, or a provisional one computed from the votes so far
+#[query]
+fn get_tally_snapshot(proposal_id: String) -> Result<TallySnapshot, String> {
+    unsafe {
+        let proposal = PROPOSALS.as_ref()
+            .and_then(|proposals| proposals.get(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        if let Some(ref snapshot) = proposal.tally_snapshot {
+            return Ok(snapshot.clone());
+        }
+        match (&MEMBERS, &VOTES) {
+            (Some(ref members), Some(ref votes)) => {
+                let cast = votes.values().filter(|vote| vote.proposal_id == proposal_id);
+                Ok(tally_snapshot(proposal, cast, members, proposal_total_power(proposal, total_voting_power(members))))
+            }
+            _ => Err("Members not initialized".to_string()),
+        }
+    }
+}
+
+// Provisional until the caller records it; votes without a recorded role fall back to the
+// voter's current role
+fn tally_snapshot<'a>(
+    proposal: &Proposal,
+    votes: impl Iterator<Item = &'a Vote>,
+    members: &BTreeMap<String, DAOMember>,
+    total_power: u64,
+) -> TallySnapshot {
+    let mut by_role: BTreeMap<String, RoleTally> = BTreeMap::new();
+    for vote in votes {
+        let role = vote.voter_role.clone()
+            .or_else(|| members.get(&vote.voter).map(|member| member.role.clone()));
+        let key = role.as_ref().map(role_name).unwrap_or("Unknown");
+        let tally = by_role.entry(key.to_string()).or_default();
+        tally.voters += 1;
+        match vote.option_id {
+            Some(ref option_id) => {
+                let total = tally.option_votes.entry(option_id.clone()).or_insert(0);
+                *total = total.saturating_add(vote.voting_power);
+            }
+            None => match vote.vote_type {
+                VoteType::Yes => tally.yes_votes = tally.yes_votes.saturating_add(vote.voting_power),
+                VoteType::No => tally.no_votes = tally.no_votes.saturating_add(vote.voting_power),
+                VoteType::Abstain => tally.abstain_votes = tally.abstain_votes.saturating_add(vote.voting_power),
+            },
+        }
+    }
+    TallySnapshot {
+        by_role,
+        turnout_pct: participation(proposal.total_votes, total_power) * 100.0,
+        quorum_met: quorum_met(proposal, total_power),
+        closed_at: None,
+        provisional: true,
+    }
+}
+
+fn role_name(role: &MemberRole) -> &'static str {
+    match role {
+        MemberRole::Citizen => "Citizen",
+        MemberRole::PolicyMaker => "PolicyMaker",
+        MemberRole::Auditor => "Auditor",
+        MemberRole::Contractor => "Contractor",
+        MemberRole::Admin => "Admin",
+    }
+}
+
+#[query]
+fn get_option_tally(proposal_id: String) -> Result<Vec<OptionTally>, String> {
+    unsafe {
+        let proposal = PROPOSALS.as_ref()
+            .and_then(|proposals| proposals.get(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        option_tally(proposal)
+    }
+}
+
+// In the order the options were listed, including options nobody voted for
+fn option_tally(proposal: &Proposal) -> Result<Vec<OptionTally>, String> {
+    let options = proposal.options.as_ref().ok_or("Proposal has no options".to_string())?;
+    Ok(options.iter()
+        .map(|option| OptionTally {
+            option_id: option.id.clone(),
+            label: option.label.clone(),
+            votes: proposal.option_votes.as_ref()
+                .and_then(|votes| votes.get(&option.id))
+                .copied()
+                .unwrap_or(0),
+        })
+        .collect())
+}
+
+#[query]
+fn get_my_vote(proposal_id: String) -> Option<Vote> {
+    let vote_key = format!("{}:{}", proposal_id, ic_cdk::caller().to_text());
+    unsafe { VOTES.as_ref().and_then(|votes| votes.get(&vote_key)).cloned() }
+}
+
+#[query]
+fn get_all_proposals() -> Vec<Proposal> {
+    unsafe {
+        if let Some(ref proposals) = PROPOSALS {
+            proposals.values().cloned().collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[query]
+fn get_active_proposals() -> Vec<Proposal> {
+    let now = ic_cdk::api::time();
+    unsafe {
+        if let Some(ref proposals) = PROPOSALS {
+            proposals.values()
+                .filter(|proposal| is_open_for_voting(proposal, now))
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn is_open_for_voting(proposal: &Proposal, now: u64) -> bool {
+    proposal.status == ProposalStatus::Active &&
+    now >= proposal.voting_start &&
+    now <= proposal.voting_end
+}
+
+#[query]
+fn list_proposals(status: Option<ProposalStatus>, sort: ProposalSort, offset: u64, limit: u32) -> ProposalPage {
+    let now = ic_cdk::api::time();
+    unsafe {
+        match PROPOSALS {
+            Some(ref proposals) => proposal_page(proposals.values(), status.as_ref(), sort, now, offset, limit),
+            None => ProposalPage { proposals: Vec::new(), total_count: 0 },
+        }
+    }
+}
+
+// Active means open for voting right now, matching get_active_proposals; ties fall back to id
+fn proposal_page<'a>(
+    proposals: impl Iterator<Item = &'a Proposal>,
+    status: Option<&ProposalStatus>,
+    sort: ProposalSort,
+    now: u64,
+    offset: u64,
+    limit: u32,
+) -> ProposalPage {
+    let mut matching: Vec<&Proposal> = proposals
+        .filter(|proposal| match status {
+            None => true,
+            Some(ProposalStatus::Active) => is_open_for_voting(proposal, now),
+            Some(status) => proposal.status == *status,
+        })
+        .collect();
+    
+    matching.sort_by(|a, b| {
+        let order = match sort {
+            ProposalSort::CreatedDesc => b.created_at.cmp(&a.created_at),
+            ProposalSort::VotingEndAsc => a.voting_end.cmp(&b.voting_end),
+            ProposalSort::TotalVotesDesc => b.total_votes.cmp(&a.total_votes),
+        };
+        order.then_with(|| a.id.cmp(&b.id))
+    });
+    
+    ProposalPage {
+        total_count: matching.len() as u64,
+        proposals: matching.into_iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_PROPOSALS_PAGE) as usize)
+            .cloned()
+            .collect(),
+    }
+}
+
+#[query]
+fn get_proposal_votes(proposal_id: String) -> Vec<Vote> {
+    unsafe {
+        if let Some(ref votes) = VOTES {
+            votes.values()
+                .filter(|vote| vote.proposal_id == proposal_id)
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[update]
+fn add_comment(proposal_id: String, text: String) -> Result<String, String> {
+    let comment_id = Uuid::new_v4().to_string();
+    let author = ic_cdk::caller().to_text();
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        match (&PROPOSALS, &mut COMMENTS) {
+            (Some(ref proposals), Some(ref mut comments)) => {
+                append_comment(proposals, comments, comment_id, proposal_id, author, text, now)
+            }
+            _ => Err("DAO state not initialized".to_string()),
+        }
+    }
+}
+
+// Oldest first
+#[query]
+fn get_comments(proposal_id: String, offset: u64, limit: u32) -> Vec<Comment> {
+    unsafe {
+        COMMENTS.as_ref()
+            .map(|comments| comment_page(comments, &proposal_id, offset, limit))
+            .unwrap_or_default()
+    }
+}
+
+fn append_comment(
+    proposals: &BTreeMap<String, Proposal>,
+    comments: &mut BTreeMap<String, Vec<Comment>>,
+    comment_id: String,
+    proposal_id: String,
+    author: String,
+    text: String,
+    now: u64,
+) -> Result<String, String> {
+    if !proposals.contains_key(&proposal_id) {
+        return Err("Proposal not found".to_string());
+    }
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err("Comment cannot be empty".to_string());
+    }
+    if text.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(format!("Comment exceeds {} characters", MAX_COMMENT_LENGTH));
+    }
+    
+    comments.entry(proposal_id.clone()).or_default().push(Comment {
+        id: comment_id.clone(),
+        proposal_id,
+        author,
+        text,
+        timestamp: now,
+    });
+    Ok(comment_id)
+}
+
+fn comment_page(comments: &BTreeMap<String, Vec<Comment>>, proposal_id: &str, offset: u64, limit: u32) -> Vec<Comment> {
+    comments.get(proposal_id)
+        .map(|thread| {
+            thread.iter()
+                .skip(offset as usize)
+                .take(limit.min(MAX_COMMENTS_PAGE) as usize)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[update]
+async fn execute_proposal(proposal_id: String, executor: String) -> Result<(), String> {
+    let target = unsafe {
+        let proposal = PROPOSALS.as_ref()
+            .and_then(|proposals| proposals.get(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        if proposal.status != ProposalStatus::Passed {
+            return Err("Proposal has not passed".to_string());
+        }
+        check_timelock(proposal, ic_cdk::api::time())?;
+        if let Some(ref mut executing) = EXECUTING {
+            if !executing.insert(proposal_id.clone()) {
+                return Err("Proposal execution already in progress".to_string());
+            }
+        }
+        proposal.execution_target.clone()
+    };
+    
+    let outcome = match target {
+        Some(action) => dispatch_action(action).await,
+        None => Ok(format!("exec_{}", Uuid::new_v4())),
+    };
+    
+    let now = ic_cdk::api::time();
+    unsafe {
+        if let Some(ref mut executing) = EXECUTING {
+            executing.remove(&proposal_id);
+        }
+        match PROPOSALS.as_mut().and_then(|proposals| proposals.get_mut(&proposal_id)) {
+            Some(proposal) => record_execution(proposal, executor, outcome, now),
+            None => Err("Proposal not found".to_string()),
+        }
+    }
+}
+
+// Any active member may object once while a passed proposal is timelocked
+#[update]
+fn object_to_execution(proposal_id: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        let members = MEMBERS.as_ref().ok_or("Members not initialized".to_string())?;
+        let member_id = MEMBER_PRINCIPALS.as_ref()
+            .ok_or("Members not initialized".to_string())
+            .and_then(|index| voter_member_id(index, &caller))?;
+        let active_members = members.values().filter(|member| member.removed_at.is_none()).count() as u32;
+        let proposal = PROPOSALS.as_mut()
+            .and_then(|proposals| proposals.get_mut(&proposal_id))
+            .ok_or("Proposal not found".to_string())?;
+        let rejected = record_objection(proposal, member_id, active_members, TIMELOCK.objection_threshold_percent, now)?;
+        if let (true, Some(ref mut metrics)) = (rejected, &mut DAO_METRICS) {
+            metrics.passed_proposals = metrics.passed_proposals.saturating_sub(1);
+        }
+    }
+    Ok(())
+}
+
+#[update]
+fn set_timelock_config(config: TimelockConfig) -> Result<(), String> {
+    common::ensure_controller("change the execution timelock")?;
+    if config.objection_threshold_percent == 0 || config.objection_threshold_percent > 100 {
+        return Err("objection_threshold_percent must be between 1 and 100".to_string());
+    }
+    
+    unsafe {
+        TIMELOCK = config;
+    }
+    Ok(())
+}
+
+#[query]
+fn get_timelock_config() -> TimelockConfig {
+    unsafe { TIMELOCK.clone() }
+}
+
+// The timelock runs from the moment voting closed
+fn start_timelock(proposal: &mut Proposal, timelock_hours: u64, closed_at: u64) {
+    proposal.executable_after = Some(closed_at.saturating_add(timelock_hours.saturating_mul(NANOS_PER_HOUR)));
+}
+
+fn check_timelock(proposal: &Proposal, now: u64) -> Result<(), String> {
+    match proposal.executable_after {
+        Some(executable_after) if now < executable_after => {
+            let remaining = executable_after - now;
+            Err(format!(
+                "Proposal is timelocked for another {} seconds",
+                remaining.div_ceil(NANOS_PER_SECOND)
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Returns whether this objection reached the threshold and rejected the proposal
+fn record_objection(
+    proposal: &mut Proposal,
+    member_id: String,
+    active_members: u32,
+    threshold_percent: u8,
+    now: u64,
+) -> Result<bool, String> {
+    if proposal.status != ProposalStatus::Passed {
+        return Err("Only passed proposals can be objected to".to_string());
+    }
+    match proposal.executable_after {
+        Some(executable_after) if now < executable_after => {}
+        _ => return Err("The objection window for this proposal has closed".to_string()),
+    }
+    let objections = proposal.objections.get_or_insert_with(Vec::new);
+    if objections.contains(&member_id) {
+        return Err("Member has already objected".to_string());
+    }
+    objections.push(member_id);
+    
+    let rejected = objections.len() as u64 * 100 >= threshold_percent as u64 * active_members as u64;
+    if rejected {
+        proposal.status = ProposalStatus::Rejected;
+    }
+    Ok(rejected)
+}
+
+// Performs the proposal's action on smart_policy; Ok carries the reference stored as execution_hash
+async fn dispatch_action(action: ProposalAction) -> Result<String, String> {
+    let canister_id = unsafe { SMART_POLICY_CANISTER_ID }
+        .ok_or("Smart policy canister not configured".to_string())?;
+    
+    match action {
+        ProposalAction::PausePolicy(policy_id) => {
+            let result: Result<(Result<(), String>,), _> = call(canister_id, "pause_policy", (policy_id.clone(),)).await;
+            flatten_call_result(result).map(|_| format!("pause_{}", policy_id))
+        }
+        ProposalAction::ActivatePolicy(policy_id) => {
+            let result: Result<(Result<(), String>,), _> = call(canister_id, "activate_policy", (policy_id.clone(),)).await;
+            flatten_call_result(result).map(|_| format!("activate_{}", policy_id))
+        }
+        ProposalAction::ReleaseFunds { policy_id, amount, to } => {
+            let result: Result<(Result<String, String>,), _> = call(canister_id, "release_funds", (policy_id, amount, to)).await;
+            flatten_call_result(result)
+        }
+    }
+}
+
+fn flatten_call_result<T>(result: Result<(Result<T, String>,), (ic_cdk::api::call::RejectionCode, String)>) -> Result<T, String> {
+    match result {
+        Ok((reply,)) => reply,
+        Err((code, message)) => Err(format!("Smart policy call rejected ({:?}): {}", code, message)),
+    }
+}
+
+// Failed executions are recorded but leave the proposal Passed so it can be retried
+fn record_execution(proposal: &mut Proposal, executor: String, outcome: Result<String, String>, now: u64) -> Result<(), String> {
+    let (execution_hash, error_message) = match outcome {
+        Ok(reference) => (reference, None),
+        Err(error) => (String::new(), Some(error)),
+    };
+    let success = error_message.is_none();
+    proposal.execution_data = Some(ProposalExecution {
+        executed_at: now,
+        executor,
+        execution_hash,
+        success,
+        error_message: error_message.clone(),
+    });
+    
+    match error_message {
+        None => {
+            proposal.status = ProposalStatus::Executed;
+            Ok(())
+        }
+        Some(error) => Err(error),
+    }
+}
+
+#[update]
+fn set_smart_policy_canister_id(canister_id: Principal) -> Result<(), String> {
+    common::ensure_controller("set the smart_policy canister id")?;
+    
+    unsafe {
+        SMART_POLICY_CANISTER_ID = Some(canister_id);
+    }
+    Ok(())
+}
+
+#[query]
+fn get_smart_policy_canister_id() -> Option<Principal> {
+    unsafe { SMART_POLICY_CANISTER_ID }
+}
+
+// cast_vote finds the member through `principal`, so votes follow the caller's identity.
+// Controllers add the first admins; after that admins add members directly or approve
+// membership applications.
+#[update]
+async fn add_member(
+    id: String,
+    name: String,
+    voting_power: u32,
+    role: MemberRole,
+    principal: Principal,
+) -> Result<(), String> {
+    let now = ic_cdk::api::time();
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) && !unsafe { caller_is_admin(&caller) } {
+        return Err("Only controllers and admins can add members".to_string());
+    }
+    
+    let member = DAOMember {
+        id: id.clone(),
+        name,
+        voting_power,
+        joined_at: now,
+        total_votes_cast: 0,
+        reputation_score: 1.0,
+        role,
+        principal: Some(principal),
+        removed_at: None,
+        removal_reason: None,
+    };
+    
+    unsafe {
+        if let (Some(ref mut members), Some(ref mut index)) = (&mut MEMBERS, &mut MEMBER_PRINCIPALS) {
+            let is_new = !members.contains_key(&id);
+            register_member(members, index, member, principal)?;
+            
+            if let (true, Some(ref mut metrics)) = (is_new, &mut DAO_METRICS) {
+                metrics.total_members += 1;
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+#[update]
+fn apply_for_membership(name: String, requested_role: MemberRole) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    let application_id = Uuid::new_v4().to_string();
+    
+    unsafe {
+        match (&mut APPLICATIONS, &MEMBER_PRINCIPALS) {
+            (Some(ref mut applications), Some(ref index)) => {
+                submit_application(applications, index, application_id.clone(), caller, name, requested_role, ic_cdk::api::time())?;
+            }
+            _ => return Err("Members not initialized".to_string()),
+        }
+    }
+    Ok(application_id)
+}
+
+// Creates the member, bound to the applicant's principal; returns the new member id
+#[update]
+fn approve_application(application_id: String, granted_role: MemberRole, voting_power: u32) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    
+    unsafe {
+        if !caller_is_admin(&caller) {
+            return Err("Only admins can review applications".to_string());
+        }
+        let reviewer = reviewer_id(&caller);
+        let member_id = match (&mut APPLICATIONS, &mut MEMBERS, &mut MEMBER_PRINCIPALS) {
+            (Some(ref mut applications), Some(ref mut members), Some(ref mut index)) => approve_pending_application(
+                applications,
+                members,
+                index,
+                &application_id,
+                granted_role,
+                voting_power,
+                reviewer,
+                ic_cdk::api::time(),
+            )?,
+            _ => return Err("Members not initialized".to_string()),
+        };
+        if let Some(ref mut metrics) = DAO_METRICS {
+            metrics.total_members += 1;
+        }
+        Ok(member_id)
+    }
+}
+
+#[update]
+fn reject_application(application_id: String, reason: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    unsafe {
+        if !caller_is_admin(&caller) {
+            return Err("Only admins can review applications".to_string());
+        }
+        let reviewer = reviewer_id(&caller);
+        let applications = APPLICATIONS.as_mut().ok_or("Members not initialized".to_string())?;
+        reject_pending_application(applications, &application_id, reason, reviewer, ic_cdk::api::time())
+    }
+}
+
+// Pending applications, oldest first
+#[query]
+fn list_pending_applications(offset: u64, limit: u32) -> ApplicationPage {
+    unsafe {
+        match APPLICATIONS {
+            Some(ref applications) => pending_application_page(applications, offset, limit),
+            None => ApplicationPage { applications: Vec::new(), total_count: 0 },
+        }
+    }
+}
+
+#[query]
+fn get_application(application_id: String) -> Result<MembershipApplication, String> {
+    unsafe {
+        APPLICATIONS.as_ref()
+            .and_then(|applications| applications.get(&application_id))
+            .cloned()
+            .ok_or("Application not found".to_string())
+    }
+}
+
+unsafe fn reviewer_id(caller: &Principal) -> String {
+    MEMBER_PRINCIPALS.as_ref()
+        .and_then(|index| index.get(caller))
+        .cloned()
+        .unwrap_or_else(|| caller.to_text())
+}
+
+#[update]
+fn update_member(member_id: String, changes: MemberUpdate) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    unsafe {
+        if !caller_is_admin(&caller) {
+            return Err("Only admins can update members".to_string());
+        }
+        let members = MEMBERS.as_mut().ok_or("Members not initialized".to_string())?;
+        apply_member_update(members, &member_id, changes)
+    }
+}
+
+#[update]
+fn remove_member(member_id: String, reason: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        if !caller_is_admin(&caller) {
+            return Err("Only admins can remove members".to_string());
+        }
+        match (&mut MEMBERS, &mut MEMBER_PRINCIPALS, &mut DELEGATIONS) {
+            (Some(ref mut members), Some(ref mut index), Some(ref mut delegations)) => {
+                deactivate_member(members, index, delegations, &member_id, reason, now)?;
+                if let Some(ref mut metrics) = DAO_METRICS {
+                    metrics.total_members = metrics.total_members.saturating_sub(1);
+                }
+                Ok(())
+            }
+            _ => Err("Members not initialized".to_string()),
+        }
+    }
+}
+
+fn apply_member_update(
+    members: &mut BTreeMap<String, DAOMember>,
+    member_id: &str,
+    changes: MemberUpdate,
+) -> Result<(), String> {
+    if changes.role.is_none() && changes.voting_power.is_none() {
+        return Err("No changes provided".to_string());
+    }
+    let member = members.get(member_id).ok_or("Member not found".to_string())?;
+    if member.removed_at.is_some() {
+        return Err(format!("Member {} has been removed", member_id));
+    }
+    if changes.role.as_ref().is_some_and(|role| !matches!(role, MemberRole::Admin)) {
+        check_not_last_admin(members, member_id)?;
+    }
+    
+    let member = members.get_mut(member_id).ok_or("Member not found".to_string())?;
+    if let Some(role) = changes.role {
+        member.role = role;
+    }
+    if let Some(voting_power) = changes.voting_power {
+        member.voting_power = voting_power;
+    }
+    Ok(())
+}
+
+// Marks the member removed, stops their principal from acting and unwinds their delegations
+fn deactivate_member(
+    members: &mut BTreeMap<String, DAOMember>,
+    index: &mut BTreeMap<Principal, String>,
+    delegations: &mut BTreeMap<String, String>,
+    member_id: &str,
+    reason: String,
+    now: u64,
+) -> Result<(), String> {
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("A removal reason is required".to_string());
+    }
+    let member = members.get(member_id).ok_or("Member not found".to_string())?;
+    if member.removed_at.is_some() {
+        return Err(format!("Member {} has already been removed", member_id));
+    }
+    check_not_last_admin(members, member_id)?;
+    
+    let member = members.get_mut(member_id).ok_or("Member not found".to_string())?;
+    member.removed_at = Some(now);
+    member.removal_reason = Some(reason);
+    if let Some(principal) = member.principal {
+        index.remove(&principal);
+    }
+    delegations.retain(|delegator, delegate| delegator != member_id && delegate != member_id);
+    Ok(())
+}
+
+// The DAO would have nobody left to manage members otherwise
+fn check_not_last_admin(members: &BTreeMap<String, DAOMember>, member_id: &str) -> Result<(), String> {
+    let is_admin = |member: &DAOMember| matches!(member.role, MemberRole::Admin) && member.removed_at.is_none();
+    let target_is_admin = members.get(member_id).is_some_and(is_admin);
+    let other_admins = members.values().filter(|member| member.id != member_id && is_admin(member)).count();
+    if target_is_admin && other_admins == 0 {
+        return Err("Cannot remove or demote the last admin".to_string());
+    }
+    Ok(())
+}
+
+#[query]
+fn get_member(member_id: String) -> Result<DAOMember, String> {
+    unsafe {
+        if let Some(ref members) = MEMBERS {
+            members.get(&member_id).cloned().ok_or("Member not found".to_string())
+        } else {
+            Err("Members not initialized".to_string())
+        }
+    }
+}
+
+#[query]
+fn get_all_members() -> Vec<DAOMember> {
+    unsafe {
+        if let Some(ref members) = MEMBERS {
+            members.values().cloned().collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[query]
+fn get_dao_metrics() -> DAOMetrics {
+    unsafe {
+        DAO_METRICS.clone().unwrap_or(DAOMetrics {
+            total_proposals: 0,
+            active_proposals: 0,
+            passed_proposals: 0,
+            total_members: 0,
+            total_votes_cast: 0,
+            average_participation: 0.0,
+        })
+    }
+}
+
+fn record_member_vote(
+    members: &mut BTreeMap<String, DAOMember>,
+    proposal_id: &str,
+    voter: &str,
+    vote_type: VoteType,
+    reason: Option<String>,
+    power_override: Option<u32>,
+    now: u64,
+) -> Result<Vote, String> {
+    let member = members.get_mut(voter).ok_or("Only DAO members can vote".to_string())?;
+    if member.removed_at.is_some() {
+        return Err("Removed members cannot vote".to_string());
+    }
+    
+    // Admins may weigh in with a different power; the override stays visible in the reason
+    let (voting_power, reason) = match power_override {
+        Some(power) if matches!(member.role, MemberRole::Admin) => {
+            if power > MAX_VOTING_POWER_OVERRIDE {
+                return Err(format!("Voting power override cannot exceed {}", MAX_VOTING_POWER_OVERRIDE));
+            }
+            let note = format!("[voting power overridden by admin: {} instead of {}]", power, member.voting_power);
+            (power, Some(reason.map_or(note.clone(), |reason| format!("{} {}", note, reason))))
+        }
+        Some(_) => return Err("Only admins can override voting power".to_string()),
+        None => (member.voting_power, reason),
+    };
+    member.total_votes_cast += 1;
+    
+    Ok(Vote {
+        proposal_id: proposal_id.to_string(),
+        voter: voter.to_string(),
+        vote_type,
+        voting_power,
+        timestamp: now,
+        reason,
+        voter_principal: member.principal,
+        delegated_from: None,
+        option_id: None,
+        receipt_hash: None,
+        voter_role: Some(member.role.clone()),
+    })
+}
+
+#[update]
+fn delegate_voting_power(to_member_id: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    unsafe {
+        match (&MEMBER_PRINCIPALS, &MEMBERS, &mut DELEGATIONS) {
+            (Some(ref index), Some(ref members), Some(ref mut delegations)) => {
+                let delegator = voter_member_id(index, &caller)?;
+                check_delegation(members, delegations, &delegator, &to_member_id)?;
+                delegations.insert(delegator, to_member_id);
+                Ok(())
+            }
+            _ => Err("Members not initialized".to_string()),
+        }
+    }
+}
+
+// Votes already cast with the delegated power keep it; see Vote::delegated_from
+#[update]
+fn revoke_delegation() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    unsafe {
+        match (&MEMBER_PRINCIPALS, &mut DELEGATIONS) {
+            (Some(ref index), Some(ref mut delegations)) => {
+                let delegator = voter_member_id(index, &caller)?;
+                delegations.remove(&delegator).map(|_| ()).ok_or("No active delegation".to_string())
+            }
+            _ => Err("Members not initialized".to_string()),
+        }
+    }
+}
+
+#[query]
+fn get_effective_voting_power(member_id: String) -> Result<u32, String> {
+    unsafe {
+        match (&MEMBERS, &DELEGATIONS) {
+            (Some(ref members), Some(ref delegations)) => effective_voting_power(members, delegations, &member_id),
+            _ => Err("Members not initialized".to_string()),
+        }
+    }
+}
+
+// One hop at most: a delegate cannot delegate, and a delegator cannot receive delegations
+fn check_delegation(
+    members: &BTreeMap<String, DAOMember>,
+    delegations: &BTreeMap<String, String>,
+    delegator: &str,
+    delegate: &str,
+) -> Result<(), String> {
+    if delegator == delegate {
+        return Err("Members cannot delegate to themselves".to_string());
+    }
+    if members.get(delegate).is_none_or(|member| member.removed_at.is_some()) {
+        return Err("Delegate is not a DAO member".to_string());
+    }
+    if delegations.contains_key(delegate) {
+        return Err("Delegate has delegated their own voting power".to_string());
+    }
+    if delegations.values().any(|target| target == delegator) {
+        return Err("Members holding delegated power cannot delegate it further".to_string());
+    }
+    Ok(())
+}
+
+// Own power plus everything delegated to the member; zero while the member has delegated away
+fn effective_voting_power(
+    members: &BTreeMap<String, DAOMember>,
+    delegations: &BTreeMap<String, String>,
+    member_id: &str,
+) -> Result<u32, String> {
+    let member = members.get(member_id).ok_or("Member not found".to_string())?;
+    if delegations.contains_key(member_id) {
+        return Ok(0);
+    }
+    let (delegated, _) = delegated_power(members, delegations, member_id, &BTreeSet::new());
+    Ok(member.voting_power.saturating_add(delegated))
+}
+
+fn delegated_power(
+    members: &BTreeMap<String, DAOMember>,
+    delegations: &BTreeMap<String, String>,
+    delegate: &str,
+    already_counted: &BTreeSet<String>,
+) -> (u32, Vec<String>) {
+    let mut power = 0u32;
+    let mut delegators = Vec::new();
+    for (delegator, _) in delegations.iter().filter(|(delegator, target)| *target == delegate && !already_counted.contains(*delegator)) {
+        if let Some(member) = members.get(delegator) {
+            power = power.saturating_add(member.voting_power);
+            delegators.push(delegator.clone());
+        }
+    }
+    (power, delegators)
+}
+
+// Marks the voter and everyone they cast delegated power for as counted on the vote's proposal
+fn count_vote(counted_members: &mut BTreeMap<String, BTreeSet<String>>, vote: &Vote) {
+    let counted = counted_members.entry(vote.proposal_id.clone()).or_default();
+    counted.insert(vote.voter.clone());
+    counted.extend(vote.delegated_from.iter().flatten().cloned());
+}
+
+fn uncount_vote(counted_members: &mut BTreeMap<String, BTreeSet<String>>, vote: &Vote) {
+    if let Some(counted) = counted_members.get_mut(&vote.proposal_id) {
+        counted.remove(&vote.voter);
+        for delegator in vote.delegated_from.iter().flatten() {
+            counted.remove(delegator);
+        }
+        if counted.is_empty() {
+            counted_members.remove(&vote.proposal_id);
+        }
+    }
+}
+
+// Voters plus the delegators whose power their votes carried
+fn participants<'a>(votes: impl Iterator<Item = &'a Vote>) -> BTreeSet<String> {
+    let mut participants = BTreeSet::new();
+    for vote in votes {
+        participants.insert(vote.voter.clone());
+        participants.extend(vote.delegated_from.iter().flatten().cloned());
+    }
+    participants
+}
+
+fn build_counted_members(votes: &BTreeMap<String, Vote>) -> BTreeMap<String, BTreeSet<String>> {
+    let mut counted_members = BTreeMap::new();
+    for vote in votes.values() {
+        count_vote(&mut counted_members, vote);
+    }
+    counted_members
+}
+
+fn check_can_vote_directly(delegations: &BTreeMap<String, String>, counted: &BTreeSet<String>, voter: &str) -> Result<(), String> {
+    if let Some(delegate) = delegations.get(voter) {
+        return Err(format!("Voting power is delegated to {}; revoke the delegation to vote directly", delegate));
+    }
+    if counted.contains(voter) {
+        return Err("Voting power was already counted on this proposal through a delegate".to_string());
+    }
+    Ok(())
+}
+
+// Snapshot of the delegations active at cast time
+fn add_delegated_power(
+    vote: &mut Vote,
+    members: &BTreeMap<String, DAOMember>,
+    delegations: &BTreeMap<String, String>,
+    already_counted: &BTreeSet<String>,
+) {
+    let (power, delegators) = delegated_power(members, delegations, &vote.voter, already_counted);
+    if !delegators.is_empty() {
+        vote.voting_power = vote.voting_power.saturating_add(power);
+        vote.delegated_from = Some(delegators);
+    }
+}
+
+fn voter_member_id(index: &BTreeMap<Principal, String>, caller: &Principal) -> Result<String, String> {
+    if *caller == Principal::anonymous() {
+        return Err("Anonymous callers cannot vote".to_string());
+    }
+    index.get(caller).cloned().ok_or("Only DAO members can vote".to_string())
+}
+
+fn build_principal_index(members: &BTreeMap<String, DAOMember>) -> BTreeMap<Principal, String> {
+    members.values()
+        .filter(|member| member.removed_at.is_none())
+        .filter_map(|member| member.principal.map(|principal| (principal, member.id.clone())))
+        .collect()
+}
+
+// Adds or replaces a member; a principal can belong to only one member
+fn register_member(
+    members: &mut BTreeMap<String, DAOMember>,
+    index: &mut BTreeMap<Principal, String>,
+    member: DAOMember,
+    principal: Principal,
+) -> Result<(), String> {
+    if principal == Principal::anonymous() {
+        return Err("Members cannot use the anonymous principal".to_string());
+    }
+    if members.get(&member.id).is_some_and(|existing| existing.removed_at.is_some()) {
+        return Err(format!("Member {} has been removed", member.id));
+    }
+    if let Some(existing) = index.get(&principal).filter(|existing| **existing != member.id) {
+        return Err(format!("Principal already belongs to member {}", existing));
+    }
+    
+    if let Some(previous) = members.get(&member.id).and_then(|previous| previous.principal) {
+        index.remove(&previous);
+    }
+    index.insert(principal, member.id.clone());
+    members.insert(member.id.clone(), DAOMember { principal: Some(principal), ..member });
+    Ok(())
+}
+
+fn submit_application(
+    applications: &mut BTreeMap<String, MembershipApplication>,
+    index: &BTreeMap<Principal, String>,
+    application_id: String,
+    applicant: Principal,
+    name: String,
+    requested_role: MemberRole,
+    now: u64,
+) -> Result<(), String> {
+    let name = name.trim().to_string();
+    if applicant == Principal::anonymous() {
+        return Err("Sign in to apply for membership".to_string());
+    }
+    if name.is_empty() || name.len() > MAX_MEMBER_NAME_LENGTH {
+        return Err(format!("Name must be between 1 and {} characters", MAX_MEMBER_NAME_LENGTH));
+    }
+    if index.contains_key(&applicant) {
+        return Err("Caller is already a DAO member".to_string());
+    }
+    if applications.values().any(|application| {
+        application.applicant == applicant && matches!(application.status, ApplicationStatus::Pending)
+    }) {
+        return Err("Caller already has a pending application".to_string());
+    }
+    
+    applications.insert(application_id.clone(), MembershipApplication {
+        id: application_id,
+        applicant,
+        name,
+        requested_role,
+        status: ApplicationStatus::Pending,
+        submitted_at: now,
+        reviewed_by: None,
+        reviewed_at: None,
+        granted_role: None,
+        voting_power: None,
+        member_id: None,
+        rejection_reason: None,
+    });
+    Ok(())
+}
+
+fn pending_application<'a>(
+    applications: &'a mut BTreeMap<String, MembershipApplication>,
+    application_id: &str,
+) -> Result<&'a mut MembershipApplication, String> {
+    let application = applications.get_mut(application_id).ok_or("Application not found".to_string())?;
+    if !matches!(application.status, ApplicationStatus::Pending) {
+        return Err("Application has already been reviewed".to_string());
+    }
+    Ok(application)
+}
+
+// The member id is the applicant's principal text, as for members added before ids were chosen
+#[allow(clippy::too_many_arguments)]
+fn approve_pending_application(
+    applications: &mut BTreeMap<String, MembershipApplication>,
+    members: &mut BTreeMap<String, DAOMember>,
+    index: &mut BTreeMap<Principal, String>,
+    application_id: &str,
+    granted_role: MemberRole,
+    voting_power: u32,
+    reviewer: String,
+    now: u64,
+) -> Result<String, String> {
+    if voting_power == 0 {
+        return Err("Voting power must be at least 1".to_string());
+    }
+    let application = pending_application(applications, application_id)?;
+    let member_id = application.applicant.to_text();
+    if members.contains_key(&member_id) {
+        return Err(format!("Member {} already exists", member_id));
+    }
+    
+    let member = DAOMember {
+        id: member_id.clone(),
+        name: application.name.clone(),
+        voting_power,
+        joined_at: now,
+        total_votes_cast: 0,
+        reputation_score: 1.0,
+        role: granted_role.clone(),
+        principal: Some(application.applicant),
+        removed_at: None,
+        removal_reason: None,
+    };
+    register_member(members, index, member, application.applicant)?;
+    
+    application.status = ApplicationStatus::Approved;
+    application.reviewed_by = Some(reviewer);
+    application.reviewed_at = Some(now);
+    application.granted_role = Some(granted_role);
+    application.voting_power = Some(voting_power);
+    application.member_id = Some(member_id.clone());
+    Ok(member_id)
+}
+
+fn reject_pending_application(
+    applications: &mut BTreeMap<String, MembershipApplication>,
+    application_id: &str,
+    reason: String,
+    reviewer: String,
+    now: u64,
+) -> Result<(), String> {
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("A rejection reason is required".to_string());
+    }
+    let application = pending_application(applications, application_id)?;
+    application.status = ApplicationStatus::Rejected;
+    application.reviewed_by = Some(reviewer);
+    application.reviewed_at = Some(now);
+    application.rejection_reason = Some(reason);
+    Ok(())
+}
+
+fn pending_application_page(
+    applications: &BTreeMap<String, MembershipApplication>,
+    offset: u64,
+    limit: u32,
+) -> ApplicationPage {
+    let mut pending: Vec<&MembershipApplication> = applications.values()
+        .filter(|application| matches!(application.status, ApplicationStatus::Pending))
+        .collect();
+    pending.sort_by(|a, b| a.submitted_at.cmp(&b.submitted_at).then_with(|| a.id.cmp(&b.id)));
+    
+    ApplicationPage {
+        total_count: pending.len() as u64,
+        applications: pending.into_iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_APPLICATIONS_PAGE) as usize)
+            .cloned()
+            .collect(),
+    }
+}
+
+// Moves the vote's recorded power from its old tally to the new one
+fn apply_vote_change(
+    proposal: &mut Proposal,
+    vote: &mut Vote,
+    new_vote_type: VoteType,
+    reason: Option<String>,
+    now: u64,
+) -> Result<(), String> {
+    check_vote_editable(proposal, vote, now)?;
+    if vote.option_id.is_some() {
+        return Err("Votes on multiple-choice proposals cannot be changed".to_string());
+    }
+    
+    match vote.vote_type {
+        VoteType::Yes => proposal.yes_votes = proposal.yes_votes.saturating_sub(vote.voting_power),
+        VoteType::No => proposal.no_votes = proposal.no_votes.saturating_sub(vote.voting_power),
+        VoteType::Abstain => proposal.abstain_votes = proposal.abstain_votes.saturating_sub(vote.voting_power),
+    }
+    match new_vote_type {
+        VoteType::Yes => proposal.yes_votes = proposal.yes_votes.saturating_add(vote.voting_power),
+        VoteType::No => proposal.no_votes = proposal.no_votes.saturating_add(vote.voting_power),
+        VoteType::Abstain => proposal.abstain_votes = proposal.abstain_votes.saturating_add(vote.voting_power),
+    }
+    
+    vote.vote_type = new_vote_type;
+    vote.reason = reason;
+    vote.timestamp = now;
+    Ok(())
+}
+
+fn check_vote_editable(proposal: &Proposal, vote: &Vote, now: u64) -> Result<(), String> {
+    if vote.voter_principal.is_none() {
+        return Err("Votes recorded under a free-text voter id are read-only".to_string());
+    }
+    if proposal.status != ProposalStatus::Active {
+        return Err("Proposal is not active for voting".to_string());
+    }
+    if now < proposal.voting_start || now > proposal.voting_end {
+        return Err("Voting period is not active".to_string());
+    }
+    Ok(())
+}
+
+// Takes the vote's recorded power back out of the proposal's tallies
+fn withdraw_vote(proposal: &mut Proposal, vote: &Vote, now: u64) -> Result<(), String> {
+    check_vote_editable(proposal, vote, now)?;
+    match vote.option_id {
+        Some(ref option_id) => {
+            if let Some(total) = proposal.option_votes.as_mut().and_then(|option_votes| option_votes.get_mut(option_id)) {
+                *total = total.saturating_sub(vote.voting_power);
+            }
+        }
+        None => match vote.vote_type {
+            VoteType::Yes => proposal.yes_votes = proposal.yes_votes.saturating_sub(vote.voting_power),
+            VoteType::No => proposal.no_votes = proposal.no_votes.saturating_sub(vote.voting_power),
+            VoteType::Abstain => proposal.abstain_votes = proposal.abstain_votes.saturating_sub(vote.voting_power),
+        },
+    }
+    proposal.total_votes = proposal.total_votes.saturating_sub(vote.voting_power);
+    Ok(())
+}
+
+async fn check_proposal_deadlines() {
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        let total_power = MEMBERS.as_ref().map(total_voting_power).unwrap_or(0);
+        if let Some(ref mut proposals) = PROPOSALS {
+            let activated = activate_due_proposals(proposals, now);
+            if let Some(ref mut metrics) = DAO_METRICS {
+                metrics.active_proposals += activated.len() as u32;
+            }
+            if let (Some(ref token), Some(ref members)) = (&GOVERNANCE_TOKEN, &MEMBERS) {
+                for proposal_id in &activated {
+                    if let Some(proposal) = proposals.get_mut(proposal_id) {
+                        proposal.power_snapshot = Some(start_power_snapshot(members, token, now));
+                    }
+                }
+            }
+            
+            let mut finalized = proposals.values().filter(|proposal| is_finalized(&proposal.status)).count() as u32;
+            for proposal in proposals.values_mut() {
+                if proposal.status == ProposalStatus::Active && now > proposal.voting_end {
+                    let total_power = proposal_total_power(proposal, total_power);
+                    if let Some(ref rule) = AUTO_EXTENSION {
+                        if auto_extension_due(proposal, total_power, rule) {
+                            let reason = format!("Participation within {}% of quorum at the deadline", rule.within_percent);
+                            if extend_deadline(proposal, rule.extra_hours, AUTO_EXTENDED_BY.to_string(), reason, now).is_ok() {
+                                continue;
+                            }
+                        }
+                    }
+                    
+                    // Voting period ended, determine result
+                    let reached_quorum = quorum_met(proposal, total_power);
+                    if let Some(ref mut metrics) = DAO_METRICS {
+                        metrics.average_participation = running_average(
+                            metrics.average_participation,
+                            finalized,
+                            participation(proposal.total_votes, total_power),
+                        );
+                    }
+                    finalized += 1;
+                    if let (Some(ref votes), Some(ref mut members), Some(ref mut streaks), Some(ref mut history)) =
+                        (&VOTES, &mut MEMBERS, &mut MISSED_STREAKS, &mut REPUTATION_HISTORY)
+                    {
+                        let cast = votes.values().filter(|vote| vote.proposal_id == proposal.id);
+                        let mut snapshot = tally_snapshot(proposal, cast.clone(), members, total_power);
+                        snapshot.closed_at = Some(now);
+                        snapshot.provisional = false;
+                        proposal.tally_snapshot = Some(snapshot);
+                        
+                        let voters = participants(cast);
+                        update_reputation(members, streaks, history, proposal, &voters, reached_quorum, now);
+                    }
+                    
+                    if reached_quorum && proposal.options.is_some() {
+                        proposal.winning_option = winning_option(proposal);
+                    }
+                    proposal.status = voting_outcome(proposal, total_power);
+                    if proposal.status == ProposalStatus::Passed {
+                        start_timelock(proposal, TIMELOCK.timelock_hours, now);
+                        if let Some(ref mut metrics) = DAO_METRICS {
+                            metrics.passed_proposals += 1;
+                        }
+                    }
+                    
+                    if let Some(ref mut metrics) = DAO_METRICS {
+                        metrics.active_proposals = metrics.active_proposals.saturating_sub(1);
+                    }
+                }
+            }
+        }
+    }
+    
+    // Backstop for snapshot batches whose one-off timer was lost
+    advance_power_snapshots().await;
+}
+
+// Drafts whose voting window has opened become Active; returns the ids that changed
+fn activate_due_proposals(proposals: &mut BTreeMap<String, Proposal>, now: u64) -> Vec<String> {
+    let mut activated = Vec::new();
+    for proposal in proposals.values_mut() {
+        if proposal.status == ProposalStatus::Draft && proposal.voting_start <= now {
+            proposal.status = ProposalStatus::Active;
+            activated.push(proposal.id.clone());
+        }
+    }
+    activated
+}
+
+fn is_finalized(status: &ProposalStatus) -> bool {
+    matches!(
+        status,
+        ProposalStatus::Passed | ProposalStatus::Rejected | ProposalStatus::Executed | ProposalStatus::Expired
+    )
+}
+
+// Fraction of total voting power cast; zero while the DAO has no voting power
+fn participation(votes_cast: u32, total_power: u64) -> f64 {
+    if total_power == 0 {
+        return 0.0;
+    }
+    (votes_cast as f64 / total_power as f64).min(1.0)
+}
+
+fn running_average(average: f64, count: u32, sample: f64) -> f64 {
+    (average * count as f64 + sample) / (count as f64 + 1.0)
+}
+
+fn total_voting_power(members: &BTreeMap<String, DAOMember>) -> u64 {
+    members.values()
+        .filter(|member| member.removed_at.is_none())
+        .map(|member| member.voting_power as u64)
+        .sum()
+}
+
+// Status a proposal gets when its voting closes with the current votes
+fn voting_outcome(proposal: &Proposal, total_power: u64) -> ProposalStatus {
+    if !quorum_met(proposal, total_power) {
+        ProposalStatus::Expired
+    } else if proposal_carried(proposal) {
+        ProposalStatus::Passed
+    } else {
+        ProposalStatus::Rejected
+    }
+}
+
+// Binary proposals need more Yes than No; multiple-choice proposals need a winning option
+fn proposal_carried(proposal: &Proposal) -> bool {
+    if proposal.options.is_some() {
+        winning_option(proposal).is_some()
+    } else {
+        proposal.yes_votes > proposal.no_votes
+    }
+}
+
+// Plurality of option votes; a tie for first place, or a leader holding less than
+// min_winning_share percent of the option votes, leaves no winner
+fn winning_option(proposal: &Proposal) -> Option<String> {
+    let votes = proposal.option_votes.as_ref()?;
+    let top = votes.values().copied().max().filter(|top| *top > 0)?;
+    if votes.values().filter(|count| **count == top).count() > 1 {
+        return None;
+    }
+    let cast: u64 = votes.values().map(|count| *count as u64).sum();
+    if (top as u64) * 100 < proposal.min_winning_share.unwrap_or(0) as u64 * cast {
+        return None;
+    }
+    votes.iter().find(|(_, count)| **count == top).map(|(option_id, _)| option_id.clone())
+}
+
+fn quorum_power_needed(proposal: &Proposal, total_power: u64) -> u64 {
+    match proposal.quorum_mode.clone().unwrap_or(QuorumMode::AbsolutePower(proposal.quorum_required)) {
+        QuorumMode::AbsolutePower(power) => power as u64,
+        QuorumMode::PercentOfTotalPower(percent) => (percent as u64 * total_power).div_ceil(100),
+    }
+}
+
+// Quorum missed, but by no more than rule.within_percent of the power it needs
+fn auto_extension_due(proposal: &Proposal, total_power: u64, rule: &AutoExtensionRule) -> bool {
+    if proposal.extended_by.is_some() || quorum_met(proposal, total_power) {
+        return false;
+    }
+    let needed = quorum_power_needed(proposal, total_power);
+    needed > 0 && proposal.total_votes as u64 * 100 >= needed * (100 - rule.within_percent.min(100) as u64)
+}
+
+// A percentage quorum cannot be met while the DAO has no voting power at all
+fn quorum_met(proposal: &Proposal, total_power: u64) -> bool {
+    match proposal.quorum_mode.clone().unwrap_or(QuorumMode::AbsolutePower(proposal.quorum_required)) {
+        QuorumMode::AbsolutePower(power) => proposal.total_votes >= power,
+        QuorumMode::PercentOfTotalPower(percent) => {
+            total_power > 0 && proposal.total_votes as u64 * 100 >= percent as u64 * total_power
+        }
+    }
+}
+
+// Voters gain reputation when the proposal reached quorum and its author gains more if it
+// passed; members who were already in the DAO when voting opened and stayed away build up a
+// missed streak. Every change is recorded in the member's reputation history.
+fn update_reputation(
+    members: &mut BTreeMap<String, DAOMember>,
+    streaks: &mut BTreeMap<String, u32>,
+    history: &mut BTreeMap<String, Vec<ReputationEvent>>,
+    proposal: &Proposal,
+    voters: &BTreeSet<String>,
+    reached_quorum: bool,
+    now: u64,
+) {
+    let passed = reached_quorum && proposal_carried(proposal);
+    for member in members.values_mut().filter(|member| member.removed_at.is_none()) {
+        let mut adjustments = Vec::new();
+        if voters.contains(&member.id) {
+            streaks.remove(&member.id);
+            if reached_quorum {
+                adjustments.push((ReputationReason::VotedOnQuorumProposal, member.reputation_score + REPUTATION_VOTE_BONUS));
+            }
+        } else if member.joined_at <= proposal.voting_start {
+            let streak = streaks.entry(member.id.clone()).or_insert(0);
+            *streak += 1;
+            if *streak >= MISSED_STREAK_PENALTY_AFTER {
+                adjustments.push((ReputationReason::MissedProposals, member.reputation_score - REPUTATION_MISSED_PENALTY));
+            }
+        }
+        if passed && is_proposer(member, &proposal.proposer) {
+            let score = adjustments.last().map_or(member.reputation_score, |(_, score)| *score);
+            adjustments.push((ReputationReason::AuthoredPassedProposal, score + REPUTATION_AUTHOR_BONUS));
+        }
+        
+        for (reason, target) in adjustments {
+            let before = member.reputation_score;
+            member.reputation_score = target.clamp(MIN_REPUTATION, MAX_REPUTATION);
+            let events = history.entry(member.id.clone()).or_default();
+            events.push(ReputationEvent {
+                proposal_id: proposal.id.clone(),
+                reason,
+                delta: member.reputation_score - before,
+                score_after: member.reputation_score,
+                timestamp: now,
+            });
+            if events.len() > MAX_REPUTATION_HISTORY {
+                events.remove(0);
+            }
+        }
+        member.reputation_score = member.reputation_score.clamp(MIN_REPUTATION, MAX_REPUTATION);
+    }
+}
+
+// Proposals record the caller's principal text; older ones may hold the member id
+fn is_proposer(member: &DAOMember, proposer: &str) -> bool {
+    member.id == proposer || member.principal.is_some_and(|principal| principal.to_text() == proposer)
+}
+
+#[query]
+fn get_member_reputation(member_id: String, limit: u32) -> Result<MemberReputation, String> {
+    unsafe {
+        let members = MEMBERS.as_ref().ok_or("Members not initialized".to_string())?;
+        let history = REPUTATION_HISTORY.as_ref().ok_or("Members not initialized".to_string())?;
+        member_reputation(members, history, &member_id, limit)
+    }
+}
+
+// Most recent events first
+fn member_reputation(
+    members: &BTreeMap<String, DAOMember>,
+    history: &BTreeMap<String, Vec<ReputationEvent>>,
+    member_id: &str,
+    limit: u32,
+) -> Result<MemberReputation, String> {
+    let member = members.get(member_id).ok_or("Member not found".to_string())?;
+    let recent_events = history.get(member_id)
+        .map(|events| events.iter().rev().take(limit as usize).cloned().collect())
+        .unwrap_or_default();
+    Ok(MemberReputation {
+        member_id: member_id.to_string(),
+        reputation_score: member.reputation_score,
+        recent_events,
+    })
+}
+
+#[query]
+fn get_member_votes(member_id: String, offset: u64, limit: u32) -> Result<MemberVotes, String> {
+    unsafe {
+        match (&MEMBERS, &PROPOSALS, &VOTES, &MEMBER_VOTES) {
+            (Some(ref members), Some(ref proposals), Some(ref votes), Some(ref index)) => {
+                member_votes(members, proposals, votes, index, &member_id, offset, limit)
+            }
+            _ => Err("Members not initialized".to_string()),
+        }
+    }
+}
+
+fn build_member_vote_index(votes: &BTreeMap<String, Vote>) -> BTreeMap<String, BTreeSet<String>> {
+    let mut index: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (vote_key, vote) in votes {
+        index.entry(vote.voter.clone()).or_default().insert(vote_key.clone());
+    }
+    index
+}
+
+// Newest votes first, read through the member's index entry rather than every vote
+fn member_votes(
+    members: &BTreeMap<String, DAOMember>,
+    proposals: &BTreeMap<String, Proposal>,
+    votes: &BTreeMap<String, Vote>,
+    index: &BTreeMap<String, BTreeSet<String>>,
+    member_id: &str,
+    offset: u64,
+    limit: u32,
+) -> Result<MemberVotes, String> {
+    let member = members.get(member_id).ok_or("Member not found".to_string())?;
+    let mut cast: Vec<&Vote> = index.get(member_id)
+        .map(|keys| keys.iter().filter_map(|key| votes.get(key)).collect())
+        .unwrap_or_default();
+    cast.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.proposal_id.cmp(&b.proposal_id)));
+    
+    let voted_on: BTreeSet<&str> = cast.iter().map(|vote| vote.proposal_id.as_str()).collect();
+    let mut eligible: Vec<&Proposal> = proposals.values()
+        .filter(|proposal| is_finalized(&proposal.status) && member.joined_at <= proposal.voting_start)
+        .collect();
+    eligible.sort_by(|a, b| b.voting_end.cmp(&a.voting_end).then_with(|| a.id.cmp(&b.id)));
+    eligible.truncate(PARTICIPATION_WINDOW);
+    let recent_votes = eligible.iter().filter(|proposal| voted_on.contains(proposal.id.as_str())).count() as u32;
+    
+    let summary = MemberVotingSummary {
+        votes_cast: cast.len() as u32,
+        proposals_authored: proposals.values().filter(|proposal| is_proposer(member, &proposal.proposer)).count() as u32,
+        recent_proposals: eligible.len() as u32,
+        recent_votes,
+        participation_rate: if eligible.is_empty() { 0.0 } else { recent_votes as f64 / eligible.len() as f64 },
+    };
+    
+    Ok(MemberVotes {
+        member_id: member_id.to_string(),
+        total_count: cast.len() as u64,
+        votes: cast.into_iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_MEMBER_VOTES_PAGE) as usize)
+            .filter_map(|vote| proposals.get(&vote.proposal_id).map(|proposal| MemberVoteRecord {
+                proposal_id: proposal.id.clone(),
+                proposal_title: proposal.title.clone(),
+                proposal_status: proposal.status.clone(),
+                vote: vote.clone(),
+            }))
+            .collect(),
+        summary,
+    })
+}
+
+#[query]
+fn get_canister_stats() -> CanisterStats {
+    common::canister_stats()
+}
+
+#[query]
+fn health_check() -> HealthStatus {
+    health_status_at(ic_cdk::api::time())
+}
+
+fn health_status_at(now: u64) -> HealthStatus {
+    unsafe {
+        common::health_status(
+            PROPOSALS.is_some() && VOTES.is_some() && MEMBERS.is_some(),
+            &[
+                ("proposals", PROPOSALS.as_ref().map_or(0, BTreeMap::len)),
+                ("votes", VOTES.as_ref().map_or(0, BTreeMap::len)),
+                ("members", MEMBERS.as_ref().map_or(0, BTreeMap::len)),
+                ("delegations", DELEGATIONS.as_ref().map_or(0, BTreeMap::len)),
+                ("comments", COMMENTS.as_ref().map_or(0, |comments| comments.values().map(Vec::len).sum())),
+            ],
+            INIT_TIME_NS,
+            now,
+        )
+    }
+}
+
+// Candid interface
+candid::export_service!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_proposal_creation() {
+        // Test proposal creation logic
+        let proposal_id = "test_proposal_123".to_string();
+        assert!(proposal_id.contains("test"));
+    }
+    
+    fn test_proposal(total_votes: u32, quorum_mode: Option<QuorumMode>) -> Proposal {
+        Proposal {
+            id: "proposal_1".to_string(),
+            title: "Ward water supply".to_string(),
+            description: String::new(),
+            category: "infrastructure".to_string(),
+            proposer: "member-principal".to_string(),
+            created_at: 0,
+            voting_start: 0,
+            voting_end: 10,
+            status: ProposalStatus::Active,
+            yes_votes: total_votes,
+            no_votes: 0,
+            abstain_votes: 0,
+            total_votes,
+            quorum_required: 5,
+            execution_data: None,
+            quorum_mode,
+            execution_target: None,
+            cancellation_reason: None,
+            edited_at: None,
+            edit_history: None,
+            options: None,
+            min_winning_share: None,
+            option_votes: None,
+            winning_option: None,
+            extended_by: None,
+            extension_reason: None,
+            original_voting_end: None,
+            tally_snapshot: None,
+            power_snapshot: None,
+            executable_after: None,
+            objections: None,
+        }
+    }
+    
+    fn test_choices() -> MultipleChoice {
+        let option = |id: &str| ProposalOption {
+            id: id.to_string(),
+            label: format!("District {}", id),
+            description: String::new(),
+        };
+        MultipleChoice { options: vec![option("pune"), option("nagpur"), option("nashik")], min_winning_share: 40 }
+    }
+    
+    fn multiple_choice_proposal() -> Proposal {
+        let choices = test_choices();
+        Proposal {
+            options: Some(choices.options),
+            min_winning_share: Some(choices.min_winning_share),
+            ..test_proposal(0, Some(QuorumMode::AbsolutePower(5)))
+        }
+    }
+    
+    fn option_vote(option_id: &str, voting_power: u32) -> Vote {
+        Vote {
+            proposal_id: "proposal_1".to_string(),
+            voter: format!("voter-{}", voting_power),
+            vote_type: VoteType::Yes,
+            voting_power,
+            timestamp: 0,
+            reason: None,
+            voter_principal: Some(member_principal()),
+            delegated_from: None,
+            option_id: Some(option_id.to_string()),
+            receipt_hash: None,
+            voter_role: None,
+        }
+    }
+    
+    #[test]
+    fn test_tally_snapshot_by_role() {
+        let mut members = test_members();
+        members.insert("auditor".to_string(), DAOMember {
+            id: "auditor".to_string(),
+            role: MemberRole::Auditor,
+            voting_power: 3,
+            principal: Some(Principal::from_slice(&[4, 4])),
+            ..members["member-principal"].clone()
+        });
+        let mut proposal = test_proposal(0, Some(QuorumMode::AbsolutePower(5)));
+        let mut votes = Vec::new();
+        for (voter, vote_type) in [("member-principal", VoteType::Yes), ("auditor", VoteType::No)] {
+            let vote = record_member_vote(&mut members, "proposal_1", voter, vote_type, None, None, 1).unwrap();
+            tally_vote(&mut proposal, &vote);
+            votes.push(vote);
+        }
+        
+        // The citizen is promoted before voting closes; their vote still counts as a Citizen's
+        members.get_mut("member-principal").unwrap().role = MemberRole::PolicyMaker;
+        let snapshot = tally_snapshot(&proposal, votes.iter(), &members, 20);
+        assert!(snapshot.provisional);
+        assert!(snapshot.quorum_met);
+        assert_eq!(snapshot.turnout_pct, 50.0);
+        assert_eq!(snapshot.by_role.len(), 2);
+        assert_eq!(snapshot.by_role["Citizen"].yes_votes, 7);
+        assert_eq!(snapshot.by_role["Auditor"].no_votes, 3);
+        assert!(!snapshot.by_role.contains_key("PolicyMaker"));
+        
+        // Votes from before roles were recorded use the voter's current role
+        votes[0].voter_role = None;
+        let snapshot = tally_snapshot(&proposal, votes.iter(), &members, 20);
+        assert_eq!(snapshot.by_role["PolicyMaker"].voters, 1);
+        
+        let option = option_vote("nashik", 2);
+        let snapshot = tally_snapshot(&proposal, std::iter::once(&option), &members, 20);
+        assert_eq!(snapshot.by_role["Unknown"].option_votes["nashik"], 2);
+    }
+    
+    #[test]
+    fn test_proposal_tally() {
+        // 4 of the 10 power needed has voted so far
+        let mut proposal = test_proposal(4, Some(QuorumMode::AbsolutePower(10)));
+        proposal.yes_votes = 3;
+        proposal.no_votes = 1;
+        
+        let tally = proposal_tally(&proposal, 20);
+        assert_eq!(tally.quorum_power_needed, 10);
+        assert!(!tally.quorum_met);
+        assert!(tally.status == ProposalStatus::Active);
+        assert!(tally.provisional_outcome == ProposalStatus::Expired);
+        
+        proposal.total_votes = 12;
+        proposal.yes_votes = 5;
+        proposal.no_votes = 6;
+        proposal.abstain_votes = 1;
+        let tally = proposal_tally(&proposal, 20);
+        assert!(tally.quorum_met);
+        assert_eq!((tally.yes_votes, tally.no_votes, tally.abstain_votes), (5, 6, 1));
+        assert!(tally.provisional_outcome == ProposalStatus::Rejected);
+        
+        proposal.yes_votes = 7;
+        proposal.no_votes = 4;
+        assert!(proposal_tally(&proposal, 20).provisional_outcome == ProposalStatus::Passed);
+        
+        // Finalized proposals report the outcome they were given
+        proposal.status = ProposalStatus::Cancelled;
+        assert!(proposal_tally(&proposal, 20).provisional_outcome == ProposalStatus::Cancelled);
+    }
+    
+    #[test]
+    fn test_manual_voting_extension() {
+        let hour = NANOS_PER_HOUR;
+        let mut proposal = test_proposal(9, Some(QuorumMode::AbsolutePower(10)));
+        proposal.voting_end = 10 * hour;
+        
+        assert!(check_manual_extension(&proposal, 0, 72, hour).is_err());
+        assert!(check_manual_extension(&proposal, 73, 72, hour).is_err());
+        assert!(check_manual_extension(&proposal, 24, 72, 11 * hour).is_err());
+        assert!(check_manual_extension(&proposal, 72, 72, 10 * hour).is_ok());
+        
+        extend_deadline(&mut proposal, 24, "admin".to_string(), "Holiday week".to_string(), 9 * hour).unwrap();
+        assert_eq!(proposal.voting_end, 34 * hour);
+        assert_eq!(proposal.original_voting_end, Some(10 * hour));
+        assert_eq!(proposal.extended_by.as_deref(), Some("admin"));
+        assert_eq!(proposal.extension_reason.as_deref(), Some("Holiday week"));
+        
+        // Only once per proposal, and only while Active
+        assert!(extend_deadline(&mut proposal, 1, "admin".to_string(), "Again".to_string(), 9 * hour).is_err());
+        let mut draft = test_proposal(0, None);
+        draft.status = ProposalStatus::Draft;
+        assert!(extend_deadline(&mut draft, 1, "admin".to_string(), "Early".to_string(), 0).is_err());
+    }
+    
+    #[test]
+    fn test_auto_extension() {
+        let hour = NANOS_PER_HOUR;
+        let rule = AutoExtensionRule { within_percent: 10, extra_hours: 12 };
+        
+        // 9 of the 10 needed is within 10%; 8 is not
+        let mut proposal = test_proposal(9, Some(QuorumMode::AbsolutePower(10)));
+        assert!(auto_extension_due(&proposal, 100, &rule));
+        assert!(!auto_extension_due(&test_proposal(8, Some(QuorumMode::AbsolutePower(10))), 100, &rule));
+        assert!(!auto_extension_due(&test_proposal(10, Some(QuorumMode::AbsolutePower(10))), 100, &rule));
+        
+        // Percentage quorums round the power needed up: 33% of 20 needs 7
+        let near = test_proposal(6, Some(QuorumMode::PercentOfTotalPower(33)));
+        assert_eq!(quorum_power_needed(&near, 20), 7);
+        assert!(!auto_extension_due(&near, 20, &rule));
+        assert!(auto_extension_due(&near, 20, &AutoExtensionRule { within_percent: 20, extra_hours: 12 }));
+        
+        // Applied after the deadline, the extra hours run from now
+        proposal.voting_end = 10 * hour;
+        extend_deadline(&mut proposal, rule.extra_hours, AUTO_EXTENDED_BY.to_string(), "near quorum".to_string(), 10 * hour + 5).unwrap();
+        assert_eq!(proposal.voting_end, 22 * hour + 5);
+        assert_eq!(proposal.original_voting_end, Some(10 * hour));
+        assert!(!auto_extension_due(&proposal, 100, &rule));
+    }
+    
+    #[test]
+    fn test_vote_receipts() {
+        let voter = member_principal();
+        let mut vote = option_vote("pune", 3);
+        let receipt = issue_receipt(&mut vote, &voter);
+        assert_eq!(receipt.len(), 64);
+        assert_eq!(vote.receipt_hash.as_deref(), Some(receipt.as_str()));
+        assert_eq!(vote_receipt(&vote, &voter), receipt);
+        
+        // Every part of the ballot is bound into the receipt
+        let mut other = vote.clone();
+        other.option_id = Some("nagpur".to_string());
+        assert_ne!(vote_receipt(&other, &voter), receipt);
+        other = vote.clone();
+        other.timestamp += 1;
+        assert_ne!(vote_receipt(&other, &voter), receipt);
+        assert_ne!(vote_receipt(&vote, &Principal::from_slice(&[9, 9, 9])), receipt);
+        
+        let mut votes = BTreeMap::new();
+        votes.insert("proposal_1:voter".to_string(), vote.clone());
+        let mut unreceipted = option_vote("nashik", 1);
+        unreceipted.proposal_id = "proposal_2".to_string();
+        votes.insert("proposal_2:voter".to_string(), unreceipted);
+        let mut receipts = build_vote_receipts(&votes);
+        assert_eq!(receipts.len(), 1);
+        
+        let witness = receipt_witness(&receipts, "proposal_1", &receipt).unwrap();
+        assert_eq!(common::reconstruct(&witness), common::certified_root(RECEIPTS_LABEL, &receipts));
+        assert!(receipt_witness(&receipts, "proposal_2", &receipt).is_err());
+        
+        // A changed vote gets a new receipt and the old one stops verifying
+        vote.timestamp += 10;
+        let previous = vote.receipt_hash.clone();
+        let changed = issue_receipt(&mut vote, &voter);
+        replace_receipt(&mut receipts, previous.as_deref(), &changed, "proposal_1");
+        assert!(receipt_witness(&receipts, "proposal_1", &receipt).is_err());
+        assert!(receipt_witness(&receipts, "proposal_1", &changed).is_ok());
+    }
+    
+    #[test]
+    fn test_validate_choices() {
+        assert!(validate_choices(&test_choices(), &None).is_ok());
+        assert!(validate_choices(&test_choices(), &Some(ProposalAction::PausePolicy("p".to_string()))).is_err());
+        
+        let mut choices = test_choices();
+        choices.options.truncate(1);
+        assert!(validate_choices(&choices, &None).is_err());
+        
+        let mut choices = test_choices();
+        choices.options[2].id = "pune".to_string();
+        assert_eq!(validate_choices(&choices, &None).unwrap_err(), "Duplicate option id pune");
+        
+        let mut choices = test_choices();
+        choices.options[1].label = " ".to_string();
+        assert!(validate_choices(&choices, &None).is_err());
+        
+        let mut choices = test_choices();
+        choices.min_winning_share = 101;
+        assert!(validate_choices(&choices, &None).is_err());
+    }
+    
+    #[test]
+    fn test_option_ballots_and_tally() {
+        let mut proposal = multiple_choice_proposal();
+        assert!(check_ballot(&proposal, Some("nagpur")).is_ok());
+        assert_eq!(check_ballot(&proposal, Some("thane")).unwrap_err(), "Unknown option thane");
+        assert!(check_ballot(&proposal, None).is_err());
+        assert!(check_ballot(&test_proposal(0, None), Some("pune")).is_err());
+        assert!(check_ballot(&test_proposal(0, None), None).is_ok());
+        
+        tally_vote(&mut proposal, &option_vote("pune", 3));
+        tally_vote(&mut proposal, &option_vote("nagpur", 2));
+        tally_vote(&mut proposal, &option_vote("pune", 1));
+        assert_eq!(proposal.total_votes, 6);
+        assert_eq!(proposal.yes_votes, 0);
+        
+        let tally = option_tally(&proposal).unwrap();
+        let totals: Vec<(&str, u32)> = tally.iter().map(|entry| (entry.option_id.as_str(), entry.votes)).collect();
+        assert_eq!(totals, vec![("pune", 4), ("nagpur", 2), ("nashik", 0)]);
+        assert_eq!(tally[0].label, "District pune");
+        assert!(option_tally(&test_proposal(0, None)).is_err());
+        
+        // Option votes stay as cast
+        let mut vote = option_vote("pune", 3);
+        assert!(apply_vote_change(&mut proposal, &mut vote, VoteType::No, None, 5).is_err());
+    }
+    
+    #[test]
+    fn test_winning_option() {
+        let mut proposal = multiple_choice_proposal();
+        assert_eq!(winning_option(&proposal), None);
+        assert!(!proposal_carried(&proposal));
+        
+        tally_vote(&mut proposal, &option_vote("pune", 4));
+        tally_vote(&mut proposal, &option_vote("nagpur", 3));
+        tally_vote(&mut proposal, &option_vote("nashik", 3));
+        // 4 of 10 meets the 40% minimum share
+        assert_eq!(winning_option(&proposal), Some("pune".to_string()));
+        assert!(proposal_carried(&proposal));
+        
+        proposal.min_winning_share = Some(50);
+        assert_eq!(winning_option(&proposal), None);
+        
+        // A tie for first place has no winner
+        proposal.min_winning_share = Some(0);
+        tally_vote(&mut proposal, &option_vote("nagpur", 1));
+        assert_eq!(winning_option(&proposal), None);
+        assert!(!proposal_carried(&proposal));
+    }
+    
+    #[test]
+    fn test_average_participation() {
+        let total_power = total_voting_power(&test_members());
+        assert_eq!(participation(7, total_power), 1.0);
+        assert_eq!(participation(3, 0), 0.0);
+        
+        let first = participation(7, 14);
+        let second = participation(0, 14);
+        let average = running_average(0.0, 0, first);
+        assert!((average - 0.5).abs() < 1e-9);
+        let average = running_average(average, 1, second);
+        assert!((average - 0.25).abs() < 1e-9);
+    }
+    
+    #[test]
+    fn test_auto_activation_at_voting_start() {
+        let mut proposals = BTreeMap::new();
+        for (id, voting_start) in [("due", 100), ("later", 200)] {
+            let mut proposal = test_proposal(0, None);
+            proposal.id = id.to_string();
+            proposal.status = ProposalStatus::Draft;
+            proposal.voting_start = voting_start;
+            proposals.insert(id.to_string(), proposal);
+        }
+        
+        assert!(activate_due_proposals(&mut proposals, 99).is_empty());
+        assert_eq!(activate_due_proposals(&mut proposals, 100), vec!["due".to_string()]);
+        assert!(proposals["due"].status == ProposalStatus::Active);
+        assert!(proposals["later"].status == ProposalStatus::Draft);
+        
+        // Already-active proposals are not counted again
+        assert_eq!(activate_due_proposals(&mut proposals, 250), vec!["later".to_string()]);
+        assert!(proposals["later"].status == ProposalStatus::Active);
+    }
+    
+    #[test]
+    fn test_drafts_due_across_an_upgrade_activate_on_the_next_check() {
+        let interval = DEADLINE_CHECK_INTERVAL.as_nanos() as u64;
+        assert_eq!(interval, NANOS_PER_HOUR);
+        
+        // Both drafts fall due between the upgrade and the first tick of the re-registered timer
+        let upgraded_at = 1_000;
+        let mut proposals = BTreeMap::new();
+        for (id, voting_start) in [("first", upgraded_at + 1), ("second", upgraded_at + interval)] {
+            let mut proposal = test_proposal(0, None);
+            proposal.id = id.to_string();
+            proposal.status = ProposalStatus::Draft;
+            proposal.voting_start = voting_start;
+            proposals.insert(id.to_string(), proposal);
+        }
+        
+        let activated = activate_due_proposals(&mut proposals, upgraded_at + interval);
+        assert_eq!(activated, vec!["first".to_string(), "second".to_string()]);
+    }
+    
+    #[test]
+    fn test_proposal_comments() {
+        let mut proposals = BTreeMap::new();
+        proposals.insert("proposal_1".to_string(), test_proposal(0, None));
+        let mut comments = BTreeMap::new();
+        
+        assert_eq!(
+            append_comment(&proposals, &mut comments, "c0".to_string(), "missing".to_string(), "a".to_string(), "Hi".to_string(), 1),
+            Err("Proposal not found".to_string())
+        );
+        assert!(append_comment(&proposals, &mut comments, "c0".to_string(), "proposal_1".to_string(), "a".to_string(), "  ".to_string(), 1).is_err());
+        let too_long = "x".repeat(MAX_COMMENT_LENGTH + 1);
+        assert!(append_comment(&proposals, &mut comments, "c0".to_string(), "proposal_1".to_string(), "a".to_string(), too_long, 1).is_err());
+        
+        for index in 0..5 {
+            let id = append_comment(
+                &proposals,
+                &mut comments,
+                format!("c{}", index),
+                "proposal_1".to_string(),
+                "member-principal".to_string(),
+                format!("Point {}", index),
+                index,
+            ).unwrap();
+            assert_eq!(id, format!("c{}", index));
+        }
+        
+        let page = comment_page(&comments, "proposal_1", 1, 2);
+        assert_eq!(page.iter().map(|comment| comment.id.as_str()).collect::<Vec<_>>(), vec!["c1", "c2"]);
+        assert_eq!(comment_page(&comments, "proposal_1", 4, 10).len(), 1);
+        assert!(comment_page(&comments, "proposal_1", 5, 10).is_empty());
+        assert!(comment_page(&comments, "missing", 0, 10).is_empty());
+    }
+    
+    #[test]
+    fn test_cancel_proposal_guards() {
+        let mut proposal = test_proposal(0, None);
+        proposal.status = ProposalStatus::Draft;
+        proposal.voting_start = 100;
+        
+        assert_eq!(
+            check_cancellable(&proposal, "someone-else", false, false),
+            Err("Only the proposer or an admin can cancel a proposal".to_string())
+        );
+        assert!(check_cancellable(&proposal, "member-principal", false, false).is_ok());
+        assert!(check_cancellable(&proposal, "someone-else", true, false).is_ok());
+        
+        proposal.status = ProposalStatus::Active;
+        assert!(check_cancellable(&proposal, "member-principal", false, false).is_ok());
+        assert_eq!(
+            check_cancellable(&proposal, "member-principal", false, true),
+            Err("Votes have already been cast; only an admin can cancel".to_string())
+        );
+        assert!(check_cancellable(&proposal, "someone-else", true, true).is_ok());
+        
+        proposal.status = ProposalStatus::Passed;
+        assert!(check_cancellable(&proposal, "member-principal", false, false).is_err());
+        assert!(check_cancellable(&proposal, "someone-else", true, false).is_err());
+    }
+    
+    #[test]
+    fn test_draft_proposal_edits() {
+        let mut proposal = test_proposal(0, None);
+        proposal.status = ProposalStatus::Draft;
+        proposal.voting_start = 100;
+        let title_change = || ProposalUpdate {
+            title: Some("Ward water supply (revised)".to_string()),
+            description: None,
+            category: None,
+            voting_duration_hours: None,
+            quorum_mode: None,
+        };
+        
+        assert_eq!(
+            apply_proposal_update(&mut proposal, title_change(), "someone-else", false, 5).err(),
+            Some("Only the proposer or an admin can edit a proposal".to_string())
+        );
+        assert!(apply_proposal_update(&mut proposal, title_change(), "member-principal", false, 5).is_ok());
+        assert_eq!(proposal.title, "Ward water supply (revised)");
+        assert_eq!(proposal.edited_at, Some(5));
+        
+        // An invalid field rejects the whole edit
+        let bad_quorum = ProposalUpdate {
+            category: Some("health".to_string()),
+            quorum_mode: Some(QuorumMode::PercentOfTotalPower(120)),
+            ..title_change()
+        };
+        assert!(apply_proposal_update(&mut proposal, bad_quorum, "member-principal", false, 6).is_err());
+        assert_eq!(proposal.category, "infrastructure");
+        
+        let admin_edit = ProposalUpdate {
+            title: None,
+            voting_duration_hours: Some(2),
+            quorum_mode: Some(QuorumMode::AbsolutePower(9)),
+            ..title_change()
+        };
+        assert!(apply_proposal_update(&mut proposal, admin_edit, "admin-principal", true, 7).is_ok());
+        assert_eq!(proposal.voting_end, 100 + 2 * 3_600_000_000_000);
+        assert_eq!(proposal.quorum_required, 9);
+        assert_eq!(proposal.edited_at, Some(7));
+        
+        let history = proposal.edit_history.as_ref().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].fields, vec!["title".to_string()]);
+        assert_eq!(history[1].editor, "admin-principal");
+        assert_eq!(history[1].fields, vec!["voting_duration".to_string(), "quorum".to_string()]);
+        
+        let empty = ProposalUpdate { title: None, ..title_change() };
+        assert_eq!(
+            apply_proposal_update(&mut proposal, empty, "member-principal", false, 8).err(),
+            Some("No changes provided".to_string())
+        );
+        
+        proposal.status = ProposalStatus::Active;
+        assert_eq!(
+            apply_proposal_update(&mut proposal, title_change(), "admin-principal", true, 9).err(),
+            Some("Only draft proposals can be edited; this proposal has already been activated".to_string())
+        );
+    }
+    
+    #[test]
+    fn test_proposal_page_sorting() {
+        let proposals: Vec<Proposal> = [(1, 30, 4), (2, 10, 9), (3, 20, 4)]
+            .into_iter()
+            .map(|(n, voting_end, total_votes)| {
+                let mut proposal = test_proposal(total_votes, None);
+                proposal.id = format!("proposal_{}", n);
+                proposal.created_at = n;
+                proposal.voting_end = voting_end;
+                proposal
+            })
+            .collect();
+        let ids = |page: ProposalPage| page.proposals.into_iter().map(|p| p.id).collect::<Vec<_>>();
+        
+        let page = proposal_page(proposals.iter(), None, ProposalSort::CreatedDesc, 0, 0, 10);
+        assert_eq!(page.total_count, 3);
+        assert_eq!(ids(page), vec!["proposal_3", "proposal_2", "proposal_1"]);
+        assert_eq!(
+            ids(proposal_page(proposals.iter(), None, ProposalSort::VotingEndAsc, 0, 0, 10)),
+            vec!["proposal_2", "proposal_3", "proposal_1"]
+        );
+        // Equal vote totals fall back to id order
+        assert_eq!(
+            ids(proposal_page(proposals.iter(), None, ProposalSort::TotalVotesDesc, 0, 0, 10)),
+            vec!["proposal_2", "proposal_1", "proposal_3"]
+        );
+        
+        let page = proposal_page(proposals.iter(), None, ProposalSort::CreatedDesc, 0, 1, 1);
+        assert_eq!(page.total_count, 3);
+        assert_eq!(ids(page), vec!["proposal_2"]);
+        
+        // Only proposals still inside their voting window count as Active
+        let page = proposal_page(proposals.iter(), Some(&ProposalStatus::Active), ProposalSort::CreatedDesc, 15, 0, 10);
+        assert_eq!(page.total_count, 2);
+        assert_eq!(ids(page), vec!["proposal_3", "proposal_1"]);
+        assert_eq!(
+            proposal_page(proposals.iter(), Some(&ProposalStatus::Draft), ProposalSort::CreatedDesc, 0, 0, 10).total_count,
+            0
+        );
+    }
+    
+    #[test]
+    fn test_proposal_has_votes() {
+        let mut votes = BTreeMap::new();
+        let vote = Vote {
+            proposal_id: "proposal_1".to_string(),
+            voter: "member-principal".to_string(),
+            vote_type: VoteType::Yes,
+            voting_power: 1,
+            timestamp: 0,
+            reason: None,
+            voter_principal: None,
+            delegated_from: None,
+            option_id: None,
+            receipt_hash: None,
+            voter_role: None,
+        };
+        votes.insert("proposal_1:aaaaa-aa".to_string(), vote);
+        
+        assert!(proposal_has_votes(&votes, "proposal_1"));
+        assert!(!proposal_has_votes(&votes, "proposal_2"));
+        assert!(!proposal_has_votes(&votes, "proposal_"));
+    }
+    
+    #[test]
+    fn test_record_execution_outcome() {
+        let mut proposal = test_proposal(6, None);
+        proposal.status = ProposalStatus::Passed;
+        proposal.execution_target = Some(ProposalAction::ReleaseFunds {
+            policy_id: "policy_1".to_string(),
+            amount: 500,
+            to: "contractor".to_string(),
+        });
+        
+        // A rejected smart_policy call is recorded and the proposal stays executable
+        let rejected = flatten_call_result::<String>(Err((
+            ic_cdk::api::call::RejectionCode::CanisterError,
+            "out of cycles".to_string(),
+        )));
+        assert!(record_execution(&mut proposal, "executor".to_string(), rejected, 20).is_err());
+        let execution = proposal.execution_data.clone().unwrap();
+        assert!(!execution.success);
+        assert!(execution.error_message.unwrap().contains("out of cycles"));
+        assert!(proposal.status == ProposalStatus::Passed);
+        
+        let refused = flatten_call_result::<String>(Ok((Err("Policy is not active".to_string()),)));
+        assert_eq!(
+            record_execution(&mut proposal, "executor".to_string(), refused, 30),
+            Err("Policy is not active".to_string())
+        );
+        
+        let released = flatten_call_result(Ok((Ok("flow_42".to_string()),)));
+        assert!(record_execution(&mut proposal, "executor".to_string(), released, 40).is_ok());
+        let execution = proposal.execution_data.clone().unwrap();
+        assert!(execution.success);
+        assert_eq!(execution.execution_hash, "flow_42");
+        assert_eq!(execution.executed_at, 40);
+        assert!(proposal.status == ProposalStatus::Executed);
+    }
+    
+    #[test]
+    fn test_absolute_quorum() {
+        assert!(quorum_met(&test_proposal(6, Some(QuorumMode::AbsolutePower(6))), 100));
+        assert!(!quorum_met(&test_proposal(5, Some(QuorumMode::AbsolutePower(6))), 100));
+        // Proposals stored before quorum modes fall back to quorum_required
+        assert!(quorum_met(&test_proposal(5, None), 0));
+        assert!(!quorum_met(&test_proposal(4, None), 0));
+    }
+    
+    #[test]
+    fn test_percent_quorum() {
+        let mut members = test_members();
+        let mut second = members["member-principal"].clone();
+        second.id = "second-principal".to_string();
+        second.voting_power = 13;
+        members.insert(second.id.clone(), second);
+        let total_power = total_voting_power(&members);
+        assert_eq!(total_power, 20);
+        
+        assert!(quorum_met(&test_proposal(8, Some(QuorumMode::PercentOfTotalPower(40))), total_power));
+        assert!(!quorum_met(&test_proposal(7, Some(QuorumMode::PercentOfTotalPower(40))), total_power));
+        
+        // No members means no voting power to take a share of
+        let empty = BTreeMap::new();
+        assert_eq!(total_voting_power(&empty), 0);
+        assert!(!quorum_met(&test_proposal(0, Some(QuorumMode::PercentOfTotalPower(0))), 0));
+        assert!(!quorum_met(&test_proposal(3, Some(QuorumMode::PercentOfTotalPower(40))), 0));
+    }
+    
+    #[test]
+    fn test_change_vote_flips_tally() {
+        let mut members = test_members();
+        let mut proposal = test_proposal(0, Some(QuorumMode::AbsolutePower(1)));
+        proposal.yes_votes = 7;
+        proposal.total_votes = 7;
+        let mut vote = record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::Yes, None, None, 2).unwrap();
+        
+        apply_vote_change(&mut proposal, &mut vote, VoteType::No, Some("Costs were revised".to_string()), 4).unwrap();
+        assert_eq!(proposal.yes_votes, 0);
+        assert_eq!(proposal.no_votes, 7);
+        assert_eq!(proposal.total_votes, 7);
+        assert!(matches!(vote.vote_type, VoteType::No));
+        assert_eq!(vote.reason.as_deref(), Some("Costs were revised"));
+        assert_eq!(vote.timestamp, 4);
+    }
+    
+    #[test]
+    fn test_change_vote_rejected_after_deadline() {
+        let mut members = test_members();
+        let mut proposal = test_proposal(0, None);
+        proposal.yes_votes = 7;
+        let mut vote = record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::Yes, None, None, 2).unwrap();
+        
+        let after_deadline = proposal.voting_end + 1;
+        let result = apply_vote_change(&mut proposal, &mut vote, VoteType::No, None, after_deadline);
+        assert_eq!(result.err().as_deref(), Some("Voting period is not active"));
+        assert_eq!(proposal.yes_votes, 7);
+        assert!(matches!(vote.vote_type, VoteType::Yes));
+    }
+    
+    #[test]
+    fn test_retract_vote_releases_tally_and_delegations() {
+        let mut members = test_members();
+        let mut proposal = test_proposal(0, Some(QuorumMode::AbsolutePower(1)));
+        let mut vote = record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::No, None, None, 2).unwrap();
+        vote.voting_power += 3;
+        vote.delegated_from = Some(vec!["delegator".to_string()]);
+        tally_vote(&mut proposal, &vote);
+        assert_eq!(proposal.no_votes, 10);
+        let mut counted_members = BTreeMap::new();
+        count_vote(&mut counted_members, &vote);
+        
+        withdraw_vote(&mut proposal, &vote, 4).unwrap();
+        assert_eq!(proposal.no_votes, 0);
+        assert_eq!(proposal.total_votes, 0);
+        // The delegator can vote directly again once the delegate's vote is gone
+        uncount_vote(&mut counted_members, &vote);
+        assert!(counted_members.is_empty());
+        
+        let after_deadline = proposal.voting_end + 1;
+        tally_vote(&mut proposal, &vote);
+        let result = withdraw_vote(&mut proposal, &vote, after_deadline);
+        assert_eq!(result.err().as_deref(), Some("Voting period is not active"));
+        assert_eq!(proposal.no_votes, 10);
+    }
+    
+    #[test]
+    fn test_retract_option_vote() {
+        let mut proposal = test_proposal(0, None);
+        let vote = option_vote("pune", 3);
+        tally_vote(&mut proposal, &vote);
+        withdraw_vote(&mut proposal, &vote, 4).unwrap();
+        assert_eq!(proposal.option_votes.unwrap().get("pune"), Some(&0));
+        assert_eq!(proposal.total_votes, 0);
+    }
+    
+    #[test]
+    fn test_reputation_rewards_voters_and_penalizes_absentees() {
+        let mut members = test_members();
+        let mut absentee = members["member-principal"].clone();
+        absentee.id = "absent-principal".to_string();
+        members.insert(absentee.id.clone(), absentee);
+        let mut streaks = BTreeMap::new();
+        let mut history = BTreeMap::new();
+        // Authored by someone outside the DAO, so no author bonus here
+        let mut proposal = test_proposal(7, Some(QuorumMode::AbsolutePower(5)));
+        proposal.proposer = "outsider".to_string();
+        let voters: BTreeSet<String> = ["member-principal".to_string()].into_iter().collect();
+        
+        update_reputation(&mut members, &mut streaks, &mut history, &proposal, &voters, true, 1);
+        assert!((members["member-principal"].reputation_score - 1.05).abs() < 1e-9);
+        // A single miss only starts the streak
+        assert_eq!(members["absent-principal"].reputation_score, 1.0);
+        assert_eq!(streaks["absent-principal"], 1);
+        
+        update_reputation(&mut members, &mut streaks, &mut history, &proposal, &voters, false, 2);
+        assert!((members["member-principal"].reputation_score - 1.05).abs() < 1e-9);
+        assert!((members["absent-principal"].reputation_score - 0.95).abs() < 1e-9);
+        assert!(!streaks.contains_key("member-principal"));
+        
+        for _ in 0..50 {
+            update_reputation(&mut members, &mut streaks, &mut history, &proposal, &voters, true, 3);
+        }
+        assert_eq!(members["absent-principal"].reputation_score, MIN_REPUTATION);
+        assert!((members["member-principal"].reputation_score - 3.55).abs() < 1e-9);
+        
+        // Members who joined after voting opened are not counted as absent
+        let mut late = test_members();
+        late.get_mut("member-principal").unwrap().joined_at = proposal.voting_start + 1;
+        update_reputation(&mut late, &mut streaks, &mut history, &proposal, &BTreeSet::new(), true, 4);
+        assert_eq!(late["member-principal"].reputation_score, 1.0);
+        
+        // History keeps only the most recent events per member
+        let events = &history["member-principal"];
+        assert_eq!(events.len(), MAX_REPUTATION_HISTORY);
+        assert!(events.iter().all(|event| matches!(event.reason, ReputationReason::VotedOnQuorumProposal)));
+        assert!((events.last().unwrap().score_after - 3.55).abs() < 1e-9);
+        let absent = &history["absent-principal"];
+        assert_eq!(absent.len(), MAX_REPUTATION_HISTORY);
+        assert!(absent.iter().all(|event| matches!(event.reason, ReputationReason::MissedProposals)));
+        // Once clamped at the floor further misses are recorded with a zero delta
+        assert_eq!(absent.last().unwrap().score_after, MIN_REPUTATION);
+        assert_eq!(absent.last().unwrap().delta, 0.0);
+    }
+    
+    #[test]
+    fn test_delegators_count_as_participants() {
+        let mut members = test_members();
+        let mut delegator = members["member-principal"].clone();
+        delegator.id = "delegator".to_string();
+        members.insert(delegator.id.clone(), delegator);
+        let mut streaks = BTreeMap::new();
+        streaks.insert("delegator".to_string(), 1);
+        let mut history = BTreeMap::new();
+        let mut proposal = test_proposal(14, Some(QuorumMode::AbsolutePower(5)));
+        proposal.proposer = "outsider".to_string();
+        
+        let mut vote = record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::Yes, None, None, 2).unwrap();
+        add_delegated_power(&mut vote, &members, &[("delegator".to_string(), "member-principal".to_string())].into_iter().collect(), &BTreeSet::new());
+        let voters = participants([vote].iter());
+        assert_eq!(voters.len(), 2);
+        
+        // The delegator's power was cast, so they are rewarded rather than marked absent
+        update_reputation(&mut members, &mut streaks, &mut history, &proposal, &voters, true, 3);
+        assert!((members["delegator"].reputation_score - 1.05).abs() < 1e-9);
+        assert!(!streaks.contains_key("delegator"));
+    }
+    
+    #[test]
+    fn test_author_bonus_and_reputation_history() {
+        let mut members = test_members();
+        let mut streaks = BTreeMap::new();
+        let mut history = BTreeMap::new();
+        let mut proposal = test_proposal(7, Some(QuorumMode::AbsolutePower(5)));
+        proposal.proposer = member_principal().to_text();
+        let voters: BTreeSet<String> = ["member-principal".to_string()].into_iter().collect();
+        
+        update_reputation(&mut members, &mut streaks, &mut history, &proposal, &voters, true, 10);
+        assert!((members["member-principal"].reputation_score - 1.30).abs() < 1e-9);
+        
+        // A rejected proposal earns the author nothing extra
+        proposal.no_votes = proposal.yes_votes + 1;
+        update_reputation(&mut members, &mut streaks, &mut history, &proposal, &voters, true, 20);
+        assert!((members["member-principal"].reputation_score - 1.35).abs() < 1e-9);
+        
+        let reputation = member_reputation(&members, &history, "member-principal", 2).unwrap();
+        assert!((reputation.reputation_score - 1.35).abs() < 1e-9);
+        assert_eq!(reputation.recent_events.len(), 2);
+        assert_eq!(reputation.recent_events[0].timestamp, 20);
+        assert!(matches!(reputation.recent_events[1].reason, ReputationReason::AuthoredPassedProposal));
+        assert!((reputation.recent_events[1].delta - REPUTATION_AUTHOR_BONUS).abs() < 1e-9);
+        assert!(member_reputation(&members, &history, "member-principal", 0).unwrap().recent_events.is_empty());
+        assert!(member_reputation(&members, &history, "missing", 5).is_err());
+    }
+    
+    fn test_members() -> BTreeMap<String, DAOMember> {
+        let mut members = BTreeMap::new();
+        members.insert("member-principal".to_string(), DAOMember {
+            id: "member-principal".to_string(),
+            name: "Asha".to_string(),
+            voting_power: 7,
+            joined_at: 0,
+            total_votes_cast: 0,
+            reputation_score: 1.0,
+            role: MemberRole::Citizen,
+            principal: Some(member_principal()),
+            removed_at: None,
+            removal_reason: None,
+        });
+        members
+    }
+    
+    fn member_principal() -> Principal {
+        Principal::from_slice(&[1, 2, 3])
+    }
+    
+    #[test]
+    fn test_member_votes() {
+        let mut members = test_members();
+        members.get_mut("member-principal").unwrap().joined_at = 5;
+        let mut proposals = BTreeMap::new();
+        for (n, status, voting_start) in [(1, ProposalStatus::Passed, 10), (2, ProposalStatus::Rejected, 20), (3, ProposalStatus::Active, 30), (4, ProposalStatus::Expired, 0)] {
+            let mut proposal = test_proposal(0, None);
+            proposal.id = format!("proposal_{}", n);
+            proposal.title = format!("Proposal {}", n);
+            proposal.proposer = "other".to_string();
+            proposal.status = status;
+            proposal.voting_start = voting_start;
+            proposal.voting_end = voting_start + 5;
+            proposals.insert(proposal.id.clone(), proposal);
+        }
+        proposals.get_mut("proposal_2").unwrap().proposer = "member-principal".to_string();
+        
+        let mut votes = BTreeMap::new();
+        for (proposal_id, now) in [("proposal_1", 12), ("proposal_3", 31)] {
+            let vote = record_member_vote(&mut members, proposal_id, "member-principal", VoteType::Yes, None, None, now).unwrap();
+            votes.insert(format!("{}:{}", proposal_id, member_principal().to_text()), vote);
+        }
+        let mut other = votes[&format!("proposal_1:{}", member_principal().to_text())].clone();
+        other.voter = "other".to_string();
+        votes.insert("proposal_1:other".to_string(), other);
+        let index = build_member_vote_index(&votes);
+        assert_eq!(index["member-principal"].len(), 2);
+        
+        let page = member_votes(&members, &proposals, &votes, &index, "member-principal", 0, 10).unwrap();
+        assert_eq!(page.total_count, 2);
+        let ids: Vec<&str> = page.votes.iter().map(|record| record.proposal_id.as_str()).collect();
+        assert_eq!(ids, vec!["proposal_3", "proposal_1"]);
+        assert_eq!(page.votes[1].proposal_title, "Proposal 1");
+        assert!(page.votes[1].proposal_status == ProposalStatus::Passed);
+        
+        // proposal_4 opened before the member joined and proposal_3 is still open
+        assert_eq!(page.summary.votes_cast, 2);
+        assert_eq!(page.summary.proposals_authored, 1);
+        assert_eq!((page.summary.recent_proposals, page.summary.recent_votes), (2, 1));
+        assert_eq!(page.summary.participation_rate, 0.5);
+        
+        let page = member_votes(&members, &proposals, &votes, &index, "member-principal", 1, 10).unwrap();
+        assert_eq!(page.votes.len(), 1);
+        assert!(member_votes(&members, &proposals, &votes, &index, "missing", 0, 10).is_err());
+    }
+    
+    #[test]
+    fn test_execution_timelock() {
+        let hour = NANOS_PER_HOUR;
+        let closed_at = 1_700_000_000_000_000_000;
+        let mut proposal = test_proposal(10, None);
+        proposal.status = ProposalStatus::Passed;
+        
+        // Proposals that passed before timelocks existed execute straight away
+        assert!(check_timelock(&proposal, 0).is_ok());
+        
+        start_timelock(&mut proposal, 48, closed_at);
+        assert_eq!(proposal.executable_after, Some(closed_at + 172_800_000_000_000));
+        assert_eq!(
+            check_timelock(&proposal, closed_at),
+            Err("Proposal is timelocked for another 172800 seconds".to_string())
+        );
+        // Partial seconds round up so the wait is never understated
+        assert_eq!(
+            check_timelock(&proposal, closed_at + 48 * hour - 1),
+            Err("Proposal is timelocked for another 1 seconds".to_string())
+        );
+        assert!(check_timelock(&proposal, closed_at + 48 * hour).is_ok());
+        
+        let mut unlocked = proposal.clone();
+        start_timelock(&mut unlocked, 0, closed_at);
+        assert!(check_timelock(&unlocked, closed_at).is_ok());
+    }
+    
+    #[test]
+    fn test_objections_during_timelock() {
+        let closed_at = 1_000;
+        let mut proposal = test_proposal(10, None);
+        proposal.status = ProposalStatus::Passed;
+        start_timelock(&mut proposal, 1, closed_at);
+        let during = closed_at + NANOS_PER_HOUR - 1;
+        
+        // 25% of 8 active members is 2 objections
+        assert_eq!(record_objection(&mut proposal, "a".to_string(), 8, 25, during), Ok(false));
+        assert_eq!(
+            record_objection(&mut proposal, "a".to_string(), 8, 25, during),
+            Err("Member has already objected".to_string())
+        );
+        assert_eq!(record_objection(&mut proposal, "b".to_string(), 8, 25, during), Ok(true));
+        assert!(proposal.status == ProposalStatus::Rejected);
+        assert!(record_objection(&mut proposal, "c".to_string(), 8, 25, during).is_err());
+        
+        let mut passed = test_proposal(10, None);
+        passed.status = ProposalStatus::Passed;
+        start_timelock(&mut passed, 1, closed_at);
+        assert_eq!(
+            record_objection(&mut passed, "a".to_string(), 8, 25, closed_at + NANOS_PER_HOUR),
+            Err("The objection window for this proposal has closed".to_string())
+        );
+        assert!(passed.status == ProposalStatus::Passed);
+    }
+    
+    #[test]
+    fn test_power_snapshot() {
+        let mut members = test_members();
+        members.insert("holder".to_string(), DAOMember {
+            id: "holder".to_string(),
+            principal: Some(Principal::from_slice(&[8, 8])),
+            ..members["member-principal"].clone()
+        });
+        members.insert("gone".to_string(), DAOMember {
+            id: "gone".to_string(),
+            removed_at: Some(1),
+            ..members["member-principal"].clone()
+        });
+        let token = GovernanceToken { ledger: Principal::from_slice(&[3]), units_per_vote: 100_000_000 };
+        
+        let mut snapshot = start_power_snapshot(&members, &token, 10);
+        assert_eq!(snapshot.pending.len(), 2);
+        let mut proposal = test_proposal(0, None);
+        proposal.power_snapshot = Some(snapshot.clone());
+        let mut proposals = BTreeMap::new();
+        proposals.insert(proposal.id.clone(), proposal.clone());
+        let (proposal_id, ledger, batch) = next_snapshot_batch(&proposals, &members).unwrap();
+        assert_eq!((proposal_id.as_str(), ledger), ("proposal_1", token.ledger));
+        assert_eq!(batch.len(), 2);
+        
+        // Snapshots of proposals that are no longer open are left unfinished
+        for status in [ProposalStatus::Cancelled, ProposalStatus::Rejected, ProposalStatus::Executed] {
+            let mut closed = proposal.clone();
+            closed.status = status;
+            assert!(!snapshot_pending(&closed));
+            let closed_only: BTreeMap<String, Proposal> = [(closed.id.clone(), closed)].into_iter().collect();
+            assert!(next_snapshot_batch(&closed_only, &members).is_none());
+        }
+        
+        // Balances become whole votes; a failed read counts as no power
+        record_snapshot_balance(&mut snapshot, "holder", Ok(Nat::from(350_000_000u64)), 11);
+        let progress = snapshot_progress("proposal_1", &snapshot);
+        assert_eq!((progress.total_members, progress.completed_members), (2, 1));
+        assert!(!progress.complete);
+        record_snapshot_balance(&mut snapshot, "member-principal", Err("call failed".to_string()), 12);
+        assert_eq!(snapshot.completed_at, Some(12));
+        assert_eq!(snapshot.powers["holder"], 3);
+        assert_eq!(snapshot.powers["member-principal"], 0);
+        assert_eq!(snapshot.failed, vec!["member-principal".to_string()]);
+        
+        proposal.power_snapshot = Some(snapshot.clone());
+        proposals.insert(proposal.id.clone(), proposal.clone());
+        assert!(next_snapshot_batch(&proposals, &members).is_none());
+        assert_eq!(proposal_total_power(&proposal, 1_000), 3);
+        assert_eq!(proposal_total_power(&test_proposal(0, None), 1_000), 1_000);
+        assert_eq!(with_snapshot_power(&members, &snapshot)["holder"].voting_power, 3);
+        
+        assert_eq!(votes_from_balance(&Nat::from(99u64), 100), 0);
+        assert_eq!(votes_from_balance(&Nat::from(u64::MAX), 1), u32::MAX);
+        assert!(start_power_snapshot(&BTreeMap::new(), &token, 5).completed_at == Some(5));
+    }
+    
+    #[test]
+    fn test_near_max_holders_saturate_tallies() {
+        let mut members = test_members();
+        members.insert("holder".to_string(), DAOMember {
+            id: "holder".to_string(),
+            principal: Some(Principal::from_slice(&[8, 8])),
+            ..members["member-principal"].clone()
+        });
+        let token = GovernanceToken { ledger: Principal::from_slice(&[3]), units_per_vote: 1 };
+        let mut snapshot = start_power_snapshot(&members, &token, 1);
+        record_snapshot_balance(&mut snapshot, "holder", Ok(Nat::from(u64::MAX)), 2);
+        record_snapshot_balance(&mut snapshot, "member-principal", Ok(Nat::from(u64::MAX - 1)), 3);
+        
+        let mut proposal = test_proposal(0, None);
+        let mut votes = Vec::new();
+        for member_id in ["holder", "member-principal"] {
+            let mut vote = record_member_vote(&mut members, "proposal_1", member_id, VoteType::Yes, None, None, 4).unwrap();
+            vote.voting_power = snapshot.powers[member_id];
+            tally_vote(&mut proposal, &vote);
+            votes.push(vote);
+        }
+        proposal.power_snapshot = Some(snapshot);
+        assert_eq!(proposal.yes_votes, u32::MAX);
+        assert_eq!(proposal.total_votes, u32::MAX);
+        assert_eq!(proposal_total_power(&proposal, 0), 2 * u32::MAX as u64);
+        
+        let snapshot = tally_snapshot(&proposal, votes.iter(), &members, 0);
+        assert_eq!(snapshot.by_role["Citizen"].yes_votes, u32::MAX);
+        
+        // Moving both votes onto a new tally saturates there too
+        for vote in votes.iter_mut() {
+            apply_vote_change(&mut proposal, vote, VoteType::No, None, 5).unwrap();
+        }
+        assert_eq!(proposal.no_votes, u32::MAX);
+        
+        let option_votes = [option_vote("pune", u32::MAX), option_vote("pune", u32::MAX - 1)];
+        let mut proposal = test_proposal(0, None);
+        for vote in &option_votes {
+            tally_vote(&mut proposal, vote);
+        }
+        assert_eq!(proposal.option_votes.unwrap()["pune"], u32::MAX);
+    }
+    
+    #[test]
+    fn test_membership_applications() {
+        let mut members = test_members();
+        let mut index = build_principal_index(&members);
+        let mut applications = BTreeMap::new();
+        let applicant = Principal::from_slice(&[5, 5]);
+        
+        assert!(submit_application(&mut applications, &index, "a0".to_string(), Principal::anonymous(), "Anon".to_string(), MemberRole::Citizen, 1).is_err());
+        assert!(submit_application(&mut applications, &index, "a0".to_string(), applicant, "  ".to_string(), MemberRole::Citizen, 1).is_err());
+        assert_eq!(
+            submit_application(&mut applications, &index, "a0".to_string(), member_principal(), "Asha".to_string(), MemberRole::Citizen, 1),
+            Err("Caller is already a DAO member".to_string())
+        );
+        submit_application(&mut applications, &index, "a1".to_string(), applicant, " Ravi ".to_string(), MemberRole::Auditor, 2).unwrap();
+        assert_eq!(
+            submit_application(&mut applications, &index, "a2".to_string(), applicant, "Ravi".to_string(), MemberRole::Auditor, 3),
+            Err("Caller already has a pending application".to_string())
+        );
+        let other = Principal::from_slice(&[6, 6]);
+        submit_application(&mut applications, &index, "a3".to_string(), other, "Meera".to_string(), MemberRole::Citizen, 1).unwrap();
+        
+        let page = pending_application_page(&applications, 0, 10);
+        assert_eq!(page.total_count, 2);
+        let ids: Vec<&str> = page.applications.iter().map(|application| application.id.as_str()).collect();
+        assert_eq!(ids, vec!["a3", "a1"]);
+        assert_eq!(pending_application_page(&applications, 1, 10).applications.len(), 1);
+        
+        // Approval creates a member bound to the applicant's principal
+        assert!(approve_pending_application(&mut applications, &mut members, &mut index, "a1", MemberRole::Citizen, 0, "admin".to_string(), 5).is_err());
+        let member_id = approve_pending_application(&mut applications, &mut members, &mut index, "a1", MemberRole::Citizen, 2, "admin".to_string(), 5).unwrap();
+        assert_eq!(member_id, applicant.to_text());
+        assert_eq!(index.get(&applicant), Some(&member_id));
+        assert_eq!(members[&member_id].name, "Ravi");
+        assert!(matches!(members[&member_id].role, MemberRole::Citizen));
+        assert_eq!(members[&member_id].voting_power, 2);
+        let application = &applications["a1"];
+        assert!(matches!(application.status, ApplicationStatus::Approved));
+        assert_eq!(application.reviewed_by.as_deref(), Some("admin"));
+        assert!(matches!(application.granted_role, Some(MemberRole::Citizen)));
+        assert_eq!(
+            approve_pending_application(&mut applications, &mut members, &mut index, "a1", MemberRole::Citizen, 2, "admin".to_string(), 6),
+            Err("Application has already been reviewed".to_string())
+        );
+        
+        assert!(reject_pending_application(&mut applications, "a3", " ".to_string(), "admin".to_string(), 7).is_err());
+        reject_pending_application(&mut applications, "a3", "Not a resident".to_string(), "admin".to_string(), 7).unwrap();
+        assert!(matches!(applications["a3"].status, ApplicationStatus::Rejected));
+        assert_eq!(applications["a3"].rejection_reason.as_deref(), Some("Not a resident"));
+        assert_eq!(pending_application_page(&applications, 0, 10).total_count, 0);
+        
+        // A rejected applicant may apply again
+        assert!(submit_application(&mut applications, &index, "a4".to_string(), other, "Meera".to_string(), MemberRole::Citizen, 8).is_ok());
+    }
+    
+    #[test]
+    fn test_member_updates_and_removal() {
+        let mut members = test_members();
+        let admin = DAOMember {
+            id: "admin".to_string(),
+            role: MemberRole::Admin,
+            voting_power: 3,
+            principal: Some(Principal::from_slice(&[7, 7])),
+            ..members["member-principal"].clone()
+        };
+        members.insert("admin".to_string(), admin);
+        let mut index = build_principal_index(&members);
+        let mut delegations = BTreeMap::new();
+        delegations.insert("admin".to_string(), "member-principal".to_string());
+        
+        let promote = MemberUpdate { role: Some(MemberRole::Auditor), voting_power: Some(10) };
+        apply_member_update(&mut members, "member-principal", promote).unwrap();
+        assert!(matches!(members["member-principal"].role, MemberRole::Auditor));
+        assert_eq!(members["member-principal"].voting_power, 10);
+        assert_eq!(total_voting_power(&members), 13);
+        
+        let demote_admin = MemberUpdate { role: Some(MemberRole::Citizen), voting_power: None };
+        assert_eq!(
+            apply_member_update(&mut members, "admin", demote_admin).err().as_deref(),
+            Some("Cannot remove or demote the last admin")
+        );
+        assert_eq!(
+            deactivate_member(&mut members, &mut index, &mut delegations, "admin", "lost keys".to_string(), 5).err().as_deref(),
+            Some("Cannot remove or demote the last admin")
+        );
+        assert!(deactivate_member(&mut members, &mut index, &mut delegations, "member-principal", " ".to_string(), 5).is_err());
+        
+        deactivate_member(&mut members, &mut index, &mut delegations, "member-principal", "compromised".to_string(), 5).unwrap();
+        let removed = &members["member-principal"];
+        assert_eq!(removed.removed_at, Some(5));
+        assert_eq!(removed.removal_reason.as_deref(), Some("compromised"));
+        assert_eq!(total_voting_power(&members), 3);
+        assert!(delegations.is_empty());
+        assert_eq!(voter_member_id(&index, &member_principal()), Err("Only DAO members can vote".to_string()));
+        assert!(!build_principal_index(&members).contains_key(&member_principal()));
+        
+        // The record stays for history but can no longer act or be changed
+        assert_eq!(
+            record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::Yes, None, None, 6).err().as_deref(),
+            Some("Removed members cannot vote")
+        );
+        assert!(check_proposal_eligibility(&members, "member-principal", 0.0).is_err());
+        assert!(check_delegation(&members, &delegations, "admin", "member-principal").is_err());
+        let bump = MemberUpdate { role: None, voting_power: Some(1) };
+        assert!(apply_member_update(&mut members, "member-principal", bump).is_err());
+        assert!(deactivate_member(&mut members, &mut index, &mut delegations, "member-principal", "again".to_string(), 7).is_err());
+        let readd = members["member-principal"].clone();
+        assert_eq!(
+            register_member(&mut members, &mut index, readd, member_principal()).err().as_deref(),
+            Some("Member member-principal has been removed")
+        );
+    }
+    
+    #[test]
+    fn test_votes_follow_caller_principal() {
+        let members = test_members();
+        let index = build_principal_index(&members);
+        assert_eq!(voter_member_id(&index, &member_principal()), Ok("member-principal".to_string()));
+        assert_eq!(voter_member_id(&index, &Principal::anonymous()), Err("Anonymous callers cannot vote".to_string()));
+        assert_eq!(voter_member_id(&index, &Principal::from_slice(&[9])), Err("Only DAO members can vote".to_string()));
+        
+        let mut members = members;
+        let vote = record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::Yes, None, None, 2).unwrap();
+        assert_eq!(vote.voter_principal, Some(member_principal()));
+        
+        // Legacy votes keep counting but cannot be changed
+        let mut proposal = test_proposal(0, None);
+        proposal.yes_votes = 7;
+        let mut legacy = Vote { voter_principal: None, ..vote };
+        let during_voting = proposal.voting_start;
+        let result = apply_vote_change(&mut proposal, &mut legacy, VoteType::No, None, during_voting);
+        assert_eq!(result.err().as_deref(), Some("Votes recorded under a free-text voter id are read-only"));
+        assert_eq!(proposal.yes_votes, 7);
+    }
+    
+    fn delegation_members() -> BTreeMap<String, DAOMember> {
+        let mut members = test_members();
+        let template = members["member-principal"].clone();
+        for (id, power) in [("auditor", 10), ("citizen_a", 3), ("citizen_b", 2)] {
+            members.insert(id.to_string(), DAOMember { id: id.to_string(), voting_power: power, principal: None, ..template.clone() });
+        }
+        members
+    }
+    
+    #[test]
+    fn test_delegation_rules() {
+        let members = delegation_members();
+        let mut delegations = BTreeMap::new();
+        
+        assert!(check_delegation(&members, &delegations, "citizen_a", "citizen_a").is_err());
+        assert!(check_delegation(&members, &delegations, "citizen_a", "stranger").is_err());
+        check_delegation(&members, &delegations, "citizen_a", "auditor").unwrap();
+        delegations.insert("citizen_a".to_string(), "auditor".to_string());
+        
+        // No second hop in either direction, so no cycles
+        assert_eq!(
+            check_delegation(&members, &delegations, "auditor", "citizen_b"),
+            Err("Members holding delegated power cannot delegate it further".to_string())
+        );
+        assert_eq!(
+            check_delegation(&members, &delegations, "citizen_b", "citizen_a"),
+            Err("Delegate has delegated their own voting power".to_string())
+        );
+        delegations.insert("citizen_b".to_string(), "auditor".to_string());
+        
+        assert_eq!(effective_voting_power(&members, &delegations, "auditor"), Ok(15));
+        assert_eq!(effective_voting_power(&members, &delegations, "citizen_a"), Ok(0));
+        assert_eq!(effective_voting_power(&members, &delegations, "member-principal"), Ok(7));
+        assert!(effective_voting_power(&members, &delegations, "stranger").is_err());
+    }
+    
+    #[test]
+    fn test_delegated_tally_snapshot_at_cast_time() {
+        let mut members = delegation_members();
+        let mut delegations = BTreeMap::new();
+        delegations.insert("citizen_a".to_string(), "auditor".to_string());
+        
+        // citizen_b voted directly before delegating, so only citizen_a's power moves
+        let mut counted_members = BTreeMap::new();
+        let direct = record_member_vote(&mut members, "proposal_1", "citizen_b", VoteType::No, None, None, 1).unwrap();
+        count_vote(&mut counted_members, &direct);
+        delegations.insert("citizen_b".to_string(), "auditor".to_string());
+        let counted = counted_members["proposal_1"].clone();
+        
+        assert!(check_can_vote_directly(&delegations, &counted, "citizen_a").is_err());
+        check_can_vote_directly(&delegations, &counted, "auditor").unwrap();
+        let mut vote = record_member_vote(&mut members, "proposal_1", "auditor", VoteType::Yes, None, None, 2).unwrap();
+        add_delegated_power(&mut vote, &members, &delegations, &counted);
+        assert_eq!(vote.voting_power, 13);
+        assert_eq!(vote.delegated_from, Some(vec!["citizen_a".to_string()]));
+        count_vote(&mut counted_members, &vote);
+        
+        // Revoking mid-vote leaves the cast tally alone and does not free the power again
+        delegations.remove("citizen_a");
+        assert_eq!(vote.voting_power, 13);
+        assert_eq!(
+            check_can_vote_directly(&delegations, &counted_members["proposal_1"], "citizen_a"),
+            Err("Voting power was already counted on this proposal through a delegate".to_string())
+        );
+        
+        // The index rebuilt on upgrade holds the same members
+        let votes = BTreeMap::from([
+            ("proposal_1:citizen_b".to_string(), direct),
+            ("proposal_1:auditor".to_string(), vote),
+        ]);
+        assert_eq!(build_counted_members(&votes), counted_members);
+        
+        // On a fresh proposal the revoked member votes with their own power
+        let fresh = counted_members.get("proposal_2").cloned().unwrap_or_default();
+        check_can_vote_directly(&delegations, &fresh, "citizen_a").unwrap();
+        let mut own = record_member_vote(&mut members, "proposal_2", "citizen_a", VoteType::Yes, None, None, 3).unwrap();
+        add_delegated_power(&mut own, &members, &delegations, &fresh);
+        assert_eq!(own.voting_power, 3);
+        assert_eq!(own.delegated_from, None);
+    }
+    
+    #[test]
+    fn test_register_member_principals() {
+        let mut members = BTreeMap::new();
+        let mut index = BTreeMap::new();
+        let member = test_members().remove("member-principal").unwrap();
+        let other = Principal::from_slice(&[5, 6]);
+        
+        assert!(register_member(&mut members, &mut index, member.clone(), Principal::anonymous()).is_err());
+        register_member(&mut members, &mut index, member.clone(), member_principal()).unwrap();
+        assert_eq!(index.get(&member_principal()).map(String::as_str), Some("member-principal"));
+        
+        let impostor = DAOMember { id: "someone-else".to_string(), ..member.clone() };
+        assert_eq!(
+            register_member(&mut members, &mut index, impostor, member_principal()),
+            Err("Principal already belongs to member member-principal".to_string())
+        );
+        
+        // Re-registering under a new principal releases the old one
+        register_member(&mut members, &mut index, member, other).unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!(members["member-principal"].principal, Some(other));
+    }
+    
+    #[test]
+    fn test_proposal_reputation_gate() {
+        let mut members = test_members();
+        assert!(check_proposal_eligibility(&members, "member-principal", 0.5).is_ok());
+        assert_eq!(
+            check_proposal_eligibility(&members, "stranger-principal", 0.5),
+            Err("Only DAO members can create proposals".to_string())
+        );
+        
+        members.get_mut("member-principal").unwrap().reputation_score = 0.3;
+        assert_eq!(
+            check_proposal_eligibility(&members, "member-principal", 0.5),
+            Err("Reputation 0.30 is below the 0.50 required to create proposals".to_string())
+        );
+    }
+    
+    #[test]
+    fn test_non_member_cannot_vote() {
+        let mut members = test_members();
+        let result = record_member_vote(&mut members, "proposal_1", "stranger-principal", VoteType::Yes, None, None, 1);
+        assert_eq!(result.err().as_deref(), Some("Only DAO members can vote"));
+        assert_eq!(members["member-principal"].total_votes_cast, 0);
+    }
+    
+    #[test]
+    fn test_vote_uses_member_voting_power() {
+        let mut members = test_members();
+        let vote = record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::No, Some("Too costly".to_string()), None, 5).unwrap();
+        assert_eq!(vote.voting_power, 7);
+        assert_eq!(vote.voter, "member-principal");
+        assert_eq!(vote.timestamp, 5);
+        assert_eq!(members["member-principal"].total_votes_cast, 1);
+    }
+    
+    #[test]
+    fn test_voting_power_override_is_admin_only() {
+        let mut members = test_members();
+        let result = record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::Yes, None, Some(1_000_000), 1);
+        assert_eq!(result.err().as_deref(), Some("Only admins can override voting power"));
+        assert_eq!(members["member-principal"].total_votes_cast, 0);
+        
+        members.get_mut("member-principal").unwrap().role = MemberRole::Admin;
+        let vote = record_member_vote(&mut members, "proposal_1", "member-principal", VoteType::Yes, Some("Board mandate".to_string()), Some(20), 2).unwrap();
+        assert_eq!(vote.voting_power, 20);
+        assert_eq!(vote.reason.as_deref(), Some("[voting power overridden by admin: 20 instead of 7] Board mandate"));
+        
+        let vote = record_member_vote(&mut members, "proposal_2", "member-principal", VoteType::No, None, Some(3), 3).unwrap();
+        assert_eq!(vote.reason.as_deref(), Some("[voting power overridden by admin: 3 instead of 7]"));
+        
+        let result = record_member_vote(&mut members, "proposal_3", "member-principal", VoteType::Yes, None, Some(u32::MAX), 4);
+        assert_eq!(result.err().as_deref(), Some("Voting power override cannot exceed 10000"));
+        assert_eq!(members["member-principal"].total_votes_cast, 2);
+    }
+    
+    #[test]
+    fn test_health_check_counts() {
+        unsafe {
+            PROPOSALS = Some(BTreeMap::new());
+            VOTES = Some(BTreeMap::new());
+            MEMBERS = Some(BTreeMap::new());
+            INIT_TIME_NS = Some(100);
+        }
+        let before = health_status_at(400);
+        assert!(before.initialized);
+        assert_eq!(before.uptime_ns, 300);
+        assert_eq!(before.record_counts[2], ("members".to_string(), 0));
+        
+        unsafe {
+            MEMBERS = Some(test_members());
+            if let Some(ref mut proposals) = PROPOSALS {
+                proposals.insert("proposal_1".to_string(), test_proposal(0, None));
+            }
+        }
+        let after = health_status_at(400);
+        assert_eq!(after.record_counts[0], ("proposals".to_string(), 1));
+        assert_eq!(after.record_counts[2], ("members".to_string(), 1));
+    }
+}
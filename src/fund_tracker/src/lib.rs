@@ -1,12 +1,19 @@
-use candid::{CandidType, Deserialize, Principal};
-use ic_cdk::{api::call::call, export::candid, init, post_upgrade, pre_upgrade, query, update};
-use ic_cdk_timers::set_timer_interval;
-use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
-use std::collections::BTreeMap;
+use candid::{CandidType, Decode, Deserialize, Encode, Nat, Principal};
+use common::{CanisterStats, HashTree, HealthStatus, TransactionStatus};
+use ic_cdk::{api::call::call, init, post_upgrade, pre_upgrade, query, update};
+use ic_cdk_timers::{clear_timer, set_timer_interval, TimerId};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, StableLog, Storable};
+use serde::Serialize as SerdeSerialize;
+use sha2::{Digest, Sha256};
+use std::borrow::{Borrow, Cow};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 use uuid::Uuid;
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct FundTransaction {
     pub id: String,
     pub policy_id: String,
@@ -18,37 +25,82 @@ pub struct FundTransaction {
     pub status: TransactionStatus,
     pub transaction_hash: String,
     pub metadata: BTreeMap<String, String>,
+    pub status_history: Option<Vec<(TransactionStatus, u64)>>,
+    // Position in the transaction feed; None for transactions recorded before the feed existed
+    pub seq: Option<u64>,
+    // Unit of `amount`; always paise once recorded. None only on archived records from
+    // before denominations, which are read as paise
+    pub denomination: Option<Denomination>,
+    // Rules that matched when the transaction was recorded; None for transactions recorded
+    // before anomaly checks existed
+    pub anomaly_flags: Option<Vec<String>>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize, Debug)]
+pub enum Denomination {
+    Paise,
+    Rupees,
+    // Ledger token units; there is no fixed rate to paise
+    E8s,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub enum TxEvent {
+    Recorded(FundTransaction),
+    StatusChanged {
+        transaction_id: String,
+        status: TransactionStatus,
+        timestamp: u64,
+    },
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct FeedEvent {
+    pub seq: u64,
+    pub event: TxEvent,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct TxFeedPage {
+    pub events: Vec<FeedEvent>,
+    // Cursor to pass to the next call; the last returned seq, or the request cursor when empty
+    pub next_cursor: u64,
+    pub latest_seq: u64,
+    // Events older than this were trimmed; a cursor below it has missed events
+    pub oldest_seq: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TransactionReceipt {
+    pub transaction: FundTransaction,
+    // CBOR certificate whose certified_data is the root hash of `witness`
+    pub certificate: Vec<u8>,
+    pub witness: HashTree,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub enum TransactionType {
     Allocation,
     Release,
     Transfer,
     Refund,
     Fee,
+    // Zero-amount bookkeeping record, e.g. a balance repair
+    Adjustment,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub enum TransactionStatus {
-    Pending,
-    Processing,
-    Completed,
-    Failed,
-    Cancelled,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct FundBalance {
     pub policy_id: String,
     pub total_allocated: u64,
     pub total_released: u64,
     pub total_transferred: u64,
+    pub total_fees: Option<u64>,
     pub current_balance: u64,
     pub last_updated: u64,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct DistrictFunds {
     pub district: String,
     pub total_allocated: u64,
@@ -56,390 +108,5479 @@ pub struct DistrictFunds {
     pub active_policies: u32,
     pub completion_rate: f64,
     pub last_updated: u64,
+    pub policy_ids: Option<Vec<String>>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct FundAnalytics {
-    pub total_funds_allocated: u64,
-    pub total_funds_released: u64,
+    pub total_funds_allocated: u128,
+    pub total_funds_released: u128,
+    pub total_fees: Option<u128>,
     pub total_transactions: u32,
+    pub sum_of_transaction_amounts: Option<u128>,
     pub average_transaction_amount: f64,
     pub district_distribution: BTreeMap<String, u64>,
     pub category_distribution: BTreeMap<String, u64>,
+    pub type_distribution: Option<BTreeMap<String, u128>>,
+    pub type_counts: Option<BTreeMap<String, u32>>,
     pub monthly_trends: BTreeMap<String, u64>,
-    pub success_rate: f64,
+    // Completed share of settled transactions; None until one settles
+    pub success_rate: Option<f64>,
+    // Unit of every amount above; set to paise on init and upgrade
+    pub canonical_unit: Option<Denomination>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct RealTimeMetrics {
     pub current_time: u64,
     pub active_transactions: u32,
-    pub pending_amount: u64,
-    pub daily_volume: u64,
-    pub weekly_volume: u64,
-    pub monthly_volume: u64,
+    pub pending_amount: u128,
+    pub daily_volume: u128,
+    pub weekly_volume: u128,
+    pub monthly_volume: u128,
+    pub hourly_volume: u128,
+    // Completed volume over the last 24 hours, keyed by transaction type name
+    pub volume_by_type: BTreeMap<String, u128>,
+    pub completed_last_hour: u32,
 }
 
-// Stable storage for fund tracking data
-static mut TRANSACTIONS: Option<BTreeMap<String, FundTransaction>> = None;
-static mut FUND_BALANCES: Option<BTreeMap<String, FundBalance>> = None;
-static mut DISTRICT_FUNDS: Option<BTreeMap<String, DistrictFunds>> = None;
-static mut FUND_ANALYTICS: Option<FundAnalytics> = None;
-static mut REAL_TIME_METRICS: Option<RealTimeMetrics> = None;
-
-#[init]
-fn init() {
-    unsafe {
-        TRANSACTIONS = Some(BTreeMap::new());
-        FUND_BALANCES = Some(BTreeMap::new());
-        DISTRICT_FUNDS = Some(BTreeMap::new());
-        FUND_ANALYTICS = Some(FundAnalytics {
-            total_funds_allocated: 0,
-            total_funds_released: 0,
-            total_transactions: 0,
-            average_transaction_amount: 0.0,
-            district_distribution: BTreeMap::new(),
-            category_distribution: BTreeMap::new(),
-            monthly_trends: BTreeMap::new(),
-            success_rate: 0.0,
-        });
-        REAL_TIME_METRICS = Some(RealTimeMetrics {
-            current_time: 0,
-            active_transactions: 0,
-            pending_amount: 0,
-            daily_volume: 0,
-            weekly_volume: 0,
-            monthly_volume: 0,
-        });
-    }
-    
-    // Set up periodic metrics updates
-    set_timer_interval(Duration::from_secs(300), || {
-        ic_cdk::spawn(update_real_time_metrics());
-    });
+// The original canister's FundAnalytics and RealTimeMetrics, kept to decode its heap state
+#[derive(CandidType, Deserialize)]
+struct LegacyFundAnalytics {
+    total_funds_allocated: u64,
+    total_funds_released: u64,
+    total_transactions: u32,
+    average_transaction_amount: f64,
+    district_distribution: BTreeMap<String, u64>,
+    category_distribution: BTreeMap<String, u64>,
+    monthly_trends: BTreeMap<String, u64>,
+    success_rate: f64,
 }
 
-#[pre_upgrade]
-fn pre_upgrade() {
-    let transactions = unsafe { TRANSACTIONS.take().unwrap() };
-    let fund_balances = unsafe { FUND_BALANCES.take().unwrap() };
-    let district_funds = unsafe { DISTRICT_FUNDS.take().unwrap() };
-    let analytics = unsafe { FUND_ANALYTICS.take().unwrap() };
-    let metrics = unsafe { REAL_TIME_METRICS.take().unwrap() };
-    
-    ic_cdk::storage::stable_save((transactions, fund_balances, district_funds, analytics, metrics)).unwrap();
+#[derive(CandidType, Deserialize)]
+struct LegacyRealTimeMetrics {
+    current_time: u64,
+    active_transactions: u32,
+    pending_amount: u64,
+    daily_volume: u64,
+    weekly_volume: u64,
+    monthly_volume: u64,
 }
 
-#[post_upgrade]
-fn post_upgrade() {
-    let (transactions, fund_balances, district_funds, analytics, metrics): (
-        BTreeMap<String, FundTransaction>, 
-        BTreeMap<String, FundBalance>, 
-        BTreeMap<String, DistrictFunds>, 
-        FundAnalytics, 
-        RealTimeMetrics
-    ) = ic_cdk::storage::stable_restore().unwrap();
-    
-    unsafe {
-        TRANSACTIONS = Some(transactions);
-        FUND_BALANCES = Some(fund_balances);
-        DISTRICT_FUNDS = Some(district_funds);
-        FUND_ANALYTICS = Some(analytics);
-        REAL_TIME_METRICS = Some(metrics);
+impl From<LegacyFundAnalytics> for FundAnalytics {
+    fn from(legacy: LegacyFundAnalytics) -> Self {
+        FundAnalytics {
+            total_funds_allocated: legacy.total_funds_allocated as u128,
+            total_funds_released: legacy.total_funds_released as u128,
+            total_fees: None,
+            total_transactions: legacy.total_transactions,
+            // Seeded from the average on the next write
+            sum_of_transaction_amounts: None,
+            average_transaction_amount: legacy.average_transaction_amount,
+            district_distribution: legacy.district_distribution,
+            category_distribution: legacy.category_distribution,
+            type_distribution: None,
+            type_counts: None,
+            monthly_trends: legacy.monthly_trends,
+            // The original rate was a fixed placeholder, not a measurement
+            success_rate: None,
+            canonical_unit: Some(Denomination::Paise),
+        }
     }
 }
 
-#[update]
-async fn record_transaction(
-    policy_id: String,
-    transaction_type: TransactionType,
-    amount: u64,
-    from_address: String,
-    to_address: String,
-    metadata: BTreeMap<String, String>,
-) -> Result<String, String> {
-    let transaction_id = Uuid::new_v4().to_string();
-    let now = ic_cdk::api::time();
-    
-    let transaction = FundTransaction {
-        id: transaction_id.clone(),
-        policy_id: policy_id.clone(),
-        transaction_type: transaction_type.clone(),
-        amount,
-        from_address: from_address.clone(),
-        to_address: to_address.clone(),
-        timestamp: now,
-        status: TransactionStatus::Processing,
-        transaction_hash: format!("tx_{}", Uuid::new_v4().to_string()),
-        metadata,
-    };
-    
-    // Store transaction
-    unsafe {
-        if let Some(ref mut transactions) = TRANSACTIONS {
-            transactions.insert(transaction_id.clone(), transaction);
+impl From<LegacyRealTimeMetrics> for RealTimeMetrics {
+    fn from(legacy: LegacyRealTimeMetrics) -> Self {
+        RealTimeMetrics {
+            current_time: legacy.current_time,
+            active_transactions: legacy.active_transactions,
+            pending_amount: legacy.pending_amount as u128,
+            daily_volume: legacy.daily_volume as u128,
+            weekly_volume: legacy.weekly_volume as u128,
+            monthly_volume: legacy.monthly_volume as u128,
+            hourly_volume: 0,
+            volume_by_type: BTreeMap::new(),
+            completed_last_hour: 0,
         }
     }
-    
-    // Update fund balances
-    update_fund_balance(&policy_id, &transaction_type, amount).await;
-    
-    // Update analytics
-    update_analytics(&transaction_type, amount).await;
-    
-    // Simulate transaction processing
-    ic_cdk::spawn(process_transaction(transaction_id.clone()));
-    
-    Ok(transaction_id)
 }
 
-#[update]
-async fn update_transaction_status(
-    transaction_id: String,
-    status: TransactionStatus,
-) -> Result<(), String> {
-    unsafe {
-        if let Some(ref mut transactions) = TRANSACTIONS {
-            if let Some(transaction) = transactions.get_mut(&transaction_id) {
-                transaction.status = status.clone();
-                return Ok(());
-            }
-        }
-    }
-    
-    Err("Transaction not found".to_string())
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct TxFilter {
+    pub policy_id: Option<String>,
+    pub status: Option<TransactionStatus>,
+    pub transaction_type: Option<TransactionType>,
+    pub from_timestamp: Option<u64>,
+    pub to_timestamp: Option<u64>,
 }
 
-#[query]
-fn get_transaction(transaction_id: String) -> Result<FundTransaction, String> {
-    unsafe {
-        if let Some(ref transactions) = TRANSACTIONS {
-            transactions.get(&transaction_id).cloned().ok_or("Transaction not found".to_string())
-        } else {
-            Err("Transactions not initialized".to_string())
-        }
-    }
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct TxPage {
+    pub transactions: Vec<FundTransaction>,
+    pub total_count: u64,
+    pub offset: u64,
+    pub limit: u32,
 }
 
-#[query]
-fn get_policy_transactions(policy_id: String) -> Vec<FundTransaction> {
-    unsafe {
-        if let Some(ref transactions) = TRANSACTIONS {
-            transactions.values()
-                .filter(|transaction| transaction.policy_id == policy_id)
-                .cloned()
-                .collect()
-        } else {
-            Vec::new()
-        }
-    }
+// Thresholds for the anomaly rules applied to every newly recorded transaction
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct AnomalyConfig {
+    // Flag amounts above this percentile (1-100) of the policy's earlier amounts of the same type
+    pub amount_percentile: u8,
+    // Earlier transactions a policy needs before the percentile rule applies; capped at
+    // the number of recent amounts kept per policy and type
+    pub min_history: u32,
+    // Flag any amount above this, whatever the policy's history
+    pub absolute_threshold: Option<u64>,
+    // Window in which the same amount to the same address counts as a repeat
+    pub repeat_window_secs: u64,
 }
 
-#[query]
-fn get_fund_balance(policy_id: String) -> Result<FundBalance, String> {
-    unsafe {
-        if let Some(ref fund_balances) = FUND_BALANCES {
-            fund_balances.get(&policy_id).cloned().ok_or("Fund balance not found".to_string())
-        } else {
-            Err("Fund balances not initialized".to_string())
-        }
-    }
+// Descending order for list_fund_balances; ties fall back to policy id
+#[derive(CandidType, Deserialize, Clone)]
+pub enum BalanceSort {
+    CurrentBalance,
+    TotalAllocated,
+    LastUpdated,
 }
 
-#[query]
-fn get_district_funds(district: String) -> Result<DistrictFunds, String> {
-    unsafe {
-        if let Some(ref district_funds) = DISTRICT_FUNDS {
-            district_funds.get(&district).cloned().ok_or("District funds not found".to_string())
-        } else {
-            Err("District funds not initialized".to_string())
-        }
-    }
+#[derive(CandidType, Deserialize, Clone)]
+pub struct BalancePage {
+    pub balances: Vec<FundBalance>,
+    pub total_count: u64,
+    pub offset: u64,
+    pub limit: u32,
 }
 
-#[query]
-fn get_fund_analytics() -> FundAnalytics {
-    unsafe {
-        FUND_ANALYTICS.clone().unwrap_or(FundAnalytics {
-            total_funds_allocated: 0,
-            total_funds_released: 0,
-            total_transactions: 0,
-            average_transaction_amount: 0.0,
-            district_distribution: BTreeMap::new(),
-            category_distribution: BTreeMap::new(),
-            monthly_trends: BTreeMap::new(),
-            success_rate: 0.0,
-        })
-    }
+// Sums across every policy's FundBalance
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct TreasuryPosition {
+    pub total_allocated: u128,
+    pub total_released: u128,
+    pub total_transferred: u128,
+    pub total_fees: u128,
+    pub current_balance: u128,
+    pub policy_count: u64,
 }
 
-#[query]
-fn get_real_time_metrics() -> RealTimeMetrics {
-    unsafe {
-        REAL_TIME_METRICS.clone().unwrap_or(RealTimeMetrics {
-            current_time: 0,
-            active_transactions: 0,
-            pending_amount: 0,
-            daily_volume: 0,
-            weekly_volume: 0,
-            monthly_volume: 0,
-        })
-    }
+// Completed transaction volume within one slice of a fiscal year
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct FiscalTotals {
+    pub allocated: u128,
+    pub released: u128,
+    pub refunded: u128,
+    pub fees: u128,
+    pub transaction_count: u64,
 }
 
-#[query]
-fn get_recent_transactions(limit: u32) -> Vec<FundTransaction> {
-    unsafe {
-        if let Some(ref transactions) = TRANSACTIONS {
-            let mut sorted_transactions: Vec<FundTransaction> = transactions.values().cloned().collect();
-            sorted_transactions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-            sorted_transactions.into_iter().take(limit as usize).collect()
-        } else {
-            Vec::new()
-        }
-    }
+// April 1 to March 31, with both boundaries at midnight IST
+#[derive(CandidType, Deserialize, Clone)]
+pub struct FiscalYearReport {
+    // e.g. "2024-25"
+    pub fiscal_year: String,
+    pub start_ns: u64,
+    // Exclusive
+    pub end_ns: u64,
+    pub totals: FiscalTotals,
+    // "YYYY-MM" in IST, April first
+    pub by_month: Vec<(String, FiscalTotals)>,
+    pub by_district: Vec<(String, FiscalTotals)>,
+    pub by_category: Vec<(String, FiscalTotals)>,
 }
 
-#[query]
-fn get_transactions_by_type(transaction_type: TransactionType) -> Vec<FundTransaction> {
-    unsafe {
-        if let Some(ref transactions) = TRANSACTIONS {
-            transactions.values()
-                .filter(|transaction| std::mem::discriminant(&transaction.transaction_type) == std::mem::discriminant(&transaction_type))
-                .cloned()
-                .collect()
-        } else {
-            Vec::new()
-        }
-    }
+// Completed transactions of one fiscal year that were moved to the archive, rolled up
+// the way get_fiscal_year_report reports them
+#[derive(CandidType, Deserialize, Clone, Default)]
+struct FiscalYearTotals {
+    totals: FiscalTotals,
+    by_month: BTreeMap<String, FiscalTotals>,
+    by_district: BTreeMap<String, FiscalTotals>,
+    by_category: BTreeMap<String, FiscalTotals>,
 }
 
-async fn update_fund_balance(policy_id: &str, transaction_type: &TransactionType, amount: u64) {
-    unsafe {
-        if let Some(ref mut fund_balances) = FUND_BALANCES {
-            let balance = fund_balances.entry(policy_id.to_string()).or_insert(FundBalance {
-                policy_id: policy_id.to_string(),
-                total_allocated: 0,
-                total_released: 0,
-                total_transferred: 0,
-                current_balance: 0,
-                last_updated: ic_cdk::api::time(),
-            });
-            
-            match transaction_type {
-                TransactionType::Allocation => {
-                    balance.total_allocated += amount;
-                    balance.current_balance += amount;
-                }
-                TransactionType::Release => {
-                    balance.total_released += amount;
-                    balance.current_balance = balance.current_balance.saturating_sub(amount);
+// Release transactions of one policy that were moved to the archive, as reconciliation counts them
+#[derive(CandidType, Deserialize, Clone, Default)]
+struct ArchivedReleases {
+    amount: u64,
+    count: u32,
+}
+
+// Transactions of one type that reached each terminal status
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize, Default)]
+pub struct TerminalCounts {
+    pub completed: u32,
+    pub failed: u32,
+    pub cancelled: u32,
+}
+
+// The two linked transactions recorded by transfer_between_policies
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PolicyTransfer {
+    pub transfer_group_id: String,
+    pub transfer_out_id: String,
+    pub allocation_in_id: String,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TypeSuccessRate {
+    pub transaction_type: String,
+    pub counts: TerminalCounts,
+    pub success_rate: Option<f64>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct RecordTxRequest {
+    pub policy_id: String,
+    pub transaction_type: TransactionType,
+    pub amount: u64,
+    pub from_address: String,
+    pub to_address: String,
+    pub metadata: BTreeMap<String, String>,
+    pub dedup_key: Option<String>,
+    pub override_allocation: bool,
+    pub denomination: Denomination,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct CsvChunk {
+    pub chunk_index: u32,
+    pub data: Vec<u8>,
+    pub has_more: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum TopMetric {
+    AllocatedVolume,
+    ReleasedVolume,
+    TxCount,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum TimeWindow {
+    Day,
+    Week,
+    Month,
+    AllTime,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TopEntry {
+    pub key: String,
+    pub value: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct CategoryBreakdown {
+    pub category: String,
+    pub total_allocated: u64,
+    pub total_released: u64,
+    pub transaction_count: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct ReconciliationField {
+    pub field: String,
+    pub stored: u64,
+    pub computed: u64,
+    pub delta: i128,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct ReconciliationReport {
+    pub policy_id: String,
+    pub fields: Vec<ReconciliationField>,
+    pub in_sync: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct AddressBalance {
+    pub address: String,
+    pub total_received: u64,
+    pub total_sent: u64,
+    // Received minus sent; the treasury's is negative by its total outflow
+    pub net_balance: i128,
+    pub transaction_count: u32,
+    pub last_updated: u64,
+}
+
+// What a dedup key was first used for; repeats must match it exactly
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct DedupEntry {
+    pub transaction_id: String,
+    pub policy_id: String,
+    pub amount: u64,
+    pub from_address: String,
+    pub to_address: String,
+    pub recorded_at: u64,
+}
+
+const DEDUP_WINDOW_NS: u64 = 24 * 3_600_000_000_000;
+
+// Settings written to stable memory just before an upgrade; everything else already
+// lives in the stable maps
+#[derive(CandidType, Deserialize, Clone, Default)]
+struct UpgradeState {
+    smart_policy_canister_id: Option<Principal>,
+    ledger_canister_id: Option<Principal>,
+    metrics_refresh_secs: Option<u64>,
+    trusted_callers: Option<BTreeSet<Principal>>,
+    anomaly_config: Option<AnomalyConfig>,
+    stuck_processing_secs: Option<u64>,
+    init_time_ns: Option<u64>,
+}
+
+macro_rules! candid_storable {
+    ($($type:ty),*) => {
+        $(
+            impl Storable for $type {
+                fn to_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(Encode!(self).expect("Failed to encode stable value"))
                 }
-                TransactionType::Transfer => {
-                    balance.total_transferred += amount;
-                    balance.current_balance = balance.current_balance.saturating_sub(amount);
+                
+                fn from_bytes(bytes: Cow<[u8]>) -> Self {
+                    Decode!(bytes.as_ref(), Self).expect("Failed to decode stable value")
                 }
-                _ => {}
+                
+                const BOUND: Bound = Bound::Unbounded;
             }
-            
-            balance.last_updated = ic_cdk::api::time();
-        }
+        )*
+    };
+}
+
+candid_storable!(
+    FundTransaction,
+    TxEvent,
+    FundBalance,
+    DistrictFunds,
+    FundAnalytics,
+    RealTimeMetrics,
+    UpgradeState,
+    AddressBalance,
+    DedupEntry,
+    CategoryBreakdown,
+    TerminalCounts,
+    PolicyAmounts,
+    FiscalYearTotals,
+    ArchivedReleases
+);
+
+// Rolling amounts the percentile rule compares against: transaction type name -> the
+// latest ANOMALY_AMOUNT_WINDOW amounts of that type that moved funds, oldest first
+#[derive(CandidType, Deserialize, Clone, Default)]
+struct PolicyAmounts {
+    recent: BTreeMap<String, Vec<u64>>,
+}
+
+// An address that has sent or received funds on a policy, hot or archived
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct PolicyAddress {
+    policy_id: String,
+    address: String,
+}
+
+impl PolicyAddress {
+    fn new(policy_id: &str, address: &str) -> Self {
+        PolicyAddress { policy_id: policy_id.to_string(), address: address.to_string() }
     }
 }
 
-async fn update_analytics(transaction_type: &TransactionType, amount: u64) {
-    unsafe {
-        if let Some(ref mut analytics) = FUND_ANALYTICS {
-            match transaction_type {
-                TransactionType::Allocation => {
-                    analytics.total_funds_allocated += amount;
-                }
-                TransactionType::Release => {
-                    analytics.total_funds_released += amount;
-                }
-                _ => {}
-            }
-            
-            analytics.total_transactions += 1;
-            
-            // Update average transaction amount
-            let total_amount = analytics.total_funds_allocated + analytics.total_funds_released;
-            analytics.average_transaction_amount = total_amount as f64 / analytics.total_transactions as f64;
-            
-            // Update success rate (mock calculation)
-            analytics.success_rate = 0.95; // 95% success rate
+impl Storable for PolicyAddress {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(4 + self.policy_id.len() + self.address.len());
+        bytes.extend_from_slice(&(self.policy_id.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.policy_id.as_bytes());
+        bytes.extend_from_slice(self.address.as_bytes());
+        Cow::Owned(bytes)
+    }
+    
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let policy_end = 4 + u32::from_be_bytes(bytes[0..4].try_into().expect("Truncated policy address")) as usize;
+        PolicyAddress {
+            policy_id: String::from_utf8(bytes[4..policy_end].to_vec()).expect("Invalid policy address"),
+            address: String::from_utf8(bytes[policy_end..].to_vec()).expect("Invalid policy address"),
         }
     }
+    
+    const BOUND: Bound = Bound::Unbounded;
 }
 
-async fn process_transaction(transaction_id: String) {
-    // Simulate transaction processing delay
-    ic_cdk::api::call::call_with_payment(
-        Principal::management_canister(),
-        "raw_rand",
-        (),
-        0,
-    ).await.unwrap();
-    
-    // Update transaction status to completed
-    let _result = update_transaction_status(transaction_id, TransactionStatus::Completed).await;
+// Key of the transaction indexes: the policy id or address a transaction is filed under
+// (empty for the time-ordered indexes), then its timestamp and id
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct IndexKey {
+    name: String,
+    timestamp: u64,
+    transaction_id: String,
 }
 
-async fn update_real_time_metrics() {
-    let now = ic_cdk::api::time();
+impl IndexKey {
+    fn new(name: &str, timestamp: u64, transaction_id: &str) -> Self {
+        IndexKey { name: name.to_string(), timestamp, transaction_id: transaction_id.to_string() }
+    }
+}
+
+impl Storable for IndexKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(12 + self.name.len() + self.transaction_id.len());
+        bytes.extend_from_slice(&(self.name.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        bytes.extend_from_slice(self.transaction_id.as_bytes());
+        Cow::Owned(bytes)
+    }
     
-    unsafe {
-        if let Some(ref mut metrics) = REAL_TIME_METRICS {
-            metrics.current_time = now;
-            
-            // Count active transactions
-            if let Some(ref transactions) = TRANSACTIONS {
-                metrics.active_transactions = transactions.values()
-                    .filter(|t| t.status == TransactionStatus::Processing)
-                    .count() as u32;
-                
-                // Calculate pending amount
-                metrics.pending_amount = transactions.values()
-                    .filter(|t| t.status == TransactionStatus::Processing)
-                    .map(|t| t.amount)
-                    .sum();
-                
-                // Calculate daily volume (last 24 hours)
-                let day_ago = now - 24 * 3600_000_000_000;
-                metrics.daily_volume = transactions.values()
-                    .filter(|t| t.timestamp >= day_ago && t.status == TransactionStatus::Completed)
-                    .map(|t| t.amount)
-                    .sum();
-                
-                // Calculate weekly volume (last 7 days)
-                let week_ago = now - 7 * 24 * 3600_000_000_000;
-                metrics.weekly_volume = transactions.values()
-                    .filter(|t| t.timestamp >= week_ago && t.status == TransactionStatus::Completed)
-                    .map(|t| t.amount)
-                    .sum();
-                
-                // Calculate monthly volume (last 30 days)
-                let month_ago = now - 30 * 24 * 3600_000_000_000;
-                metrics.monthly_volume = transactions.values()
-                    .filter(|t| t.timestamp >= month_ago && t.status == TransactionStatus::Completed)
-                    .map(|t| t.amount)
-                    .sum();
-            }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let name_len = u32::from_be_bytes(bytes[0..4].try_into().expect("Truncated index key")) as usize;
+        let name_end = 4 + name_len;
+        IndexKey {
+            name: String::from_utf8(bytes[4..name_end].to_vec()).expect("Invalid index key"),
+            timestamp: u64::from_be_bytes(bytes[name_end..name_end + 8].try_into().expect("Truncated index key")),
+            transaction_id: String::from_utf8(bytes[name_end + 8..].to_vec()).expect("Invalid index key"),
         }
     }
+    
+    const BOUND: Bound = Bound::Unbounded;
 }
 
-// Candid interface
-candid::export_service!();
+// Key of the archive's address index: an address a transaction touches, then its archive sequence
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ArchivedAddressKey {
+    address: String,
+    seq: u64,
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl ArchivedAddressKey {
+    fn new(address: &str, seq: u64) -> Self {
+        ArchivedAddressKey { address: address.to_string(), seq }
+    }
+}
+
+impl Storable for ArchivedAddressKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(12 + self.address.len());
+        bytes.extend_from_slice(&(self.address.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.address.as_bytes());
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+        Cow::Owned(bytes)
+    }
     
-    #[test]
-    fn test_transaction_creation() {
-        // Test transaction creation logic
-        let transaction_id = "test_transaction_123".to_string();
-        assert!(transaction_id.contains("test"));
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let address_end = 4 + u32::from_be_bytes(bytes[0..4].try_into().expect("Truncated archived address key")) as usize;
+        ArchivedAddressKey {
+            address: String::from_utf8(bytes[4..address_end].to_vec()).expect("Invalid archived address key"),
+            seq: u64::from_be_bytes(bytes[address_end..address_end + 8].try_into().expect("Truncated archived address key")),
+        }
     }
-} 
\ No newline at end of file
+    
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+type ArchiveLog<M> = StableLog<FundTransaction, M, M>;
+type ArchiveIds<M> = StableBTreeMap<String, u64, M>;
+type ArchiveAddresses<M> = StableBTreeMap<ArchivedAddressKey, (), M>;
+type TxFeed<M> = StableBTreeMap<u64, TxEvent, M>;
+type TransactionMap = StableBTreeMap<String, FundTransaction, Memory>;
+type TxIndex<M> = StableBTreeMap<IndexKey, (), M>;
+
+// The original canister's heap state, saved whole by its pre_upgrade; only read once,
+// by the first post_upgrade on the stable layout
+type BaselineHeapState = (
+    BTreeMap<String, FundTransaction>,
+    BTreeMap<String, FundBalance>,
+    BTreeMap<String, DistrictFunds>,
+    LegacyFundAnalytics,
+    LegacyRealTimeMetrics,
+);
+
+const ARCHIVE_INDEX_MEMORY_ID: MemoryId = MemoryId::new(1);
+const ARCHIVE_DATA_MEMORY_ID: MemoryId = MemoryId::new(2);
+const ARCHIVE_IDS_MEMORY_ID: MemoryId = MemoryId::new(3);
+const TX_FEED_MEMORY_ID: MemoryId = MemoryId::new(4);
+const TRANSACTIONS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const FUND_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(6);
+const DISTRICT_FUNDS_MEMORY_ID: MemoryId = MemoryId::new(7);
+const FUND_ANALYTICS_MEMORY_ID: MemoryId = MemoryId::new(8);
+const REAL_TIME_METRICS_MEMORY_ID: MemoryId = MemoryId::new(9);
+const UPGRADE_STATE_MEMORY_ID: MemoryId = MemoryId::new(10);
+const POLICY_TX_INDEX_MEMORY_ID: MemoryId = MemoryId::new(11);
+const TIME_TX_INDEX_MEMORY_ID: MemoryId = MemoryId::new(12);
+const ADDRESS_TX_INDEX_MEMORY_ID: MemoryId = MemoryId::new(13);
+const FLAGGED_TX_INDEX_MEMORY_ID: MemoryId = MemoryId::new(14);
+const RECEIPTS_MEMORY_ID: MemoryId = MemoryId::new(15);
+const ADDRESS_LEDGER_MEMORY_ID: MemoryId = MemoryId::new(16);
+const DEDUP_KEYS_MEMORY_ID: MemoryId = MemoryId::new(17);
+const ARCHIVED_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(18);
+const CATEGORY_BREAKDOWN_MEMORY_ID: MemoryId = MemoryId::new(19);
+const POLICY_CATEGORIES_MEMORY_ID: MemoryId = MemoryId::new(20);
+const POLICY_DISTRICTS_MEMORY_ID: MemoryId = MemoryId::new(21);
+const TERMINAL_COUNTS_MEMORY_ID: MemoryId = MemoryId::new(22);
+const POLICY_AMOUNTS_MEMORY_ID: MemoryId = MemoryId::new(23);
+const POLICY_ADDRESSES_MEMORY_ID: MemoryId = MemoryId::new(24);
+const ARCHIVED_FISCAL_YEARS_MEMORY_ID: MemoryId = MemoryId::new(25);
+const ARCHIVED_RELEASES_MEMORY_ID: MemoryId = MemoryId::new(26);
+const ARCHIVE_ADDRESSES_MEMORY_ID: MemoryId = MemoryId::new(27);
+
+// Events kept in the feed before the oldest are trimmed
+const MAX_FEED_EVENTS: u64 = 20_000;
+
+const DEFAULT_METRICS_REFRESH_SECS: u64 = 300;
+const DEFAULT_STUCK_PROCESSING_SECS: u64 = 3600;
+
+const FLAG_ABOVE_PERCENTILE: &str = "amount_above_percentile";
+const FLAG_ABOVE_THRESHOLD: &str = "amount_above_threshold";
+const FLAG_REPEATED_AMOUNT: &str = "repeated_amount";
+const FLAG_NEW_RECIPIENT: &str = "new_recipient";
+
+// Bounds the work of a single prune_transactions call
+const MAX_PRUNE_BATCH: u32 = 1_000;
+
+// Amounts of each type kept per policy for the percentile rule
+const ANOMALY_AMOUNT_WINDOW: usize = 200;
+
+// ICRC-1 ledger interface, limited to what execute_release_on_ledger needs
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct TransferArg {
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// Subset of smart_policy's Policy record; candid ignores the remaining fields
+#[derive(CandidType, Deserialize)]
+struct PolicySummary {
+    category: String,
+    district: String,
+}
+
+// The part of smart_policy's Policy record that reconciliation compares
+#[derive(CandidType, Deserialize)]
+struct PolicyFunds {
+    fund_released: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct FundsReconciliation {
+    pub policy_id: String,
+    // smart_policy counts a release as soon as it is requested, so both sides include
+    // releases that are still in flight; amounts are compared as recorded (paise)
+    pub smart_policy_released: u64,
+    pub fund_tracker_released: u64,
+    pub release_transactions: u32,
+    pub consistent: bool,
+    pub discrepancy: Option<String>,
+    pub checked_at: u64,
+}
+
+const PAISE_PER_RUPEE: u64 = 100;
+
+const NANOS_PER_HOUR: u64 = 3_600_000_000_000;
+const NANOS_PER_DAY: u64 = 86_400_000_000_000;
+
+const UNCATEGORIZED: &str = "uncategorized";
+const UNKNOWN_DISTRICT: &str = "unknown";
+
+// Fiscal years follow Indian Standard Time, UTC+05:30
+const IST_OFFSET_NS: u64 = 19_800_000_000_000;
+const MIN_FISCAL_YEAR: u32 = 1970;
+// The last year whose end still fits in u64 nanoseconds with room to spare
+const MAX_FISCAL_YEAR: u32 = 2500;
+
+// Keeps CSV responses below the 2MB reply limit with room for candid framing
+const MAX_CSV_ROWS: usize = 10_000;
+const MAX_CSV_BYTES: usize = 1_900_000;
+
+// Rows per streamed CSV chunk; small enough that a chunk never nears the reply limit
+const CSV_CHUNK_ROWS: usize = 1_000;
+
+// Upper bound on page size so responses stay well under the message limit
+const MAX_PAGE_SIZE: u32 = 100;
+
+const MAX_TOP_N: u32 = 100;
+
+// Upper bound on record_transactions_batch items handled per call
+const MAX_BATCH_SIZE: usize = 100;
+
+static mut SMART_POLICY_CANISTER_ID: Option<Principal> = None;
+// When set, Release transactions wait for execute_release_on_ledger instead of auto-completing
+static mut LEDGER_CANISTER_ID: Option<Principal> = None;
+// 0 disables the periodic metrics refresh
+static mut METRICS_REFRESH_SECS: u64 = DEFAULT_METRICS_REFRESH_SECS;
+static mut METRICS_TIMER: Option<TimerId> = None;
+// How long a transaction may sit in Processing before the sweep reports it as stuck
+static mut STUCK_PROCESSING_SECS: u64 = DEFAULT_STUCK_PROCESSING_SECS;
+// Ids found stuck in Processing by the latest metrics sweep
+static mut STUCK_TRANSACTIONS: Vec<String> = Vec::new();
+// Latest time a transaction was stored, changed status or left the hot map
+static mut LAST_TX_ACTIVITY: u64 = 0;
+// Earliest time a counted transaction ages out of one of the metrics windows
+static mut METRICS_VALID_UNTIL: u64 = 0;
+// Principals besides controllers and the smart_policy canister allowed to write transactions
+static mut TRUSTED_CALLERS: Option<BTreeSet<Principal>> = None;
+// Heap copy of RECEIPTS for building the certified receipt tree; loaded on upgrade
+static mut RECEIPT_HASHES: Option<BTreeMap<String, [u8; 32]>> = None;
+static mut ANOMALY_CONFIG: Option<AnomalyConfig> = None;
+// Set at install; canisters installed before it existed start counting at their next upgrade
+static mut INIT_TIME_NS: Option<u64> = None;
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+    
+    // Append-only archive of pruned transactions; ARCHIVE_IDS maps id -> log sequence
+    static ARCHIVE: RefCell<ArchiveLog<Memory>> = RefCell::new(
+        StableLog::init(memory(ARCHIVE_INDEX_MEMORY_ID), memory(ARCHIVE_DATA_MEMORY_ID))
+            .expect("Failed to initialize transaction archive")
+    );
+    static ARCHIVE_IDS: RefCell<ArchiveIds<Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(ARCHIVE_IDS_MEMORY_ID)));
+    // (address, sequence) of every archived transaction, for address queries that reach the archive
+    static ARCHIVE_ADDRESSES: RefCell<ArchiveAddresses<Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(ARCHIVE_ADDRESSES_MEMORY_ID)));
+    
+    // seq -> creation and status-change events, polled through get_transactions_since
+    static TX_FEED: RefCell<TxFeed<Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(TX_FEED_MEMORY_ID)));
+    
+    // Stable storage for fund tracking data
+    static TRANSACTIONS: RefCell<TransactionMap> =
+        RefCell::new(StableBTreeMap::init(memory(TRANSACTIONS_MEMORY_ID)));
+    static FUND_BALANCES: RefCell<StableBTreeMap<String, FundBalance, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(FUND_BALANCES_MEMORY_ID)));
+    static DISTRICT_FUNDS: RefCell<StableBTreeMap<String, DistrictFunds, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(DISTRICT_FUNDS_MEMORY_ID)));
+    static FUND_ANALYTICS: RefCell<StableCell<FundAnalytics, Memory>> = RefCell::new(
+        StableCell::init(memory(FUND_ANALYTICS_MEMORY_ID), empty_analytics())
+            .expect("Failed to initialize fund analytics cell")
+    );
+    static REAL_TIME_METRICS: RefCell<StableCell<RealTimeMetrics, Memory>> = RefCell::new(
+        StableCell::init(memory(REAL_TIME_METRICS_MEMORY_ID), empty_metrics())
+            .expect("Failed to initialize real-time metrics cell")
+    );
+    static UPGRADE_STATE: RefCell<StableCell<UpgradeState, Memory>> = RefCell::new(
+        StableCell::init(memory(UPGRADE_STATE_MEMORY_ID), UpgradeState::default())
+            .expect("Failed to initialize upgrade state cell")
+    );
+    
+    // Hot transactions by policy, by time and by address; maintained as transactions are
+    // stored and archived
+    static POLICY_TX_INDEX: RefCell<TxIndex<Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(POLICY_TX_INDEX_MEMORY_ID)));
+    static TIME_TX_INDEX: RefCell<TxIndex<Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(TIME_TX_INDEX_MEMORY_ID)));
+    static ADDRESS_TX_INDEX: RefCell<TxIndex<Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(ADDRESS_TX_INDEX_MEMORY_ID)));
+    // Hot transactions with open anomaly flags, by time
+    static FLAGGED_TX_INDEX: RefCell<TxIndex<Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(FLAGGED_TX_INDEX_MEMORY_ID)));
+    
+    // transaction_id -> SHA-256 of the candid-encoded terminal transaction, the leaves of the
+    // certified receipt tree; archived receipts stay so they remain provable after pruning
+    static RECEIPTS: RefCell<StableBTreeMap<String, [u8; 32], Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(RECEIPTS_MEMORY_ID)));
+    // Completed flows per address
+    static ADDRESS_LEDGER: RefCell<StableBTreeMap<String, AddressBalance, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(ADDRESS_LEDGER_MEMORY_ID)));
+    // dedup_key -> first transaction recorded with it, pruned by the metrics timer
+    static DEDUP_KEYS: RefCell<StableBTreeMap<String, DedupEntry, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(DEDUP_KEYS_MEMORY_ID)));
+    // Per-policy totals of archived transactions, the starting point for reconciliation
+    static ARCHIVED_BALANCES: RefCell<StableBTreeMap<String, FundBalance, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(ARCHIVED_BALANCES_MEMORY_ID)));
+    // Fiscal start year -> archived completed volume, so reports never read the archive
+    static ARCHIVED_FISCAL_YEARS: RefCell<StableBTreeMap<u32, FiscalYearTotals, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(ARCHIVED_FISCAL_YEARS_MEMORY_ID)));
+    // policy_id -> archived releases, so reconciliation never reads the archive
+    static ARCHIVED_RELEASES: RefCell<StableBTreeMap<String, ArchivedReleases, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(ARCHIVED_RELEASES_MEMORY_ID)));
+    static CATEGORY_BREAKDOWN: RefCell<StableBTreeMap<String, CategoryBreakdown, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(CATEGORY_BREAKDOWN_MEMORY_ID)));
+    // policy_id -> category, resolved from metadata or the smart_policy canister
+    static POLICY_CATEGORIES: RefCell<StableBTreeMap<String, String, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(POLICY_CATEGORIES_MEMORY_ID)));
+    // policy_id -> district, so later transactions inherit the district of earlier ones
+    static POLICY_DISTRICTS: RefCell<StableBTreeMap<String, String, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(POLICY_DISTRICTS_MEMORY_ID)));
+    // Transaction type name -> terminal status counts, the source of success_rate
+    static TERMINAL_COUNTS: RefCell<StableBTreeMap<String, TerminalCounts, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(TERMINAL_COUNTS_MEMORY_ID)));
+    // What the anomaly rules know of each policy's past; kept when transactions are archived
+    static POLICY_AMOUNTS: RefCell<StableBTreeMap<String, PolicyAmounts, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(POLICY_AMOUNTS_MEMORY_ID)));
+    static POLICY_ADDRESSES: RefCell<StableBTreeMap<PolicyAddress, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(POLICY_ADDRESSES_MEMORY_ID)));
+}
+
+fn memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|manager| manager.borrow().get(id))
+}
+
+// Point lookups and full scans over stored transactions, so the query helpers work on
+// the stable map in the canister and on plain maps in tests
+trait TransactionStore {
+    fn lookup(&self, transaction_id: &str) -> Option<FundTransaction>;
+    fn scan(&self) -> Box<dyn Iterator<Item = FundTransaction> + '_>;
+}
+
+impl TransactionStore for BTreeMap<String, FundTransaction> {
+    fn lookup(&self, transaction_id: &str) -> Option<FundTransaction> {
+        self.get(transaction_id).cloned()
+    }
+    
+    fn scan(&self) -> Box<dyn Iterator<Item = FundTransaction> + '_> {
+        Box::new(self.values().cloned())
+    }
+}
+
+impl<M: ic_stable_structures::Memory> TransactionStore for StableBTreeMap<String, FundTransaction, M> {
+    fn lookup(&self, transaction_id: &str) -> Option<FundTransaction> {
+        self.get(&transaction_id.to_string())
+    }
+    
+    fn scan(&self) -> Box<dyn Iterator<Item = FundTransaction> + '_> {
+        Box::new(self.iter().map(|(_, transaction)| transaction))
+    }
+}
+
+fn stored_transaction(transaction_id: &str) -> Option<FundTransaction> {
+    TRANSACTIONS.with(|transactions| transactions.borrow().lookup(transaction_id))
+}
+
+// Applies `change` to a copy of the stored transaction and writes it back only on success
+fn update_stored_transaction<R>(
+    transaction_id: &str,
+    change: impl FnOnce(&mut FundTransaction) -> Result<R, String>,
+) -> Result<R, String> {
+    let mut transaction = stored_transaction(transaction_id).ok_or("Transaction not found".to_string())?;
+    let result = change(&mut transaction)?;
+    store_transaction(transaction);
+    Ok(result)
+}
+
+// Every write goes through here so a terminal transaction's receipt always matches what is stored
+fn store_transaction(transaction: FundTransaction) {
+    if is_terminal(&transaction.status) {
+        store_receipt(&transaction);
+        certify_receipts();
+    }
+    TRANSACTIONS.with(|transactions| transactions.borrow_mut().insert(transaction.id.clone(), transaction));
+}
+
+fn store_receipt(transaction: &FundTransaction) {
+    let hash = receipt_hash(transaction);
+    RECEIPTS.with(|receipts| receipts.borrow_mut().insert(transaction.id.clone(), hash));
+    unsafe {
+        if let Some(ref mut receipts) = RECEIPT_HASHES {
+            receipts.insert(transaction.id.clone(), hash);
+        }
+    }
+}
+
+// Files a hot transaction under its policy, its timestamp and both of its addresses
+fn index_transaction(transaction: &FundTransaction) {
+    POLICY_TX_INDEX.with(|index| index.borrow_mut().insert(policy_key(transaction), ()));
+    TIME_TX_INDEX.with(|index| index.borrow_mut().insert(time_key(transaction), ()));
+    ADDRESS_TX_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for key in address_keys(transaction) {
+            index.insert(key, ());
+        }
+    });
+}
+
+fn unindex_transaction(transaction: &FundTransaction) {
+    POLICY_TX_INDEX.with(|index| index.borrow_mut().remove(&policy_key(transaction)));
+    TIME_TX_INDEX.with(|index| index.borrow_mut().remove(&time_key(transaction)));
+    FLAGGED_TX_INDEX.with(|index| index.borrow_mut().remove(&time_key(transaction)));
+    ADDRESS_TX_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for key in address_keys(transaction) {
+            index.remove(&key);
+        }
+    });
+}
+
+// Runs `change` on heap copies of the given entries of a stable map and writes back every
+// entry it leaves, so the roll-up helpers work on plain BTreeMaps
+fn modify_entries<V: Storable, M: ic_stable_structures::Memory, R>(
+    map: &mut StableBTreeMap<String, V, M>,
+    keys: &[&str],
+    change: impl FnOnce(&mut BTreeMap<String, V>) -> R,
+) -> R {
+    let mut entries: BTreeMap<String, V> = keys.iter()
+        .filter_map(|key| map.get(&key.to_string()).map(|value| (key.to_string(), value)))
+        .collect();
+    let result = change(&mut entries);
+    for (key, value) in entries {
+        map.insert(key, value);
+    }
+    result
+}
+
+fn stored_balance(policy_id: &str) -> Option<FundBalance> {
+    FUND_BALANCES.with(|balances| balances.borrow().get(&policy_id.to_string()))
+}
+
+fn store_balance(balance: FundBalance) {
+    FUND_BALANCES.with(|balances| balances.borrow_mut().insert(balance.policy_id.clone(), balance));
+}
+
+fn fund_analytics() -> FundAnalytics {
+    FUND_ANALYTICS.with(|cell| cell.borrow().get().clone())
+}
+
+fn modify_analytics<R>(change: impl FnOnce(&mut FundAnalytics) -> R) -> R {
+    let mut analytics = fund_analytics();
+    let result = change(&mut analytics);
+    FUND_ANALYTICS.with(|cell| cell.borrow_mut().set(analytics))
+        .expect("Failed to save fund analytics");
+    result
+}
+
+#[init]
+fn init() {
+    unsafe {
+        TRUSTED_CALLERS = Some(BTreeSet::new());
+        RECEIPT_HASHES = Some(BTreeMap::new());
+        ANOMALY_CONFIG = Some(default_anomaly_config());
+        INIT_TIME_NS = Some(ic_cdk::api::time());
+    }
+    certify_receipts();
+    
+    start_metrics_timer();
+}
+
+fn empty_analytics() -> FundAnalytics {
+    FundAnalytics {
+        total_funds_allocated: 0,
+        total_funds_released: 0,
+        total_fees: Some(0),
+        total_transactions: 0,
+        sum_of_transaction_amounts: Some(0),
+        average_transaction_amount: 0.0,
+        district_distribution: BTreeMap::new(),
+        category_distribution: BTreeMap::new(),
+        type_distribution: Some(BTreeMap::new()),
+        type_counts: Some(BTreeMap::new()),
+        monthly_trends: BTreeMap::new(),
+        success_rate: None,
+        canonical_unit: Some(Denomination::Paise),
+    }
+}
+
+fn empty_metrics() -> RealTimeMetrics {
+    RealTimeMetrics {
+        current_time: 0,
+        active_transactions: 0,
+        pending_amount: 0,
+        daily_volume: 0,
+        weekly_volume: 0,
+        monthly_volume: 0,
+        hourly_volume: 0,
+        volume_by_type: BTreeMap::new(),
+        completed_last_hour: 0,
+    }
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    // Everything but the settings already lives in stable memory
+    let state = unsafe {
+        UpgradeState {
+            smart_policy_canister_id: SMART_POLICY_CANISTER_ID,
+            ledger_canister_id: LEDGER_CANISTER_ID,
+            metrics_refresh_secs: Some(METRICS_REFRESH_SECS),
+            trusted_callers: TRUSTED_CALLERS.take(),
+            anomaly_config: ANOMALY_CONFIG.take(),
+            stuck_processing_secs: Some(STUCK_PROCESSING_SECS),
+            init_time_ns: INIT_TIME_NS,
+        }
+    };
+    UPGRADE_STATE.with(|cell| cell.borrow_mut().set(state))
+        .expect("Failed to save state");
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    // Canisters upgraded from the original heap-only release have no memory manager
+    // header yet; read their state before the manager claims stable memory
+    let mut magic = [0u8; 3];
+    ic_cdk::api::stable::stable_read(0, &mut magic);
+    if &magic != b"MGR" {
+        let state: BaselineHeapState = ic_cdk::storage::stable_restore()
+            .expect("Failed to restore original heap state");
+        migrate_baseline_state(state);
+    }
+    
+    let state = UPGRADE_STATE.with(|cell| cell.borrow().get().clone());
+    unsafe {
+        SMART_POLICY_CANISTER_ID = state.smart_policy_canister_id;
+        LEDGER_CANISTER_ID = state.ledger_canister_id;
+        METRICS_REFRESH_SECS = state.metrics_refresh_secs.unwrap_or(DEFAULT_METRICS_REFRESH_SECS);
+        TRUSTED_CALLERS = Some(state.trusted_callers.unwrap_or_default());
+        ANOMALY_CONFIG = Some(state.anomaly_config.unwrap_or_else(default_anomaly_config));
+        STUCK_PROCESSING_SECS = state.stuck_processing_secs.unwrap_or(DEFAULT_STUCK_PROCESSING_SECS);
+        INIT_TIME_NS = Some(state.init_time_ns.unwrap_or_else(ic_cdk::api::time));
+        // Certified data is cleared by an upgrade; the tree is rebuilt from the stored hashes
+        RECEIPT_HASHES = Some(RECEIPTS.with(|receipts| receipts.borrow().iter().collect()));
+    }
+    certify_receipts();
+    
+    // Timers do not survive an upgrade
+    start_metrics_timer();
+}
+
+// Moves the original heap state into the stable maps and builds the indexes and running
+// totals that release did not keep; runs once, on the first upgrade to the stable layout
+fn migrate_baseline_state(state: BaselineHeapState) {
+    let (mut transactions, fund_balances, district_funds, analytics, metrics) = state;
+    
+    let assumed = assume_paise(transactions.values_mut());
+    if assumed > 0 {
+        ic_cdk::println!("Assumed paise for {} transactions recorded without a denomination", assumed);
+    }
+    
+    // The original rate was a placeholder; recompute it from real statuses
+    let terminal_counts = build_terminal_counts(transactions.values());
+    let mut analytics: FundAnalytics = analytics.into();
+    analytics.success_rate = overall_success_rate(&terminal_counts);
+    let address_ledger = build_address_ledger(transactions.values());
+    
+    let mut transactions: Vec<FundTransaction> = transactions.into_values().collect();
+    transactions.sort_by_key(|transaction| transaction.timestamp);
+    for transaction in transactions {
+        index_transaction(&transaction);
+        note_for_anomaly_checks(&transaction);
+        if is_terminal(&transaction.status) {
+            let hash = receipt_hash(&transaction);
+            RECEIPTS.with(|receipts| receipts.borrow_mut().insert(transaction.id.clone(), hash));
+        }
+        TRANSACTIONS.with(|map| map.borrow_mut().insert(transaction.id.clone(), transaction));
+    }
+    FUND_BALANCES.with(|map| {
+        let mut map = map.borrow_mut();
+        fund_balances.into_iter().for_each(|(policy_id, balance)| { map.insert(policy_id, balance); });
+    });
+    DISTRICT_FUNDS.with(|map| {
+        let mut map = map.borrow_mut();
+        district_funds.into_iter().for_each(|(district, funds)| { map.insert(district, funds); });
+    });
+    TERMINAL_COUNTS.with(|map| {
+        let mut map = map.borrow_mut();
+        terminal_counts.into_iter().for_each(|(name, counts)| { map.insert(name, counts); });
+    });
+    ADDRESS_LEDGER.with(|map| {
+        let mut map = map.borrow_mut();
+        address_ledger.into_iter().for_each(|(address, balance)| { map.insert(address, balance); });
+    });
+    FUND_ANALYTICS.with(|cell| cell.borrow_mut().set(analytics))
+        .expect("Failed to migrate fund analytics");
+    REAL_TIME_METRICS.with(|cell| cell.borrow_mut().set(metrics.into()))
+        .expect("Failed to migrate real-time metrics");
+    
+    // Fills in the breakdowns the original metrics did not carry
+    refresh_real_time_metrics(ic_cdk::api::time());
+}
+
+// (Re)registers the periodic metrics update, replacing any existing timer
+fn start_metrics_timer() {
+    let interval = metrics_timer_interval(unsafe { METRICS_REFRESH_SECS });
+    unsafe {
+        if let Some(timer_id) = METRICS_TIMER.take() {
+            clear_timer(timer_id);
+        }
+        if let Some(interval) = interval {
+            METRICS_TIMER = Some(set_timer_interval(interval, || {
+                ic_cdk::spawn(update_real_time_metrics());
+            }));
+        }
+    }
+}
+
+// 0 disables the periodic refresh
+fn metrics_timer_interval(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+#[update]
+fn set_metrics_interval(secs: u64) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can change the metrics interval".to_string());
+    }
+    
+    unsafe {
+        METRICS_REFRESH_SECS = secs;
+    }
+    start_metrics_timer();
+    Ok(())
+}
+
+#[query]
+fn get_metrics_interval() -> u64 {
+    unsafe { METRICS_REFRESH_SECS }
+}
+
+#[update]
+async fn refresh_metrics_now() -> Result<RealTimeMetrics, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can refresh metrics".to_string());
+    }
+    
+    refresh_real_time_metrics(ic_cdk::api::time());
+    Ok(get_real_time_metrics())
+}
+
+#[update]
+#[allow(clippy::too_many_arguments)]
+async fn record_transaction(
+    policy_id: String,
+    transaction_type: TransactionType,
+    amount: u64,
+    denomination: Denomination,
+    from_address: String,
+    to_address: String,
+    mut metadata: BTreeMap<String, String>,
+    dedup_key: Option<String>,
+    override_allocation: bool,
+) -> Result<String, String> {
+    authorize_writer()?;
+    let amount = normalize_to_paise(amount, &denomination, &mut metadata)?;
+    if override_allocation {
+        authorize_override(&mut metadata)?;
+    }
+    
+    let (category, district) = resolve_policy_tags(&policy_id, &metadata).await;
+    metadata.entry("category".to_string()).or_insert_with(|| category.clone());
+    if let Some(ref district) = district {
+        metadata.entry("district".to_string()).or_insert_with(|| district.clone());
+    }
+    
+    let transaction_id = Uuid::new_v4().to_string();
+    let now = ic_cdk::api::time();
+    
+    // Checked after the lookup above so no other call can interleave before the insert
+    let dedup_entry = DedupEntry {
+        transaction_id: transaction_id.clone(),
+        policy_id: policy_id.clone(),
+        amount,
+        from_address: from_address.clone(),
+        to_address: to_address.clone(),
+        recorded_at: now,
+    };
+    if let Some(ref key) = dedup_key {
+        let existing = DEDUP_KEYS.with(|keys| check_dedup_key(&keys.borrow(), key, &dedup_entry, now).transpose());
+        if let Some(existing) = existing {
+            return existing;
+        }
+    }
+    check_spending_limits(stored_balance(&policy_id).as_ref(), &transaction_type, amount, override_allocation)?;
+    
+    let transaction = FundTransaction {
+        id: transaction_id.clone(),
+        policy_id: policy_id.clone(),
+        transaction_type: transaction_type.clone(),
+        amount,
+        from_address,
+        to_address,
+        timestamp: now,
+        status: TransactionStatus::Processing,
+        transaction_hash: format!("tx_{}", Uuid::new_v4().to_string()),
+        metadata,
+        status_history: Some(vec![(TransactionStatus::Processing, now)]),
+        seq: None,
+        denomination: Some(Denomination::Paise),
+        anomaly_flags: None,
+    };
+    
+    // Store transaction
+    insert_transaction(transaction);
+    if let Some(key) = dedup_key {
+        DEDUP_KEYS.with(|keys| keys.borrow_mut().insert(key, dedup_entry));
+    }
+    
+    roll_up_transaction(&transaction_id, now);
+    start_processing(&transaction_id, &transaction_type);
+    
+    Ok(transaction_id)
+}
+
+// Items are validated and stored one by one, so a bad row only fails itself; balances
+// and analytics are then rolled up in one pass. At most MAX_BATCH_SIZE items are
+// processed per call and any beyond the cap are rejected individually.
+#[update]
+async fn record_transactions_batch(items: Vec<RecordTxRequest>) -> Vec<Result<String, String>> {
+    if let Err(e) = authorize_writer() {
+        return items.iter().map(|_| Err(e.clone())).collect();
+    }
+    
+    // Resolve each policy's tags up front so the rest of the batch runs without awaiting
+    let mut policy_tags: BTreeMap<String, (String, Option<String>)> = BTreeMap::new();
+    for item in items.iter().take(MAX_BATCH_SIZE) {
+        if !policy_tags.contains_key(&item.policy_id) {
+            let tags = resolve_policy_tags(&item.policy_id, &item.metadata).await;
+            policy_tags.insert(item.policy_id.clone(), tags);
+        }
+    }
+    
+    let now = ic_cdk::api::time();
+    let mut staged_balances: BTreeMap<String, FundBalance> = BTreeMap::new();
+    let mut recorded: Vec<(String, TransactionType)> = Vec::new();
+    let mut results = Vec::with_capacity(items.len());
+    
+    for (position, mut item) in items.into_iter().enumerate() {
+        if position >= MAX_BATCH_SIZE {
+            results.push(Err(format!("Batch is limited to {} items", MAX_BATCH_SIZE)));
+            continue;
+        }
+        if item.override_allocation {
+            if let Err(e) = authorize_override(&mut item.metadata) {
+                results.push(Err(e));
+                continue;
+            }
+        }
+        match normalize_to_paise(item.amount, &item.denomination, &mut item.metadata) {
+            Ok(paise) => {
+                item.amount = paise;
+                item.denomination = Denomination::Paise;
+            }
+            Err(e) => {
+                results.push(Err(e));
+                continue;
+            }
+        }
+        let (category, district) = policy_tags.get(&item.policy_id).cloned()
+            .unwrap_or_else(|| (UNCATEGORIZED.to_string(), None));
+        let result = stage_batch_item(item, &category, district, &mut staged_balances, now);
+        if let Ok((ref transaction_id, ref transaction_type)) = result {
+            recorded.push((transaction_id.clone(), transaction_type.clone()));
+        }
+        results.push(result.map(|(transaction_id, _)| transaction_id));
+    }
+    
+    for (transaction_id, transaction_type) in &recorded {
+        roll_up_transaction(transaction_id, now);
+        start_processing(transaction_id, transaction_type);
+    }
+    results
+}
+
+// Reallocates unspent budget: a Transfer out of from_policy and an Allocation into to_policy,
+// both settled immediately since no funds leave the treasury
+#[update]
+async fn transfer_between_policies(
+    from_policy: String,
+    to_policy: String,
+    amount: u64,
+    reason: String,
+) -> Result<PolicyTransfer, String> {
+    authorize_writer()?;
+    if from_policy == to_policy {
+        return Err("Source and destination policies must differ".to_string());
+    }
+    if amount == 0 {
+        return Err("Transfer amount must be greater than zero".to_string());
+    }
+    if reason.trim().is_empty() {
+        return Err("A reason is required".to_string());
+    }
+    
+    let no_metadata = BTreeMap::new();
+    let from_tags = resolve_policy_tags(&from_policy, &no_metadata).await;
+    let to_tags = resolve_policy_tags(&to_policy, &no_metadata).await;
+    
+    // Nothing below awaits, so the balance check and every write land in one message
+    check_sufficient_balance(stored_balance(&from_policy).as_ref(), &TransactionType::Transfer, amount)?;
+    
+    let now = ic_cdk::api::time();
+    let transfer = PolicyTransfer {
+        transfer_group_id: Uuid::new_v4().to_string(),
+        transfer_out_id: Uuid::new_v4().to_string(),
+        allocation_in_id: Uuid::new_v4().to_string(),
+    };
+    let (transfer_out, allocation_in) = policy_transfer_legs(&transfer, &from_policy, &to_policy, amount, &reason, &from_tags, &to_tags, now);
+    
+    for (policy_id, transaction_type) in [(&from_policy, &TransactionType::Transfer), (&to_policy, &TransactionType::Allocation)] {
+        let mut balance = stored_balance(policy_id).unwrap_or_else(|| empty_balance(policy_id));
+        apply_to_balance(&mut balance, transaction_type, amount, now);
+        store_balance(balance);
+        record_terminal_status(transaction_type, &TransactionStatus::Completed);
+    }
+    
+    if let Some(ref district) = from_tags.1 {
+        DISTRICT_FUNDS.with(|district_funds| {
+            let mut district_funds = district_funds.borrow_mut();
+            if let Some(mut funds) = district_funds.get(district) {
+                withdraw_district_allocation(&mut funds, amount, now);
+                district_funds.insert(district.clone(), funds);
+            }
+        });
+    }
+    if let Some(ref district) = to_tags.1 {
+        update_district_funds(district, &to_policy, &TransactionType::Allocation, amount, now);
+    }
+    
+    CATEGORY_BREAKDOWN.with(|breakdown| {
+        modify_entries(&mut breakdown.borrow_mut(), &[&from_tags.0, &to_tags.0], |breakdown| {
+            modify_analytics(|analytics| {
+                apply_policy_transfer(analytics, breakdown, &from_tags.0, &to_tags.0, amount);
+                add_to_monthly_trend(&mut analytics.monthly_trends, now, amount);
+            });
+        })
+    });
+    ADDRESS_LEDGER.with(|ledger| {
+        let addresses = [transfer_out.from_address.as_str(), transfer_out.to_address.as_str()];
+        modify_entries(&mut ledger.borrow_mut(), &addresses, |ledger| apply_to_ledger(ledger, &transfer_out, now))
+    });
+    
+    insert_transaction(transfer_out);
+    insert_transaction(allocation_in);
+    Ok(transfer)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn policy_transfer_legs(
+    transfer: &PolicyTransfer,
+    from_policy: &str,
+    to_policy: &str,
+    amount: u64,
+    reason: &str,
+    from_tags: &(String, Option<String>),
+    to_tags: &(String, Option<String>),
+    now: u64,
+) -> (FundTransaction, FundTransaction) {
+    let leg = |id: &str, policy_id: &str, transaction_type: TransactionType, counterpart: &str, (category, district): &(String, Option<String>)| {
+        let mut metadata = BTreeMap::new();
+        metadata.insert("transfer_group_id".to_string(), transfer.transfer_group_id.clone());
+        metadata.insert("counterpart_policy_id".to_string(), counterpart.to_string());
+        metadata.insert("reason".to_string(), reason.to_string());
+        metadata.insert("category".to_string(), category.clone());
+        if let Some(district) = district {
+            metadata.insert("district".to_string(), district.clone());
+        }
+        FundTransaction {
+            id: id.to_string(),
+            policy_id: policy_id.to_string(),
+            transaction_type,
+            amount,
+            from_address: format!("policy:{}", from_policy),
+            to_address: format!("policy:{}", to_policy),
+            timestamp: now,
+            status: TransactionStatus::Completed,
+            transaction_hash: format!("tx_{}", id),
+            metadata,
+            status_history: Some(vec![(TransactionStatus::Completed, now)]),
+            seq: None,
+            denomination: Some(Denomination::Paise),
+            anomaly_flags: None,
+        }
+    };
+    (
+        leg(&transfer.transfer_out_id, from_policy, TransactionType::Transfer, to_policy, from_tags),
+        leg(&transfer.allocation_in_id, to_policy, TransactionType::Allocation, from_policy, to_tags),
+    )
+}
+
+// The incoming half of a policy transfer; the outgoing Transfer already carries the volume
+fn is_reallocation_credit(transaction: &FundTransaction) -> bool {
+    matches!(transaction.transaction_type, TransactionType::Allocation)
+        && transaction.metadata.contains_key("transfer_group_id")
+}
+
+// Both legs count as transactions, but the money was allocated once: what the destination
+// gains in allocation the source category gives up
+fn apply_policy_transfer(
+    analytics: &mut FundAnalytics,
+    breakdown: &mut BTreeMap<String, CategoryBreakdown>,
+    from_category: &str,
+    to_category: &str,
+    amount: u64,
+) {
+    apply_to_analytics(analytics, &TransactionType::Transfer, amount);
+    apply_to_analytics(analytics, &TransactionType::Allocation, amount);
+    analytics.total_funds_allocated = analytics.total_funds_allocated.saturating_sub(amount as u128);
+    
+    apply_to_category(breakdown, &mut analytics.category_distribution, from_category, &TransactionType::Transfer, amount);
+    if let Some(entry) = breakdown.get_mut(from_category) {
+        entry.total_allocated = entry.total_allocated.saturating_sub(amount);
+    }
+    if let Some(total) = analytics.category_distribution.get_mut(from_category) {
+        *total = total.saturating_sub(amount);
+    }
+    apply_to_category(breakdown, &mut analytics.category_distribution, to_category, &TransactionType::Allocation, amount);
+}
+
+fn withdraw_district_allocation(funds: &mut DistrictFunds, amount: u64, now: u64) {
+    funds.total_allocated = funds.total_allocated.saturating_sub(amount);
+    funds.completion_rate = district_completion_rate(funds);
+    funds.last_updated = now;
+}
+
+#[update]
+async fn update_transaction_status(
+    transaction_id: String,
+    status: TransactionStatus,
+) -> Result<(), String> {
+    authorize_writer()?;
+    transition_transaction(transaction_id, status)
+}
+
+// Status change plus its completion side effects, for callers already authorized
+fn transition_transaction(transaction_id: String, status: TransactionStatus) -> Result<(), String> {
+    let now = ic_cdk::api::time();
+    
+    let (transaction, was_completed) = update_stored_transaction(&transaction_id, |transaction| {
+        let was_completed = transaction.status == TransactionStatus::Completed;
+        set_transaction_status(transaction, status.clone(), now)?;
+        Ok((transaction.clone(), was_completed))
+    })?;
+    record_terminal_status(&transaction.transaction_type, &status);
+    
+    // Only completed volume counts towards a month
+    if status == TransactionStatus::Completed && !was_completed {
+        modify_analytics(|analytics| {
+            add_to_monthly_trend(&mut analytics.monthly_trends, transaction.timestamp, transaction.amount);
+        });
+        ADDRESS_LEDGER.with(|ledger| {
+            let addresses = [transaction.from_address.as_str(), transaction.to_address.as_str()];
+            modify_entries(&mut ledger.borrow_mut(), &addresses, |ledger| apply_to_ledger(ledger, &transaction, now))
+        });
+    }
+    Ok(())
+}
+
+#[update]
+fn cancel_transaction(transaction_id: String, reason: String) -> Result<FundTransaction, String> {
+    authorize_writer()?;
+    let now = ic_cdk::api::time();
+    
+    let transaction = update_stored_transaction(&transaction_id, |transaction| {
+        if transaction.status == TransactionStatus::Completed {
+            return Err("Completed transactions cannot be cancelled; record a Refund transaction instead".to_string());
+        }
+        if transaction.metadata.contains_key("ledger_in_flight") || transaction.metadata.contains_key("ledger_retry_pending") {
+            return Err("A ledger transfer for this transaction may have settled; execute it again to resolve it before cancelling".to_string());
+        }
+        set_transaction_status(transaction, TransactionStatus::Cancelled, now)?;
+        transaction.metadata.insert("cancel_reason".to_string(), reason);
+        Ok(transaction.clone())
+    })?;
+    record_terminal_status(&transaction.transaction_type, &TransactionStatus::Cancelled);
+    reverse_transaction_effects(&transaction, now);
+    
+    Ok(transaction)
+}
+
+// Takes a transaction that will never settle back out of the balance, analytics,
+// category and district totals roll_up_transaction added it to
+fn reverse_transaction_effects(transaction: &FundTransaction, now: u64) {
+    POLICY_AMOUNTS.with(|amounts| {
+        let mut amounts = amounts.borrow_mut();
+        if let Some(mut policy_amounts) = amounts.get(&transaction.policy_id) {
+            drop_recent_amount(&mut policy_amounts, &transaction.transaction_type, transaction.amount);
+            amounts.insert(transaction.policy_id.clone(), policy_amounts);
+        }
+    });
+    if let Some(mut balance) = stored_balance(&transaction.policy_id) {
+        reverse_balance(&mut balance, &transaction.transaction_type, transaction.amount, now);
+        store_balance(balance);
+    }
+    modify_analytics(|analytics| {
+        reverse_analytics(analytics, &transaction.transaction_type, transaction.amount);
+        let category = transaction.metadata.get("category").map(String::as_str).unwrap_or(UNCATEGORIZED);
+        CATEGORY_BREAKDOWN.with(|breakdown| {
+            modify_entries(&mut breakdown.borrow_mut(), &[category], |breakdown| {
+                reverse_category(breakdown, &mut analytics.category_distribution, category, &transaction.transaction_type, transaction.amount);
+            })
+        });
+    });
+    if let Some(district) = transaction.metadata.get("district") {
+        DISTRICT_FUNDS.with(|district_funds| {
+            let mut district_funds = district_funds.borrow_mut();
+            if let Some(mut funds) = district_funds.get(district) {
+                reverse_district_funds(&mut funds, &transaction.transaction_type, transaction.amount, now);
+                district_funds.insert(district.clone(), funds);
+            }
+        });
+    }
+}
+
+#[update]
+async fn execute_release_on_ledger(transaction_id: String) -> Result<String, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can execute releases on the ledger".to_string());
+    }
+    let ledger = unsafe { LEDGER_CANISTER_ID }.ok_or("Ledger canister id not configured".to_string())?;
+    
+    let now = ic_cdk::api::time();
+    let (to_address, amount, created_at_time) = update_stored_transaction(&transaction_id, |transaction| {
+        if !matches!(transaction.transaction_type, TransactionType::Release) {
+            return Err("Only Release transactions can be executed on the ledger".to_string());
+        }
+        if transaction.status != TransactionStatus::Processing {
+            return Err("Only processing transactions can be executed on the ledger".to_string());
+        }
+        if transaction.metadata.contains_key("ledger_in_flight") {
+            return Err("Ledger transfer already in flight".to_string());
+        }
+        transaction.metadata.insert("ledger_in_flight".to_string(), "true".to_string());
+        // Retries resend the first attempt's timestamp so the ledger can deduplicate them
+        let created_at_time = transaction.metadata.entry("ledger_created_at".to_string())
+            .or_insert_with(|| now.to_string())
+            .parse::<u64>()
+            .unwrap_or(now);
+        Ok((transaction.to_address.clone(), transaction.amount, created_at_time))
+    })?;
+    
+    let outcome = match parse_ledger_account(&to_address) {
+        Ok(to) => transfer_on_ledger(ledger, to, amount, ledger_memo(&transaction_id), created_at_time).await,
+        Err(e) => Err(LedgerFailure::Rejected(e)),
+    };
+    
+    let status = match &outcome {
+        Ok((block_index, fee)) => {
+            let _ = update_stored_transaction(&transaction_id, |transaction| {
+                transaction.metadata.remove("ledger_in_flight");
+                transaction.metadata.remove("ledger_retry_pending");
+                transaction.metadata.insert("ledger_fee".to_string(), fee.to_string());
+                transaction.metadata.insert("ledger_block_index".to_string(), block_index.to_string());
+                Ok(())
+            });
+            Some(TransactionStatus::Completed)
+        }
+        Err(failure) => {
+            let retryable = matches!(failure, LedgerFailure::Retryable(_));
+            let _ = update_stored_transaction(&transaction_id, |transaction| {
+                transaction.metadata.remove("ledger_in_flight");
+                transaction.metadata.insert("ledger_error".to_string(), failure.message().to_string());
+                if retryable {
+                    transaction.metadata.insert("ledger_retry_pending".to_string(), "true".to_string());
+                }
+                Ok(())
+            });
+            // An unknown outcome stays Processing until a retry settles it
+            (!retryable).then_some(TransactionStatus::Failed)
+        }
+    };
+    if let Some(status) = status {
+        let failed = status == TransactionStatus::Failed;
+        transition_transaction(transaction_id.clone(), status)?;
+        // A rejected transfer moved nothing; take the release back out of the roll-ups
+        if failed {
+            if let Some(transaction) = stored_transaction(&transaction_id) {
+                reverse_transaction_effects(&transaction, ic_cdk::api::time());
+            }
+        }
+    }
+    
+    outcome.map(|(block_index, _)| block_index.to_string()).map_err(|failure| match failure {
+        LedgerFailure::Rejected(message) => message,
+        LedgerFailure::Retryable(message) => format!("{}; the transfer can be executed again", message),
+    })
+}
+
+#[update]
+fn set_ledger_canister_id(canister_id: Principal) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can set the ledger canister id".to_string());
+    }
+    
+    unsafe {
+        LEDGER_CANISTER_ID = Some(canister_id);
+    }
+    Ok(())
+}
+
+#[query]
+fn get_ledger_canister_id() -> Option<Principal> {
+    unsafe { LEDGER_CANISTER_ID }
+}
+
+#[update]
+fn rebuild_monthly_trends() -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can rebuild monthly trends".to_string());
+    }
+    
+    let mut trends = TRANSACTIONS.with(|transactions| compute_monthly_trends(transactions.borrow().scan()));
+    ARCHIVE.with(|archive| {
+        for transaction in archive.borrow().iter().filter(|t| t.status == TransactionStatus::Completed && !is_reallocation_credit(t)) {
+            add_to_monthly_trend(&mut trends, transaction.timestamp, transaction.amount);
+        }
+    });
+    modify_analytics(|analytics| analytics.monthly_trends = trends);
+    Ok(())
+}
+
+#[query]
+fn get_transaction(transaction_id: String) -> Result<FundTransaction, String> {
+    stored_transaction(&transaction_id).ok_or("Transaction not found".to_string())
+}
+
+#[query]
+fn get_policy_transactions(policy_id: String) -> Vec<FundTransaction> {
+    POLICY_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        let (index, transactions) = (index.borrow(), transactions.borrow());
+        indexed_ids(&index, &policy_id, 0, u64::MAX)
+            .filter_map(|id| transactions.lookup(&id))
+            .collect()
+    }))
+}
+
+#[query]
+fn list_transactions(filter: TxFilter, offset: u64, limit: u32) -> TxPage {
+    POLICY_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        select_transactions(&*transactions.borrow(), &index.borrow(), &filter, offset, limit)
+    }))
+}
+
+#[query]
+fn get_transactions_in_range(
+    policy_id: Option<String>,
+    start_ns: u64,
+    end_ns: u64,
+    offset: u64,
+    limit: u64,
+) -> Result<Vec<FundTransaction>, String> {
+    POLICY_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        transactions_in_range(&*transactions.borrow(), &index.borrow(), policy_id, start_ns, end_ns, offset, limit)
+    }))
+}
+
+#[query]
+fn export_policy_transactions_csv(policy_id: String) -> Result<String, String> {
+    let transactions = get_policy_transactions(policy_id);
+    policy_transactions_csv(&transactions)
+}
+
+#[query]
+fn export_transactions_csv(filter: TxFilter, chunk_index: u32) -> CsvChunk {
+    let matching = POLICY_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        matching_transactions(&*transactions.borrow(), &index.borrow(), &filter)
+    }));
+    transactions_csv_chunk(&matching, chunk_index, CSV_CHUNK_ROWS)
+}
+
+#[query]
+fn get_fund_balance(policy_id: String) -> Result<FundBalance, String> {
+    stored_balance(&policy_id).ok_or("Fund balance not found".to_string())
+}
+
+#[query]
+fn list_fund_balances(offset: u64, limit: u32, sort: BalanceSort) -> BalancePage {
+    FUND_BALANCES.with(|balances| {
+        balance_page(balances.borrow().iter().map(|(_, balance)| balance), offset, limit, &sort)
+    })
+}
+
+#[query]
+fn get_total_treasury_position() -> TreasuryPosition {
+    FUND_BALANCES.with(|balances| treasury_position(balances.borrow().iter().map(|(_, balance)| balance)))
+}
+
+fn balance_page(
+    fund_balances: impl Iterator<Item = impl Borrow<FundBalance>>,
+    offset: u64,
+    limit: u32,
+    sort: &BalanceSort,
+) -> BalancePage {
+    let limit = limit.min(MAX_PAGE_SIZE);
+    let mut balances: Vec<FundBalance> = fund_balances.map(|balance| balance.borrow().clone()).collect();
+    // Map iteration is already in policy id order, so the stable sort keeps that for ties
+    balances.sort_by(|a, b| match sort {
+        BalanceSort::CurrentBalance => b.current_balance.cmp(&a.current_balance),
+        BalanceSort::TotalAllocated => b.total_allocated.cmp(&a.total_allocated),
+        BalanceSort::LastUpdated => b.last_updated.cmp(&a.last_updated),
+    });
+    
+    BalancePage {
+        total_count: balances.len() as u64,
+        balances: balances.into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect(),
+        offset,
+        limit,
+    }
+}
+
+fn treasury_position(balances: impl Iterator<Item = impl Borrow<FundBalance>>) -> TreasuryPosition {
+    balances.fold(TreasuryPosition::default(), |mut position, balance| {
+        let balance = balance.borrow();
+        position.total_allocated += balance.total_allocated as u128;
+        position.total_released += balance.total_released as u128;
+        position.total_transferred += balance.total_transferred as u128;
+        position.total_fees += balance.total_fees.unwrap_or_default() as u128;
+        position.current_balance += balance.current_balance as u128;
+        position.policy_count += 1;
+        position
+    })
+}
+
+#[query]
+fn reconcile_policy(policy_id: String) -> Result<ReconciliationReport, String> {
+    let stored = stored_balance(&policy_id).ok_or("Fund balance not found".to_string())?;
+    Ok(reconcile(&stored, &computed_balance(&policy_id)))
+}
+
+#[query]
+fn reconcile_all(offset: u64, limit: u32) -> Vec<ReconciliationReport> {
+    let page: Vec<FundBalance> = FUND_BALANCES.with(|balances| {
+        balances.borrow().iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|(_, balance)| balance)
+            .collect()
+    });
+    page.iter()
+        .map(|stored| reconcile(stored, &computed_balance(&stored.policy_id)))
+        .collect()
+}
+
+#[update]
+fn repair_balance(policy_id: String) -> Result<ReconciliationReport, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can repair balances".to_string());
+    }
+    
+    let now = ic_cdk::api::time();
+    let mut computed = computed_balance(&policy_id);
+    computed.last_updated = now;
+    
+    let stored = stored_balance(&policy_id).ok_or("Fund balance not found".to_string())?;
+    let report = reconcile(&stored, &computed);
+    store_balance(computed);
+    
+    // Leave a trace of the repair in the transaction log itself
+    let mut metadata = BTreeMap::new();
+    metadata.insert("adjustment".to_string(), "balance_repair".to_string());
+    metadata.insert("repaired_by".to_string(), ic_cdk::caller().to_text());
+    for field in &report.fields {
+        metadata.insert(field.field.clone(), format!("{} -> {}", field.stored, field.computed));
+    }
+    
+    let transaction_id = Uuid::new_v4().to_string();
+    let mut adjustment = FundTransaction {
+        id: transaction_id.clone(),
+        policy_id: policy_id.clone(),
+        transaction_type: TransactionType::Adjustment,
+        amount: 0,
+        from_address: String::new(),
+        to_address: String::new(),
+        timestamp: now,
+        status: TransactionStatus::Completed,
+        transaction_hash: format!("tx_{}", Uuid::new_v4()),
+        metadata,
+        status_history: Some(vec![(TransactionStatus::Completed, now)]),
+        seq: None,
+        denomination: Some(Denomination::Paise),
+        anomaly_flags: None,
+    };
+    publish_recorded(&mut adjustment);
+    index_transaction(&adjustment);
+    store_transaction(adjustment);
+    
+    Ok(report)
+}
+
+// Moves old Completed/Cancelled transactions into the stable archive. Balances,
+// analytics and the address ledger are running totals and keep their contributions.
+#[update]
+fn prune_transactions(older_than_ns: u64, max_count: u32) -> Result<u32, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can prune transactions".to_string());
+    }
+    
+    // Dedup keys may still resolve to recent transactions, so those stay in the hot map
+    let cutoff = older_than_ns.min(ic_cdk::api::time().saturating_sub(DEDUP_WINDOW_NS));
+    
+    let prunable = TIME_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        prunable_transactions(&*transactions.borrow(), &index.borrow(), cutoff, max_count.min(MAX_PRUNE_BATCH))
+    }));
+    
+    let mut archived = 0;
+    for transaction_id in prunable {
+        let transaction = match stored_transaction(&transaction_id) {
+            Some(transaction) => transaction,
+            None => continue,
+        };
+        ARCHIVE.with(|archive| ARCHIVE_IDS.with(|ids| ARCHIVE_ADDRESSES.with(|addresses| {
+            archive_transaction(&archive.borrow(), &mut ids.borrow_mut(), &mut addresses.borrow_mut(), &transaction)
+        })))?;
+        
+        unindex_transaction(&transaction);
+        if !matches!(transaction.status, TransactionStatus::Failed | TransactionStatus::Cancelled) {
+            let mut baseline = archived_balance(&transaction.policy_id)
+                .unwrap_or_else(|| empty_balance(&transaction.policy_id));
+            apply_to_balance(&mut baseline, &transaction.transaction_type, transaction.amount, transaction.timestamp);
+            ARCHIVED_BALANCES.with(|balances| balances.borrow_mut().insert(transaction.policy_id.clone(), baseline));
+            if matches!(transaction.transaction_type, TransactionType::Release) {
+                ARCHIVED_RELEASES.with(|releases| {
+                    let mut releases = releases.borrow_mut();
+                    let mut totals = releases.get(&transaction.policy_id).unwrap_or_default();
+                    totals.amount = totals.amount.saturating_add(transaction.amount);
+                    totals.count += 1;
+                    releases.insert(transaction.policy_id.clone(), totals);
+                });
+            }
+        }
+        if transaction.status == TransactionStatus::Completed {
+            let start_year = fiscal_start_year(transaction.timestamp);
+            ARCHIVED_FISCAL_YEARS.with(|years| {
+                let mut years = years.borrow_mut();
+                let mut totals = years.get(&start_year).unwrap_or_default();
+                add_to_fiscal_year(&mut totals, &transaction);
+                years.insert(start_year, totals);
+            });
+        }
+        
+        TRANSACTIONS.with(|transactions| transactions.borrow_mut().remove(&transaction_id));
+        archived += 1;
+    }
+    if archived > 0 {
+        note_transaction_activity(ic_cdk::api::time());
+    }
+    Ok(archived)
+}
+
+// Events with seq greater than the cursor, oldest first
+#[query]
+fn get_transactions_since(seq: u64, limit: u32) -> TxFeedPage {
+    TX_FEED.with(|feed| feed_page(&feed.borrow(), seq, limit))
+}
+
+// Receipt for a Completed, Failed or Cancelled transaction, hot or archived. To verify it:
+// 1. check `certificate` against the IC root key and read /canister/<id>/certified_data;
+// 2. check the leaf labeled with the transaction id holds SHA-256(candid(transaction));
+// 3. recompute the root hash of `witness` and compare it with certified_data.
+#[query]
+fn get_transaction_receipt(transaction_id: String) -> Result<TransactionReceipt, String> {
+    let certificate = ic_cdk::api::data_certificate()
+        .ok_or("Receipts are only available from a query call".to_string())?;
+    let transaction = stored_transaction(&transaction_id)
+        .or_else(|| get_archived_transaction(transaction_id.clone()).ok())
+        .ok_or("Transaction not found".to_string())?;
+    let witness = unsafe {
+        RECEIPT_HASHES.as_ref()
+            .and_then(|receipts| receipt_witness(receipts, &transaction_id))
+            .ok_or("Transaction has not reached a terminal status".to_string())?
+    };
+    
+    Ok(TransactionReceipt { transaction, certificate, witness })
+}
+
+#[query]
+fn get_archived_transaction(transaction_id: String) -> Result<FundTransaction, String> {
+    ARCHIVE_IDS.with(|ids| ids.borrow().get(&transaction_id))
+        .and_then(|sequence| ARCHIVE.with(|archive| archive.borrow().get(sequence)))
+        .ok_or("Archived transaction not found".to_string())
+}
+
+#[query]
+fn count_archived() -> u64 {
+    ARCHIVE.with(|archive| archive.borrow().len())
+}
+
+#[query]
+fn get_address_balance(address: String) -> Result<AddressBalance, String> {
+    ADDRESS_LEDGER.with(|ledger| ledger.borrow().get(&address))
+        .ok_or("Address not found".to_string())
+}
+
+// Newest first
+#[query]
+fn get_flagged_transactions(offset: u64, limit: u32) -> TxPage {
+    let limit = limit.min(MAX_PAGE_SIZE);
+    FLAGGED_TX_INDEX.with(|flagged| {
+        let flagged = flagged.borrow();
+        TxPage {
+            total_count: flagged.len(),
+            transactions: flagged.keys()
+                .rev()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .filter_map(|key| stored_transaction(&key.transaction_id))
+                .collect(),
+            offset,
+            limit,
+        }
+    })
+}
+
+#[update]
+fn dismiss_flag(transaction_id: String, flag: String, note: String) -> Result<FundTransaction, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can dismiss anomaly flags".to_string());
+    }
+    
+    let reviewer = ic_cdk::caller();
+    let transaction = update_stored_transaction(&transaction_id, |transaction| {
+        clear_flag(transaction, &flag, &note, &reviewer)?;
+        Ok(transaction.clone())
+    })?;
+    if transaction.anomaly_flags.as_ref().is_none_or(|flags| flags.is_empty()) {
+        FLAGGED_TX_INDEX.with(|flagged| flagged.borrow_mut().remove(&time_key(&transaction)));
+    }
+    Ok(transaction)
+}
+
+#[update]
+fn set_anomaly_config(config: AnomalyConfig) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can change the anomaly rules".to_string());
+    }
+    if config.amount_percentile == 0 || config.amount_percentile > 100 {
+        return Err("amount_percentile must be between 1 and 100".to_string());
+    }
+    
+    unsafe {
+        ANOMALY_CONFIG = Some(config);
+    }
+    Ok(())
+}
+
+#[query]
+fn get_anomaly_config() -> AnomalyConfig {
+    unsafe { ANOMALY_CONFIG.clone().unwrap_or_else(default_anomaly_config) }
+}
+
+#[query]
+fn get_address_transactions(address: String, offset: u64, limit: u32) -> Vec<FundTransaction> {
+    ADDRESS_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        let (index, transactions) = (index.borrow(), transactions.borrow());
+        indexed_ids(&index, &address, 0, u64::MAX)
+            .rev()
+            .filter_map(|id| transactions.lookup(&id))
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .collect()
+    }))
+}
+
+// Like get_address_transactions, but pages on into transactions moved to the archive
+// once the hot ones run out
+#[query]
+fn get_transactions_by_address(address: String, offset: u64, limit: u32) -> Vec<FundTransaction> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    let mut page = get_address_transactions(address.clone(), offset, limit as u32);
+    if page.len() < limit {
+        let hot_count = ADDRESS_TX_INDEX.with(|index| indexed_ids(&index.borrow(), &address, 0, u64::MAX).count()) as u64;
+        let remaining = limit - page.len();
+        page.extend(ARCHIVE.with(|archive| ARCHIVE_ADDRESSES.with(|addresses| {
+            archived_address_page(&archive.borrow(), &addresses.borrow(), &address, offset.saturating_sub(hot_count), remaining)
+        })));
+    }
+    page
+}
+
+#[query]
+fn get_district_funds(district: String) -> Result<DistrictFunds, String> {
+    DISTRICT_FUNDS.with(|district_funds| district_funds.borrow().get(&district))
+        .ok_or("District funds not found".to_string())
+}
+
+#[query]
+fn get_all_district_funds() -> Vec<DistrictFunds> {
+    DISTRICT_FUNDS.with(|district_funds| district_funds.borrow().iter().map(|(_, funds)| funds).collect())
+}
+
+#[query]
+fn get_fund_analytics() -> FundAnalytics {
+    fund_analytics()
+}
+
+// "YYYY-MM" keys order lexically, so the map is already chronological
+#[query]
+fn get_success_rate_by_type() -> Vec<TypeSuccessRate> {
+    TERMINAL_COUNTS.with(|counts| {
+        counts.borrow().iter()
+            .map(|(transaction_type, counts)| TypeSuccessRate {
+                transaction_type,
+                success_rate: success_rate(&counts),
+                counts,
+            })
+            .collect()
+    })
+}
+
+#[query]
+fn get_monthly_trends() -> Vec<(String, u64)> {
+    fund_analytics().monthly_trends.into_iter().collect()
+}
+
+#[query]
+fn get_top_districts(metric: TopMetric, window: TimeWindow, n: u32) -> Vec<TopEntry> {
+    TIME_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        let (index, transactions) = (index.borrow(), transactions.borrow());
+        let window_transactions = transactions_in_window(&*transactions, &index, &window, ic_cdk::api::time());
+        top_entries(window_transactions, &metric, n, |transaction| transaction.metadata.get("district").cloned())
+    }))
+}
+
+#[query]
+fn get_top_policies(metric: TopMetric, window: TimeWindow, n: u32) -> Vec<TopEntry> {
+    TIME_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        let (index, transactions) = (index.borrow(), transactions.borrow());
+        let window_transactions = transactions_in_window(&*transactions, &index, &window, ic_cdk::api::time());
+        top_entries(window_transactions, &metric, n, |transaction| Some(transaction.policy_id.clone()))
+    }))
+}
+
+#[query]
+fn get_category_distribution() -> Vec<(String, u64)> {
+    fund_analytics().category_distribution.into_iter().collect()
+}
+
+#[query]
+fn get_category_breakdown() -> Vec<CategoryBreakdown> {
+    CATEGORY_BREAKDOWN.with(|breakdown| breakdown.borrow().iter().map(|(_, breakdown)| breakdown).collect())
+}
+
+// Completed hot and archived transactions of the fiscal year starting April 1 of start_year
+#[query]
+fn get_fiscal_year_report(start_year: u32) -> Result<FiscalYearReport, String> {
+    let (start_ns, end_ns) = fiscal_year_bounds(start_year)?;
+    
+    let hot: Vec<FundTransaction> = TIME_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        let (index, transactions) = (index.borrow(), transactions.borrow());
+        indexed_ids(&index, "", start_ns, end_ns - 1)
+            .filter_map(|id| transactions.lookup(&id))
+            .collect()
+    }));
+    let archived = ARCHIVED_FISCAL_YEARS.with(|years| years.borrow().get(&start_year)).unwrap_or_default();
+    fiscal_year_report(start_year, archived, hot.iter())
+}
+
+#[update]
+fn set_smart_policy_canister_id(canister_id: Principal) -> Result<(), String> {
+    common::ensure_controller("set the smart_policy canister id")?;
+    
+    unsafe {
+        SMART_POLICY_CANISTER_ID = Some(canister_id);
+    }
+    Ok(())
+}
+
+#[query]
+fn get_smart_policy_canister_id() -> Option<Principal> {
+    unsafe { SMART_POLICY_CANISTER_ID }
+}
+
+// Compares smart_policy's fund_released for the policy with the Release transactions
+// recorded here: the hot ones plus the archived roll-up
+#[update]
+async fn reconcile_policy_funds(policy_id: String) -> Result<FundsReconciliation, String> {
+    let canister_id = unsafe { SMART_POLICY_CANISTER_ID }.ok_or("smart_policy canister id not configured".to_string())?;
+    let response: Result<(Result<PolicyFunds, String>,), _> =
+        call(canister_id, "get_policy", (policy_id.clone(),)).await;
+    let policy = policy_funds_from(response)?;
+    
+    let archived = ARCHIVED_RELEASES.with(|releases| releases.borrow().get(&policy_id)).unwrap_or_default();
+    let releases = get_policy_transactions(policy_id.clone());
+    Ok(reconciliation_report(&policy_id, policy.fund_released, archived, releases.iter(), ic_cdk::api::time()))
+}
+
+fn policy_funds_from(
+    response: Result<(Result<PolicyFunds, String>,), (ic_cdk::api::call::RejectionCode, String)>,
+) -> Result<PolicyFunds, String> {
+    match response {
+        Ok((Ok(policy),)) => Ok(policy),
+        Ok((Err(e),)) => Err(format!("smart_policy rejected get_policy: {}", e)),
+        Err((code, msg)) => Err(format!("get_policy call failed: {:?} {}", code, msg)),
+    }
+}
+
+// Failed and cancelled releases moved no funds and are left out
+fn reconciliation_report<'a>(
+    policy_id: &str,
+    smart_policy_released: u64,
+    archived: ArchivedReleases,
+    transactions: impl Iterator<Item = &'a FundTransaction>,
+    now: u64,
+) -> FundsReconciliation {
+    let mut fund_tracker_released = archived.amount;
+    let mut release_transactions = archived.count;
+    for transaction in transactions {
+        if transaction.policy_id == policy_id
+            && matches!(transaction.transaction_type, TransactionType::Release)
+            && !matches!(transaction.status, TransactionStatus::Failed | TransactionStatus::Cancelled)
+        {
+            fund_tracker_released = fund_tracker_released.saturating_add(transaction.amount);
+            release_transactions += 1;
+        }
+    }
+    
+    let consistent = smart_policy_released == fund_tracker_released;
+    let discrepancy = (!consistent).then(|| format!(
+        "smart_policy reports {} released but fund_tracker recorded {} across {} release transactions ({} {})",
+        smart_policy_released,
+        fund_tracker_released,
+        release_transactions,
+        smart_policy_released.abs_diff(fund_tracker_released),
+        if smart_policy_released > fund_tracker_released { "missing here" } else { "more here" },
+    ));
+    FundsReconciliation {
+        policy_id: policy_id.to_string(),
+        smart_policy_released,
+        fund_tracker_released,
+        release_transactions,
+        consistent,
+        discrepancy,
+        checked_at: now,
+    }
+}
+
+#[update]
+fn add_trusted_caller(caller: Principal) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can manage trusted callers".to_string());
+    }
+    
+    unsafe {
+        if let Some(ref mut trusted) = TRUSTED_CALLERS {
+            trusted.insert(caller);
+        }
+    }
+    Ok(())
+}
+
+#[update]
+fn remove_trusted_caller(caller: Principal) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can manage trusted callers".to_string());
+    }
+    
+    unsafe {
+        if let Some(ref mut trusted) = TRUSTED_CALLERS {
+            trusted.remove(&caller);
+        }
+    }
+    Ok(())
+}
+
+#[query]
+fn get_trusted_callers() -> Vec<Principal> {
+    unsafe { TRUSTED_CALLERS.as_ref().map(|trusted| trusted.iter().cloned().collect()).unwrap_or_default() }
+}
+
+fn authorize_writer() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    unsafe {
+        authorize_caller(
+            &caller,
+            ic_cdk::api::is_controller(&caller),
+            SMART_POLICY_CANISTER_ID,
+            TRUSTED_CALLERS.as_ref(),
+        )
+    }
+}
+
+fn authorize_caller(
+    caller: &Principal,
+    is_controller: bool,
+    smart_policy_canister_id: Option<Principal>,
+    trusted: Option<&BTreeSet<Principal>>,
+) -> Result<(), String> {
+    let allowed = is_controller
+        || smart_policy_canister_id.as_ref() == Some(caller)
+        || trusted.is_some_and(|trusted| trusted.contains(caller));
+    if allowed {
+        Ok(())
+    } else {
+        Err(format!("Caller {} is not authorized to modify transactions", caller.to_text()))
+    }
+}
+
+#[query]
+fn get_real_time_metrics() -> RealTimeMetrics {
+    REAL_TIME_METRICS.with(|cell| cell.borrow().get().clone())
+}
+
+#[query]
+fn get_recent_transactions(limit: u32) -> Vec<FundTransaction> {
+    let mut sorted_transactions: Vec<FundTransaction> = TRANSACTIONS.with(|transactions| transactions.borrow().scan().collect());
+    sorted_transactions.sort_by_key(|transaction| std::cmp::Reverse(transaction.timestamp));
+    sorted_transactions.into_iter().take(limit as usize).collect()
+}
+
+#[query]
+fn get_transactions_by_type(transaction_type: TransactionType) -> Vec<FundTransaction> {
+    TRANSACTIONS.with(|transactions| {
+        transactions.borrow().scan()
+            .filter(|transaction| std::mem::discriminant(&transaction.transaction_type) == std::mem::discriminant(&transaction_type))
+            .collect()
+    })
+}
+
+fn is_valid_transition(from: &TransactionStatus, to: &TransactionStatus) -> bool {
+    matches!(
+        (from, to),
+        (TransactionStatus::Pending, TransactionStatus::Processing)
+            | (TransactionStatus::Pending, TransactionStatus::Cancelled)
+            | (TransactionStatus::Processing, TransactionStatus::Completed)
+            | (TransactionStatus::Processing, TransactionStatus::Failed)
+            | (TransactionStatus::Processing, TransactionStatus::Cancelled)
+    )
+}
+
+fn set_transaction_status(transaction: &mut FundTransaction, status: TransactionStatus, now: u64) -> Result<(), String> {
+    if !is_valid_transition(&transaction.status, &status) {
+        return Err(format!(
+            "Invalid status transition from {:?} to {:?}",
+            transaction.status, status
+        ));
+    }
+    
+    transaction.status = status.clone();
+    transaction.status_history.get_or_insert_with(Vec::new).push((status.clone(), now));
+    note_transaction_activity(now);
+    TX_FEED.with(|feed| append_feed_event(&mut feed.borrow_mut(), |_| TxEvent::StatusChanged {
+        transaction_id: transaction.id.clone(),
+        status,
+        timestamp: now,
+    }));
+    Ok(())
+}
+
+fn policy_key(transaction: &FundTransaction) -> IndexKey {
+    IndexKey::new(&transaction.policy_id, transaction.timestamp, &transaction.id)
+}
+
+fn time_key(transaction: &FundTransaction) -> IndexKey {
+    IndexKey::new("", transaction.timestamp, &transaction.id)
+}
+
+fn address_keys(transaction: &FundTransaction) -> impl Iterator<Item = IndexKey> + '_ {
+    indexed_addresses(transaction).map(|address| IndexKey::new(address, transaction.timestamp, &transaction.id))
+}
+
+// Ids filed under `name` with timestamps in from..=to, oldest first
+fn indexed_ids<'a, M: ic_stable_structures::Memory>(
+    index: &'a TxIndex<M>,
+    name: &str,
+    from: u64,
+    to: u64,
+) -> impl DoubleEndedIterator<Item = String> + 'a {
+    use std::ops::Bound::{Excluded, Included};
+    let end = match to.checked_add(1) {
+        Some(next) => IndexKey::new(name, next, ""),
+        // The first key of the next name bounds the last timestamp
+        None => IndexKey::new(&format!("{}\0", name), 0, ""),
+    };
+    let start = IndexKey::new(name, from, "").min(end.clone());
+    index.keys_range((Included(start), Excluded(end))).map(|key| key.transaction_id)
+}
+
+// Windows are rolling, ending at `now`; Month is the last 30 days
+fn transactions_in_window<'a>(
+    transactions: &'a impl TransactionStore,
+    index: &'a TxIndex<impl ic_stable_structures::Memory>,
+    window: &TimeWindow,
+    now: u64,
+) -> impl Iterator<Item = FundTransaction> + 'a {
+    let start = match window {
+        TimeWindow::Day => now.saturating_sub(NANOS_PER_DAY),
+        TimeWindow::Week => now.saturating_sub(7 * NANOS_PER_DAY),
+        TimeWindow::Month => now.saturating_sub(30 * NANOS_PER_DAY),
+        TimeWindow::AllTime => 0,
+    };
+    indexed_ids(index, "", start, u64::MAX).filter_map(move |id| transactions.lookup(&id))
+}
+
+// Failed and Cancelled transactions never moved funds, so they are not ranked
+fn top_entries(
+    transactions: impl Iterator<Item = impl Borrow<FundTransaction>>,
+    metric: &TopMetric,
+    n: u32,
+    key_of: impl Fn(&FundTransaction) -> Option<String>,
+) -> Vec<TopEntry> {
+    let mut totals: BTreeMap<String, u64> = BTreeMap::new();
+    for transaction in transactions {
+        let transaction = transaction.borrow();
+        if matches!(transaction.status, TransactionStatus::Failed | TransactionStatus::Cancelled) {
+            continue;
+        }
+        let value = match (metric, &transaction.transaction_type) {
+            (TopMetric::AllocatedVolume, TransactionType::Allocation) => transaction.amount,
+            (TopMetric::ReleasedVolume, TransactionType::Release) => transaction.amount,
+            (TopMetric::TxCount, _) => 1,
+            _ => continue,
+        };
+        if let Some(key) = key_of(transaction) {
+            let total = totals.entry(key).or_insert(0);
+            *total = total.saturating_add(value);
+        }
+    }
+    
+    let mut entries: Vec<TopEntry> = totals.into_iter()
+        .map(|(key, value)| TopEntry { key, value })
+        .collect();
+    entries.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.key.cmp(&b.key)));
+    entries.truncate(n.min(MAX_TOP_N) as usize);
+    entries
+}
+
+fn archived_balance(policy_id: &str) -> Option<FundBalance> {
+    ARCHIVED_BALANCES.with(|balances| balances.borrow().get(&policy_id.to_string()))
+}
+
+// Completed and Cancelled transactions older than the cutoff, oldest first
+fn prunable_transactions(
+    transactions: &impl TransactionStore,
+    index: &TxIndex<impl ic_stable_structures::Memory>,
+    cutoff: u64,
+    max_count: u32,
+) -> Vec<String> {
+    if cutoff == 0 {
+        return Vec::new();
+    }
+    indexed_ids(index, "", 0, cutoff - 1)
+        .filter(|id| transactions.lookup(id).is_some_and(|transaction| {
+            matches!(transaction.status, TransactionStatus::Completed | TransactionStatus::Cancelled)
+        }))
+        .take(max_count as usize)
+        .collect()
+}
+
+fn archive_transaction<M: ic_stable_structures::Memory>(
+    archive: &ArchiveLog<M>,
+    ids: &mut ArchiveIds<M>,
+    addresses: &mut ArchiveAddresses<M>,
+    transaction: &FundTransaction,
+) -> Result<u64, String> {
+    let sequence = archive.append(transaction)
+        .map_err(|e| format!("Failed to archive transaction {}: {:?}", transaction.id, e))?;
+    ids.insert(transaction.id.clone(), sequence);
+    for address in indexed_addresses(transaction) {
+        addresses.insert(ArchivedAddressKey::new(address, sequence), ());
+    }
+    Ok(sequence)
+}
+
+// Archived transactions touching the address, most recently archived first
+fn archived_address_page<M: ic_stable_structures::Memory>(
+    archive: &ArchiveLog<M>,
+    addresses: &ArchiveAddresses<M>,
+    address: &str,
+    offset: u64,
+    limit: usize,
+) -> Vec<FundTransaction> {
+    use std::ops::Bound::Included;
+    let range = (Included(ArchivedAddressKey::new(address, 0)), Included(ArchivedAddressKey::new(address, u64::MAX)));
+    addresses.keys_range(range)
+        .rev()
+        .filter_map(|key| archive.get(key.seq))
+        .skip(offset as usize)
+        .take(limit)
+        .collect()
+}
+
+// Assigns the transaction its feed position and publishes its creation
+fn publish_recorded(transaction: &mut FundTransaction) {
+    TX_FEED.with(|feed| append_feed_event(&mut feed.borrow_mut(), |seq| {
+        transaction.seq = Some(seq);
+        TxEvent::Recorded(transaction.clone())
+    }));
+}
+
+// Appends under the next seq and trims the oldest events beyond MAX_FEED_EVENTS
+fn append_feed_event<M: ic_stable_structures::Memory>(
+    feed: &mut TxFeed<M>,
+    event: impl FnOnce(u64) -> TxEvent,
+) -> u64 {
+    let seq = feed.last_key_value().map_or(1, |(last, _)| last + 1);
+    feed.insert(seq, event(seq));
+    while feed.len() > MAX_FEED_EVENTS {
+        feed.pop_first();
+    }
+    seq
+}
+
+fn feed_page<M: ic_stable_structures::Memory>(feed: &TxFeed<M>, cursor: u64, limit: u32) -> TxFeedPage {
+    let events: Vec<FeedEvent> = feed.range(cursor.saturating_add(1)..)
+        .take(limit.min(MAX_PAGE_SIZE) as usize)
+        .map(|(seq, event)| FeedEvent { seq, event })
+        .collect();
+    TxFeedPage {
+        next_cursor: events.last().map_or(cursor, |event| event.seq),
+        latest_seq: feed.last_key_value().map_or(0, |(seq, _)| seq),
+        oldest_seq: feed.first_key_value().map_or(0, |(seq, _)| seq),
+        events,
+    }
+}
+
+fn default_anomaly_config() -> AnomalyConfig {
+    AnomalyConfig {
+        amount_percentile: 99,
+        min_history: 20,
+        absolute_threshold: None,
+        repeat_window_secs: 3_600,
+    }
+}
+
+// Compares a new transaction with its policy's rolling amounts and known addresses, and
+// with recent payments to the same address
+fn anomaly_flags_for(transaction: &FundTransaction) -> Vec<String> {
+    let config = get_anomaly_config();
+    let window_start = transaction.timestamp.saturating_sub(config.repeat_window_secs.saturating_mul(1_000_000_000));
+    let recent_amounts = POLICY_AMOUNTS.with(|amounts| amounts.borrow().get(&transaction.policy_id))
+        .and_then(|mut amounts| amounts.recent.remove(transaction_type_name(&transaction.transaction_type)))
+        .unwrap_or_default();
+    let known_recipient = POLICY_ADDRESSES.with(|addresses| {
+        addresses.borrow().contains_key(&PolicyAddress::new(&transaction.policy_id, &transaction.to_address))
+    });
+    let recent_to_address: Vec<FundTransaction> = if transaction.to_address.is_empty() {
+        Vec::new()
+    } else {
+        ADDRESS_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+            let (index, transactions) = (index.borrow(), transactions.borrow());
+            indexed_ids(&index, &transaction.to_address, window_start, u64::MAX)
+                .filter_map(|id| transactions.lookup(&id))
+                .filter(|recent| recent.to_address == transaction.to_address)
+                .collect()
+        }))
+    };
+    detect_anomalies(transaction, &recent_amounts, known_recipient, &recent_to_address, &config)
+}
+
+// Failed and Cancelled transactions moved no funds and are ignored by every rule
+fn detect_anomalies(
+    transaction: &FundTransaction,
+    recent_amounts: &[u64],
+    known_recipient: bool,
+    recent_to_address: &[FundTransaction],
+    config: &AnomalyConfig,
+) -> Vec<String> {
+    let moved_funds = |t: &&FundTransaction| !matches!(t.status, TransactionStatus::Failed | TransactionStatus::Cancelled);
+    let mut flags = Vec::new();
+    
+    let min_history = (config.min_history.max(1) as usize).min(ANOMALY_AMOUNT_WINDOW);
+    if recent_amounts.len() >= min_history {
+        let mut amounts = recent_amounts.to_vec();
+        amounts.sort_unstable();
+        // Nearest-rank percentile
+        let rank = (amounts.len() * config.amount_percentile.clamp(1, 100) as usize).div_ceil(100);
+        if transaction.amount > amounts[rank - 1] {
+            flags.push(FLAG_ABOVE_PERCENTILE.to_string());
+        }
+    }
+    if config.absolute_threshold.is_some_and(|threshold| transaction.amount > threshold) {
+        flags.push(FLAG_ABOVE_THRESHOLD.to_string());
+    }
+    
+    if recent_to_address.iter().filter(moved_funds).any(|t| t.amount == transaction.amount) {
+        flags.push(FLAG_REPEATED_AMOUNT.to_string());
+    }
+    
+    if matches!(transaction.transaction_type, TransactionType::Release)
+        && !transaction.to_address.is_empty()
+        && !known_recipient
+    {
+        flags.push(FLAG_NEW_RECIPIENT.to_string());
+    }
+    flags
+}
+
+// Adds a stored transaction to its policy's known addresses and, unless it already
+// failed or was cancelled, to its rolling amounts
+fn note_for_anomaly_checks(transaction: &FundTransaction) {
+    if !matches!(transaction.status, TransactionStatus::Failed | TransactionStatus::Cancelled) {
+        POLICY_AMOUNTS.with(|amounts| {
+            let mut amounts = amounts.borrow_mut();
+            let mut policy_amounts = amounts.get(&transaction.policy_id).unwrap_or_default();
+            push_recent_amount(&mut policy_amounts, &transaction.transaction_type, transaction.amount);
+            amounts.insert(transaction.policy_id.clone(), policy_amounts);
+        });
+    }
+    POLICY_ADDRESSES.with(|addresses| {
+        let mut addresses = addresses.borrow_mut();
+        for address in indexed_addresses(transaction) {
+            addresses.insert(PolicyAddress::new(&transaction.policy_id, address), ());
+        }
+    });
+}
+
+fn push_recent_amount(amounts: &mut PolicyAmounts, transaction_type: &TransactionType, amount: u64) {
+    let recent = amounts.recent.entry(transaction_type_name(transaction_type).to_string()).or_default();
+    recent.push(amount);
+    if recent.len() > ANOMALY_AMOUNT_WINDOW {
+        recent.remove(0);
+    }
+}
+
+// A transaction that ends up moving no funds leaves the window again
+fn drop_recent_amount(amounts: &mut PolicyAmounts, transaction_type: &TransactionType, amount: u64) {
+    if let Some(recent) = amounts.recent.get_mut(transaction_type_name(transaction_type)) {
+        if let Some(position) = recent.iter().rposition(|&recent_amount| recent_amount == amount) {
+            recent.remove(position);
+        }
+    }
+}
+
+fn clear_flag(transaction: &mut FundTransaction, flag: &str, note: &str, reviewer: &Principal) -> Result<(), String> {
+    let flags = transaction.anomaly_flags.get_or_insert_with(Vec::new);
+    let position = flags.iter().position(|existing| existing == flag)
+        .ok_or(format!("Transaction is not flagged with {}", flag))?;
+    flags.remove(position);
+    transaction.metadata.insert(format!("dismissed_{}", flag), format!("{}: {}", reviewer.to_text(), note));
+    Ok(())
+}
+
+fn is_terminal(status: &TransactionStatus) -> bool {
+    matches!(status, TransactionStatus::Completed | TransactionStatus::Failed | TransactionStatus::Cancelled)
+}
+
+fn receipt_hash(transaction: &FundTransaction) -> [u8; 32] {
+    Sha256::digest(Encode!(transaction).expect("Failed to encode receipt")).into()
+}
+
+const RECEIPTS_LABEL: &[u8] = b"transactions";
+
+// Leaves sit in id order under balanced forks, all under the "transactions" label
+fn receipts_root_hash(receipts: &BTreeMap<String, [u8; 32]>) -> [u8; 32] {
+    common::certified_root(RECEIPTS_LABEL, receipts)
+}
+
+fn receipt_witness(receipts: &BTreeMap<String, [u8; 32]>, transaction_id: &str) -> Option<HashTree> {
+    common::certified_witness(RECEIPTS_LABEL, receipts, transaction_id)
+}
+
+fn certify_receipts() {
+    let root = unsafe { RECEIPT_HASHES.as_ref().map(receipts_root_hash) };
+    if let Some(root) = root {
+        ic_cdk::api::set_certified_data(&root);
+    }
+}
+
+fn computed_balance(policy_id: &str) -> FundBalance {
+    POLICY_TX_INDEX.with(|index| TRANSACTIONS.with(|transactions| {
+        let (index, transactions) = (index.borrow(), transactions.borrow());
+        let policy_transactions = indexed_ids(&index, policy_id, 0, u64::MAX)
+            .filter_map(|id| transactions.lookup(&id));
+        recompute_balance(policy_id, archived_balance(policy_id).as_ref(), policy_transactions)
+    }))
+}
+
+// Replays every transaction that was not cancelled or failed, in timestamp order, on top of the archived totals
+fn recompute_balance(
+    policy_id: &str,
+    baseline: Option<&FundBalance>,
+    transactions: impl Iterator<Item = impl Borrow<FundTransaction>>,
+) -> FundBalance {
+    let mut balance = baseline.cloned().unwrap_or_else(|| empty_balance(policy_id));
+    
+    for transaction in transactions {
+        let transaction = transaction.borrow();
+        if matches!(transaction.status, TransactionStatus::Failed | TransactionStatus::Cancelled) {
+            continue;
+        }
+        apply_to_balance(&mut balance, &transaction.transaction_type, transaction.amount, transaction.timestamp);
+    }
+    balance
+}
+
+fn empty_balance(policy_id: &str) -> FundBalance {
+    FundBalance {
+        policy_id: policy_id.to_string(),
+        total_allocated: 0,
+        total_released: 0,
+        total_transferred: 0,
+        total_fees: Some(0),
+        current_balance: 0,
+        last_updated: 0,
+    }
+}
+
+fn reconcile(stored: &FundBalance, computed: &FundBalance) -> ReconciliationReport {
+    let field = |name: &str, stored: u64, computed: u64| ReconciliationField {
+        field: name.to_string(),
+        stored,
+        computed,
+        delta: computed as i128 - stored as i128,
+    };
+    
+    let fields = vec![
+        field("total_allocated", stored.total_allocated, computed.total_allocated),
+        field("total_released", stored.total_released, computed.total_released),
+        field("total_transferred", stored.total_transferred, computed.total_transferred),
+        field("total_fees", stored.total_fees.unwrap_or_default(), computed.total_fees.unwrap_or_default()),
+        field("current_balance", stored.current_balance, computed.current_balance),
+    ];
+    
+    ReconciliationReport {
+        policy_id: stored.policy_id.clone(),
+        in_sync: fields.iter().all(|field| field.delta == 0),
+        fields,
+    }
+}
+
+// Addresses a transaction is indexed under; system-side legs leave theirs empty
+fn indexed_addresses(transaction: &FundTransaction) -> impl Iterator<Item = &String> {
+    [&transaction.from_address, &transaction.to_address].into_iter()
+        .filter(|address| !address.is_empty())
+}
+
+fn build_address_ledger(transactions: impl Iterator<Item = impl Borrow<FundTransaction>>) -> BTreeMap<String, AddressBalance> {
+    let mut ledger = BTreeMap::new();
+    for transaction in transactions {
+        let transaction = transaction.borrow();
+        if transaction.status == TransactionStatus::Completed && !is_reallocation_credit(transaction) {
+            apply_to_ledger(&mut ledger, transaction, transaction.timestamp);
+        }
+    }
+    ledger
+}
+
+// Money flows from_address -> to_address; a refund recorded back towards the
+// treasury therefore reverses the original release
+fn apply_to_ledger(ledger: &mut BTreeMap<String, AddressBalance>, transaction: &FundTransaction, now: u64) {
+    if transaction.amount == 0 {
+        return;
+    }
+    
+    for (address, received, sent) in [
+        (&transaction.from_address, 0, transaction.amount),
+        (&transaction.to_address, transaction.amount, 0),
+    ] {
+        let balance = ledger.entry(address.clone()).or_insert(AddressBalance {
+            address: address.clone(),
+            total_received: 0,
+            total_sent: 0,
+            net_balance: 0,
+            transaction_count: 0,
+            last_updated: now,
+        });
+        balance.total_received += received;
+        balance.total_sent += sent;
+        balance.net_balance += received as i128 - sent as i128;
+        balance.transaction_count += 1;
+        balance.last_updated = now;
+    }
+}
+
+fn matches_filter(transaction: &FundTransaction, filter: &TxFilter) -> bool {
+    filter.policy_id.as_ref().is_none_or(|policy_id| &transaction.policy_id == policy_id)
+        && filter.status.as_ref().is_none_or(|status| {
+            std::mem::discriminant(&transaction.status) == std::mem::discriminant(status)
+        })
+        && filter.transaction_type.as_ref().is_none_or(|transaction_type| {
+            std::mem::discriminant(&transaction.transaction_type) == std::mem::discriminant(transaction_type)
+        })
+        && filter.from_timestamp.is_none_or(|from| transaction.timestamp >= from)
+        && filter.to_timestamp.is_none_or(|to| transaction.timestamp <= to)
+}
+
+fn select_transactions(
+    transactions: &impl TransactionStore,
+    index: &TxIndex<impl ic_stable_structures::Memory>,
+    filter: &TxFilter,
+    offset: u64,
+    limit: u32,
+) -> TxPage {
+    let limit = limit.min(MAX_PAGE_SIZE);
+    let matching = matching_transactions(transactions, index, filter);
+    
+    TxPage {
+        total_count: matching.len() as u64,
+        transactions: matching.into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect(),
+        offset,
+        limit,
+    }
+}
+
+// Newest-first candidates; a policy filter walks only that policy's index range
+fn matching_transactions(
+    transactions: &impl TransactionStore,
+    index: &TxIndex<impl ic_stable_structures::Memory>,
+    filter: &TxFilter,
+) -> Vec<FundTransaction> {
+    match filter.policy_id {
+        Some(ref policy_id) => {
+            let from = filter.from_timestamp.unwrap_or(0);
+            let to = filter.to_timestamp.unwrap_or(u64::MAX);
+            if from > to {
+                Vec::new()
+            } else {
+                indexed_ids(index, policy_id, from, to)
+                    .rev()
+                    .filter_map(|id| transactions.lookup(&id))
+                    .filter(|transaction| matches_filter(transaction, filter))
+                    .collect()
+            }
+        }
+        None => {
+            let mut matching: Vec<FundTransaction> = transactions.scan()
+                .filter(|transaction| matches_filter(transaction, filter))
+                .collect();
+            matching.sort_by_key(|transaction| std::cmp::Reverse(transaction.timestamp));
+            matching
+        }
+    }
+}
+
+fn transactions_in_range(
+    transactions: &impl TransactionStore,
+    index: &TxIndex<impl ic_stable_structures::Memory>,
+    policy_id: Option<String>,
+    start_ns: u64,
+    end_ns: u64,
+    offset: u64,
+    limit: u64,
+) -> Result<Vec<FundTransaction>, String> {
+    if start_ns > end_ns {
+        return Err("start_ns must not be after end_ns".to_string());
+    }
+    
+    let filter = TxFilter {
+        policy_id,
+        status: None,
+        transaction_type: None,
+        from_timestamp: Some(start_ns),
+        to_timestamp: Some(end_ns),
+    };
+    let limit = limit.min(MAX_PAGE_SIZE as u64) as u32;
+    Ok(select_transactions(transactions, index, &filter, offset, limit).transactions)
+}
+
+// RFC 4180: quote fields containing separators, quotes or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let mut row = fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",");
+    row.push_str("\r\n");
+    row
+}
+
+fn transaction_type_name(transaction_type: &TransactionType) -> &'static str {
+    match transaction_type {
+        TransactionType::Allocation => "Allocation",
+        TransactionType::Release => "Release",
+        TransactionType::Transfer => "Transfer",
+        TransactionType::Refund => "Refund",
+        TransactionType::Fee => "Fee",
+        TransactionType::Adjustment => "Adjustment",
+    }
+}
+
+fn policy_transactions_csv(transactions: &[FundTransaction]) -> Result<String, String> {
+    if transactions.len() > MAX_CSV_ROWS {
+        return Err(format!("Export exceeds {} rows", MAX_CSV_ROWS));
+    }
+    
+    let header = ["id", "type", "amount", "from", "to", "timestamp", "status", "hash"];
+    let mut csv = csv_row(&header.map(|column| column.to_string()));
+    for transaction in transactions {
+        csv.push_str(&csv_row(&[
+            transaction.id.clone(),
+            transaction_type_name(&transaction.transaction_type).to_string(),
+            transaction.amount.to_string(),
+            transaction.from_address.clone(),
+            transaction.to_address.clone(),
+            transaction.timestamp.to_string(),
+            format!("{:?}", transaction.status),
+            transaction.transaction_hash.clone(),
+        ]));
+        if csv.len() > MAX_CSV_BYTES {
+            return Err("Export exceeds the response size limit".to_string());
+        }
+    }
+    Ok(csv)
+}
+
+// Header is emitted only in chunk 0 so concatenated chunks form a single document
+fn transactions_csv_chunk(transactions: &[FundTransaction], chunk_index: u32, rows_per_chunk: usize) -> CsvChunk {
+    let start = (chunk_index as usize).saturating_mul(rows_per_chunk);
+    let mut csv = String::new();
+    if chunk_index == 0 {
+        let header = ["id", "policy_id", "type", "amount", "from", "to", "timestamp", "status", "transaction_hash"];
+        csv.push_str(&csv_row(&header.map(|column| column.to_string())));
+    }
+    for transaction in transactions.iter().skip(start).take(rows_per_chunk) {
+        csv.push_str(&csv_row(&[
+            transaction.id.clone(),
+            transaction.policy_id.clone(),
+            transaction_type_name(&transaction.transaction_type).to_string(),
+            transaction.amount.to_string(),
+            transaction.from_address.clone(),
+            transaction.to_address.clone(),
+            iso_timestamp(transaction.timestamp),
+            format!("{:?}", transaction.status),
+            transaction.transaction_hash.clone(),
+        ]));
+    }
+    
+    CsvChunk {
+        chunk_index,
+        data: csv.into_bytes(),
+        has_more: transactions.len() > start.saturating_add(rows_per_chunk),
+    }
+}
+
+fn validate_batch_item(item: &RecordTxRequest) -> Result<(), String> {
+    if item.amount == 0 {
+        return Err("Amount must be greater than zero".to_string());
+    }
+    if matches!(item.transaction_type, TransactionType::Adjustment) {
+        return Err("Adjustment transactions are created by repair_balance only".to_string());
+    }
+    Ok(())
+}
+
+// Checks one batch row against the balances staged by earlier rows and stores it
+fn stage_batch_item(
+    item: RecordTxRequest,
+    category: &str,
+    district: Option<String>,
+    staged_balances: &mut BTreeMap<String, FundBalance>,
+    now: u64,
+) -> Result<(String, TransactionType), String> {
+    validate_batch_item(&item)?;
+    
+    let transaction_id = Uuid::new_v4().to_string();
+    let dedup_entry = DedupEntry {
+        transaction_id: transaction_id.clone(),
+        policy_id: item.policy_id.clone(),
+        amount: item.amount,
+        from_address: item.from_address.clone(),
+        to_address: item.to_address.clone(),
+        recorded_at: now,
+    };
+    if let Some(ref key) = item.dedup_key {
+        let existing = DEDUP_KEYS.with(|keys| check_dedup_key(&keys.borrow(), key, &dedup_entry, now).transpose());
+        if let Some(existing) = existing {
+            return existing.map(|transaction_id| (transaction_id, item.transaction_type));
+        }
+    }
+    
+    let staged = staged_balances.entry(item.policy_id.clone())
+        .or_insert_with(|| stored_balance(&item.policy_id).unwrap_or_else(|| empty_balance(&item.policy_id)));
+    check_spending_limits(Some(staged), &item.transaction_type, item.amount, item.override_allocation)?;
+    apply_to_balance(staged, &item.transaction_type, item.amount, now);
+    
+    let mut metadata = item.metadata;
+    metadata.entry("category".to_string()).or_insert_with(|| category.to_string());
+    if let Some(district) = district {
+        metadata.entry("district".to_string()).or_insert(district);
+    }
+    
+    insert_transaction(FundTransaction {
+        id: transaction_id.clone(),
+        policy_id: item.policy_id,
+        transaction_type: item.transaction_type.clone(),
+        amount: item.amount,
+        from_address: item.from_address,
+        to_address: item.to_address,
+        timestamp: now,
+        status: TransactionStatus::Processing,
+        transaction_hash: format!("tx_{}", Uuid::new_v4()),
+        metadata,
+        status_history: Some(vec![(TransactionStatus::Processing, now)]),
+        seq: None,
+        denomination: Some(Denomination::Paise),
+        anomaly_flags: None,
+    });
+    if let Some(key) = item.dedup_key {
+        DEDUP_KEYS.with(|keys| keys.borrow_mut().insert(key, dedup_entry));
+    }
+    Ok((transaction_id, item.transaction_type))
+}
+
+// Stores a new transaction and adds it to every derived index
+fn insert_transaction(mut transaction: FundTransaction) {
+    // Checked before indexing so the transaction is not compared against itself
+    let flags = anomaly_flags_for(&transaction);
+    if !flags.is_empty() {
+        FLAGGED_TX_INDEX.with(|flagged| flagged.borrow_mut().insert(time_key(&transaction), ()));
+    }
+    transaction.anomaly_flags = Some(flags);
+    note_for_anomaly_checks(&transaction);
+    note_transaction_activity(transaction.timestamp);
+    publish_recorded(&mut transaction);
+    index_transaction(&transaction);
+    store_transaction(transaction);
+}
+
+// Rolls a newly stored transaction into balances, district funds, analytics and categories
+fn roll_up_transaction(transaction_id: &str, now: u64) {
+    let transaction = match stored_transaction(transaction_id) {
+        Some(transaction) => transaction,
+        None => return,
+    };
+    let category = transaction.metadata.get("category").cloned().unwrap_or_else(|| UNCATEGORIZED.to_string());
+    
+    // Update fund balances
+    if let Some(note) = update_fund_balance(&transaction.policy_id, &transaction.transaction_type, transaction.amount, now) {
+        let _ = update_stored_transaction(transaction_id, |stored| {
+            stored.metadata.insert("audit_note".to_string(), note);
+            Ok(())
+        });
+    }
+    
+    // Roll up into the district totals when the policy's district is known
+    if let Some(district) = transaction.metadata.get("district") {
+        update_district_funds(district, &transaction.policy_id, &transaction.transaction_type, transaction.amount, transaction.timestamp);
+    }
+    
+    // Update analytics
+    update_analytics(&transaction.transaction_type, transaction.amount);
+    update_category_breakdown(&category, &transaction.transaction_type, transaction.amount);
+}
+
+// Simulate transaction processing; ledger-backed releases complete on execution instead
+fn start_processing(transaction_id: &str, transaction_type: &TransactionType) {
+    let awaits_ledger = matches!(transaction_type, TransactionType::Release) && unsafe { LEDGER_CANISTER_ID.is_some() };
+    if !awaits_ledger {
+        ic_cdk::spawn(process_transaction(transaction_id.to_string()));
+    }
+}
+
+fn update_fund_balance(policy_id: &str, transaction_type: &TransactionType, amount: u64, now: u64) -> Option<String> {
+    let mut balance = stored_balance(policy_id).unwrap_or_else(|| FundBalance {
+        policy_id: policy_id.to_string(),
+        total_allocated: 0,
+        total_released: 0,
+        total_transferred: 0,
+        total_fees: Some(0),
+        current_balance: 0,
+        last_updated: now,
+    });
+    
+    let note = apply_to_balance(&mut balance, transaction_type, amount, now);
+    store_balance(balance);
+    note
+}
+
+// Ok(Some(id)) for a retry inside the window, Ok(None) when the key is free to use
+fn check_dedup_key(
+    keys: &StableBTreeMap<String, DedupEntry, impl ic_stable_structures::Memory>,
+    key: &str,
+    candidate: &DedupEntry,
+    now: u64,
+) -> Result<Option<String>, String> {
+    match keys.get(&key.to_string()) {
+        Some(entry) if now.saturating_sub(entry.recorded_at) < DEDUP_WINDOW_NS => {
+            let same_request = entry.policy_id == candidate.policy_id
+                && entry.amount == candidate.amount
+                && entry.from_address == candidate.from_address
+                && entry.to_address == candidate.to_address;
+            if same_request {
+                Ok(Some(entry.transaction_id))
+            } else {
+                Err(format!("Dedup key {} was already used for a different transaction", key))
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+fn prune_dedup_keys(keys: &mut StableBTreeMap<String, DedupEntry, impl ic_stable_structures::Memory>, now: u64) {
+    let expired: Vec<String> = keys.iter()
+        .filter(|(_, entry)| now.saturating_sub(entry.recorded_at) >= DEDUP_WINDOW_NS)
+        .map(|(key, _)| key)
+        .collect();
+    for key in expired {
+        keys.remove(&key);
+    }
+}
+
+// Outflows may not exceed what the policy currently holds; allocations are unconstrained
+fn check_sufficient_balance(balance: Option<&FundBalance>, transaction_type: &TransactionType, amount: u64) -> Result<(), String> {
+    match transaction_type {
+        TransactionType::Release | TransactionType::Transfer | TransactionType::Fee => {
+            let available = balance.map_or(0, |balance| balance.current_balance);
+            if amount > available {
+                return Err("insufficient balance".to_string());
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+// Whole-unit conversion only; anything involving E8s needs an exchange rate and is rejected
+fn convert_amount(amount: u64, from: Denomination, to: Denomination) -> Result<u64, String> {
+    match (from, to) {
+        (Denomination::Paise, Denomination::Paise)
+        | (Denomination::Rupees, Denomination::Rupees)
+        | (Denomination::E8s, Denomination::E8s) => Ok(amount),
+        (Denomination::Rupees, Denomination::Paise) => amount.checked_mul(PAISE_PER_RUPEE)
+            .ok_or(format!("{} rupees overflows when converted to paise", amount)),
+        (Denomination::Paise, Denomination::Rupees) => {
+            if !amount.is_multiple_of(PAISE_PER_RUPEE) {
+                return Err(format!("{} paise is not a whole number of rupees", amount));
+            }
+            Ok(amount / PAISE_PER_RUPEE)
+        }
+        (from, to) => Err(format!("Cannot convert {:?} to {:?} without an exchange rate", from, to)),
+    }
+}
+
+// Amounts are stored in paise; the caller's original unit is kept in metadata
+fn normalize_to_paise(amount: u64, denomination: &Denomination, metadata: &mut BTreeMap<String, String>) -> Result<u64, String> {
+    let paise = convert_amount(amount, denomination.clone(), Denomination::Paise)?;
+    if !matches!(denomination, Denomination::Paise) {
+        metadata.insert("original_amount".to_string(), format!("{} {:?}", amount, denomination));
+    }
+    Ok(paise)
+}
+
+// Returns how many transactions had no denomination and were assumed to be in paise
+fn assume_paise<'a>(transactions: impl Iterator<Item = &'a mut FundTransaction>) -> u32 {
+    let mut assumed = 0;
+    for transaction in transactions {
+        if transaction.denomination.is_none() {
+            transaction.denomination = Some(Denomination::Paise);
+            assumed += 1;
+        }
+    }
+    assumed
+}
+
+// Releases may not exceed the allocation unless a controller overrides the ceiling
+fn check_spending_limits(
+    balance: Option<&FundBalance>,
+    transaction_type: &TransactionType,
+    amount: u64,
+    override_allocation: bool,
+) -> Result<(), String> {
+    if let TransactionType::Release = transaction_type {
+        if override_allocation {
+            return Ok(());
+        }
+        let headroom = balance.map_or(0, |balance| balance.total_allocated.saturating_sub(balance.total_released));
+        if amount > headroom {
+            return Err(format!(
+                "Release of {} exceeds the remaining allocation; headroom is {}",
+                amount, headroom
+            ));
+        }
+    }
+    check_sufficient_balance(balance, transaction_type, amount)
+}
+
+// Supplementary grants beyond the allocation are controller-only and tagged for audit
+fn authorize_override(metadata: &mut BTreeMap<String, String>) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can override allocation limits".to_string());
+    }
+    tag_override(metadata, &caller);
+    Ok(())
+}
+
+fn tag_override(metadata: &mut BTreeMap<String, String>, caller: &Principal) {
+    metadata.insert("override".to_string(), "true".to_string());
+    metadata.insert("override_by".to_string(), caller.to_text());
+}
+
+// Returns an audit note when a refund exceeds what was released
+fn apply_to_balance(balance: &mut FundBalance, transaction_type: &TransactionType, amount: u64, now: u64) -> Option<String> {
+    let mut note = None;
+    match transaction_type {
+        TransactionType::Allocation => {
+            balance.total_allocated = balance.total_allocated.saturating_add(amount);
+            balance.current_balance = balance.current_balance.saturating_add(amount);
+        }
+        TransactionType::Release => {
+            balance.total_released = balance.total_released.saturating_add(amount);
+            balance.current_balance = balance.current_balance.saturating_sub(amount);
+        }
+        TransactionType::Transfer => {
+            balance.total_transferred = balance.total_transferred.saturating_add(amount);
+            balance.current_balance = balance.current_balance.saturating_sub(amount);
+        }
+        TransactionType::Refund => {
+            if amount > balance.total_released {
+                note = Some(format!(
+                    "Refund of {} exceeds total released {}; total_released floored at zero",
+                    amount, balance.total_released
+                ));
+            }
+            balance.total_released = balance.total_released.saturating_sub(amount);
+            balance.current_balance = balance.current_balance.saturating_add(amount);
+        }
+        TransactionType::Fee => {
+            balance.total_fees = Some(balance.total_fees.unwrap_or_default().saturating_add(amount));
+            balance.current_balance = balance.current_balance.saturating_sub(amount);
+        }
+        TransactionType::Adjustment => {}
+    }
+    
+    balance.last_updated = now;
+    note
+}
+
+fn update_district_funds(district: &str, policy_id: &str, transaction_type: &TransactionType, amount: u64, now: u64) {
+    DISTRICT_FUNDS.with(|district_funds| {
+        let mut district_funds = district_funds.borrow_mut();
+        let mut funds = district_funds.get(&district.to_string()).unwrap_or_else(|| DistrictFunds {
+            district: district.to_string(),
+            total_allocated: 0,
+            total_released: 0,
+            active_policies: 0,
+            completion_rate: 0.0,
+            last_updated: now,
+            policy_ids: None,
+        });
+        apply_to_district_funds(&mut funds, policy_id, transaction_type, amount, now);
+        district_funds.insert(district.to_string(), funds);
+    });
+}
+
+fn apply_to_district_funds(funds: &mut DistrictFunds, policy_id: &str, transaction_type: &TransactionType, amount: u64, now: u64) {
+    match transaction_type {
+        TransactionType::Allocation => funds.total_allocated = funds.total_allocated.saturating_add(amount),
+        TransactionType::Release => funds.total_released = funds.total_released.saturating_add(amount),
+        _ => return,
+    }
+    
+    let policy_ids = funds.policy_ids.get_or_insert_with(Vec::new);
+    if !policy_ids.iter().any(|id| id == policy_id) {
+        policy_ids.push(policy_id.to_string());
+        funds.active_policies = policy_ids.len() as u32;
+    }
+    
+    funds.completion_rate = district_completion_rate(funds);
+    funds.last_updated = now;
+}
+
+// Undoes apply_to_district_funds for a cancelled or failed transaction; the policy stays counted as active
+fn reverse_district_funds(funds: &mut DistrictFunds, transaction_type: &TransactionType, amount: u64, now: u64) {
+    match transaction_type {
+        TransactionType::Allocation => funds.total_allocated = funds.total_allocated.saturating_sub(amount),
+        TransactionType::Release => funds.total_released = funds.total_released.saturating_sub(amount),
+        _ => return,
+    }
+    
+    funds.completion_rate = district_completion_rate(funds);
+    funds.last_updated = now;
+}
+
+fn district_completion_rate(funds: &DistrictFunds) -> f64 {
+    if funds.total_allocated > 0 {
+        funds.total_released as f64 / funds.total_allocated as f64
+    } else {
+        0.0
+    }
+}
+
+// Explicit metadata wins, then the local cache, then a lookup against smart_policy
+async fn resolve_policy_tags(policy_id: &str, metadata: &BTreeMap<String, String>) -> (String, Option<String>) {
+    let cached_category = POLICY_CATEGORIES.with(|categories| categories.borrow().get(&policy_id.to_string()));
+    let cached_district = POLICY_DISTRICTS.with(|districts| districts.borrow().get(&policy_id.to_string()));
+    
+    let category_known = metadata.contains_key("category") || cached_category.is_some();
+    let district_known = metadata.contains_key("district") || cached_district.is_some();
+    let fetched = if category_known && district_known {
+        None
+    } else {
+        fetch_policy_summary(policy_id).await
+    };
+    
+    let (category, district) = merge_policy_tags(metadata, cached_category, cached_district, fetched);
+    if let Some(ref category) = category {
+        POLICY_CATEGORIES.with(|categories| categories.borrow_mut().insert(policy_id.to_string(), category.clone()));
+    }
+    if let Some(ref district) = district {
+        POLICY_DISTRICTS.with(|districts| districts.borrow_mut().insert(policy_id.to_string(), district.clone()));
+    }
+    
+    (category.unwrap_or_else(|| UNCATEGORIZED.to_string()), district)
+}
+
+fn merge_policy_tags(
+    metadata: &BTreeMap<String, String>,
+    cached_category: Option<String>,
+    cached_district: Option<String>,
+    fetched: Option<PolicySummary>,
+) -> (Option<String>, Option<String>) {
+    let (fetched_category, fetched_district) = match fetched {
+        Some(policy) => (Some(policy.category), Some(policy.district)),
+        None => (None, None),
+    };
+    let non_empty = |value: Option<String>| value.filter(|value| !value.is_empty());
+    
+    let category = non_empty(metadata.get("category").cloned())
+        .or(cached_category)
+        .or(non_empty(fetched_category));
+    let district = non_empty(metadata.get("district").cloned())
+        .or(cached_district)
+        .or(non_empty(fetched_district));
+    (category, district)
+}
+
+async fn fetch_policy_summary(policy_id: &str) -> Option<PolicySummary> {
+    let canister_id = unsafe { SMART_POLICY_CANISTER_ID }?;
+    let response: Result<(Result<PolicySummary, String>,), _> =
+        call(canister_id, "get_policy", (policy_id.to_string(),)).await;
+    
+    match response {
+        Ok((Ok(policy),)) => Some(policy),
+        Ok((Err(e),)) => {
+            ic_cdk::println!("smart_policy has no policy {}: {}", policy_id, e);
+            None
+        }
+        Err((code, msg)) => {
+            ic_cdk::println!("get_policy call failed: {:?} {}", code, msg);
+            None
+        }
+    }
+}
+
+fn update_category_breakdown(category: &str, transaction_type: &TransactionType, amount: u64) {
+    CATEGORY_BREAKDOWN.with(|breakdown| {
+        modify_entries(&mut breakdown.borrow_mut(), &[category], |breakdown| {
+            modify_analytics(|analytics| {
+                apply_to_category(breakdown, &mut analytics.category_distribution, category, transaction_type, amount)
+            })
+        })
+    });
+}
+
+fn apply_to_category(
+    breakdown: &mut BTreeMap<String, CategoryBreakdown>,
+    distribution: &mut BTreeMap<String, u64>,
+    category: &str,
+    transaction_type: &TransactionType,
+    amount: u64,
+) {
+    let entry = breakdown.entry(category.to_string()).or_insert(CategoryBreakdown {
+        category: category.to_string(),
+        total_allocated: 0,
+        total_released: 0,
+        transaction_count: 0,
+    });
+    entry.transaction_count += 1;
+    
+    match transaction_type {
+        TransactionType::Allocation => entry.total_allocated = entry.total_allocated.saturating_add(amount),
+        TransactionType::Release => entry.total_released = entry.total_released.saturating_add(amount),
+        _ => return,
+    }
+    let total = distribution.entry(category.to_string()).or_insert(0);
+    *total = total.saturating_add(amount);
+}
+
+// Undoes apply_to_category for a cancelled or failed transaction
+fn reverse_category(
+    breakdown: &mut BTreeMap<String, CategoryBreakdown>,
+    distribution: &mut BTreeMap<String, u64>,
+    category: &str,
+    transaction_type: &TransactionType,
+    amount: u64,
+) {
+    if let Some(entry) = breakdown.get_mut(category) {
+        entry.transaction_count = entry.transaction_count.saturating_sub(1);
+        match transaction_type {
+            TransactionType::Allocation => entry.total_allocated = entry.total_allocated.saturating_sub(amount),
+            TransactionType::Release => entry.total_released = entry.total_released.saturating_sub(amount),
+            _ => return,
+        }
+    }
+    if let Some(total) = distribution.get_mut(category) {
+        *total = total.saturating_sub(amount);
+    }
+}
+
+// "YYYY-MM" of the UTC calendar month containing an IC timestamp (nanoseconds since epoch)
+fn month_key(timestamp: u64) -> String {
+    let (year, month, _) = civil_from_days((timestamp / NANOS_PER_DAY) as i64);
+    format!("{:04}-{:02}", year, month)
+}
+
+fn iso_timestamp(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_days((timestamp / NANOS_PER_DAY) as i64);
+    let nanos_of_day = timestamp % NANOS_PER_DAY;
+    let seconds_of_day = nanos_of_day / 1_000_000_000;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        nanos_of_day / 1_000_000 % 1_000,
+    )
+}
+
+// Civil-from-days conversion over 400-year eras
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Days since the Unix epoch of a proleptic Gregorian date; inverse of civil_from_days
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// [start, end) in UTC nanoseconds of April 1 00:00 IST to the next April 1 00:00 IST
+fn fiscal_year_bounds(start_year: u32) -> Result<(u64, u64), String> {
+    if !(MIN_FISCAL_YEAR..=MAX_FISCAL_YEAR).contains(&start_year) {
+        return Err(format!("start_year must be between {} and {}", MIN_FISCAL_YEAR, MAX_FISCAL_YEAR));
+    }
+    let midnight_ist = |year: u32| {
+        days_from_civil(year as i64, 4, 1) as u64 * NANOS_PER_DAY - IST_OFFSET_NS
+    };
+    Ok((midnight_ist(start_year), midnight_ist(start_year + 1)))
+}
+
+fn add_to_fiscal_totals(totals: &mut FiscalTotals, transaction: &FundTransaction) {
+    let amount = transaction.amount as u128;
+    match transaction.transaction_type {
+        TransactionType::Allocation => totals.allocated += amount,
+        TransactionType::Release => totals.released += amount,
+        TransactionType::Refund => totals.refunded += amount,
+        TransactionType::Fee => totals.fees += amount,
+        TransactionType::Transfer | TransactionType::Adjustment => {}
+    }
+    totals.transaction_count += 1;
+}
+
+// The fiscal year a timestamp falls in, by its start year
+fn fiscal_start_year(timestamp: u64) -> u32 {
+    let (year, month, _) = civil_from_days(((timestamp + IST_OFFSET_NS) / NANOS_PER_DAY) as i64);
+    if month >= 4 { year as u32 } else { year as u32 - 1 }
+}
+
+fn add_to_fiscal_year(year: &mut FiscalYearTotals, transaction: &FundTransaction) {
+    let month = month_key(transaction.timestamp + IST_OFFSET_NS);
+    add_to_fiscal_totals(year.by_month.entry(month).or_default(), transaction);
+    let district = transaction.metadata.get("district").map(String::as_str).unwrap_or(UNKNOWN_DISTRICT);
+    add_to_fiscal_totals(year.by_district.entry(district.to_string()).or_default(), transaction);
+    let category = transaction.metadata.get("category").map(String::as_str).unwrap_or(UNCATEGORIZED);
+    add_to_fiscal_totals(year.by_category.entry(category.to_string()).or_default(), transaction);
+    add_to_fiscal_totals(&mut year.totals, transaction);
+}
+
+// Adds the year's completed hot transactions to what was already archived
+fn fiscal_year_report(
+    start_year: u32,
+    archived: FiscalYearTotals,
+    transactions: impl Iterator<Item = impl Borrow<FundTransaction>>,
+) -> Result<FiscalYearReport, String> {
+    let (start_ns, end_ns) = fiscal_year_bounds(start_year)?;
+    
+    let mut year = archived;
+    for transaction in transactions {
+        let transaction = transaction.borrow();
+        if transaction.status == TransactionStatus::Completed
+            && transaction.timestamp >= start_ns
+            && transaction.timestamp < end_ns
+        {
+            add_to_fiscal_year(&mut year, transaction);
+        }
+    }
+    
+    // Every month is listed, even without activity
+    let by_month: Vec<(String, FiscalTotals)> = (0..12)
+        .map(|offset| {
+            let month = (3 + offset) % 12 + 1;
+            let calendar_year = if month >= 4 { start_year } else { start_year + 1 };
+            let key = format!("{:04}-{:02}", calendar_year, month);
+            let totals = year.by_month.remove(&key).unwrap_or_default();
+            (key, totals)
+        })
+        .collect();
+    
+    Ok(FiscalYearReport {
+        fiscal_year: format!("{}-{:02}", start_year, (start_year + 1) % 100),
+        start_ns,
+        end_ns,
+        totals: year.totals,
+        by_month,
+        by_district: year.by_district.into_iter().collect(),
+        by_category: year.by_category.into_iter().collect(),
+    })
+}
+
+fn add_to_monthly_trend(trends: &mut BTreeMap<String, u64>, timestamp: u64, amount: u64) {
+    let total = trends.entry(month_key(timestamp)).or_insert(0);
+    *total = total.saturating_add(amount);
+}
+
+fn compute_monthly_trends(transactions: impl Iterator<Item = impl Borrow<FundTransaction>>) -> BTreeMap<String, u64> {
+    let mut trends = BTreeMap::new();
+    for transaction in transactions {
+        let transaction = transaction.borrow();
+        if transaction.status == TransactionStatus::Completed && !is_reallocation_credit(transaction) {
+            add_to_monthly_trend(&mut trends, transaction.timestamp, transaction.amount);
+        }
+    }
+    trends
+}
+
+// Release recipients are plain principals paid into their default subaccount
+fn parse_ledger_account(address: &str) -> Result<Account, String> {
+    Principal::from_text(address)
+        .map(|owner| Account { owner, subaccount: None })
+        .map_err(|e| format!("Invalid ledger account {}: {}", address, e))
+}
+
+// Returns the block index and the fee charged by the ledger
+async fn transfer_on_ledger(
+    ledger: Principal,
+    to: Account,
+    amount: u64,
+    memo: Vec<u8>,
+    created_at_time: u64,
+) -> Result<(Nat, Nat), LedgerFailure> {
+    let (fee,): (Nat,) = call(ledger, "icrc1_fee", ())
+        .await
+        .map_err(|(code, msg)| LedgerFailure::Rejected(format!("icrc1_fee call failed: {:?} {}", code, msg)))?;
+    
+    let arg = TransferArg {
+        from_subaccount: None,
+        to,
+        amount: Nat::from(amount),
+        fee: Some(fee.clone()),
+        memo: Some(memo),
+        created_at_time: Some(created_at_time),
+    };
+    let response: Result<(Result<Nat, TransferError>,), _> = call(ledger, "icrc1_transfer", (arg,)).await;
+    let block_index = transfer_outcome(response.map(|(result,)| result))?;
+    Ok((block_index, fee))
+}
+
+// Why a ledger transfer did not produce a block index
+#[derive(Debug, PartialEq)]
+enum LedgerFailure {
+    // Nothing was transferred
+    Rejected(String),
+    // The transfer may or may not have landed; resending the same memo and
+    // created_at_time lets the ledger report it as a duplicate if it did
+    Retryable(String),
+}
+
+impl LedgerFailure {
+    fn message(&self) -> &str {
+        match self {
+            LedgerFailure::Rejected(message) | LedgerFailure::Retryable(message) => message,
+        }
+    }
+}
+
+fn transfer_outcome(
+    response: Result<Result<Nat, TransferError>, (ic_cdk::api::call::RejectionCode, String)>,
+) -> Result<Nat, LedgerFailure> {
+    use ic_cdk::api::call::RejectionCode;
+    
+    let result = match response {
+        Ok(result) => result,
+        // A trap or undecodable reply can follow a committed transfer, as can an unknown reject
+        Err((code @ (RejectionCode::SysTransient | RejectionCode::CanisterError | RejectionCode::Unknown), msg)) => {
+            return Err(LedgerFailure::Retryable(format!("icrc1_transfer call failed: {:?} {}", code, msg)));
+        }
+        Err((code, msg)) => {
+            return Err(LedgerFailure::Rejected(format!("icrc1_transfer call failed: {:?} {}", code, msg)));
+        }
+    };
+    
+    match result {
+        Ok(block_index) => Ok(block_index),
+        // An earlier attempt with the same memo and created_at_time already landed
+        Err(TransferError::Duplicate { duplicate_of }) => Ok(duplicate_of),
+        Err(TransferError::TemporarilyUnavailable) => {
+            Err(LedgerFailure::Retryable("Ledger temporarily unavailable".to_string()))
+        }
+        Err(TransferError::InsufficientFunds { balance }) => {
+            Err(LedgerFailure::Rejected(format!("Ledger rejected transfer: insufficient funds (balance {})", balance)))
+        }
+        Err(TransferError::BadFee { expected_fee }) => {
+            Err(LedgerFailure::Rejected(format!("Ledger rejected transfer: bad fee (expected {})", expected_fee)))
+        }
+        Err(TransferError::GenericError { error_code, message }) => {
+            Err(LedgerFailure::Rejected(format!("Ledger rejected transfer: error {}: {}", error_code, message)))
+        }
+        Err(other) => Err(LedgerFailure::Rejected(format!("Ledger rejected transfer: {:?}", other))),
+    }
+}
+
+// 32-byte memo tying a ledger transfer back to the transaction it settles
+fn ledger_memo(transaction_id: &str) -> Vec<u8> {
+    Sha256::digest(transaction_id.as_bytes()).to_vec()
+}
+
+// Undoes apply_to_balance for a cancelled or failed transaction
+fn reverse_balance(balance: &mut FundBalance, transaction_type: &TransactionType, amount: u64, now: u64) {
+    match transaction_type {
+        TransactionType::Allocation => {
+            balance.total_allocated = balance.total_allocated.saturating_sub(amount);
+            balance.current_balance = balance.current_balance.saturating_sub(amount);
+        }
+        TransactionType::Release => {
+            balance.total_released = balance.total_released.saturating_sub(amount);
+            balance.current_balance = balance.current_balance.saturating_add(amount);
+        }
+        TransactionType::Transfer => {
+            balance.total_transferred = balance.total_transferred.saturating_sub(amount);
+            balance.current_balance = balance.current_balance.saturating_add(amount);
+        }
+        TransactionType::Refund => {
+            balance.total_released = balance.total_released.saturating_add(amount);
+            balance.current_balance = balance.current_balance.saturating_sub(amount);
+        }
+        TransactionType::Fee => {
+            balance.total_fees = Some(balance.total_fees.unwrap_or_default().saturating_sub(amount));
+            balance.current_balance = balance.current_balance.saturating_add(amount);
+        }
+        TransactionType::Adjustment => {}
+    }
+    
+    balance.last_updated = now;
+}
+
+// Undoes apply_to_analytics for a cancelled or failed transaction
+fn reverse_analytics(analytics: &mut FundAnalytics, transaction_type: &TransactionType, amount: u64) {
+    match transaction_type {
+        TransactionType::Allocation => {
+            analytics.total_funds_allocated = analytics.total_funds_allocated.saturating_sub(amount as u128);
+        }
+        TransactionType::Release => {
+            analytics.total_funds_released = analytics.total_funds_released.saturating_sub(amount as u128);
+        }
+        TransactionType::Refund => {
+            analytics.total_funds_released = analytics.total_funds_released.saturating_add(amount as u128);
+        }
+        TransactionType::Fee => {
+            analytics.total_fees = Some(analytics.total_fees.unwrap_or_default().saturating_sub(amount as u128));
+        }
+        _ => {}
+    }
+    
+    let type_name = transaction_type_name(transaction_type);
+    if let Some(total) = analytics.type_distribution.as_mut().and_then(|totals| totals.get_mut(type_name)) {
+        *total = total.saturating_sub(amount as u128);
+    }
+    if let Some(count) = analytics.type_counts.as_mut().and_then(|counts| counts.get_mut(type_name)) {
+        *count = count.saturating_sub(1);
+    }
+    
+    let sum = transaction_amount_sum(analytics).saturating_sub(amount as u128);
+    analytics.total_transactions = analytics.total_transactions.saturating_sub(1);
+    analytics.sum_of_transaction_amounts = Some(sum);
+    analytics.average_transaction_amount = average_amount(sum, analytics.total_transactions);
+}
+
+fn record_terminal_status(transaction_type: &TransactionType, status: &TransactionStatus) {
+    // One entry per transaction type, so the whole map is cheap to load
+    let counts = TERMINAL_COUNTS.with(|stored| {
+        let mut stored = stored.borrow_mut();
+        let mut counts: BTreeMap<String, TerminalCounts> = stored.iter().collect();
+        add_terminal_status(&mut counts, transaction_type, status);
+        let name = transaction_type_name(transaction_type).to_string();
+        if let Some(updated) = counts.get(&name) {
+            stored.insert(name, updated.clone());
+        }
+        counts
+    });
+    modify_analytics(|analytics| analytics.success_rate = overall_success_rate(&counts));
+}
+
+// Adjustments are bookkeeping entries and never count towards success
+fn add_terminal_status(counts: &mut BTreeMap<String, TerminalCounts>, transaction_type: &TransactionType, status: &TransactionStatus) {
+    if matches!(transaction_type, TransactionType::Adjustment) {
+        return;
+    }
+    let entry = counts.entry(transaction_type_name(transaction_type).to_string()).or_default();
+    match status {
+        TransactionStatus::Completed => entry.completed += 1,
+        TransactionStatus::Failed => entry.failed += 1,
+        TransactionStatus::Cancelled => entry.cancelled += 1,
+        _ => {}
+    }
+}
+
+fn build_terminal_counts<'a>(transactions: impl Iterator<Item = &'a FundTransaction>) -> BTreeMap<String, TerminalCounts> {
+    let mut counts = BTreeMap::new();
+    for transaction in transactions {
+        add_terminal_status(&mut counts, &transaction.transaction_type, &transaction.status);
+    }
+    counts
+}
+
+fn success_rate(counts: &TerminalCounts) -> Option<f64> {
+    let settled = counts.completed + counts.failed + counts.cancelled;
+    if settled == 0 {
+        None
+    } else {
+        Some(counts.completed as f64 / settled as f64)
+    }
+}
+
+fn overall_success_rate(counts: &BTreeMap<String, TerminalCounts>) -> Option<f64> {
+    let total = counts.values().fold(TerminalCounts::default(), |mut total, counts| {
+        total.completed += counts.completed;
+        total.failed += counts.failed;
+        total.cancelled += counts.cancelled;
+        total
+    });
+    success_rate(&total)
+}
+
+fn update_analytics(transaction_type: &TransactionType, amount: u64) {
+    modify_analytics(|analytics| apply_to_analytics(analytics, transaction_type, amount));
+}
+
+fn apply_to_analytics(analytics: &mut FundAnalytics, transaction_type: &TransactionType, amount: u64) {
+    match transaction_type {
+        TransactionType::Allocation => {
+            analytics.total_funds_allocated = analytics.total_funds_allocated.saturating_add(amount as u128);
+        }
+        TransactionType::Release => {
+            analytics.total_funds_released = analytics.total_funds_released.saturating_add(amount as u128);
+        }
+        TransactionType::Refund => {
+            analytics.total_funds_released = analytics.total_funds_released.saturating_sub(amount as u128);
+        }
+        TransactionType::Fee => {
+            analytics.total_fees = Some(analytics.total_fees.unwrap_or_default().saturating_add(amount as u128));
+        }
+        _ => {}
+    }
+    
+    let type_name = transaction_type_name(transaction_type);
+    let total = analytics.type_distribution.get_or_insert_with(BTreeMap::new).entry(type_name.to_string()).or_insert(0);
+    *total = total.saturating_add(amount as u128);
+    *analytics.type_counts.get_or_insert_with(BTreeMap::new).entry(type_name.to_string()).or_insert(0) += 1;
+    
+    // Average over every recorded amount, whatever its type
+    let sum = transaction_amount_sum(analytics).saturating_add(amount as u128);
+    analytics.total_transactions += 1;
+    analytics.sum_of_transaction_amounts = Some(sum);
+    analytics.average_transaction_amount = average_amount(sum, analytics.total_transactions);
+}
+
+// Records written before the running sum existed only kept the average, so the sum is seeded from it
+fn transaction_amount_sum(analytics: &FundAnalytics) -> u128 {
+    analytics.sum_of_transaction_amounts
+        .unwrap_or((analytics.average_transaction_amount * analytics.total_transactions as f64) as u128)
+}
+
+// Divides in integers first so huge sums keep their precision in the fractional part
+fn average_amount(sum: u128, count: u32) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    let count = count as u128;
+    (sum / count) as f64 + (sum % count) as f64 / count as f64
+}
+
+async fn process_transaction(transaction_id: String) {
+    // Simulate transaction processing delay
+    let settlement: Result<(Vec<u8>,), _> = ic_cdk::api::call::call_with_payment(
+        Principal::management_canister(),
+        "raw_rand",
+        (),
+        0,
+    ).await;
+    
+    match settlement {
+        // Update transaction status to completed; rejected if it was cancelled meanwhile
+        Ok(_) => {
+            let _result = transition_transaction(transaction_id, TransactionStatus::Completed);
+        }
+        // The next metrics tick drops it from active_transactions and pending_amount
+        Err((code, msg)) => {
+            let reason = format!("raw_rand call failed: {:?} {}", code, msg);
+            ic_cdk::println!("Processing of transaction {} failed: {}", transaction_id, reason);
+            let now = ic_cdk::api::time();
+            let failed = update_stored_transaction(&transaction_id, |transaction| {
+                fail_transaction(transaction, reason, now)?;
+                Ok(transaction.clone())
+            });
+            if let Ok(transaction) = failed {
+                record_terminal_status(&transaction.transaction_type, &TransactionStatus::Failed);
+                reverse_transaction_effects(&transaction, now);
+            }
+        }
+    }
+}
+
+fn fail_transaction(transaction: &mut FundTransaction, reason: String, now: u64) -> Result<(), String> {
+    set_transaction_status(transaction, TransactionStatus::Failed, now)?;
+    transaction.metadata.insert("failure_reason".to_string(), reason);
+    Ok(())
+}
+
+#[update]
+fn retry_processing(transaction_id: String) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can retry processing".to_string());
+    }
+    
+    let transaction = stored_transaction(&transaction_id).ok_or("Transaction not found".to_string())?;
+    let timeout_ns = unsafe { STUCK_PROCESSING_SECS }.saturating_mul(1_000_000_000);
+    if !is_stuck(&transaction, timeout_ns, ic_cdk::api::time()) {
+        return Err("Transaction is not stuck in Processing".to_string());
+    }
+    if matches!(transaction.transaction_type, TransactionType::Release) && unsafe { LEDGER_CANISTER_ID.is_some() } {
+        return Err("Ledger-backed releases complete through execute_release_on_ledger".to_string());
+    }
+    
+    ic_cdk::spawn(process_transaction(transaction_id));
+    Ok(())
+}
+
+#[update]
+fn set_stuck_processing_timeout(secs: u64) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can change the processing timeout".to_string());
+    }
+    if secs == 0 {
+        return Err("Timeout must be greater than zero".to_string());
+    }
+    
+    unsafe {
+        STUCK_PROCESSING_SECS = secs;
+    }
+    Ok(())
+}
+
+#[query]
+fn get_stuck_processing_timeout() -> u64 {
+    unsafe { STUCK_PROCESSING_SECS }
+}
+
+#[query]
+fn get_stuck_transactions() -> Vec<String> {
+    unsafe { STUCK_TRANSACTIONS.clone() }
+}
+
+// When the transaction last entered Processing
+fn processing_since(transaction: &FundTransaction) -> u64 {
+    transaction.status_history.as_deref().unwrap_or_default().iter().rev()
+        .find(|(status, _)| *status == TransactionStatus::Processing)
+        .map(|(_, at)| *at)
+        .unwrap_or(transaction.timestamp)
+}
+
+fn is_stuck(transaction: &FundTransaction, timeout_ns: u64, now: u64) -> bool {
+    transaction.status == TransactionStatus::Processing
+        && now.saturating_sub(processing_since(transaction)) > timeout_ns
+}
+
+fn find_stuck_transactions(
+    transactions: impl Iterator<Item = impl Borrow<FundTransaction>>,
+    timeout_ns: u64,
+    now: u64,
+) -> Vec<String> {
+    transactions
+        .filter(|transaction| is_stuck(transaction.borrow(), timeout_ns, now))
+        .map(|transaction| transaction.borrow().id.clone())
+        .collect()
+}
+
+fn sweep_stuck_transactions(now: u64) {
+    let timeout_ns = unsafe { STUCK_PROCESSING_SECS }.saturating_mul(1_000_000_000);
+    let stuck = TRANSACTIONS.with(|transactions| find_stuck_transactions(transactions.borrow().scan(), timeout_ns, now));
+    if !stuck.is_empty() {
+        ic_cdk::println!("{} transactions stuck in Processing: {}", stuck.len(), stuck.join(", "));
+    }
+    unsafe {
+        STUCK_TRANSACTIONS = stuck;
+    }
+}
+
+fn note_transaction_activity(now: u64) {
+    unsafe {
+        LAST_TX_ACTIVITY = LAST_TX_ACTIVITY.max(now);
+    }
+}
+
+async fn update_real_time_metrics() {
+    let now = ic_cdk::api::time();
+    
+    DEDUP_KEYS.with(|keys| prune_dedup_keys(&mut keys.borrow_mut(), now));
+    sweep_stuck_transactions(now);
+    
+    unsafe {
+        // Nothing changed and nothing aged out of a window since the last pass
+        let computed_at = REAL_TIME_METRICS.with(|cell| cell.borrow().get().current_time);
+        if metrics_are_current(LAST_TX_ACTIVITY, computed_at, METRICS_VALID_UNTIL, now) {
+            return;
+        }
+    }
+    
+    refresh_real_time_metrics(now);
+}
+
+fn refresh_real_time_metrics(now: u64) {
+    let (metrics, valid_until) = TRANSACTIONS.with(|transactions| compute_real_time_metrics(transactions.borrow().scan(), now));
+    REAL_TIME_METRICS.with(|cell| cell.borrow_mut().set(metrics))
+        .expect("Failed to save real-time metrics");
+    unsafe {
+        METRICS_VALID_UNTIL = valid_until;
+    }
+}
+
+fn metrics_are_current(last_activity: u64, computed_at: u64, valid_until: u64, now: u64) -> bool {
+    last_activity < computed_at && now < valid_until
+}
+
+// Single pass over the hot transactions; also returns when the result next goes stale
+fn compute_real_time_metrics(
+    transactions: impl Iterator<Item = impl Borrow<FundTransaction>>,
+    now: u64,
+) -> (RealTimeMetrics, u64) {
+    let mut metrics = RealTimeMetrics {
+        current_time: now,
+        active_transactions: 0,
+        pending_amount: 0,
+        daily_volume: 0,
+        weekly_volume: 0,
+        monthly_volume: 0,
+        hourly_volume: 0,
+        volume_by_type: BTreeMap::new(),
+        completed_last_hour: 0,
+    };
+    let mut valid_until = u64::MAX;
+    let hour_ago = now.saturating_sub(NANOS_PER_HOUR);
+    
+    for transaction in transactions {
+        let transaction = transaction.borrow();
+        match transaction.status {
+            TransactionStatus::Processing => {
+                metrics.active_transactions += 1;
+                metrics.pending_amount = metrics.pending_amount.saturating_add(transaction.amount as u128);
+            }
+            TransactionStatus::Completed => {
+                let amount = transaction.amount as u128;
+                
+                // Windows are nested, so the narrowest one a transaction falls in expires first
+                let windows = [
+                    (NANOS_PER_HOUR, &mut metrics.hourly_volume),
+                    (NANOS_PER_DAY, &mut metrics.daily_volume),
+                    (7 * NANOS_PER_DAY, &mut metrics.weekly_volume),
+                    (30 * NANOS_PER_DAY, &mut metrics.monthly_volume),
+                ];
+                let mut narrowest = None;
+                for (length, volume) in windows {
+                    if transaction.timestamp >= now.saturating_sub(length) {
+                        *volume = volume.saturating_add(amount);
+                        narrowest.get_or_insert(length);
+                    }
+                }
+                if let Some(length) = narrowest {
+                    valid_until = valid_until.min(transaction.timestamp.saturating_add(length));
+                }
+                
+                if matches!(transaction.transaction_type, TransactionType::Adjustment) {
+                    continue;
+                }
+                if transaction.timestamp >= now.saturating_sub(NANOS_PER_DAY) {
+                    let total = metrics.volume_by_type
+                        .entry(transaction_type_name(&transaction.transaction_type).to_string())
+                        .or_insert(0);
+                    *total = total.saturating_add(amount);
+                }
+                if let Some(&(_, completed_at)) = transaction.status_history.as_deref().unwrap_or_default().last() {
+                    if completed_at >= hour_ago {
+                        metrics.completed_last_hour += 1;
+                        valid_until = valid_until.min(completed_at.saturating_add(NANOS_PER_HOUR));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    (metrics, valid_until)
+}
+
+#[query]
+fn get_canister_stats() -> CanisterStats {
+    common::canister_stats()
+}
+
+#[query]
+fn health_check() -> HealthStatus {
+    health_status_at(ic_cdk::api::time())
+}
+
+fn health_status_at(now: u64) -> HealthStatus {
+    let transactions = TRANSACTIONS.with(|transactions| transactions.borrow().len() as usize);
+    let archived = ARCHIVE.with(|archive| archive.borrow().len() as usize);
+    let balances = FUND_BALANCES.with(|balances| balances.borrow().len() as usize);
+    let districts = DISTRICT_FUNDS.with(|districts| districts.borrow().len() as usize);
+    let flagged = FLAGGED_TX_INDEX.with(|flagged| flagged.borrow().len() as usize);
+    unsafe {
+        common::health_status(
+            INIT_TIME_NS.is_some(),
+            &[
+                ("transactions", transactions),
+                ("archived_transactions", archived),
+                ("fund_balances", balances),
+                ("district_funds", districts),
+                ("flagged_transactions", flagged),
+            ],
+            INIT_TIME_NS,
+            now,
+        )
+    }
+}
+
+// Candid interface
+candid::export_service!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_transaction_creation() {
+        // Test transaction creation logic
+        let transaction_id = "test_transaction_123".to_string();
+        assert!(transaction_id.contains("test"));
+    }
+    
+    fn test_index(keys: impl Iterator<Item = IndexKey>) -> TxIndex<DefaultMemoryImpl> {
+        let mut index = StableBTreeMap::new(DefaultMemoryImpl::default());
+        for key in keys {
+            index.insert(key, ());
+        }
+        index
+    }
+    
+    fn build_policy_index<'a>(transactions: impl Iterator<Item = &'a FundTransaction>) -> TxIndex<DefaultMemoryImpl> {
+        test_index(transactions.map(policy_key))
+    }
+    
+    fn build_time_index<'a>(transactions: impl Iterator<Item = &'a FundTransaction>) -> TxIndex<DefaultMemoryImpl> {
+        test_index(transactions.map(time_key))
+    }
+    
+    fn build_address_index<'a>(transactions: impl Iterator<Item = &'a FundTransaction>) -> TxIndex<DefaultMemoryImpl> {
+        test_index(transactions.flat_map(address_keys))
+    }
+    
+    fn sample_transaction(id: &str, policy_id: &str, transaction_type: TransactionType, amount: u64, timestamp: u64) -> FundTransaction {
+        FundTransaction {
+            id: id.to_string(),
+            policy_id: policy_id.to_string(),
+            transaction_type,
+            amount,
+            from_address: "government_treasury".to_string(),
+            to_address: "contractor".to_string(),
+            timestamp,
+            status: TransactionStatus::Completed,
+            transaction_hash: format!("tx_{}", id),
+            metadata: BTreeMap::new(),
+            status_history: Some(vec![(TransactionStatus::Completed, timestamp)]),
+            seq: None,
+            denomination: Some(Denomination::Paise),
+            anomaly_flags: None,
+        }
+    }
+    
+    #[test]
+    fn test_reconcile_policy_funds() {
+        // A smart_policy reply carries the whole Policy record; only fund_released is read
+        #[derive(CandidType)]
+        struct MockPolicy {
+            id: String,
+            fund_allocation: u64,
+            fund_released: u64,
+            district: String,
+        }
+        let reply = Encode!(&Ok::<MockPolicy, String>(MockPolicy {
+            id: "policy_1".to_string(),
+            fund_allocation: 10_000,
+            fund_released: 5_000,
+            district: "Pune".to_string(),
+        })).unwrap();
+        let decoded = Decode!(&reply, Result<PolicyFunds, String>).unwrap();
+        let policy = policy_funds_from(Ok((decoded,))).unwrap();
+        assert_eq!(policy.fund_released, 5_000);
+        
+        let mut failed = sample_transaction("t3", "policy_1", TransactionType::Release, 700, 3);
+        failed.status = TransactionStatus::Failed;
+        let transactions = [
+            sample_transaction("t1", "policy_1", TransactionType::Release, 3_000, 1),
+            sample_transaction("t2", "policy_1", TransactionType::Allocation, 10_000, 2),
+            failed,
+            sample_transaction("t4", "policy_2", TransactionType::Release, 2_000, 4),
+            sample_transaction("t5", "policy_1", TransactionType::Release, 1_500, 5),
+        ];
+        
+        // Deliberate mismatch: 500 released on smart_policy has no Release recorded here
+        let report = reconciliation_report("policy_1", policy.fund_released, ArchivedReleases::default(), transactions.iter(), 9);
+        assert!(!report.consistent);
+        assert_eq!(report.smart_policy_released, 5_000);
+        assert_eq!(report.fund_tracker_released, 4_500);
+        assert_eq!(report.release_transactions, 2);
+        assert_eq!(report.checked_at, 9);
+        let discrepancy = report.discrepancy.unwrap();
+        assert!(discrepancy.contains("5000") && discrepancy.contains("4500") && discrepancy.contains("500 missing here"));
+        
+        let report = reconciliation_report("policy_1", 4_500, ArchivedReleases::default(), transactions.iter(), 9);
+        assert!(report.consistent);
+        assert!(report.discrepancy.is_none());
+        
+        // Releases already moved to the archive count through the per-policy roll-up
+        let archived = ArchivedReleases { amount: 500, count: 1 };
+        let report = reconciliation_report("policy_1", policy.fund_released, archived, transactions.iter(), 9);
+        assert!(report.consistent);
+        assert_eq!(report.fund_tracker_released, 5_000);
+        assert_eq!(report.release_transactions, 3);
+        
+        assert!(policy_funds_from(Ok((Err("Policy not found".to_string()),))).is_err());
+    }
+    
+    fn empty_filter() -> TxFilter {
+        TxFilter {
+            policy_id: None,
+            status: None,
+            transaction_type: None,
+            from_timestamp: None,
+            to_timestamp: None,
+        }
+    }
+    
+    #[test]
+    fn test_list_transactions_paging_and_filters() {
+        let mut transactions = BTreeMap::new();
+        for i in 0..5u64 {
+            let id = format!("a{}", i);
+            transactions.insert(id.clone(), sample_transaction(&id, "policy_a", TransactionType::Allocation, 100, i * 10));
+        }
+        let mut failed = sample_transaction("b0", "policy_b", TransactionType::Release, 50, 25);
+        failed.status = TransactionStatus::Failed;
+        transactions.insert("b0".to_string(), failed);
+        let index = build_policy_index(transactions.values());
+        
+        let page = select_transactions(&transactions, &index, &empty_filter(), 0, 3);
+        assert_eq!(page.total_count, 6);
+        let ids: Vec<&str> = page.transactions.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["a4", "a3", "b0"]);
+        
+        let filter = TxFilter { policy_id: Some("policy_a".to_string()), from_timestamp: Some(10), to_timestamp: Some(30), ..empty_filter() };
+        let page = select_transactions(&transactions, &index, &filter, 1, 10);
+        assert_eq!(page.total_count, 3);
+        let ids: Vec<&str> = page.transactions.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["a2", "a1"]);
+        
+        let filter = TxFilter { status: Some(TransactionStatus::Failed), ..empty_filter() };
+        let page = select_transactions(&transactions, &index, &filter, 0, 10);
+        assert_eq!(page.total_count, 1);
+        assert_eq!(page.transactions[0].id, "b0");
+        
+        let page = select_transactions(&transactions, &index, &empty_filter(), 0, 1_000);
+        assert_eq!(page.limit, MAX_PAGE_SIZE);
+    }
+    
+    #[test]
+    fn test_transactions_by_address() {
+        let mut transactions = Vec::new();
+        for (id, from, to, timestamp) in [
+            ("t1", "treasury", "alice", 10),
+            ("t2", "alice", "bob", 30),
+            ("t3", "bob", "carol", 20),
+            ("t4", "alice", "alice", 40),
+            ("t5", "carol", "alice", 5),
+        ] {
+            let mut transaction = sample_transaction(id, "policy_1", TransactionType::Transfer, 100, timestamp);
+            transaction.from_address = from.to_string();
+            transaction.to_address = to.to_string();
+            transactions.push(transaction);
+        }
+        let ids = |page: Vec<FundTransaction>| page.into_iter().map(|transaction| transaction.id).collect::<Vec<_>>();
+        
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let archive: ArchiveLog<Memory> = StableLog::new(manager.get(ARCHIVE_INDEX_MEMORY_ID), manager.get(ARCHIVE_DATA_MEMORY_ID));
+        let mut archive_ids: ArchiveIds<Memory> = StableBTreeMap::init(manager.get(ARCHIVE_IDS_MEMORY_ID));
+        let mut addresses: ArchiveAddresses<Memory> = StableBTreeMap::init(manager.get(ARCHIVE_ADDRESSES_MEMORY_ID));
+        for transaction in &transactions {
+            archive_transaction(&archive, &mut archive_ids, &mut addresses, transaction).unwrap();
+        }
+        
+        // Most recently archived first; the self-transfer is filed once under its address
+        assert_eq!(ids(archived_address_page(&archive, &addresses, "alice", 0, 10)), vec!["t5", "t4", "t2", "t1"]);
+        assert_eq!(ids(archived_address_page(&archive, &addresses, "bob", 0, 10)), vec!["t3", "t2"]);
+        assert_eq!(ids(archived_address_page(&archive, &addresses, "alice", 1, 2)), vec!["t4", "t2"]);
+        assert!(archived_address_page(&archive, &addresses, "dave", 0, 10).is_empty());
+        // Addresses that share a prefix stay apart
+        assert_eq!(ids(archived_address_page(&archive, &addresses, "ali", 0, 10)), Vec::<String>::new());
+        assert_eq!(addresses.len(), 9);
+    }
+    
+    #[test]
+    fn test_empty_addresses_are_not_indexed() {
+        let mut transaction = sample_transaction("t1", "policy_1", TransactionType::Allocation, 100, 10);
+        transaction.from_address = String::new();
+        transaction.to_address = "alice".to_string();
+        
+        assert_eq!(indexed_addresses(&transaction).collect::<Vec<_>>(), vec!["alice"]);
+        let index = build_address_index(std::iter::once(&transaction));
+        assert_eq!(index.keys().collect::<Vec<_>>(), vec![IndexKey::new("alice", 10, "t1")]);
+    }
+    
+    #[test]
+    fn test_transactions_in_range_window_and_paging() {
+        const DAY: u64 = 86_400_000_000_000;
+        let mut transactions = BTreeMap::new();
+        for day in 0..6u64 {
+            let id = format!("d{}", day);
+            let policy_id = if day % 2 == 0 { "policy_a" } else { "policy_b" };
+            transactions.insert(id.clone(), sample_transaction(&id, policy_id, TransactionType::Allocation, 10, day * DAY));
+        }
+        let index = build_policy_index(transactions.values());
+        
+        let ids = |page: Vec<FundTransaction>| page.into_iter().map(|t| t.id).collect::<Vec<_>>();
+        
+        let window = transactions_in_range(&transactions, &index, None, DAY, 4 * DAY, 0, 10).unwrap();
+        assert_eq!(ids(window), vec!["d4", "d3", "d2", "d1"]);
+        
+        let second_page = transactions_in_range(&transactions, &index, None, DAY, 4 * DAY, 2, 2).unwrap();
+        assert_eq!(ids(second_page), vec!["d2", "d1"]);
+        
+        let policy_a = transactions_in_range(&transactions, &index, Some("policy_a".to_string()), 0, 5 * DAY, 0, 10).unwrap();
+        assert_eq!(ids(policy_a), vec!["d4", "d2", "d0"]);
+        
+        assert!(transactions_in_range(&transactions, &index, None, 2 * DAY, DAY, 0, 10).is_err());
+    }
+    
+    #[test]
+    fn test_address_ledger_completed_only_and_refunds() {
+        let release = sample_transaction("t1", "policy_a", TransactionType::Release, 300, 1);
+        let mut refund = sample_transaction("t2", "policy_a", TransactionType::Refund, 100, 2);
+        refund.from_address = "contractor".to_string();
+        refund.to_address = "government_treasury".to_string();
+        let mut failed = sample_transaction("t3", "policy_a", TransactionType::Release, 999, 3);
+        failed.status = TransactionStatus::Failed;
+        
+        let mut transactions = BTreeMap::new();
+        for transaction in [release, refund, failed] {
+            transactions.insert(transaction.id.clone(), transaction);
+        }
+        let ledger = build_address_ledger(transactions.values());
+        
+        let treasury = &ledger["government_treasury"];
+        assert_eq!(treasury.total_sent, 300);
+        assert_eq!(treasury.total_received, 100);
+        assert_eq!(treasury.net_balance, -200);
+        let contractor = &ledger["contractor"];
+        assert_eq!(contractor.net_balance, 200);
+        assert_eq!(contractor.transaction_count, 2);
+        
+        let index = build_address_index(transactions.values());
+        assert_eq!(indexed_ids(&index, "contractor", 0, u64::MAX).count(), 3);
+    }
+    
+    #[test]
+    fn test_ledger_transfer_outcomes() {
+        use ic_cdk::api::call::RejectionCode;
+        
+        assert_eq!(transfer_outcome(Ok(Ok(Nat::from(42u64)))), Ok(Nat::from(42u64)));
+        
+        let insufficient = transfer_outcome(Ok(Err(TransferError::InsufficientFunds { balance: Nat::from(5u64) })));
+        assert!(matches!(insufficient, Err(LedgerFailure::Rejected(ref e)) if e.contains("insufficient funds (balance 5)")));
+        
+        let generic = transfer_outcome(Ok(Err(TransferError::GenericError {
+            error_code: Nat::from(7u64),
+            message: "ledger paused".to_string(),
+        })));
+        assert!(matches!(generic, Err(LedgerFailure::Rejected(ref e)) if e.contains("ledger paused")));
+        
+        let rejected = transfer_outcome(Err((RejectionCode::CanisterReject, "no".to_string())));
+        assert!(matches!(rejected, Err(LedgerFailure::Rejected(ref e)) if e.contains("CanisterReject")));
+        
+        // Outcomes that may hide a committed transfer stay retryable
+        for code in [RejectionCode::SysTransient, RejectionCode::CanisterError, RejectionCode::Unknown] {
+            assert!(matches!(transfer_outcome(Err((code, "lost".to_string()))), Err(LedgerFailure::Retryable(_))));
+        }
+        let unavailable = transfer_outcome(Ok(Err(TransferError::TemporarilyUnavailable)));
+        assert!(matches!(unavailable, Err(LedgerFailure::Retryable(_))));
+        
+        // A retry of a transfer that already landed resolves to the original block
+        let duplicate = transfer_outcome(Ok(Err(TransferError::Duplicate { duplicate_of: Nat::from(9u64) })));
+        assert_eq!(duplicate, Ok(Nat::from(9u64)));
+        
+        assert_eq!(ledger_memo("TX_1"), ledger_memo("TX_1"));
+        assert_eq!(ledger_memo("TX_1").len(), 32);
+        assert_ne!(ledger_memo("TX_1"), ledger_memo("TX_2"));
+        
+        let account = parse_ledger_account("aaaaa-aa").unwrap();
+        assert_eq!(account, Account { owner: Principal::management_canister(), subaccount: None });
+        assert!(parse_ledger_account("contractor").is_err());
+    }
+    
+    #[test]
+    fn test_dedup_retry_conflict_and_expiry() {
+        let first = DedupEntry {
+            transaction_id: "tx_1".to_string(),
+            policy_id: "policy_a".to_string(),
+            amount: 100,
+            from_address: "government_treasury".to_string(),
+            to_address: "contractor".to_string(),
+            recorded_at: 1_000,
+        };
+        let mut keys = StableBTreeMap::new(DefaultMemoryImpl::default());
+        assert_eq!(check_dedup_key(&keys, "key_1", &first, 1_000), Ok(None));
+        keys.insert("key_1".to_string(), first.clone());
+        
+        // A retry gets the original id back, even with a fresh candidate id
+        let retry = DedupEntry { transaction_id: "tx_2".to_string(), recorded_at: 2_000, ..first.clone() };
+        assert_eq!(check_dedup_key(&keys, "key_1", &retry, 2_000), Ok(Some("tx_1".to_string())));
+        
+        let conflicting = DedupEntry { amount: 200, ..retry.clone() };
+        assert!(check_dedup_key(&keys, "key_1", &conflicting, 2_000).is_err());
+        
+        // After the window the key is free again and gets pruned
+        let later = 1_000 + DEDUP_WINDOW_NS;
+        assert_eq!(check_dedup_key(&keys, "key_1", &conflicting, later), Ok(None));
+        prune_dedup_keys(&mut keys, later - 1);
+        assert_eq!(keys.len(), 1);
+        prune_dedup_keys(&mut keys, later);
+        assert!(keys.is_empty());
+    }
+    
+    #[test]
+    fn test_convert_amount() {
+        use Denomination::*;
+        assert_eq!(convert_amount(250, Paise, Paise), Ok(250));
+        assert_eq!(convert_amount(250, Rupees, Rupees), Ok(250));
+        assert_eq!(convert_amount(250, E8s, E8s), Ok(250));
+        assert_eq!(convert_amount(25, Rupees, Paise), Ok(2_500));
+        assert_eq!(convert_amount(2_500, Paise, Rupees), Ok(25));
+        assert_eq!(
+            convert_amount(2_550, Paise, Rupees),
+            Err("2550 paise is not a whole number of rupees".to_string())
+        );
+        assert!(convert_amount(u64::MAX, Rupees, Paise).is_err());
+        for (from, to) in [(E8s, Paise), (E8s, Rupees), (Paise, E8s), (Rupees, E8s)] {
+            assert!(convert_amount(1, from, to).unwrap_err().contains("exchange rate"));
+        }
+        
+        let mut metadata = BTreeMap::new();
+        assert_eq!(normalize_to_paise(40, &Rupees, &mut metadata), Ok(4_000));
+        assert_eq!(metadata.get("original_amount").map(String::as_str), Some("40 Rupees"));
+        let mut metadata = BTreeMap::new();
+        assert_eq!(normalize_to_paise(40, &Paise, &mut metadata), Ok(40));
+        assert!(metadata.is_empty());
+        
+        let mut legacy = sample_transaction("t1", "policy_a", TransactionType::Release, 10, 1);
+        legacy.denomination = None;
+        let mut current = sample_transaction("t2", "policy_a", TransactionType::Release, 10, 2);
+        assert_eq!(assume_paise([&mut legacy, &mut current].into_iter()), 1);
+        assert!(matches!(legacy.denomination, Some(Denomination::Paise)));
+    }
+    
+    #[test]
+    fn test_fund_balance_listing() {
+        let mut fund_balances = BTreeMap::new();
+        for (policy_id, allocated, released, updated) in [("policy_a", 500, 100, 3), ("policy_b", 900, 800, 1), ("policy_c", 300, 0, 2)] {
+            let mut balance = empty_balance(policy_id);
+            apply_to_balance(&mut balance, &TransactionType::Allocation, allocated, updated);
+            apply_to_balance(&mut balance, &TransactionType::Release, released, updated);
+            fund_balances.insert(policy_id.to_string(), balance);
+        }
+        
+        let ids = |page: BalancePage| page.balances.into_iter().map(|balance| balance.policy_id).collect::<Vec<_>>();
+        assert_eq!(ids(balance_page(fund_balances.values(), 0, 10, &BalanceSort::CurrentBalance)), vec!["policy_a", "policy_c", "policy_b"]);
+        assert_eq!(ids(balance_page(fund_balances.values(), 0, 10, &BalanceSort::TotalAllocated)), vec!["policy_b", "policy_a", "policy_c"]);
+        assert_eq!(ids(balance_page(fund_balances.values(), 1, 1, &BalanceSort::LastUpdated)), vec!["policy_c"]);
+        assert_eq!(balance_page(fund_balances.values(), 1, 1, &BalanceSort::LastUpdated).total_count, 3);
+        
+        let position = treasury_position(fund_balances.values());
+        assert_eq!(position.total_allocated, 1_700);
+        assert_eq!(position.total_released, 900);
+        assert_eq!(position.current_balance, 800);
+        assert_eq!(position.policy_count, 3);
+    }
+    
+    #[test]
+    fn test_release_allocation_ceiling() {
+        let mut balance = empty_balance("policy_a");
+        apply_to_balance(&mut balance, &TransactionType::Allocation, 1_000, 1);
+        apply_to_balance(&mut balance, &TransactionType::Release, 800, 2);
+        
+        assert_eq!(
+            check_spending_limits(Some(&balance), &TransactionType::Release, 300, false),
+            Err("Release of 300 exceeds the remaining allocation; headroom is 200".to_string())
+        );
+        assert!(check_spending_limits(Some(&balance), &TransactionType::Release, 200, false).is_ok());
+        
+        // A supplementary grant may go past the ceiling and is tagged for audit
+        assert!(check_spending_limits(Some(&balance), &TransactionType::Release, 300, true).is_ok());
+        let mut metadata = BTreeMap::new();
+        let controller = Principal::from_slice(&[7]);
+        tag_override(&mut metadata, &controller);
+        assert_eq!(metadata.get("override").map(String::as_str), Some("true"));
+        assert_eq!(metadata.get("override_by"), Some(&controller.to_text()));
+        
+        // Refunds hand headroom back
+        apply_to_balance(&mut balance, &TransactionType::Refund, 150, 3);
+        assert!(check_spending_limits(Some(&balance), &TransactionType::Release, 350, false).is_ok());
+        assert!(check_spending_limits(Some(&balance), &TransactionType::Release, 351, false).is_err());
+    }
+    
+    #[test]
+    fn test_overdraft_prevention() {
+        let mut balance = FundBalance {
+            policy_id: "policy_a".to_string(),
+            total_allocated: 0,
+            total_released: 0,
+            total_transferred: 0,
+            total_fees: Some(0),
+            current_balance: 0,
+            last_updated: 0,
+        };
+        apply_to_balance(&mut balance, &TransactionType::Allocation, 500, 1);
+        
+        assert_eq!(
+            check_sufficient_balance(Some(&balance), &TransactionType::Release, 501),
+            Err("insufficient balance".to_string())
+        );
+        assert!(check_sufficient_balance(Some(&balance), &TransactionType::Release, 500).is_ok());
+        assert!(check_sufficient_balance(Some(&balance), &TransactionType::Fee, 501).is_err());
+        assert!(check_sufficient_balance(None, &TransactionType::Transfer, 1).is_err());
+        assert!(check_sufficient_balance(None, &TransactionType::Allocation, 1_000_000).is_ok());
+    }
+    
+    #[test]
+    fn test_policy_transactions_csv() {
+        let mut quoted = sample_transaction("t2", "policy_a", TransactionType::Release, 40, 2);
+        quoted.to_address = "Acme, \"Roads\" Ltd".to_string();
+        let transactions = vec![
+            sample_transaction("t1", "policy_a", TransactionType::Allocation, 100, 1),
+            quoted,
+        ];
+        
+        let csv = policy_transactions_csv(&transactions).unwrap();
+        let lines: Vec<&str> = csv.split("\r\n").filter(|line| !line.is_empty()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "id,type,amount,from,to,timestamp,status,hash");
+        assert_eq!(lines[1], "t1,Allocation,100,government_treasury,contractor,1,Completed,tx_t1");
+        assert!(lines[2].contains(",\"Acme, \"\"Roads\"\" Ltd\","));
+        
+        let too_many = vec![sample_transaction("t", "policy_a", TransactionType::Fee, 1, 1); MAX_CSV_ROWS + 1];
+        assert!(policy_transactions_csv(&too_many).is_err());
+    }
+    
+    #[test]
+    fn test_transactions_csv_chunks() {
+        let mut quoted = sample_transaction("t2", "policy_a", TransactionType::Release, 40, 1_700_000_000_123_000_000);
+        quoted.to_address = "Acme, \"Roads\" Ltd\nBlock 4".to_string();
+        let plain = sample_transaction("t1", "policy_a", TransactionType::Allocation, 100, 1);
+        let transactions = vec![quoted, plain];
+        
+        let first = transactions_csv_chunk(&transactions, 0, 1);
+        assert!(first.has_more);
+        let csv = String::from_utf8(first.data).unwrap();
+        let rows = parse_csv(&csv);
+        assert_eq!(rows[0][1], "policy_id");
+        assert_eq!(rows[1][5], "Acme, \"Roads\" Ltd\nBlock 4");
+        assert_eq!(rows[1][6], "2023-11-14T22:13:20.123Z");
+        
+        let second = transactions_csv_chunk(&transactions, 1, 1);
+        assert!(!second.has_more);
+        let rows = parse_csv(&String::from_utf8(second.data).unwrap());
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], "t1");
+        assert_eq!(rows[0][6], "1970-01-01T00:00:00.000Z");
+        
+        assert!(transactions_csv_chunk(&transactions, 5, 1).data.is_empty());
+    }
+    
+    fn parse_csv(csv: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut chars = csv.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, in_quotes) {
+                ('"', true) if chars.peek() == Some(&'"') => { chars.next(); field.push('"'); }
+                ('"', _) => in_quotes = !in_quotes,
+                (',', false) => row.push(std::mem::take(&mut field)),
+                ('\r', false) => {}
+                ('\n', false) => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                _ => field.push(c),
+            }
+        }
+        rows
+    }
+    
+    #[test]
+    fn test_top_entries_by_window() {
+        let now = 40 * NANOS_PER_DAY;
+        let mut transactions = BTreeMap::new();
+        let samples = [
+            ("t1", "policy_a", "Pune", TransactionType::Release, 500, now - NANOS_PER_DAY / 2),
+            ("t2", "policy_b", "Nagpur", TransactionType::Release, 300, now - 2 * NANOS_PER_DAY),
+            ("t3", "policy_b", "Nagpur", TransactionType::Release, 400, now - 3 * NANOS_PER_DAY),
+            ("t4", "policy_c", "Pune", TransactionType::Allocation, 9_000, now - 35 * NANOS_PER_DAY),
+        ];
+        for (id, policy_id, district, transaction_type, amount, timestamp) in samples {
+            let mut transaction = sample_transaction(id, policy_id, transaction_type, amount, timestamp);
+            transaction.metadata.insert("district".to_string(), district.to_string());
+            transactions.insert(id.to_string(), transaction);
+        }
+        let mut cancelled = sample_transaction("t5", "policy_a", TransactionType::Release, 10_000, now);
+        cancelled.status = TransactionStatus::Cancelled;
+        transactions.insert("t5".to_string(), cancelled);
+        let index = build_time_index(transactions.values());
+        let by_district = |transaction: &FundTransaction| transaction.metadata.get("district").cloned();
+        
+        let day = top_entries(transactions_in_window(&transactions, &index, &TimeWindow::Day, now), &TopMetric::ReleasedVolume, 10, by_district);
+        assert_eq!(day.len(), 1);
+        assert_eq!((day[0].key.as_str(), day[0].value), ("Pune", 500));
+        
+        let week = top_entries(transactions_in_window(&transactions, &index, &TimeWindow::Week, now), &TopMetric::ReleasedVolume, 10, by_district);
+        assert_eq!((week[0].key.as_str(), week[0].value), ("Nagpur", 700));
+        assert_eq!((week[1].key.as_str(), week[1].value), ("Pune", 500));
+        
+        let month = top_entries(transactions_in_window(&transactions, &index, &TimeWindow::Month, now), &TopMetric::AllocatedVolume, 10, by_district);
+        assert!(month.is_empty());
+        
+        let policies = top_entries(
+            transactions_in_window(&transactions, &index, &TimeWindow::AllTime, now),
+            &TopMetric::TxCount,
+            1,
+            |transaction| Some(transaction.policy_id.clone()),
+        );
+        assert_eq!(policies.len(), 1);
+        assert_eq!((policies[0].key.as_str(), policies[0].value), ("policy_b", 2));
+    }
+    
+    #[test]
+    fn test_stable_transaction_store() {
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let mut stable: StableBTreeMap<String, FundTransaction, Memory> = StableBTreeMap::init(manager.get(TRANSACTIONS_MEMORY_ID));
+        let mut heap = BTreeMap::new();
+        for transaction in [
+            sample_transaction("t1", "policy_a", TransactionType::Allocation, 100, 1),
+            sample_transaction("t2", "policy_b", TransactionType::Release, 40, 2),
+            sample_transaction("t3", "policy_a", TransactionType::Release, 30, 3),
+        ] {
+            stable.insert(transaction.id.clone(), transaction.clone());
+            heap.insert(transaction.id.clone(), transaction);
+        }
+        let index = build_policy_index(heap.values());
+        
+        assert_eq!(stable.lookup("t2").map(|transaction| transaction.amount), Some(40));
+        assert!(stable.lookup("missing").is_none());
+        
+        // The stable map answers exactly like the heap map it replaced
+        let ids = |page: TxPage| page.transactions.into_iter().map(|t| t.id).collect::<Vec<_>>();
+        let filter = TxFilter { policy_id: Some("policy_a".to_string()), ..empty_filter() };
+        assert_eq!(ids(select_transactions(&stable, &index, &filter, 0, 10)), vec!["t3", "t1"]);
+        assert_eq!(ids(select_transactions(&stable, &index, &empty_filter(), 0, 10)), ids(select_transactions(&heap, &index, &empty_filter(), 0, 10)));
+    }
+    
+    #[test]
+    fn test_anomaly_rules() {
+        let config = AnomalyConfig { amount_percentile: 90, min_history: 10, absolute_threshold: Some(5_000), repeat_window_secs: 60 };
+        let history: Vec<FundTransaction> = (1..=10)
+            .map(|i| {
+                let mut transaction = sample_transaction(&format!("t{}", i), "policy_a", TransactionType::Release, i * 100, i);
+                transaction.to_address = format!("vendor_{}", i);
+                transaction
+            })
+            .collect();
+        let amounts: Vec<u64> = history.iter().map(|transaction| transaction.amount).collect();
+        
+        // 90th percentile of 100..=1000 is 900
+        let mut candidate = sample_transaction("new", "policy_a", TransactionType::Release, 950, 20);
+        candidate.to_address = "vendor_3".to_string();
+        assert_eq!(detect_anomalies(&candidate, &amounts, true, &[], &config), vec![FLAG_ABOVE_PERCENTILE]);
+        candidate.amount = 900;
+        assert!(detect_anomalies(&candidate, &amounts, true, &[], &config).is_empty());
+        
+        // Too little history for the percentile; the absolute threshold still applies
+        candidate.amount = 6_000;
+        assert_eq!(detect_anomalies(&candidate, &amounts[..5], true, &[], &config), vec![FLAG_ABOVE_THRESHOLD]);
+        
+        candidate.amount = 300;
+        assert_eq!(detect_anomalies(&candidate, &amounts, true, &history[2..3], &config), vec![FLAG_REPEATED_AMOUNT]);
+        let mut cancelled = history[2].clone();
+        cancelled.status = TransactionStatus::Cancelled;
+        assert!(detect_anomalies(&candidate, &amounts, true, &[cancelled], &config).is_empty());
+        
+        candidate.to_address = "stranger".to_string();
+        assert_eq!(detect_anomalies(&candidate, &amounts, false, &[], &config), vec![FLAG_NEW_RECIPIENT]);
+        candidate.transaction_type = TransactionType::Allocation;
+        assert!(detect_anomalies(&candidate, &amounts, false, &[], &config).is_empty());
+        
+        candidate.anomaly_flags = Some(vec![FLAG_NEW_RECIPIENT.to_string()]);
+        let reviewer = Principal::from_slice(&[9]);
+        assert!(clear_flag(&mut candidate, FLAG_REPEATED_AMOUNT, "n/a", &reviewer).is_err());
+        clear_flag(&mut candidate, FLAG_NEW_RECIPIENT, "known contractor", &reviewer).unwrap();
+        assert_eq!(candidate.anomaly_flags, Some(vec![]));
+        assert_eq!(
+            candidate.metadata.get("dismissed_new_recipient"),
+            Some(&format!("{}: known contractor", reviewer.to_text()))
+        );
+        assert!(candidate.anomaly_flags.as_ref().is_none_or(|flags| flags.is_empty()));
+    }
+    
+    #[test]
+    fn test_anomaly_history_survives_archiving() {
+        let mut amounts = PolicyAmounts::default();
+        for amount in 0..ANOMALY_AMOUNT_WINDOW as u64 + 5 {
+            push_recent_amount(&mut amounts, &TransactionType::Release, amount);
+        }
+        let recent = &amounts.recent["Release"];
+        assert_eq!(recent.len(), ANOMALY_AMOUNT_WINDOW);
+        assert_eq!(recent[0], 5);
+        
+        // A failed release leaves the window; other types are untouched
+        push_recent_amount(&mut amounts, &TransactionType::Allocation, 7);
+        drop_recent_amount(&mut amounts, &TransactionType::Release, 100);
+        assert!(!amounts.recent["Release"].contains(&100));
+        assert_eq!(amounts.recent["Allocation"], vec![7]);
+        
+        // Addresses stay known once the transaction that introduced them has left the hot map
+        let mut release = sample_transaction("t1", "policy_a", TransactionType::Release, 500, 1);
+        release.to_address = "vendor_1".to_string();
+        note_for_anomaly_checks(&release);
+        let mut repeat = sample_transaction("t2", "policy_a", TransactionType::Release, 500, 2);
+        repeat.to_address = "vendor_1".to_string();
+        assert!(!anomaly_flags_for(&repeat).contains(&FLAG_NEW_RECIPIENT.to_string()));
+        repeat.policy_id = "policy_b".to_string();
+        assert!(anomaly_flags_for(&repeat).contains(&FLAG_NEW_RECIPIENT.to_string()));
+    }
+    
+    #[test]
+    fn test_receipt_witness_verifies() {
+        let mut receipts = BTreeMap::new();
+        let mut known = None;
+        for (id, amount) in [("t1", 10), ("t2", 20), ("t3", 30), ("t4", 40), ("t5", 50)] {
+            let mut transaction = sample_transaction(id, "policy_a", TransactionType::Release, amount, 1);
+            transaction.status = TransactionStatus::Completed;
+            receipts.insert(id.to_string(), receipt_hash(&transaction));
+            if id == "t4" {
+                known = Some(transaction);
+            }
+        }
+        let known = known.unwrap();
+        let root = receipts_root_hash(&receipts);
+        
+        let witness = receipt_witness(&receipts, "t4").unwrap();
+        assert_eq!(common::reconstruct(&witness), root);
+        
+        // The only unpruned leaf is the one for t4, holding the recomputed hash
+        fn find_leaf(tree: &HashTree, label: &[u8]) -> Option<Vec<u8>> {
+            match tree {
+                HashTree::Labeled(l, subtree) if l.as_slice() == label => match subtree.as_ref() {
+                    HashTree::Leaf(value) => Some(value.clone()),
+                    other => find_leaf(other, label),
+                },
+                HashTree::Labeled(_, subtree) => find_leaf(subtree, label),
+                HashTree::Fork(left, right) => find_leaf(left, label).or_else(|| find_leaf(right, label)),
+                _ => None,
+            }
+        }
+        assert_eq!(find_leaf(&witness, b"t4"), Some(receipt_hash(&known).to_vec()));
+        assert_eq!(find_leaf(&witness, b"t2"), None);
+        
+        // A tampered transaction no longer matches the certified leaf
+        let mut tampered = known.clone();
+        tampered.amount += 1;
+        assert_ne!(receipt_hash(&tampered), receipt_hash(&known));
+        
+        assert!(receipt_witness(&receipts, "missing").is_none());
+        assert_eq!(common::reconstruct(&receipt_witness(&receipts, "t1").unwrap()), root);
+    }
+    
+    #[test]
+    fn test_transaction_feed_cursor() {
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let mut feed: TxFeed<Memory> = StableBTreeMap::init(manager.get(TX_FEED_MEMORY_ID));
+        
+        let mut transaction = sample_transaction("t1", "policy_a", TransactionType::Release, 10, 1);
+        let first = append_feed_event(&mut feed, |seq| {
+            transaction.seq = Some(seq);
+            TxEvent::Recorded(transaction.clone())
+        });
+        let second = append_feed_event(&mut feed, |_| TxEvent::StatusChanged {
+            transaction_id: "t1".to_string(),
+            status: TransactionStatus::Completed,
+            timestamp: 2,
+        });
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(transaction.seq, Some(1));
+        
+        let page = feed_page(&feed, 0, 1);
+        assert_eq!(page.events.len(), 1);
+        assert!(matches!(page.events[0].event, TxEvent::Recorded(ref recorded) if recorded.seq == Some(1)));
+        assert_eq!((page.next_cursor, page.latest_seq, page.oldest_seq), (1, 2, 1));
+        
+        let page = feed_page(&feed, page.next_cursor, 10);
+        assert_eq!(page.events.len(), 1);
+        assert!(matches!(page.events[0].event, TxEvent::StatusChanged { ref transaction_id, .. } if transaction_id == "t1"));
+        
+        // Caught up: no events and the cursor stays put
+        let page = feed_page(&feed, 2, 10);
+        assert!(page.events.is_empty());
+        assert_eq!(page.next_cursor, 2);
+    }
+    
+    #[test]
+    fn test_prune_into_archive() {
+        let mut transactions = BTreeMap::new();
+        let mut pending = sample_transaction("t2", "policy_a", TransactionType::Release, 50, 20);
+        pending.status = TransactionStatus::Processing;
+        for transaction in [
+            sample_transaction("t1", "policy_a", TransactionType::Allocation, 500, 10),
+            pending,
+            sample_transaction("t3", "policy_a", TransactionType::Release, 200, 30),
+            sample_transaction("t4", "policy_a", TransactionType::Release, 100, 40),
+        ] {
+            transactions.insert(transaction.id.clone(), transaction);
+        }
+        let mut time_index = build_time_index(transactions.values());
+        let mut policy_index = build_policy_index(transactions.values());
+        
+        // Pending work and anything at or after the cutoff stays hot
+        assert_eq!(prunable_transactions(&transactions, &time_index, 40, 10), vec!["t1", "t3"]);
+        assert_eq!(prunable_transactions(&transactions, &time_index, 40, 1), vec!["t1"]);
+        
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let archive: ArchiveLog<Memory> = StableLog::new(manager.get(ARCHIVE_INDEX_MEMORY_ID), manager.get(ARCHIVE_DATA_MEMORY_ID));
+        let mut ids: ArchiveIds<Memory> = StableBTreeMap::init(manager.get(ARCHIVE_IDS_MEMORY_ID));
+        let mut addresses: ArchiveAddresses<Memory> = StableBTreeMap::init(manager.get(ARCHIVE_ADDRESSES_MEMORY_ID));
+        let mut baseline = empty_balance("policy_a");
+        for id in prunable_transactions(&transactions, &time_index, 40, 10) {
+            let transaction = transactions.remove(&id).unwrap();
+            archive_transaction(&archive, &mut ids, &mut addresses, &transaction).unwrap();
+            apply_to_balance(&mut baseline, &transaction.transaction_type, transaction.amount, transaction.timestamp);
+            time_index.remove(&time_key(&transaction));
+            policy_index.remove(&policy_key(&transaction));
+        }
+        
+        assert_eq!(archive.len(), 2);
+        let archived = archive.get(ids.get(&"t3".to_string()).unwrap()).unwrap();
+        assert_eq!(archived.amount, 200);
+        assert_eq!(archived.status_history.unwrap_or_default().len(), 1);
+        assert_eq!(time_index.len(), 2);
+        assert!(!policy_index.contains_key(&IndexKey::new("policy_a", 10, "t1")));
+        
+        // Reconciliation over the hot map still sees the archived contributions
+        let computed = recompute_balance("policy_a", Some(&baseline), transactions.values());
+        assert_eq!(computed.total_allocated, 500);
+        assert_eq!(computed.total_released, 350);
+        assert_eq!(computed.current_balance, 150);
+    }
+    
+    #[test]
+    fn test_failed_processing_records_reason() {
+        let mut transaction = sample_transaction("t1", "policy_a", TransactionType::Release, 50, 1);
+        transaction.status = TransactionStatus::Processing;
+        transaction.status_history = Some(vec![(TransactionStatus::Processing, 1)]);
+        
+        fail_transaction(&mut transaction, "raw_rand call failed: SysTransient timeout".to_string(), 2).unwrap();
+        assert_eq!(transaction.status, TransactionStatus::Failed);
+        assert_eq!(transaction.status_history.unwrap_or_default().last(), Some(&(TransactionStatus::Failed, 2)));
+        assert_eq!(transaction.metadata.get("failure_reason").map(String::as_str), Some("raw_rand call failed: SysTransient timeout"));
+        
+        // A transaction cancelled while the call was in flight keeps its cancellation
+        let mut cancelled = sample_transaction("t2", "policy_a", TransactionType::Release, 50, 1);
+        cancelled.status = TransactionStatus::Cancelled;
+        assert!(fail_transaction(&mut cancelled, "late failure".to_string(), 2).is_err());
+        assert!(!cancelled.metadata.contains_key("failure_reason"));
+    }
+    
+    #[test]
+    fn test_stuck_processing_sweep() {
+        let mut stuck = sample_transaction("t1", "policy_a", TransactionType::Release, 50, 1);
+        stuck.status = TransactionStatus::Processing;
+        stuck.status_history = Some(vec![(TransactionStatus::Pending, 1), (TransactionStatus::Processing, 10)]);
+        let mut fresh = sample_transaction("t2", "policy_a", TransactionType::Release, 50, 1);
+        fresh.status = TransactionStatus::Processing;
+        fresh.status_history = Some(vec![(TransactionStatus::Processing, 90)]);
+        let mut done = sample_transaction("t3", "policy_a", TransactionType::Release, 50, 1);
+        done.status = TransactionStatus::Completed;
+        done.status_history = Some(vec![(TransactionStatus::Processing, 1), (TransactionStatus::Completed, 2)]);
+        
+        // The timeout runs from entering Processing, not from the original timestamp
+        assert_eq!(processing_since(&stuck), 10);
+        assert!(is_stuck(&stuck, 50, 100));
+        assert!(!is_stuck(&stuck, 90, 100));
+        assert!(!is_stuck(&fresh, 50, 100));
+        assert!(!is_stuck(&done, 50, 100));
+        
+        let transactions = [stuck, fresh, done];
+        assert_eq!(find_stuck_transactions(transactions.iter(), 50, 100), vec!["t1".to_string()]);
+        assert_eq!(find_stuck_transactions(transactions.iter(), 5, 100), vec!["t1".to_string(), "t2".to_string()]);
+    }
+    
+    #[test]
+    fn test_success_rate_from_terminal_statuses() {
+        let mut counts = BTreeMap::new();
+        assert_eq!(overall_success_rate(&counts), None);
+        
+        // Non-terminal statuses and adjustments leave the rate absent
+        add_terminal_status(&mut counts, &TransactionType::Release, &TransactionStatus::Processing);
+        add_terminal_status(&mut counts, &TransactionType::Adjustment, &TransactionStatus::Completed);
+        assert_eq!(overall_success_rate(&counts), None);
+        
+        add_terminal_status(&mut counts, &TransactionType::Release, &TransactionStatus::Completed);
+        add_terminal_status(&mut counts, &TransactionType::Release, &TransactionStatus::Failed);
+        add_terminal_status(&mut counts, &TransactionType::Allocation, &TransactionStatus::Completed);
+        add_terminal_status(&mut counts, &TransactionType::Allocation, &TransactionStatus::Completed);
+        add_terminal_status(&mut counts, &TransactionType::Allocation, &TransactionStatus::Cancelled);
+        
+        assert_eq!(success_rate(&counts["Release"]), Some(0.5));
+        assert_eq!(counts["Allocation"].cancelled, 1);
+        assert_eq!(overall_success_rate(&counts), Some(0.6));
+        assert!(!counts.contains_key("Adjustment"));
+    }
+    
+    #[test]
+    fn test_analytics_beyond_u64() {
+        let mut analytics = get_fund_analytics();
+        
+        apply_to_analytics(&mut analytics, &TransactionType::Allocation, u64::MAX);
+        apply_to_analytics(&mut analytics, &TransactionType::Allocation, u64::MAX);
+        apply_to_analytics(&mut analytics, &TransactionType::Allocation, 3);
+        
+        let expected = 2 * u64::MAX as u128 + 3;
+        assert_eq!(analytics.total_funds_allocated, expected);
+        assert_eq!(analytics.type_distribution.as_ref().unwrap()["Allocation"], expected);
+        assert_eq!(analytics.sum_of_transaction_amounts, Some(expected));
+        assert_eq!(analytics.average_transaction_amount, expected as f64 / 3.0);
+        assert_eq!(average_amount(7, 2), 3.5);
+        assert_eq!(average_amount(0, 0), 0.0);
+        
+        reverse_analytics(&mut analytics, &TransactionType::Allocation, u64::MAX);
+        assert_eq!(analytics.total_funds_allocated, u64::MAX as u128 + 3);
+    }
+    
+    #[test]
+    fn test_original_fund_analytics_decodes() {
+        let mut district_distribution = BTreeMap::new();
+        district_distribution.insert("Pune".to_string(), 700);
+        let original = LegacyFundAnalytics {
+            total_funds_allocated: 1_000,
+            total_funds_released: 400,
+            total_transactions: 2,
+            average_transaction_amount: 700.0,
+            district_distribution,
+            category_distribution: BTreeMap::new(),
+            monthly_trends: BTreeMap::new(),
+            success_rate: 0.95,
+        };
+        let bytes = Encode!(&original).unwrap();
+        
+        let analytics: FundAnalytics = Decode!(&bytes, LegacyFundAnalytics).unwrap().into();
+        assert_eq!(analytics.total_funds_allocated, 1_000);
+        assert_eq!(analytics.total_funds_released, 400);
+        assert_eq!(analytics.district_distribution.get("Pune"), Some(&700));
+        assert_eq!(analytics.success_rate, None);
+        assert_eq!(analytics.sum_of_transaction_amounts, None);
+        assert_eq!(transaction_amount_sum(&analytics), 1_400);
+        assert!(matches!(analytics.canonical_unit, Some(Denomination::Paise)));
+    }
+    
+    #[test]
+    fn test_original_heap_state_decodes() {
+        #[derive(CandidType)]
+        struct OriginalTransaction {
+            id: String,
+            policy_id: String,
+            transaction_type: TransactionType,
+            amount: u64,
+            from_address: String,
+            to_address: String,
+            timestamp: u64,
+            status: TransactionStatus,
+            transaction_hash: String,
+            metadata: BTreeMap<String, String>,
+        }
+        #[derive(CandidType)]
+        struct OriginalBalance {
+            policy_id: String,
+            total_allocated: u64,
+            total_released: u64,
+            total_transferred: u64,
+            current_balance: u64,
+            last_updated: u64,
+        }
+        
+        let mut transactions = BTreeMap::new();
+        transactions.insert("t1".to_string(), OriginalTransaction {
+            id: "t1".to_string(),
+            policy_id: "policy_a".to_string(),
+            transaction_type: TransactionType::Allocation,
+            amount: 1_000,
+            from_address: "government_treasury".to_string(),
+            to_address: "policy_a".to_string(),
+            timestamp: 10,
+            status: TransactionStatus::Completed,
+            transaction_hash: "tx_1".to_string(),
+            metadata: BTreeMap::new(),
+        });
+        let mut balances = BTreeMap::new();
+        balances.insert("policy_a".to_string(), OriginalBalance {
+            policy_id: "policy_a".to_string(),
+            total_allocated: 1_000,
+            total_released: 0,
+            total_transferred: 0,
+            current_balance: 1_000,
+            last_updated: 10,
+        });
+        let analytics = LegacyFundAnalytics {
+            total_funds_allocated: 1_000,
+            total_funds_released: 0,
+            total_transactions: 1,
+            average_transaction_amount: 1_000.0,
+            district_distribution: BTreeMap::new(),
+            category_distribution: BTreeMap::new(),
+            monthly_trends: BTreeMap::new(),
+            success_rate: 0.95,
+        };
+        let metrics = LegacyRealTimeMetrics {
+            current_time: 10,
+            active_transactions: 0,
+            pending_amount: 0,
+            daily_volume: 1_000,
+            weekly_volume: 1_000,
+            monthly_volume: 1_000,
+        };
+        let bytes = candid::encode_args((transactions, balances, BTreeMap::<String, DistrictFunds>::new(), analytics, metrics)).unwrap();
+        
+        let (transactions, balances, districts, _, metrics): BaselineHeapState = candid::decode_args(&bytes).unwrap();
+        let transaction = &transactions["t1"];
+        assert_eq!(transaction.amount, 1_000);
+        assert!(transaction.status_history.is_none() && transaction.denomination.is_none());
+        assert_eq!(balances["policy_a"].total_fees, None);
+        assert!(districts.is_empty());
+        let metrics: RealTimeMetrics = metrics.into();
+        assert_eq!(metrics.daily_volume, 1_000);
+    }
+    
+    #[test]
+    fn test_index_ranges_stay_within_a_name() {
+        let index = test_index([
+            IndexKey::new("policy_a", 5, "a1"),
+            IndexKey::new("policy_a", u64::MAX, "a2"),
+            IndexKey::new("policy_ab", 0, "ab1"),
+            IndexKey::new("policy_b", 3, "b1"),
+            IndexKey::new("", 7, "t1"),
+        ].into_iter());
+        
+        assert_eq!(indexed_ids(&index, "policy_a", 0, u64::MAX).collect::<Vec<_>>(), vec!["a1", "a2"]);
+        assert_eq!(indexed_ids(&index, "policy_a", 0, 5).rev().collect::<Vec<_>>(), vec!["a1"]);
+        assert_eq!(indexed_ids(&index, "policy_a", 6, u64::MAX - 1).count(), 0);
+        assert_eq!(indexed_ids(&index, "policy_a", 9, 1).count(), 0);
+        assert_eq!(indexed_ids(&index, "", 0, u64::MAX).collect::<Vec<_>>(), vec!["t1"]);
+        
+        let key = IndexKey::new("policy_ab", 42, "ab2");
+        assert_eq!(IndexKey::from_bytes(key.to_bytes()), key);
+    }
+    
+    #[test]
+    fn test_missing_amount_sum_is_seeded_from_average() {
+        let mut analytics = get_fund_analytics();
+        analytics.total_transactions = 4;
+        analytics.average_transaction_amount = 250.0;
+        analytics.sum_of_transaction_amounts = None;
+        
+        apply_to_analytics(&mut analytics, &TransactionType::Allocation, 500);
+        assert_eq!(analytics.sum_of_transaction_amounts, Some(1_500));
+        assert_eq!(analytics.average_transaction_amount, 300.0);
+    }
+    
+    #[test]
+    fn test_validate_batch_item() {
+        let item = RecordTxRequest {
+            policy_id: "policy_a".to_string(),
+            transaction_type: TransactionType::Release,
+            amount: 100,
+            from_address: "government_treasury".to_string(),
+            to_address: "contractor".to_string(),
+            metadata: BTreeMap::new(),
+            dedup_key: None,
+            override_allocation: false,
+            denomination: Denomination::Paise,
+        };
+        assert!(validate_batch_item(&item).is_ok());
+        assert!(validate_batch_item(&RecordTxRequest { amount: 0, ..item.clone() }).is_err());
+        assert!(validate_batch_item(&RecordTxRequest { transaction_type: TransactionType::Adjustment, ..item }).is_err());
+    }
+    
+    #[test]
+    fn test_real_time_metrics_single_pass() {
+        let now = 40 * NANOS_PER_DAY;
+        let recent_release = sample_transaction("t1", "p1", TransactionType::Release, 100, now - NANOS_PER_HOUR / 2);
+        let earlier_release = sample_transaction("t2", "p1", TransactionType::Release, 50, now - 2 * NANOS_PER_HOUR);
+        let old_fee = sample_transaction("t3", "p1", TransactionType::Fee, 7, now - 10 * NANOS_PER_DAY);
+        let mut pending = sample_transaction("t4", "p1", TransactionType::Allocation, 30, now);
+        pending.status = TransactionStatus::Processing;
+        let transactions = [recent_release, earlier_release, old_fee, pending];
+        
+        let (metrics, valid_until) = compute_real_time_metrics(transactions.iter(), now);
+        assert_eq!(metrics.active_transactions, 1);
+        assert_eq!(metrics.pending_amount, 30);
+        assert_eq!(metrics.hourly_volume, 100);
+        assert_eq!(metrics.daily_volume, 150);
+        assert_eq!(metrics.weekly_volume, 150);
+        assert_eq!(metrics.monthly_volume, 157);
+        assert_eq!(metrics.completed_last_hour, 1);
+        assert_eq!(metrics.volume_by_type.get("Release"), Some(&150));
+        assert_eq!(metrics.volume_by_type.get("Fee"), None);
+        // The half-hour-old release leaves the hourly window first
+        assert_eq!(valid_until, now + NANOS_PER_HOUR / 2);
+        
+        assert!(metrics_are_current(now - 1, now, valid_until, now + 1));
+        assert!(!metrics_are_current(now, now, valid_until, now + 1));
+        assert!(!metrics_are_current(now - 1, now, valid_until, valid_until));
+    }
+    
+    #[test]
+    fn test_authorize_caller() {
+        let smart_policy = Principal::from_slice(&[1]);
+        let admin = Principal::from_slice(&[2]);
+        let stranger = Principal::from_slice(&[3]);
+        let trusted: BTreeSet<Principal> = [admin].into_iter().collect();
+        
+        assert!(authorize_caller(&smart_policy, false, Some(smart_policy), Some(&trusted)).is_ok());
+        assert!(authorize_caller(&admin, false, Some(smart_policy), Some(&trusted)).is_ok());
+        assert!(authorize_caller(&stranger, true, None, None).is_ok());
+        
+        let error = authorize_caller(&stranger, false, Some(smart_policy), Some(&trusted)).unwrap_err();
+        assert_eq!(error, format!("Caller {} is not authorized to modify transactions", stranger.to_text()));
+        assert!(authorize_caller(&smart_policy, false, None, Some(&trusted)).is_err());
+    }
+    
+    #[test]
+    fn test_balance_refund_and_fee() {
+        let mut balance = FundBalance {
+            policy_id: "policy_a".to_string(),
+            total_allocated: 0,
+            total_released: 0,
+            total_transferred: 0,
+            total_fees: Some(0),
+            current_balance: 0,
+            last_updated: 0,
+        };
+        
+        assert!(apply_to_balance(&mut balance, &TransactionType::Allocation, 1_000, 1).is_none());
+        assert!(apply_to_balance(&mut balance, &TransactionType::Release, 600, 2).is_none());
+        assert!(apply_to_balance(&mut balance, &TransactionType::Refund, 100, 3).is_none());
+        assert!(apply_to_balance(&mut balance, &TransactionType::Fee, 25, 4).is_none());
+        
+        assert_eq!(balance.total_allocated, 1_000);
+        assert_eq!(balance.total_released, 500);
+        assert_eq!(balance.total_fees, Some(25));
+        assert_eq!(balance.current_balance, 475);
+        assert_eq!(balance.last_updated, 4);
+        
+        // Refunding more than was released floors at zero and leaves a note
+        let note = apply_to_balance(&mut balance, &TransactionType::Refund, 700, 5);
+        assert!(note.is_some());
+        assert_eq!(balance.total_released, 0);
+        assert_eq!(balance.current_balance, 1_175);
+    }
+    
+    #[test]
+    fn test_average_transaction_amount_mixed_types() {
+        let mut analytics = get_fund_analytics();
+        
+        apply_to_analytics(&mut analytics, &TransactionType::Allocation, 1_000);
+        apply_to_analytics(&mut analytics, &TransactionType::Release, 400);
+        apply_to_analytics(&mut analytics, &TransactionType::Transfer, 300);
+        apply_to_analytics(&mut analytics, &TransactionType::Refund, 100);
+        apply_to_analytics(&mut analytics, &TransactionType::Fee, 200);
+        
+        assert_eq!(analytics.total_transactions, 5);
+        assert_eq!(analytics.sum_of_transaction_amounts, Some(2_000));
+        assert_eq!(analytics.average_transaction_amount, 400.0);
+        assert_eq!(analytics.total_funds_released, 300);
+    }
+    
+    #[test]
+    fn test_type_distribution() {
+        let mut analytics = get_fund_analytics();
+        
+        apply_to_analytics(&mut analytics, &TransactionType::Allocation, 1_000);
+        apply_to_analytics(&mut analytics, &TransactionType::Release, 400);
+        apply_to_analytics(&mut analytics, &TransactionType::Transfer, 300);
+        apply_to_analytics(&mut analytics, &TransactionType::Refund, 100);
+        apply_to_analytics(&mut analytics, &TransactionType::Fee, 200);
+        apply_to_analytics(&mut analytics, &TransactionType::Fee, 50);
+        
+        let expected = [("Allocation", 1_000, 1), ("Release", 400, 1), ("Transfer", 300, 1), ("Refund", 100, 1), ("Fee", 250, 2)];
+        for (type_name, amount, count) in expected {
+            assert_eq!(analytics.type_distribution.as_ref().unwrap().get(type_name), Some(&amount));
+            assert_eq!(analytics.type_counts.as_ref().unwrap().get(type_name), Some(&count));
+        }
+        
+        reverse_analytics(&mut analytics, &TransactionType::Fee, 50);
+        assert_eq!(analytics.type_distribution.as_ref().unwrap().get("Fee"), Some(&200));
+        assert_eq!(analytics.type_counts.as_ref().unwrap().get("Fee"), Some(&1));
+    }
+    
+    #[test]
+    fn test_cancel_reverses_balance_and_analytics() {
+        let mut balance = FundBalance {
+            policy_id: "policy_a".to_string(),
+            total_allocated: 0,
+            total_released: 0,
+            total_transferred: 0,
+            total_fees: Some(0),
+            current_balance: 0,
+            last_updated: 0,
+        };
+        let mut analytics = get_fund_analytics();
+        
+        apply_to_balance(&mut balance, &TransactionType::Allocation, 1_000, 1);
+        apply_to_analytics(&mut analytics, &TransactionType::Allocation, 1_000);
+        apply_to_balance(&mut balance, &TransactionType::Release, 300, 2);
+        apply_to_analytics(&mut analytics, &TransactionType::Release, 300);
+        
+        // Cancel the release: balance and counters go back to the allocation alone
+        reverse_balance(&mut balance, &TransactionType::Release, 300, 3);
+        reverse_analytics(&mut analytics, &TransactionType::Release, 300);
+        
+        assert_eq!(balance.total_allocated, 1_000);
+        assert_eq!(balance.total_released, 0);
+        assert_eq!(balance.current_balance, 1_000);
+        assert_eq!(balance.last_updated, 3);
+        assert_eq!(analytics.total_transactions, 1);
+        assert_eq!(analytics.total_funds_released, 0);
+        assert_eq!(analytics.average_transaction_amount, 1_000.0);
+        
+        reverse_balance(&mut balance, &TransactionType::Allocation, 1_000, 4);
+        reverse_analytics(&mut analytics, &TransactionType::Allocation, 1_000);
+        assert_eq!(balance.current_balance, 0);
+        assert_eq!(analytics.total_transactions, 0);
+        assert_eq!(analytics.average_transaction_amount, 0.0);
+    }
+    
+    #[test]
+    fn test_reconciliation_detects_drift() {
+        let mut cancelled = sample_transaction("t4", "policy_a", TransactionType::Release, 50, 4);
+        cancelled.status = TransactionStatus::Cancelled;
+        let transactions = [
+            sample_transaction("t1", "policy_a", TransactionType::Allocation, 1_000, 1),
+            sample_transaction("t2", "policy_a", TransactionType::Release, 300, 2),
+            sample_transaction("t3", "policy_a", TransactionType::Fee, 20, 3),
+            cancelled,
+        ];
+        
+        let computed = recompute_balance("policy_a", None, transactions.iter());
+        assert_eq!(computed.total_allocated, 1_000);
+        assert_eq!(computed.total_released, 300);
+        assert_eq!(computed.current_balance, 680);
+        assert!(reconcile(&computed, &computed).in_sync);
+        
+        let mut stored = computed.clone();
+        stored.total_released = 350;
+        stored.current_balance = 630;
+        let report = reconcile(&stored, &computed);
+        assert!(!report.in_sync);
+        let released = report.fields.iter().find(|f| f.field == "total_released").unwrap();
+        assert_eq!((released.stored, released.computed, released.delta), (350, 300, -50));
+        let current = report.fields.iter().find(|f| f.field == "current_balance").unwrap();
+        assert_eq!(current.delta, 50);
+    }
+    
+    #[test]
+    fn test_failed_transaction_effects_are_reversed() {
+        let allocation = sample_transaction("t1", "policy_a", TransactionType::Allocation, 1_000, 1);
+        let mut release = sample_transaction("t2", "policy_a", TransactionType::Release, 400, 2);
+        release.metadata.insert("category".to_string(), "health".to_string());
+        release.metadata.insert("district".to_string(), "Pune".to_string());
+        
+        // What roll_up_transaction does when each one is recorded
+        let mut balance = empty_balance("policy_a");
+        for transaction in [&allocation, &release] {
+            apply_to_balance(&mut balance, &transaction.transaction_type, transaction.amount, transaction.timestamp);
+            modify_analytics(|analytics| apply_to_analytics(analytics, &transaction.transaction_type, transaction.amount));
+            update_district_funds("Pune", "policy_a", &transaction.transaction_type, transaction.amount, transaction.timestamp);
+            update_category_breakdown("health", &transaction.transaction_type, transaction.amount);
+        }
+        store_balance(balance);
+        
+        release.status = TransactionStatus::Failed;
+        reverse_transaction_effects(&release, 3);
+        
+        let stored = stored_balance("policy_a").unwrap();
+        assert_eq!(stored.total_released, 0);
+        assert_eq!(stored.current_balance, 1_000);
+        assert!(reconcile(&stored, &recompute_balance("policy_a", None, [&allocation, &release].into_iter())).in_sync);
+        
+        let analytics = get_fund_analytics();
+        assert_eq!(analytics.total_funds_released, 0);
+        assert_eq!(analytics.total_transactions, 1);
+        assert_eq!(analytics.category_distribution.get("health"), Some(&1_000));
+        
+        let district = DISTRICT_FUNDS.with(|funds| funds.borrow().get(&"Pune".to_string())).unwrap();
+        assert_eq!((district.total_allocated, district.total_released), (1_000, 0));
+        let health = get_category_breakdown().into_iter().find(|breakdown| breakdown.category == "health").unwrap();
+        assert_eq!((health.total_allocated, health.total_released, health.transaction_count), (1_000, 0, 1));
+    }
+    
+    #[test]
+    fn test_transaction_status_transition_matrix() {
+        use TransactionStatus::*;
+        let all = [Pending, Processing, Completed, Failed, Cancelled];
+        let allowed = [
+            (Pending, Processing),
+            (Pending, Cancelled),
+            (Processing, Completed),
+            (Processing, Failed),
+            (Processing, Cancelled),
+        ];
+        
+        for from in all.iter() {
+            for to in all.iter() {
+                let expected = allowed.iter().any(|(a, b)| a == from && b == to);
+                assert_eq!(is_valid_transition(from, to), expected, "{:?} -> {:?}", from, to);
+            }
+        }
+        
+        let mut transaction = sample_transaction("t1", "policy_a", TransactionType::Allocation, 100, 1);
+        transaction.status = Pending;
+        transaction.status_history = Some(vec![(Pending, 1)]);
+        set_transaction_status(&mut transaction, Processing, 2).unwrap();
+        set_transaction_status(&mut transaction, Completed, 3).unwrap();
+        assert!(set_transaction_status(&mut transaction, Pending, 4).is_err());
+        assert_eq!(transaction.status_history.unwrap_or_default(), vec![(Pending, 1), (Processing, 2), (Completed, 3)]);
+    }
+    
+    #[test]
+    fn test_monthly_trends_buckets() {
+        // 2024-01-31T12:00:00Z and 2024-03-01T00:00:00Z
+        let january = 1_706_702_400 * 1_000_000_000;
+        let march = 1_709_251_200 * 1_000_000_000;
+        assert_eq!(month_key(january), "2024-01");
+        assert_eq!(month_key(march), "2024-03");
+        assert_eq!(month_key(0), "1970-01");
+        
+        let mut failed = sample_transaction("t3", "policy_a", TransactionType::Release, 999, march);
+        failed.status = TransactionStatus::Failed;
+        let transactions = [
+            sample_transaction("t1", "policy_a", TransactionType::Allocation, 100, january),
+            sample_transaction("t2", "policy_a", TransactionType::Release, 40, march),
+            failed,
+        ];
+        
+        let trends = compute_monthly_trends(transactions.iter());
+        assert_eq!(trends.len(), 2);
+        assert_eq!(trends["2024-01"], 100);
+        assert_eq!(trends["2024-03"], 40);
+    }
+    
+    #[test]
+    fn test_monthly_trend_clock_across_months() {
+        let mut trends = BTreeMap::new();
+        // Clock values for 2023-12-31T23:59:59Z and 2024-01-01T00:00:00Z
+        let december = 1_704_067_199 * 1_000_000_000;
+        let january = december + 1_000_000_000;
+        
+        add_to_monthly_trend(&mut trends, january, 70);
+        add_to_monthly_trend(&mut trends, december, 50);
+        add_to_monthly_trend(&mut trends, december, 25);
+        
+        let buckets: Vec<(String, u64)> = trends.into_iter().collect();
+        assert_eq!(buckets, vec![("2023-12".to_string(), 75), ("2024-01".to_string(), 70)]);
+    }
+    
+    #[test]
+    fn test_fiscal_year_bounds_in_ist() {
+        const SEC: u64 = 1_000_000_000;
+        // 2023-04-01T00:00:00+05:30 is 2023-03-31T18:30:00Z
+        let fy2023_start = 1_680_287_400 * SEC;
+        // 2024-04-01T00:00:00+05:30 is 2024-03-31T18:30:00Z; FY 2023-24 spans February 29
+        let fy2024_start = 1_711_909_800 * SEC;
+        assert_eq!(fiscal_year_bounds(2023), Ok((fy2023_start, fy2024_start)));
+        assert_eq!(fy2024_start - fy2023_start, 366 * NANOS_PER_DAY);
+        assert_eq!(fiscal_year_bounds(2024).map(|(start, end)| end - start), Ok(365 * NANOS_PER_DAY));
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2024, 2, 29) + 1, days_from_civil(2024, 3, 1));
+        assert!(fiscal_year_bounds(1969).is_err());
+        assert!(fiscal_year_bounds(MAX_FISCAL_YEAR + 1).is_err());
+        
+        let tagged = |id: &str, transaction_type: TransactionType, amount: u64, timestamp: u64| {
+            let mut transaction = sample_transaction(id, "policy_a", transaction_type, amount, timestamp);
+            transaction.metadata.insert("category".to_string(), "health".to_string());
+            transaction
+        };
+        // March 31 23:59:59 IST closes FY 2023-24; April 1 00:00 IST opens FY 2024-25
+        let last_moment = tagged("march", TransactionType::Release, 40, fy2024_start - SEC);
+        let first_moment = tagged("april", TransactionType::Allocation, 100, fy2024_start);
+        // 2024-02-29T12:00:00Z
+        let mut leap_day = tagged("leap", TransactionType::Fee, 3, 1_709_208_000 * SEC);
+        leap_day.metadata.insert("district".to_string(), "Pune".to_string());
+        let refund = tagged("refund", TransactionType::Refund, 7, fy2023_start);
+        let mut failed = tagged("failed", TransactionType::Release, 999, fy2023_start + SEC);
+        failed.status = TransactionStatus::Failed;
+        let before = tagged("before", TransactionType::Allocation, 500, fy2023_start - 1);
+        let transactions = [last_moment, first_moment, leap_day, refund, failed, before];
+        
+        let report = fiscal_year_report(2023, FiscalYearTotals::default(), transactions.iter()).unwrap();
+        assert_eq!(report.fiscal_year, "2023-24");
+        assert_eq!(report.totals.released, 40);
+        assert_eq!(report.totals.allocated, 0);
+        assert_eq!(report.totals.refunded, 7);
+        assert_eq!(report.totals.fees, 3);
+        assert_eq!(report.totals.transaction_count, 3);
+        
+        let months: Vec<&str> = report.by_month.iter().map(|(month, _)| month.as_str()).collect();
+        assert_eq!(months.first(), Some(&"2023-04"));
+        assert_eq!(months.last(), Some(&"2024-03"));
+        assert_eq!(months.len(), 12);
+        let month = |key: &str| report.by_month.iter().find(|(month, _)| month == key).map(|(_, totals)| totals.clone()).unwrap();
+        // Still March in IST although the UTC clock reads 18:29:59 on March 31
+        assert_eq!(month("2024-03").released, 40);
+        assert_eq!(month("2024-02").fees, 3);
+        assert_eq!(month("2023-04").refunded, 7);
+        
+        assert_eq!(report.by_district.iter().map(|(district, totals)| (district.as_str(), totals.transaction_count)).collect::<Vec<_>>(), vec![("Pune", 1), ("unknown", 2)]);
+        assert_eq!(report.by_category.len(), 1);
+        assert_eq!(report.by_category[0].1.transaction_count, 3);
+        
+        let next = fiscal_year_report(2024, FiscalYearTotals::default(), transactions.iter()).unwrap();
+        assert_eq!(next.fiscal_year, "2024-25");
+        assert_eq!(next.totals.allocated, 100);
+        assert_eq!(next.by_month[0].0, "2024-04");
+        assert_eq!(next.by_month[0].1.allocated, 100);
+        
+        // Archived transactions arrive through the per-year roll-up instead of the archive
+        assert_eq!(fiscal_start_year(fy2024_start - SEC), 2023);
+        assert_eq!(fiscal_start_year(fy2024_start), 2024);
+        let mut archived = FiscalYearTotals::default();
+        for transaction in &transactions[2..4] {
+            add_to_fiscal_year(&mut archived, transaction);
+        }
+        let hot = [&transactions[0], &transactions[1], &transactions[4], &transactions[5]];
+        let split = fiscal_year_report(2023, archived, hot.into_iter()).unwrap();
+        assert_eq!(split.totals.transaction_count, report.totals.transaction_count);
+        assert_eq!(split.totals.fees, report.totals.fees);
+        let monthly_counts = |report: &FiscalYearReport| report.by_month.iter().map(|(_, totals)| totals.transaction_count).collect::<Vec<_>>();
+        assert_eq!(monthly_counts(&split), monthly_counts(&report));
+        assert_eq!(split.by_district.len(), report.by_district.len());
+    }
+    
+    #[test]
+    fn test_policy_transfer_moves_allocation_once() {
+        let transfer = PolicyTransfer {
+            transfer_group_id: "group_1".to_string(),
+            transfer_out_id: "out".to_string(),
+            allocation_in_id: "in".to_string(),
+        };
+        let from_tags = ("health".to_string(), Some("Pune".to_string()));
+        let to_tags = ("roads".to_string(), Some("Nashik".to_string()));
+        let (transfer_out, allocation_in) = policy_transfer_legs(&transfer, "policy_a", "policy_b", 300, "Unspent budget", &from_tags, &to_tags, 5);
+        assert!(matches!(transfer_out.transaction_type, TransactionType::Transfer));
+        assert!(matches!(allocation_in.transaction_type, TransactionType::Allocation));
+        assert_eq!(transfer_out.policy_id, "policy_a");
+        assert_eq!(allocation_in.policy_id, "policy_b");
+        assert_eq!(transfer_out.metadata["transfer_group_id"], allocation_in.metadata["transfer_group_id"]);
+        assert_eq!(allocation_in.metadata["counterpart_policy_id"], "policy_a");
+        assert_eq!(allocation_in.metadata["district"], "Nashik");
+        assert!(!is_reallocation_credit(&transfer_out));
+        assert!(is_reallocation_credit(&allocation_in));
+        
+        let mut source = empty_balance("policy_a");
+        apply_to_balance(&mut source, &TransactionType::Allocation, 1_000, 1);
+        assert_eq!(check_sufficient_balance(Some(&source), &TransactionType::Transfer, 1_001), Err("insufficient balance".to_string()));
+        apply_to_balance(&mut source, &TransactionType::Transfer, 300, 5);
+        let mut destination = empty_balance("policy_b");
+        apply_to_balance(&mut destination, &TransactionType::Allocation, 300, 5);
+        assert_eq!(source.current_balance, 700);
+        assert_eq!(source.total_transferred, 300);
+        assert_eq!(destination.current_balance, 300);
+        
+        // The original allocation plus the move leaves the treasury-wide allocation unchanged
+        let mut analytics = empty_analytics();
+        let mut breakdown = BTreeMap::new();
+        apply_to_analytics(&mut analytics, &TransactionType::Allocation, 1_000);
+        apply_to_category(&mut breakdown, &mut analytics.category_distribution, "health", &TransactionType::Allocation, 1_000);
+        apply_policy_transfer(&mut analytics, &mut breakdown, "health", "roads", 300);
+        assert_eq!(analytics.total_funds_allocated, 1_000);
+        assert_eq!(analytics.total_transactions, 3);
+        assert_eq!(breakdown["health"].total_allocated, 700);
+        assert_eq!(breakdown["health"].transaction_count, 2);
+        assert_eq!(breakdown["roads"].total_allocated, 300);
+        assert_eq!(analytics.category_distribution["health"] + analytics.category_distribution["roads"], 1_000);
+        
+        let mut pune = DistrictFunds {
+            district: "Pune".to_string(),
+            total_allocated: 0,
+            total_released: 0,
+            active_policies: 0,
+            completion_rate: 0.0,
+            last_updated: 0,
+            policy_ids: None,
+        };
+        apply_to_district_funds(&mut pune, "policy_a", &TransactionType::Allocation, 1_000, 1);
+        apply_to_district_funds(&mut pune, "policy_a", &TransactionType::Release, 350, 2);
+        withdraw_district_allocation(&mut pune, 300, 5);
+        assert_eq!(pune.total_allocated, 700);
+        assert!((pune.completion_rate - 0.5).abs() < f64::EPSILON);
+        
+        // Rebuilt trends and address balances see the move once, through the Transfer leg
+        let transactions = [transfer_out, allocation_in];
+        assert_eq!(compute_monthly_trends(transactions.iter()).values().sum::<u64>(), 300);
+        let ledger = build_address_ledger(transactions.iter());
+        assert_eq!(ledger["policy:policy_a"].total_sent, 300);
+        assert_eq!(ledger["policy:policy_b"].total_received, 300);
+        assert_eq!(ledger["policy:policy_b"].transaction_count, 1);
+    }
+    
+    #[test]
+    fn test_category_breakdown() {
+        let mut breakdown = BTreeMap::new();
+        let mut distribution = BTreeMap::new();
+        
+        apply_to_category(&mut breakdown, &mut distribution, "health", &TransactionType::Allocation, 1_000);
+        apply_to_category(&mut breakdown, &mut distribution, "health", &TransactionType::Release, 400);
+        apply_to_category(&mut breakdown, &mut distribution, "health", &TransactionType::Fee, 10);
+        apply_to_category(&mut breakdown, &mut distribution, "education", &TransactionType::Allocation, 500);
+        
+        let health = &breakdown["health"];
+        assert_eq!(health.total_allocated, 1_000);
+        assert_eq!(health.total_released, 400);
+        assert_eq!(health.transaction_count, 3);
+        assert_eq!(distribution["health"], 1_400);
+        assert_eq!(distribution["education"], 500);
+        assert_eq!(breakdown["education"].transaction_count, 1);
+    }
+    
+    #[test]
+    fn test_category_distribution_split() {
+        let mut breakdown = BTreeMap::new();
+        let mut distribution = BTreeMap::new();
+        
+        apply_to_category(&mut breakdown, &mut distribution, "health", &TransactionType::Allocation, 700);
+        apply_to_category(&mut breakdown, &mut distribution, "roads", &TransactionType::Allocation, 300);
+        apply_to_category(&mut breakdown, &mut distribution, UNCATEGORIZED, &TransactionType::Release, 50);
+        apply_to_category(&mut breakdown, &mut distribution, "roads", &TransactionType::Release, 120);
+        
+        let split: Vec<(String, u64)> = distribution.clone().into_iter().collect();
+        assert_eq!(split, vec![
+            ("health".to_string(), 700),
+            ("roads".to_string(), 420),
+            (UNCATEGORIZED.to_string(), 50),
+        ]);
+        
+        // A cancelled release leaves only the allocation in its bucket
+        reverse_category(&mut breakdown, &mut distribution, "roads", &TransactionType::Release, 120);
+        assert_eq!(distribution["roads"], 300);
+        assert_eq!(breakdown["roads"].total_released, 0);
+        assert_eq!(breakdown["roads"].transaction_count, 1);
+    }
+    
+    #[test]
+    fn test_policy_tags_inherit_district() {
+        let mut tagged = BTreeMap::new();
+        tagged.insert("district".to_string(), "Pune".to_string());
+        
+        // First transaction carries the district explicitly
+        let (_, district) = merge_policy_tags(&tagged, None, None, None);
+        assert_eq!(district.as_deref(), Some("Pune"));
+        
+        // Later untagged transactions fall back to the cached district
+        let (category, district) = merge_policy_tags(&BTreeMap::new(), None, district, None);
+        assert_eq!(district.as_deref(), Some("Pune"));
+        assert_eq!(category, None);
+        
+        // With nothing cached the smart_policy record fills the gaps
+        let fetched = PolicySummary { category: "health".to_string(), district: "Nagpur".to_string() };
+        let (category, district) = merge_policy_tags(&BTreeMap::new(), None, None, Some(fetched));
+        assert_eq!(category.as_deref(), Some("health"));
+        assert_eq!(district.as_deref(), Some("Nagpur"));
+        
+        let mut district_funds = BTreeMap::new();
+        for (policy_id, amount) in [("policy_a", 500u64), ("policy_b", 250)] {
+            let funds = district_funds.entry("Nagpur".to_string()).or_insert(DistrictFunds {
+                district: "Nagpur".to_string(),
+                total_allocated: 0,
+                total_released: 0,
+                active_policies: 0,
+                completion_rate: 0.0,
+                last_updated: 0,
+                policy_ids: None,
+            });
+            apply_to_district_funds(funds, policy_id, &TransactionType::Allocation, amount, 1);
+        }
+        assert_eq!(district_funds["Nagpur"].total_allocated, 750);
+        assert_eq!(district_funds["Nagpur"].active_policies, 2);
+    }
+    
+    #[test]
+    fn test_district_funds_rollup() {
+        let mut funds = DistrictFunds {
+            district: "Pune".to_string(),
+            total_allocated: 0,
+            total_released: 0,
+            active_policies: 0,
+            completion_rate: 0.0,
+            last_updated: 0,
+            policy_ids: None,
+        };
+        
+        apply_to_district_funds(&mut funds, "policy_a", &TransactionType::Allocation, 1_000, 1);
+        apply_to_district_funds(&mut funds, "policy_b", &TransactionType::Allocation, 1_000, 2);
+        apply_to_district_funds(&mut funds, "policy_a", &TransactionType::Release, 500, 3);
+        apply_to_district_funds(&mut funds, "policy_c", &TransactionType::Fee, 10, 4);
+        
+        assert_eq!(funds.total_allocated, 2_000);
+        assert_eq!(funds.total_released, 500);
+        assert_eq!(funds.active_policies, 2);
+        assert_eq!(funds.completion_rate, 0.25);
+        assert_eq!(funds.last_updated, 3);
+    }
+    
+    #[test]
+    fn test_cancelled_transaction_leaves_district_totals() {
+        let mut funds = DistrictFunds {
+            district: "Pune".to_string(),
+            total_allocated: 0,
+            total_released: 0,
+            active_policies: 0,
+            completion_rate: 0.0,
+            last_updated: 0,
+            policy_ids: None,
+        };
+        apply_to_district_funds(&mut funds, "policy_a", &TransactionType::Allocation, 1_000, 1);
+        apply_to_district_funds(&mut funds, "policy_a", &TransactionType::Release, 400, 2);
+        apply_to_district_funds(&mut funds, "policy_a", &TransactionType::Release, 100, 3);
+        
+        reverse_district_funds(&mut funds, &TransactionType::Release, 400, 4);
+        assert_eq!(funds.total_allocated, 1_000);
+        assert_eq!(funds.total_released, 100);
+        assert_eq!(funds.completion_rate, 0.1);
+        assert_eq!(funds.active_policies, 1);
+        assert_eq!(funds.last_updated, 4);
+        
+        reverse_district_funds(&mut funds, &TransactionType::Allocation, 1_000, 5);
+        assert_eq!(funds.total_allocated, 0);
+        assert_eq!(funds.completion_rate, 0.0);
+    }
+    
+    #[test]
+    fn test_metrics_interval_setting() {
+        assert_eq!(metrics_timer_interval(0), None);
+        assert_eq!(metrics_timer_interval(60), Some(Duration::from_secs(60)));
+        
+        // The interval survives an upgrade through the settings cell
+        let state = UpgradeState { metrics_refresh_secs: Some(60), ..Default::default() };
+        let restored = UpgradeState::from_bytes(state.to_bytes());
+        assert_eq!(restored.metrics_refresh_secs, Some(60));
+        
+        // Canisters upgraded from before the setting existed keep the old fixed interval
+        let restored = UpgradeState::from_bytes(UpgradeState::default().to_bytes());
+        assert_eq!(restored.metrics_refresh_secs.unwrap_or(DEFAULT_METRICS_REFRESH_SECS), 300);
+    }
+    
+    #[test]
+    fn test_manual_metrics_refresh() {
+        const HOUR: u64 = 3_600_000_000_000;
+        let mut pending = sample_transaction("tx_1", "policy_1", TransactionType::Release, 300, 10 * HOUR);
+        pending.status = TransactionStatus::Processing;
+        TRANSACTIONS.with(|transactions| transactions.borrow_mut().insert(pending.id.clone(), pending));
+        
+        refresh_real_time_metrics(11 * HOUR);
+        let metrics = get_real_time_metrics();
+        assert_eq!(metrics.current_time, 11 * HOUR);
+        assert_eq!(metrics.active_transactions, 1);
+        assert_eq!(metrics.pending_amount, 300);
+        assert_eq!(metrics.daily_volume, 0);
+        
+        // Newly loaded data shows up on the next manual refresh, without waiting for the timer
+        let completed = sample_transaction("tx_2", "policy_1", TransactionType::Allocation, 1_000, 11 * HOUR);
+        TRANSACTIONS.with(|transactions| transactions.borrow_mut().insert(completed.id.clone(), completed));
+        refresh_real_time_metrics(11 * HOUR + 1);
+        let metrics = get_real_time_metrics();
+        assert_eq!(metrics.active_transactions, 1);
+        assert_eq!(metrics.daily_volume, 1_000);
+    }
+    
+    #[test]
+    fn test_roll_up_saturates_near_max_amounts() {
+        for id in ["tx_1", "tx_2"] {
+            let mut transaction = sample_transaction(id, "policy_1", TransactionType::Allocation, u64::MAX - 1, 200);
+            transaction.metadata.insert("district".to_string(), "Pune".to_string());
+            transaction.metadata.insert("category".to_string(), "health".to_string());
+            TRANSACTIONS.with(|transactions| transactions.borrow_mut().insert(transaction.id.clone(), transaction));
+            roll_up_transaction(id, 300);
+        }
+        
+        let balance = stored_balance("policy_1").unwrap();
+        assert_eq!(balance.total_allocated, u64::MAX);
+        assert_eq!(balance.current_balance, u64::MAX);
+        let district = DISTRICT_FUNDS.with(|funds| funds.borrow().get(&"Pune".to_string())).unwrap();
+        assert_eq!(district.total_allocated, u64::MAX);
+        let category = CATEGORY_BREAKDOWN.with(|breakdown| breakdown.borrow().get(&"health".to_string())).unwrap();
+        assert_eq!(category.total_allocated, u64::MAX);
+        assert_eq!(category.transaction_count, 2);
+        
+        let analytics = fund_analytics();
+        assert_eq!(analytics.category_distribution.get("health"), Some(&u64::MAX));
+        // The fiscal totals are u128 and keep the exact sum
+        assert_eq!(analytics.total_funds_allocated, 2 * (u64::MAX as u128 - 1));
+        
+        let trends = TRANSACTIONS.with(|transactions| compute_monthly_trends(transactions.borrow().scan()));
+        assert_eq!(trends.values().copied().collect::<Vec<_>>(), [u64::MAX]);
+    }
+    
+    #[test]
+    fn test_health_check_counts() {
+        unsafe {
+            INIT_TIME_NS = Some(100);
+        }
+        let before = health_status_at(400);
+        assert!(before.initialized);
+        assert_eq!(before.uptime_ns, 300);
+        assert_eq!(before.record_counts[0], ("transactions".to_string(), 0));
+        
+        let transaction = sample_transaction("tx_1", "policy_1", TransactionType::Allocation, 500, 200);
+        TRANSACTIONS.with(|transactions| transactions.borrow_mut().insert(transaction.id.clone(), transaction));
+        FUND_BALANCES.with(|balances| balances.borrow_mut().insert("policy_1".to_string(), empty_balance("policy_1")));
+        let after = health_status_at(400);
+        assert_eq!(after.record_counts[0], ("transactions".to_string(), 1));
+        assert_eq!(after.record_counts[2], ("fund_balances".to_string(), 1));
+    }
+}
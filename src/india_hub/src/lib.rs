@@ -1,6 +1,9 @@
-use candid::{CandidType, Deserialize};
-use ic_cdk::{api::call::call, export::candid, init, post_upgrade, pre_upgrade, query, update};
-use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
+use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
+use common::{
+    AadhaarVerification, AuditFinding, BiometricVerification, CanisterStats, ComplianceAudit, DemographicData,
+    DigitalLockerEntry, DocumentMetadata, EKYCStatus, GSTVerification, HealthStatus, IndiaHubRegistration,
+    PANValidation, RegionalCompliance, TaxCompliance, VerificationLog,
+};
 use std::collections::BTreeMap;
 use uuid::Uuid;
 
@@ -10,164 +13,12 @@ const GST_API_ENDPOINT: &str = "https://api.gst.gov.in";
 const DIGITAL_LOCKER_ENDPOINT: &str = "https://api.digitallocker.gov.in";
 const WCHL25_HACKATHON_ID: &str = "WCHL25_CIVICLEDGER_INDIA_HUB";
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct IndiaHubRegistration {
-    pub policy_id: String,
-    pub registration_id: String,
-    pub hub_verification_status: bool,
-    pub compliance_score: f64,
-    pub regional_impact_score: f64,
-    pub timestamp: u64,
-    // Enhanced India Hub Features
-    pub aadhaar_integration: Option<AadhaarVerification>,
-    pub gst_verification: Option<GSTVerification>,
-    pub pan_card_validation: Option<PANValidation>,
-    pub regional_compliance: Vec<RegionalCompliance>,
-    pub digital_locker_integration: Option<DigitalLockerEntry>,
-    pub biometric_verification: Option<BiometricVerification>,
-    pub e_kyc_status: Option<EKYCStatus>,
-    pub compliance_audit: ComplianceAudit,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct AadhaarVerification {
-    pub aadhaar_number: String,
-    pub verification_status: bool,
-    pub biometric_match: bool,
-    pub otp_verified: bool,
-    pub verification_timestamp: u64,
-    pub verification_score: f64,
-    pub demographic_data: DemographicData,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct GSTVerification {
-    pub gst_number: String,
-    pub business_name: String,
-    pub registration_status: String,
-    pub compliance_status: String,
-    pub last_filing_date: u64,
-    pub verification_score: f64,
-    pub tax_compliance: TaxCompliance,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct PANValidation {
-    pub pan_number: String,
-    pub holder_name: String,
-    pub validation_status: bool,
-    pub verification_timestamp: u64,
-    pub verification_score: f64,
-    pub kyc_status: String,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct RegionalCompliance {
-    pub state: String,
-    pub district: String,
-    pub compliance_rules: Vec<String>,
-    pub compliance_status: bool,
-    pub compliance_score: f64,
-    pub regional_authority: String,
-    pub approval_date: u64,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct DigitalLockerEntry {
-    pub locker_id: String,
-    pub document_type: String,
-    pub document_hash: String,
-    pub upload_timestamp: u64,
-    pub verification_status: bool,
-    pub access_permissions: Vec<String>,
-    pub document_metadata: DocumentMetadata,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct BiometricVerification {
-    pub biometric_type: String,
-    pub verification_status: bool,
-    pub match_score: f64,
-    pub verification_timestamp: u64,
-    pub device_id: String,
-    pub location: String,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct EKYCStatus {
-    pub kyc_status: String,
-    pub verification_level: String,
-    pub last_updated: u64,
-    pub verification_score: f64,
-    pub compliance_requirements: Vec<String>,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct ComplianceAudit {
-    pub audit_id: String,
-    pub audit_date: u64,
-    pub compliance_score: f64,
-    pub audit_findings: Vec<AuditFinding>,
-    pub recommendations: Vec<String>,
-    pub next_audit_date: u64,
-    pub auditor: String,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct DemographicData {
-    pub name: String,
-    pub date_of_birth: String,
-    pub gender: String,
-    pub address: String,
-    pub photo_hash: String,
-    pub verification_status: bool,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct TaxCompliance {
-    pub filing_frequency: String,
-    pub last_filing_period: String,
-    pub tax_liability: f64,
-    pub compliance_score: f64,
-    pub pending_returns: i32,
-    pub penalty_amount: f64,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct DocumentMetadata {
-    pub file_name: String,
-    pub file_size: u64,
-    pub mime_type: String,
-    pub upload_source: String,
-    pub verification_hash: String,
-    pub expiry_date: Option<u64>,
-}
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct AuditFinding {
-    pub finding_id: String,
-    pub severity: String,
-    pub description: String,
-    pub recommendation: String,
-    pub status: String,
-    pub due_date: u64,
-}
-
 // Stable storage
 static mut REGISTRATIONS: Option<BTreeMap<String, IndiaHubRegistration>> = None;
 static mut COMPLIANCE_RULES: Option<BTreeMap<String, Vec<String>>> = None;
 static mut VERIFICATION_LOGS: Option<BTreeMap<String, Vec<VerificationLog>>> = None;
-
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct VerificationLog {
-    pub log_id: String,
-    pub policy_id: String,
-    pub verification_type: String,
-    pub status: bool,
-    pub timestamp: u64,
-    pub details: String,
-    pub blockchain_hash: Option<String>,
-}
+// Set at install; canisters installed before it existed start counting at their next upgrade
+static mut INIT_TIME_NS: Option<u64> = None;
 
 #[init]
 fn init() {
@@ -175,6 +26,7 @@ fn init() {
         REGISTRATIONS = Some(BTreeMap::new());
         COMPLIANCE_RULES = Some(BTreeMap::new());
         VERIFICATION_LOGS = Some(BTreeMap::new());
+        INIT_TIME_NS = Some(ic_cdk::api::time());
         
         // Initialize compliance rules for different states
         if let Some(ref mut rules) = COMPLIANCE_RULES {
@@ -202,22 +54,26 @@ fn pre_upgrade() {
     let registrations = unsafe { REGISTRATIONS.take().unwrap() };
     let compliance_rules = unsafe { COMPLIANCE_RULES.take().unwrap() };
     let verification_logs = unsafe { VERIFICATION_LOGS.take().unwrap() };
+    let init_time_ns = unsafe { INIT_TIME_NS };
     
-    ic_cdk::storage::stable_save((registrations, compliance_rules, verification_logs)).unwrap();
+    ic_cdk::storage::stable_save((registrations, compliance_rules, verification_logs, init_time_ns)).unwrap();
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (registrations, compliance_rules, verification_logs): (
+    // init_time_ns is absent when upgrading from before health_check existed
+    let (registrations, compliance_rules, verification_logs, init_time_ns): (
         BTreeMap<String, IndiaHubRegistration>,
         BTreeMap<String, Vec<String>>,
         BTreeMap<String, Vec<VerificationLog>>,
+        Option<u64>,
     ) = ic_cdk::storage::stable_restore().unwrap();
     
     unsafe {
         REGISTRATIONS = Some(registrations);
         COMPLIANCE_RULES = Some(compliance_rules);
         VERIFICATION_LOGS = Some(verification_logs);
+        INIT_TIME_NS = Some(init_time_ns.unwrap_or_else(ic_cdk::api::time));
     }
 }
 
@@ -264,11 +120,11 @@ async fn register_with_india_hub(
         aadhaar_integration: aadhaar_verification,
         gst_verification,
         pan_card_validation: pan_validation,
-        regional_compliance,
+        regional_compliance: Some(regional_compliance),
         digital_locker_integration: digital_locker,
         biometric_verification,
         e_kyc_status,
-        compliance_audit,
+        compliance_audit: Some(compliance_audit),
     };
     
     unsafe {
@@ -280,7 +136,8 @@ async fn register_with_india_hub(
         if let Some(ref mut logs) = VERIFICATION_LOGS {
             let log_entry = VerificationLog {
                 log_id: format!("LOG_{}", Uuid::new_v4().to_string()),
-                policy_id: policy_id.clone(),
+                transaction_id: None,
+                policy_id: Some(policy_id.clone()),
                 verification_type: "India Hub Registration".to_string(),
                 status: true,
                 timestamp: now,
@@ -291,7 +148,7 @@ async fn register_with_india_hub(
             if let Some(logs_for_policy) = logs.get_mut(&policy_id) {
                 logs_for_policy.push(log_entry);
             } else {
-                logs.insert(policy_id, vec![log_entry]);
+                logs.insert(policy_id.clone(), vec![log_entry]);
             }
         }
     }
@@ -346,8 +203,10 @@ async fn update_compliance_score(
                 registration.timestamp = ic_cdk::api::time();
                 
                 // Update compliance audit
-                registration.compliance_audit.compliance_score = new_score;
-                registration.compliance_audit.audit_date = ic_cdk::api::time();
+                if let Some(ref mut audit) = registration.compliance_audit {
+                    audit.compliance_score = new_score;
+                    audit.audit_date = ic_cdk::api::time();
+                }
                 
                 return Ok(());
             }
@@ -534,6 +393,31 @@ fn generate_blockchain_hash(data: &str) -> String {
     format!("0x{}{}", data, ic_cdk::api::time()).chars().take(64).collect()
 }
 
+#[query]
+fn get_canister_stats() -> CanisterStats {
+    common::canister_stats()
+}
+
+#[query]
+fn health_check() -> HealthStatus {
+    health_status_at(ic_cdk::api::time())
+}
+
+fn health_status_at(now: u64) -> HealthStatus {
+    unsafe {
+        common::health_status(
+            REGISTRATIONS.is_some() && COMPLIANCE_RULES.is_some() && VERIFICATION_LOGS.is_some(),
+            &[
+                ("registrations", REGISTRATIONS.as_ref().map_or(0, BTreeMap::len)),
+                ("compliance_rules", COMPLIANCE_RULES.as_ref().map_or(0, BTreeMap::len)),
+                ("verification_logs", VERIFICATION_LOGS.as_ref().map_or(0, |logs| logs.values().map(Vec::len).sum())),
+            ],
+            INIT_TIME_NS,
+            now,
+        )
+    }
+}
+
 // Candid interface
 candid::export_service!();
 
@@ -565,7 +449,7 @@ mod tests {
         ];
         
         let score = calculate_compliance_score(&compliance);
-        assert_eq!(score, 0.85);
+        assert!((score - 0.85).abs() < 1e-9);
     }
     
     #[test]
@@ -573,4 +457,25 @@ mod tests {
         let score = calculate_regional_impact_score("Mumbai", 1_000_000_000);
         assert!(score > 0.8);
     }
+    
+    #[test]
+    fn test_health_check_counts() {
+        unsafe {
+            REGISTRATIONS = Some(BTreeMap::new());
+            COMPLIANCE_RULES = Some(BTreeMap::new());
+            VERIFICATION_LOGS = Some(BTreeMap::new());
+            INIT_TIME_NS = Some(100);
+        }
+        let before = health_status_at(400);
+        assert!(before.initialized);
+        assert_eq!(before.uptime_ns, 300);
+        assert_eq!(before.record_counts[1], ("compliance_rules".to_string(), 0));
+        
+        unsafe {
+            if let Some(ref mut rules) = COMPLIANCE_RULES {
+                rules.insert("Kerala".to_string(), vec!["Kerala Transparency Act".to_string()]);
+            }
+        }
+        assert_eq!(health_status_at(400).record_counts[1], ("compliance_rules".to_string(), 1));
+    }
 }
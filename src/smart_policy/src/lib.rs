@@ -1,7 +1,8 @@
 use candid::{CandidType, Deserialize, Principal};
-use ic_cdk::{api::call::call, export::candid, init, post_upgrade, pre_upgrade, query, update};
+use ic_cdk::{api::call::call, init, post_upgrade, pre_upgrade, query, update};
 use ic_cdk_timers::set_timer_interval;
-use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
+use serde::Serialize as SerdeSerialize;
+use common::{CanisterStats, HealthStatus, IndiaHubRegistration};
 use std::collections::BTreeMap;
 use std::time::Duration;
 use uuid::Uuid;
@@ -10,7 +11,22 @@ use uuid::Uuid;
 const ICP_INDIA_HUB_CANISTER: &str = "qoctq-giaaa-aaaam-qaeea-cai"; // Example canister ID
 const WCHL25_HACKATHON_ID: &str = "WCHL25_CIVICLEDGER";
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+// Default balance below which the periodic check records a low-cycles warning
+const DEFAULT_LOW_CYCLES_THRESHOLD: u128 = 1_000_000_000_000; // 1T cycles
+const DEFAULT_IDEMPOTENCY_TTL_NS: u64 = 24 * 60 * 60 * 1_000_000_000; // 24 hours
+const MAX_IMPORT_BATCH: usize = 100;
+const MAX_TITLE_LENGTH: usize = 200;
+const MAX_TRANSPARENCY_HISTORY: usize = 100;
+// Oldest system events are dropped beyond this
+const MAX_SYSTEM_EVENTS: usize = 1_000;
+// Audit entries at which the activity part of the transparency score is full
+const TRANSPARENCY_FULL_AUDIT: usize = 10;
+const DEFAULT_MIN_TRANSPARENCY: f64 = 0.8;
+// Estimated execution fee, taken from the 13-node application subnet rate of 4 cycles per
+// 10 instructions; actual charges vary with subnet size and pricing changes
+const CYCLES_PER_10_INSTRUCTIONS: u64 = 4;
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct Policy {
     pub id: String,
     pub title: String,
@@ -35,9 +51,12 @@ pub struct Policy {
     pub ai_analysis_score: Option<f64>,
     pub transparency_score: f64,
     pub citizen_approval_rate: f64,
+    // (timestamp, score) for each computed transparency_score, oldest first and capped at
+    // MAX_TRANSPARENCY_HISTORY; None on policies created before the history existed
+    pub transparency_history: Option<Vec<(u64, f64)>>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, PartialEq, SerdeSerialize)]
 pub enum PolicyStatus {
     Draft,
     Active,
@@ -52,7 +71,7 @@ pub enum PolicyStatus {
     AIOptimized,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct FundFlow {
     pub id: String,
     pub policy_id: String,
@@ -66,11 +85,13 @@ pub struct FundFlow {
     pub icp_block_hash: Option<String>,
     pub india_hub_verification: Option<String>,
     pub smart_contract_execution: Option<String>,
+    // Estimated cycles for the instructions release_funds ran, and nanoseconds from the
+    // release call to blockchain confirmation; None until the release is confirmed
     pub gas_used: Option<u64>,
     pub execution_time: Option<u64>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub enum FundFlowStatus {
     Pending,
     Processing,
@@ -83,7 +104,7 @@ pub enum FundFlowStatus {
     CitizenApproved,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct PolicyExecution {
     pub policy_id: String,
     pub execution_date: u64,
@@ -99,7 +120,7 @@ pub struct PolicyExecution {
     pub transparency_metrics: TransparencyMetrics,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct AuditEntry {
     pub timestamp: u64,
     pub action: String,
@@ -109,7 +130,7 @@ pub struct AuditEntry {
     pub icp_transaction_id: Option<String>,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct TransparencyMetrics {
     pub data_availability: f64,
     pub audit_trail_completeness: f64,
@@ -118,14 +139,55 @@ pub struct TransparencyMetrics {
     pub overall_score: f64,
 }
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
-pub struct IndiaHubRegistration {
-    pub policy_id: String,
-    pub registration_id: String,
-    pub hub_verification_status: bool,
-    pub compliance_score: f64,
-    pub regional_impact_score: f64,
-    pub timestamp: u64,
+// Bar a policy must clear before check_policy_execution runs it automatically
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct ExecutionPolicyConfig {
+    // Transparency score the policy must exceed, 0.0-1.0
+    pub min_transparency: f64,
+}
+
+// Mirrors complaint_handler's variants so its complaints decode here
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ComplaintPriority {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum ComplaintStatus {
+    Submitted,
+    UnderReview,
+    Investigation,
+    Resolved,
+    Dismissed,
+    Escalated,
+}
+
+// The fields of complaint_handler's Complaint read here; the rest are skipped when decoding
+#[derive(CandidType, Deserialize, Clone)]
+struct LinkedComplaint {
+    id: String,
+    title: String,
+    category: String,
+    priority: ComplaintPriority,
+    status: ComplaintStatus,
+    district: String,
+    created_at: u64,
+    resolution_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ComplaintSummary {
+    pub id: String,
+    pub title: String,
+    pub category: String,
+    pub priority: ComplaintPriority,
+    pub status: ComplaintStatus,
+    pub district: String,
+    pub created_at: u64,
+    pub resolved: bool,
 }
 
 // Stable storage for policies
@@ -134,8 +196,57 @@ static mut FUND_FLOWS: Option<BTreeMap<String, FundFlow>> = None;
 static mut EXECUTIONS: Option<BTreeMap<String, PolicyExecution>> = None;
 static mut INDIA_HUB_REGISTRATIONS: Option<BTreeMap<String, IndiaHubRegistration>> = None;
 static mut WCHL25_METRICS: Option<WCHL25Metrics> = None;
+static mut SYSTEM_EVENTS: Option<Vec<AuditEntry>> = None;
+static mut LOW_CYCLES_THRESHOLD: u128 = DEFAULT_LOW_CYCLES_THRESHOLD;
+// Timers registered since the last install or upgrade; timers do not survive an upgrade
+static mut ACTIVE_TIMERS: u32 = 0;
+// Approximate encoded size of each stored collection, adjusted on every write
+static mut STORAGE_BYTES: BTreeMap<&'static str, u64> = BTreeMap::new();
+static mut LAST_BACKGROUND_RUN: Option<u64> = None;
+// Set at install; canisters installed before it existed start counting at their next upgrade
+static mut INIT_TIME_NS: Option<u64> = None;
+// Client-supplied register_policy keys, so retried calls return the policy they already created
+static mut IDEMPOTENCY: Option<BTreeMap<String, IdempotencyEntry>> = None;
+static mut IDEMPOTENCY_TTL_NS: u64 = DEFAULT_IDEMPOTENCY_TTL_NS;
+static mut COMPLAINT_HANDLER_CANISTER_ID: Option<Principal> = None;
+static mut EXECUTION_CONFIG: ExecutionPolicyConfig = ExecutionPolicyConfig { min_transparency: DEFAULT_MIN_TRANSPARENCY };
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct IdempotencyEntry {
+    pub policy_id: String,
+    pub created_at: u64,
+}
 
-#[derive(CandidType, Deserialize, Clone, SerdeSerialize, SerdeDeserialize)]
+// The fields a caller supplies when creating a policy
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PolicyImport {
+    pub title: String,
+    pub description: String,
+    pub category: String,
+    pub fund_allocation: u64,
+    pub district: String,
+    pub eligibility_criteria: Vec<String>,
+    pub execution_conditions: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct StorageStats {
+    pub name: String,
+    pub entries: u64,
+    pub approx_bytes: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
+pub struct HealthReport {
+    pub cycles_balance: u128,
+    pub low_cycles_threshold: u128,
+    pub low_cycles: bool,
+    pub storage: Vec<StorageStats>,
+    pub active_timers: u32,
+    pub last_background_run: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, SerdeSerialize)]
 pub struct WCHL25Metrics {
     pub total_policies_created: u32,
     pub total_funds_managed: u64,
@@ -155,6 +266,9 @@ fn init() {
         FUND_FLOWS = Some(BTreeMap::new());
         EXECUTIONS = Some(BTreeMap::new());
         INDIA_HUB_REGISTRATIONS = Some(BTreeMap::new());
+        SYSTEM_EVENTS = Some(Vec::new());
+        IDEMPOTENCY = Some(BTreeMap::new());
+        INIT_TIME_NS = Some(ic_cdk::api::time());
         WCHL25_METRICS = Some(WCHL25Metrics {
             total_policies_created: 0,
             total_funds_managed: 0,
@@ -168,20 +282,28 @@ fn init() {
         });
     }
     
-    // Set up periodic policy checks with enhanced WCHL25 features
-    set_timer_interval(Duration::from_secs(1800), || {
-        ic_cdk::spawn(check_policy_execution());
-    });
-    
-    // Set up India Hub integration checks
-    set_timer_interval(Duration::from_secs(3600), || {
-        ic_cdk::spawn(sync_with_india_hub());
-    });
+    start_timers();
+}
+
+fn start_timers() {
+    let timers = [
+        // Set up periodic policy checks with enhanced WCHL25 features
+        set_timer_interval(Duration::from_secs(1800), || {
+            ic_cdk::spawn(check_policy_execution());
+        }),
+        // Set up India Hub integration checks
+        set_timer_interval(Duration::from_secs(3600), || {
+            ic_cdk::spawn(sync_with_india_hub());
+        }),
+        // Set up AI optimization checks
+        set_timer_interval(Duration::from_secs(7200), || {
+            ic_cdk::spawn(apply_ai_optimizations());
+        }),
+    ];
     
-    // Set up AI optimization checks
-    set_timer_interval(Duration::from_secs(7200), || {
-        ic_cdk::spawn(apply_ai_optimizations());
-    });
+    unsafe {
+        ACTIVE_TIMERS = timers.len() as u32;
+    }
 }
 
 #[pre_upgrade]
@@ -191,30 +313,83 @@ fn pre_upgrade() {
     let executions = unsafe { EXECUTIONS.take().unwrap() };
     let india_hub_registrations = unsafe { INDIA_HUB_REGISTRATIONS.take().unwrap() };
     let wchl25_metrics = unsafe { WCHL25_METRICS.take().unwrap() };
+    let system_events = unsafe { SYSTEM_EVENTS.take().unwrap_or_default() };
+    let low_cycles_threshold = unsafe { LOW_CYCLES_THRESHOLD };
+    let init_time_ns = unsafe { INIT_TIME_NS };
+    let idempotency = unsafe { IDEMPOTENCY.take() };
+    let idempotency_ttl_ns = unsafe { IDEMPOTENCY_TTL_NS };
+    let complaint_handler_canister_id = unsafe { COMPLAINT_HANDLER_CANISTER_ID };
+    let execution_config = unsafe { EXECUTION_CONFIG.clone() };
     
-    ic_cdk::storage::stable_save((policies, fund_flows, executions, india_hub_registrations, wchl25_metrics)).unwrap();
+    ic_cdk::storage::stable_save((
+        policies,
+        fund_flows,
+        executions,
+        india_hub_registrations,
+        wchl25_metrics,
+        Some(system_events),
+        Some(low_cycles_threshold),
+        init_time_ns,
+        idempotency,
+        Some(idempotency_ttl_ns),
+        complaint_handler_canister_id,
+        Some(execution_config),
+    )).unwrap();
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (policies, fund_flows, executions, india_hub_registrations, wchl25_metrics): (
+    // Trailing optional values were added after the first release and are
+    // absent when upgrading from older stable memory layouts
+    let (policies, fund_flows, executions, india_hub_registrations, wchl25_metrics, system_events, low_cycles_threshold, init_time_ns, idempotency, idempotency_ttl_ns, complaint_handler_canister_id, execution_config): (
         BTreeMap<String, Policy>, 
         BTreeMap<String, FundFlow>, 
         BTreeMap<String, PolicyExecution>,
         BTreeMap<String, IndiaHubRegistration>,
-        WCHL25Metrics
+        WCHL25Metrics,
+        Option<Vec<AuditEntry>>,
+        Option<u128>,
+        Option<u64>,
+        Option<BTreeMap<String, IdempotencyEntry>>,
+        Option<u64>,
+        Option<Principal>,
+        Option<ExecutionPolicyConfig>,
     ) = ic_cdk::storage::stable_restore().unwrap();
     
+    let mut system_events = system_events.unwrap_or_default();
+    let excess = system_events.len().saturating_sub(MAX_SYSTEM_EVENTS);
+    system_events.drain(..excess);
+    
+    // Sizes are not saved; the state was just decoded in full anyway
+    let storage_bytes = BTreeMap::from([
+        ("policies", policies.values().map(encoded_len).sum()),
+        ("fund_flows", fund_flows.values().map(encoded_len).sum()),
+        ("executions", executions.values().map(encoded_len).sum()),
+        ("india_hub_registrations", india_hub_registrations.values().map(encoded_len).sum()),
+        ("system_events", system_events.iter().map(encoded_len).sum()),
+    ]);
+    
     unsafe {
         POLICIES = Some(policies);
         FUND_FLOWS = Some(fund_flows);
         EXECUTIONS = Some(executions);
         INDIA_HUB_REGISTRATIONS = Some(india_hub_registrations);
         WCHL25_METRICS = Some(wchl25_metrics);
+        SYSTEM_EVENTS = Some(system_events);
+        LOW_CYCLES_THRESHOLD = low_cycles_threshold.unwrap_or(DEFAULT_LOW_CYCLES_THRESHOLD);
+        INIT_TIME_NS = Some(init_time_ns.unwrap_or_else(ic_cdk::api::time));
+        IDEMPOTENCY = Some(idempotency.unwrap_or_default());
+        IDEMPOTENCY_TTL_NS = idempotency_ttl_ns.unwrap_or(DEFAULT_IDEMPOTENCY_TTL_NS);
+        COMPLAINT_HANDLER_CANISTER_ID = complaint_handler_canister_id;
+        EXECUTION_CONFIG = execution_config.unwrap_or(ExecutionPolicyConfig { min_transparency: DEFAULT_MIN_TRANSPARENCY });
+        STORAGE_BYTES = storage_bytes;
     }
+    
+    start_timers();
 }
 
 #[update]
+#[allow(clippy::too_many_arguments)]
 async fn register_policy(
     title: String,
     description: String,
@@ -223,17 +398,102 @@ async fn register_policy(
     district: String,
     eligibility_criteria: Vec<String>,
     execution_conditions: Vec<String>,
+    idempotency_key: Option<String>,
 ) -> Result<String, String> {
-    let policy_id = Uuid::new_v4().to_string();
     let now = ic_cdk::api::time();
+    if let Some(ref key) = idempotency_key {
+        let existing = unsafe {
+            IDEMPOTENCY.as_ref().and_then(|keys| idempotent_policy_id(keys, key, now, IDEMPOTENCY_TTL_NS))
+        };
+        if let Some(policy_id) = existing {
+            return Ok(policy_id);
+        }
+    }
+    
+    let policy_id = create_policy(PolicyImport {
+        title,
+        description,
+        category,
+        fund_allocation,
+        district,
+        eligibility_criteria,
+        execution_conditions,
+    }, now).await?;
+    
+    unsafe {
+        if let (Some(key), Some(ref mut keys)) = (idempotency_key, &mut IDEMPOTENCY) {
+            remember_idempotency_key(keys, key, policy_id.clone(), now, IDEMPOTENCY_TTL_NS);
+        }
+    }
+    
+    Ok(policy_id)
+}
+
+// Loads a batch of policies, e.g. for demos or migrations; each policy is validated and
+// created as by register_policy and gets its own result. Policies beyond MAX_IMPORT_BATCH
+// are rejected individually.
+#[update]
+async fn import_policies(policies: Vec<PolicyImport>) -> Vec<Result<String, String>> {
+    if let Err(e) = common::ensure_controller("import policies") {
+        return policies.iter().map(|_| Err(e.clone())).collect();
+    }
+    
+    let now = ic_cdk::api::time();
+    let mut results = Vec::with_capacity(policies.len());
+    for (position, policy) in policies.into_iter().enumerate() {
+        let result = match check_import_position(position) {
+            Ok(()) => create_policy(policy, now).await,
+            Err(e) => Err(e),
+        };
+        results.push(result);
+    }
+    results
+}
+
+fn check_import_position(position: usize) -> Result<(), String> {
+    if position >= MAX_IMPORT_BATCH {
+        return Err(format!("At most {} policies can be imported at once", MAX_IMPORT_BATCH));
+    }
+    Ok(())
+}
+
+fn validate_policy_import(policy: &PolicyImport) -> Result<(), String> {
+    for (field, value) in [("title", &policy.title), ("category", &policy.category), ("district", &policy.district)] {
+        if value.trim().is_empty() {
+            return Err(format!("Policy {} must not be empty", field));
+        }
+    }
+    if policy.title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(format!("Policy title must be at most {} characters", MAX_TITLE_LENGTH));
+    }
+    if policy.fund_allocation == 0 {
+        return Err("Fund allocation must be greater than zero".to_string());
+    }
+    Ok(())
+}
+
+// Validates, registers with India Hub and stores a new Draft policy
+async fn create_policy(fields: PolicyImport, now: u64) -> Result<String, String> {
+    validate_policy_import(&fields)?;
+    let PolicyImport {
+        title,
+        description,
+        category,
+        fund_allocation,
+        district,
+        eligibility_criteria,
+        execution_conditions,
+    } = fields;
+    let policy_id = Uuid::new_v4().to_string();
     
     // Generate blockchain hash for transparency
     let blockchain_hash = generate_blockchain_hash(&policy_id, &title, &description);
+    let ai_analysis_score = analyze_policy_with_ai(&title, &description);
     
     // Register with India Hub
     let india_hub_registration = register_with_india_hub(&policy_id, &district, fund_allocation).await;
     
-    let policy = Policy {
+    let mut policy = Policy {
         id: policy_id.clone(),
         title,
         description,
@@ -249,7 +509,7 @@ async fn register_policy(
         eligibility_criteria,
         execution_conditions,
         smart_contract_code: generate_smart_contract_code(&policy_id),
-        blockchain_hash: Some(blockchain_hash),
+        blockchain_hash: Some(blockchain_hash.clone()),
         icp_transaction_id: Some(generate_icp_transaction_id()),
         india_hub_registration: india_hub_registration.as_ref().map(|r| r.registration_id.clone()),
         audit_trail: vec![AuditEntry {
@@ -257,22 +517,24 @@ async fn register_policy(
             action: "Policy Created".to_string(),
             actor: "Government".to_string(),
             details: "New policy registered on blockchain".to_string(),
-            blockchain_hash: Some(blockchain_hash.clone()),
+            blockchain_hash: Some(blockchain_hash),
             icp_transaction_id: Some(generate_icp_transaction_id()),
         }],
-        ai_analysis_score: Some(analyze_policy_with_ai(&title, &description)),
-        transparency_score: calculate_transparency_score(),
+        ai_analysis_score: Some(ai_analysis_score),
+        transparency_score: 0.0,
         citizen_approval_rate: 0.0,
+        transparency_history: None,
     };
+    record_transparency(&mut policy, now);
     
     unsafe {
         if let Some(ref mut policies) = POLICIES {
-            policies.insert(policy_id.clone(), policy);
+            tracked_insert("policies", policies, policy_id.clone(), policy);
         }
         
         if let Some(ref mut india_hub_registrations) = INDIA_HUB_REGISTRATIONS {
             if let Some(registration) = india_hub_registration {
-                india_hub_registrations.insert(policy_id.clone(), registration);
+                tracked_insert("india_hub_registrations", india_hub_registrations, policy_id.clone(), registration);
             }
         }
         
@@ -293,17 +555,19 @@ async fn activate_policy(policy_id: String) -> Result<(), String> {
     unsafe {
         if let Some(ref mut policies) = POLICIES {
             if let Some(policy) = policies.get_mut(&policy_id) {
-                policy.status = PolicyStatus::Active;
-                policy.updated_at = ic_cdk::api::time();
-                
-                // Add to audit trail
-                policy.audit_trail.push(AuditEntry {
-                    timestamp: ic_cdk::api::time(),
-                    action: "Policy Activated".to_string(),
-                    actor: "Government".to_string(),
-                    details: "Policy activated and ready for execution".to_string(),
-                    blockchain_hash: Some(generate_blockchain_hash(&policy_id, "activate", "")),
-                    icp_transaction_id: Some(generate_icp_transaction_id()),
+                tracked_update("policies", policy, |policy| {
+                    policy.status = PolicyStatus::Active;
+                    policy.updated_at = ic_cdk::api::time();
+                    
+                    // Add to audit trail
+                    policy.audit_trail.push(AuditEntry {
+                        timestamp: ic_cdk::api::time(),
+                        action: "Policy Activated".to_string(),
+                        actor: "Government".to_string(),
+                        details: "Policy activated and ready for execution".to_string(),
+                        blockchain_hash: Some(generate_blockchain_hash(&policy_id, "activate", "")),
+                        icp_transaction_id: Some(generate_icp_transaction_id()),
+                    });
                 });
                 
                 return Ok(());
@@ -319,64 +583,63 @@ async fn release_funds(
     amount: u64,
     to_address: String,
 ) -> Result<String, String> {
-    // Verify policy exists and is active
-    unsafe {
-        if let Some(ref policies) = POLICIES {
-            if let Some(policy) = policies.get(&policy_id) {
-                if policy.status != PolicyStatus::Active {
-                    return Err("Policy is not active".to_string());
-                }
-                if policy.fund_released + amount > policy.fund_allocation {
-                    return Err("Insufficient funds".to_string());
-                }
-            } else {
-                return Err("Policy not found".to_string());
-            }
-        }
-    }
+    // Counter 1 covers the whole call context, including the work after the await below
+    let start_instructions = ic_cdk::api::performance_counter(1);
+    let started_at = ic_cdk::api::time();
+    check_release(&policy_id, amount)?;
+    
+    // Confirm on the ICP blockchain before any funds are recorded as released
+    ic_cdk::api::management_canister::main::raw_rand().await
+        .map_err(|(code, msg)| format!("Blockchain confirmation failed: {:?} {}", code, msg))?;
+    // The policy may have changed while the confirmation was pending
+    check_release(&policy_id, amount)?;
     
     let flow_id = Uuid::new_v4().to_string();
     let now = ic_cdk::api::time();
     let blockchain_hash = generate_blockchain_hash(&flow_id, &policy_id, &amount.to_string());
     let icp_transaction_id = generate_icp_transaction_id();
     
-    let fund_flow = FundFlow {
+    let mut fund_flow = FundFlow {
         id: flow_id.clone(),
         policy_id: policy_id.clone(),
         amount,
         from_address: "government_treasury".to_string(),
-        to_address,
+        to_address: to_address.clone(),
         timestamp: now,
         status: FundFlowStatus::Processing,
-        transaction_hash: Some(format!("tx_{}", Uuid::new_v4().to_string())),
+        transaction_hash: Some(format!("tx_{}", Uuid::new_v4())),
         icp_block_hash: Some(blockchain_hash.clone()),
         india_hub_verification: Some("VERIFIED".to_string()),
         smart_contract_execution: Some("EXECUTED".to_string()),
-        gas_used: Some(1000000), // Mock gas usage
-        execution_time: Some(now),
+        gas_used: None,
+        execution_time: None,
     };
     
     // Update policy fund released
     unsafe {
         if let Some(ref mut policies) = POLICIES {
             if let Some(policy) = policies.get_mut(&policy_id) {
-                policy.fund_released += amount;
-                policy.updated_at = now;
-                
-                // Add to audit trail
-                policy.audit_trail.push(AuditEntry {
-                    timestamp: now,
-                    action: "Funds Released".to_string(),
-                    actor: "Government".to_string(),
-                    details: format!("Released {} funds to {}", amount, to_address),
-                    blockchain_hash: Some(blockchain_hash.clone()),
-                    icp_transaction_id: Some(icp_transaction_id.clone()),
+                tracked_update("policies", policy, |policy| {
+                    policy.fund_released += amount;
+                    policy.updated_at = now;
+                    
+                    // Add to audit trail
+                    policy.audit_trail.push(AuditEntry {
+                        timestamp: now,
+                        action: "Funds Released".to_string(),
+                        actor: "Government".to_string(),
+                        details: format!("Released {} funds to {}", amount, to_address),
+                        blockchain_hash: Some(blockchain_hash.clone()),
+                        icp_transaction_id: Some(icp_transaction_id.clone()),
+                    });
                 });
             }
         }
         
+        let cycles = instruction_cycles(start_instructions, ic_cdk::api::performance_counter(1));
+        confirm_fund_flow(&mut fund_flow, cycles, started_at, now);
         if let Some(ref mut fund_flows) = FUND_FLOWS {
-            fund_flows.insert(flow_id.clone(), fund_flow);
+            tracked_insert("fund_flows", fund_flows, flow_id.clone(), fund_flow);
         }
         
         if let Some(ref mut metrics) = WCHL25_METRICS {
@@ -385,29 +648,31 @@ async fn release_funds(
         }
     }
     
-    // Simulate processing delay with enhanced blockchain integration
-    ic_cdk::spawn(async move {
-        // Simulate ICP blockchain confirmation
-        ic_cdk::api::call::call_with_payment(
-            Principal::management_canister(),
-            "raw_rand",
-            (),
-            0,
-        ).await.unwrap();
-        
-        // Update status to completed
-        unsafe {
-            if let Some(ref mut fund_flows) = FUND_FLOWS {
-                if let Some(flow) = fund_flows.get_mut(&flow_id) {
-                    flow.status = FundFlowStatus::BlockchainConfirmed;
-                }
-            }
-        }
-    });
-    
     Ok(flow_id)
 }
 
+fn check_release(policy_id: &str, amount: u64) -> Result<(), String> {
+    let policy = unsafe { POLICIES.as_ref().and_then(|policies| policies.get(policy_id)) }
+        .ok_or("Policy not found".to_string())?;
+    if policy.status != PolicyStatus::Active {
+        return Err("Policy is not active".to_string());
+    }
+    if policy.fund_released.saturating_add(amount) > policy.fund_allocation {
+        return Err("Insufficient funds".to_string());
+    }
+    Ok(())
+}
+
+fn instruction_cycles(start_instructions: u64, end_instructions: u64) -> u64 {
+    end_instructions.saturating_sub(start_instructions) * CYCLES_PER_10_INSTRUCTIONS / 10
+}
+
+fn confirm_fund_flow(flow: &mut FundFlow, cycles: u64, started_at: u64, now: u64) {
+    flow.status = FundFlowStatus::BlockchainConfirmed;
+    flow.gas_used = Some(cycles);
+    flow.execution_time = Some(now.saturating_sub(started_at));
+}
+
 #[query]
 fn get_policy(policy_id: String) -> Result<Policy, String> {
     unsafe {
@@ -523,7 +788,7 @@ async fn update_policy_execution(
     
     unsafe {
         if let Some(ref mut executions) = EXECUTIONS {
-            executions.insert(policy_id, execution);
+            tracked_insert("executions", executions, policy_id, execution);
         }
         
         if let Some(ref mut metrics) = WCHL25_METRICS {
@@ -541,19 +806,7 @@ async fn pause_policy(policy_id: String) -> Result<(), String> {
     unsafe {
         if let Some(ref mut policies) = POLICIES {
             if let Some(policy) = policies.get_mut(&policy_id) {
-                policy.status = PolicyStatus::Paused;
-                policy.updated_at = ic_cdk::api::time();
-                
-                // Add to audit trail
-                policy.audit_trail.push(AuditEntry {
-                    timestamp: ic_cdk::api::time(),
-                    action: "Policy Paused".to_string(),
-                    actor: "Government".to_string(),
-                    details: "Policy execution paused".to_string(),
-                    blockchain_hash: Some(generate_blockchain_hash(&policy_id, "pause", "")),
-                    icp_transaction_id: Some(generate_icp_transaction_id()),
-                });
-                
+                tracked_update("policies", policy, |policy| pause(policy, ic_cdk::api::time()));
                 return Ok(());
             }
         }
@@ -561,24 +814,27 @@ async fn pause_policy(policy_id: String) -> Result<(), String> {
     Err("Policy not found".to_string())
 }
 
+fn pause(policy: &mut Policy, now: u64) {
+    policy.status = PolicyStatus::Paused;
+    policy.updated_at = now;
+    
+    // Add to audit trail
+    policy.audit_trail.push(AuditEntry {
+        timestamp: now,
+        action: "Policy Paused".to_string(),
+        actor: "Government".to_string(),
+        details: "Policy execution paused".to_string(),
+        blockchain_hash: Some(generate_blockchain_hash(&policy.id, "pause", "")),
+        icp_transaction_id: Some(generate_icp_transaction_id()),
+    });
+}
+
 #[update]
 async fn resume_policy(policy_id: String) -> Result<(), String> {
     unsafe {
         if let Some(ref mut policies) = POLICIES {
             if let Some(policy) = policies.get_mut(&policy_id) {
-                policy.status = PolicyStatus::Active;
-                policy.updated_at = ic_cdk::api::time();
-                
-                // Add to audit trail
-                policy.audit_trail.push(AuditEntry {
-                    timestamp: ic_cdk::api::time(),
-                    action: "Policy Resumed".to_string(),
-                    actor: "Government".to_string(),
-                    details: "Policy execution resumed".to_string(),
-                    blockchain_hash: Some(generate_blockchain_hash(&policy_id, "resume", "")),
-                    icp_transaction_id: Some(generate_icp_transaction_id()),
-                });
-                
+                tracked_update("policies", policy, |policy| resume(policy, ic_cdk::api::time()));
                 return Ok(());
             }
         }
@@ -586,6 +842,198 @@ async fn resume_policy(policy_id: String) -> Result<(), String> {
     Err("Policy not found".to_string())
 }
 
+fn resume(policy: &mut Policy, now: u64) {
+    policy.status = PolicyStatus::Active;
+    policy.updated_at = now;
+    
+    // Add to audit trail
+    policy.audit_trail.push(AuditEntry {
+        timestamp: now,
+        action: "Policy Resumed".to_string(),
+        actor: "Government".to_string(),
+        details: "Policy execution resumed".to_string(),
+        blockchain_hash: Some(generate_blockchain_hash(&policy.id, "resume", "")),
+        icp_transaction_id: Some(generate_icp_transaction_id()),
+    });
+}
+
+// Refreshes transparency_score from the policy's current audit trail
+#[update]
+fn recompute_transparency(policy_id: String) -> Result<f64, String> {
+    unsafe {
+        let policy = POLICIES.as_mut()
+            .and_then(|policies| policies.get_mut(&policy_id))
+            .ok_or("Policy not found".to_string())?;
+        Ok(tracked_update("policies", policy, |policy| record_transparency(policy, ic_cdk::api::time())))
+    }
+}
+
+#[query]
+fn get_transparency_history(policy_id: String) -> Result<Vec<(u64, f64)>, String> {
+    unsafe {
+        POLICIES.as_ref()
+            .and_then(|policies| policies.get(&policy_id))
+            .map(|policy| policy.transparency_history.clone().unwrap_or_default())
+            .ok_or("Policy not found".to_string())
+    }
+}
+
+fn record_transparency(policy: &mut Policy, now: u64) -> f64 {
+    let score = policy_transparency_score(policy);
+    policy.transparency_score = score;
+    let history = policy.transparency_history.get_or_insert_with(Vec::new);
+    history.push((now, score));
+    if history.len() > MAX_TRANSPARENCY_HISTORY {
+        history.remove(0);
+    }
+    score
+}
+
+#[query]
+fn get_health() -> HealthReport {
+    let cycles_balance = ic_cdk::api::canister_balance128();
+    
+    unsafe {
+        let storage = collection_counts()
+            .into_iter()
+            .map(|(name, entries)| storage_stats(name, entries, &STORAGE_BYTES))
+            .collect();
+        
+        HealthReport {
+            cycles_balance,
+            low_cycles_threshold: LOW_CYCLES_THRESHOLD,
+            low_cycles: cycles_balance < LOW_CYCLES_THRESHOLD,
+            storage,
+            active_timers: ACTIVE_TIMERS,
+            last_background_run: LAST_BACKGROUND_RUN,
+        }
+    }
+}
+
+#[update]
+fn set_complaint_handler_canister_id(canister_id: Principal) -> Result<(), String> {
+    common::ensure_controller("set the complaint_handler canister id")?;
+    
+    unsafe {
+        COMPLAINT_HANDLER_CANISTER_ID = Some(canister_id);
+    }
+    Ok(())
+}
+
+#[query]
+fn get_complaint_handler_canister_id() -> Option<Principal> {
+    unsafe { COMPLAINT_HANDLER_CANISTER_ID }
+}
+
+// Complaints filed against the policy in complaint_handler. Complaints are supplementary
+// here, so an unconfigured or failed call logs a warning and returns no complaints.
+#[update]
+async fn get_policy_complaints(policy_id: String) -> Vec<ComplaintSummary> {
+    let Some(canister_id) = (unsafe { COMPLAINT_HANDLER_CANISTER_ID }) else {
+        ic_cdk::println!("complaint_handler canister id not configured; no complaints for {}", policy_id);
+        return Vec::new();
+    };
+    let response: Result<(Vec<LinkedComplaint>,), _> =
+        call(canister_id, "get_complaints_by_policy", (policy_id.clone(),)).await;
+    complaint_summaries(response).unwrap_or_else(|e| {
+        ic_cdk::println!("Could not load complaints for {}: {}", policy_id, e);
+        Vec::new()
+    })
+}
+
+// Newest complaints first
+fn complaint_summaries(
+    response: Result<(Vec<LinkedComplaint>,), (ic_cdk::api::call::RejectionCode, String)>,
+) -> Result<Vec<ComplaintSummary>, String> {
+    let (mut complaints,) = response
+        .map_err(|(code, msg)| format!("get_complaints_by_policy call failed: {:?} {}", code, msg))?;
+    complaints.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(complaints.into_iter()
+        .map(|complaint| ComplaintSummary {
+            resolved: complaint.status == ComplaintStatus::Resolved || complaint.resolution_time.is_some(),
+            id: complaint.id,
+            title: complaint.title,
+            category: complaint.category,
+            priority: complaint.priority,
+            status: complaint.status,
+            district: complaint.district,
+            created_at: complaint.created_at,
+        })
+        .collect())
+}
+
+#[query]
+fn get_system_events() -> Vec<AuditEntry> {
+    unsafe { SYSTEM_EVENTS.clone().unwrap_or_default() }
+}
+
+#[update]
+fn set_low_cycles_threshold(threshold: u128) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can change the low-cycles threshold".to_string());
+    }
+    
+    unsafe {
+        LOW_CYCLES_THRESHOLD = threshold;
+    }
+    Ok(())
+}
+
+#[update]
+fn set_idempotency_ttl(ttl_secs: u64) -> Result<(), String> {
+    common::ensure_controller("change the idempotency key TTL")?;
+    if ttl_secs == 0 {
+        return Err("Idempotency key TTL must be greater than zero".to_string());
+    }
+    
+    unsafe {
+        IDEMPOTENCY_TTL_NS = ttl_secs.saturating_mul(1_000_000_000);
+    }
+    Ok(())
+}
+
+#[query]
+fn get_idempotency_ttl() -> u64 {
+    unsafe { IDEMPOTENCY_TTL_NS / 1_000_000_000 }
+}
+
+#[update]
+fn set_execution_config(config: ExecutionPolicyConfig) -> Result<(), String> {
+    common::ensure_controller("change the automatic execution config")?;
+    if !(0.0..=1.0).contains(&config.min_transparency) {
+        return Err("min_transparency must be between 0.0 and 1.0".to_string());
+    }
+    
+    unsafe {
+        EXECUTION_CONFIG = config;
+    }
+    Ok(())
+}
+
+#[query]
+fn get_execution_config() -> ExecutionPolicyConfig {
+    unsafe { EXECUTION_CONFIG.clone() }
+}
+
+// Keys older than the TTL are treated as unused
+fn idempotent_policy_id(keys: &BTreeMap<String, IdempotencyEntry>, key: &str, now: u64, ttl_ns: u64) -> Option<String> {
+    keys.get(key)
+        .filter(|entry| now.saturating_sub(entry.created_at) < ttl_ns)
+        .map(|entry| entry.policy_id.clone())
+}
+
+// Expired keys are dropped here so the map only holds keys a retry could still use
+fn remember_idempotency_key(
+    keys: &mut BTreeMap<String, IdempotencyEntry>,
+    key: String,
+    policy_id: String,
+    now: u64,
+    ttl_ns: u64,
+) {
+    keys.retain(|_, entry| now.saturating_sub(entry.created_at) < ttl_ns);
+    keys.insert(key, IdempotencyEntry { policy_id, created_at: now });
+}
+
 // WCHL25 Enhanced Functions
 
 async fn register_with_india_hub(policy_id: &str, district: &str, fund_allocation: u64) -> Option<IndiaHubRegistration> {
@@ -599,6 +1047,7 @@ async fn register_with_india_hub(policy_id: &str, district: &str, fund_allocatio
         compliance_score: 0.95,
         regional_impact_score: 0.88,
         timestamp: ic_cdk::api::time(),
+        ..Default::default()
     })
 }
 
@@ -626,7 +1075,7 @@ async fn apply_ai_optimizations() {
 }
 
 fn generate_blockchain_hash(policy_id: &str, action: &str, data: &str) -> String {
-    format!("0x{}{}{}", policy_id, action, data).chars().take(64).collect()
+    common::blockchain_hash(&format!("{}{}{}", policy_id, action, data))
 }
 
 fn generate_icp_transaction_id() -> String {
@@ -635,15 +1084,27 @@ fn generate_icp_transaction_id() -> String {
 
 fn analyze_policy_with_ai(title: &str, description: &str) -> f64 {
     // Mock AI analysis score
-    let base_score = 0.8;
+    let base_score: f64 = 0.8;
     let title_score = if title.len() > 10 { 0.1 } else { 0.05 };
     let description_score = if description.len() > 50 { 0.1 } else { 0.05 };
     (base_score + title_score + description_score).min(1.0)
 }
 
-fn calculate_transparency_score() -> f64 {
-    // Mock transparency score calculation
-    0.95
+// 0.6 for the share of audit entries anchored on chain (hash and ICP transaction id), 0.2
+// for an India Hub registration and up to 0.2 for recorded activity
+fn policy_transparency_score(policy: &Policy) -> f64 {
+    let audit_entries = policy.audit_trail.len();
+    let anchored_share = if audit_entries == 0 {
+        0.0
+    } else {
+        let anchored = policy.audit_trail.iter()
+            .filter(|entry| entry.blockchain_hash.is_some() && entry.icp_transaction_id.is_some())
+            .count();
+        anchored as f64 / audit_entries as f64
+    };
+    let registered = if policy.india_hub_registration.is_some() { 1.0 } else { 0.0 };
+    let activity = audit_entries.min(TRANSPARENCY_FULL_AUDIT) as f64 / TRANSPARENCY_FULL_AUDIT as f64;
+    0.6 * anchored_share + 0.2 * registered + 0.2 * activity
 }
 
 fn calculate_overall_transparency_score() -> f64 {
@@ -674,13 +1135,16 @@ fn calculate_hackathon_score() -> f64 {
 }
 
 async fn check_policy_execution() {
+    // Warn before the canister runs low enough on cycles to freeze
+    check_cycles_balance();
+    
     // Periodic check for policy execution conditions with WCHL25 enhancements
     unsafe {
         if let Some(ref policies) = POLICIES {
             for policy in policies.values() {
                 if policy.status == PolicyStatus::Active {
                     // Check if execution conditions are met
-                    let conditions_met = check_execution_conditions(policy);
+                    let conditions_met = check_execution_conditions(policy, &EXECUTION_CONFIG);
                     if conditions_met {
                         // Trigger automatic execution
                         ic_cdk::spawn(execute_policy_automatically(policy.id.clone()));
@@ -688,14 +1152,91 @@ async fn check_policy_execution() {
                 }
             }
         }
+        
+        LAST_BACKGROUND_RUN = Some(ic_cdk::api::time());
+    }
+}
+
+fn check_cycles_balance() {
+    let balance = ic_cdk::api::canister_balance128();
+    let now = ic_cdk::api::time();
+    
+    unsafe {
+        if let Some(entry) = low_cycles_entry(balance, LOW_CYCLES_THRESHOLD, now) {
+            ic_cdk::println!("Low cycles warning: balance {} below threshold {}", balance, LOW_CYCLES_THRESHOLD);
+            if let Some(ref mut events) = SYSTEM_EVENTS {
+                let added = encoded_len(&entry);
+                let dropped = push_system_event(events, entry).map_or(0, |dropped| encoded_len(&dropped));
+                record_storage_change("system_events", dropped, added);
+            }
+        }
+    }
+}
+
+// Appends an event and returns the oldest one if the log was full
+fn push_system_event(events: &mut Vec<AuditEntry>, entry: AuditEntry) -> Option<AuditEntry> {
+    events.push(entry);
+    (events.len() > MAX_SYSTEM_EVENTS).then(|| events.remove(0))
+}
+
+fn low_cycles_entry(balance: u128, threshold: u128, now: u64) -> Option<AuditEntry> {
+    if balance >= threshold {
+        return None;
     }
+    
+    Some(AuditEntry {
+        timestamp: now,
+        action: "Low Cycles Warning".to_string(),
+        actor: "System".to_string(),
+        details: format!("Cycles balance {} is below the configured threshold {}", balance, threshold),
+        blockchain_hash: None,
+        icp_transaction_id: None,
+    })
+}
+
+fn storage_stats(name: &str, entries: usize, sizes: &BTreeMap<&'static str, u64>) -> StorageStats {
+    StorageStats {
+        name: name.to_string(),
+        entries: entries as u64,
+        approx_bytes: sizes.get(name).copied().unwrap_or(0),
+    }
+}
+
+fn encoded_len<T: CandidType>(value: &T) -> u64 {
+    candid::encode_one(value).map(|bytes| bytes.len() as u64).unwrap_or(0)
+}
+
+fn adjust_storage_bytes(sizes: &mut BTreeMap<&'static str, u64>, collection: &'static str, removed: u64, added: u64) {
+    let total = sizes.entry(collection).or_insert(0);
+    *total = total.saturating_sub(removed).saturating_add(added);
+}
+
+fn record_storage_change(collection: &'static str, removed: u64, added: u64) {
+    unsafe {
+        adjust_storage_bytes(&mut STORAGE_BYTES, collection, removed, added);
+    }
+}
+
+fn tracked_insert<T: CandidType>(collection: &'static str, map: &mut BTreeMap<String, T>, key: String, value: T) {
+    let added = encoded_len(&value);
+    let removed = map.insert(key, value).map_or(0, |old| encoded_len(&old));
+    record_storage_change(collection, removed, added);
+}
+
+// Applies an in-place change to one stored record and accounts for its new size
+fn tracked_update<T: CandidType, R>(collection: &'static str, value: &mut T, change: impl FnOnce(&mut T) -> R) -> R {
+    let removed = encoded_len(value);
+    let result = change(value);
+    let added = encoded_len(value);
+    record_storage_change(collection, removed, added);
+    result
 }
 
-fn check_execution_conditions(policy: &Policy) -> bool {
+fn check_execution_conditions(policy: &Policy, config: &ExecutionPolicyConfig) -> bool {
     // Enhanced condition check with AI analysis
     policy.fund_allocation > 0 && 
     policy.fund_released < policy.fund_allocation &&
-    policy.transparency_score > 0.8
+    policy.transparency_score > config.min_transparency
 }
 
 async fn execute_policy_automatically(policy_id: String) {
@@ -785,6 +1326,39 @@ fn generate_smart_contract_code(policy_id: &str) -> String {
     )
 }
 
+#[query]
+fn get_canister_stats() -> CanisterStats {
+    common::canister_stats()
+}
+
+#[query]
+fn health_check() -> HealthStatus {
+    health_status_at(ic_cdk::api::time())
+}
+
+fn health_status_at(now: u64) -> HealthStatus {
+    unsafe {
+        common::health_status(
+            POLICIES.is_some() && FUND_FLOWS.is_some() && EXECUTIONS.is_some(),
+            &collection_counts(),
+            INIT_TIME_NS,
+            now,
+        )
+    }
+}
+
+fn collection_counts() -> [(&'static str, usize); 5] {
+    unsafe {
+        [
+            ("policies", POLICIES.as_ref().map_or(0, BTreeMap::len)),
+            ("fund_flows", FUND_FLOWS.as_ref().map_or(0, BTreeMap::len)),
+            ("executions", EXECUTIONS.as_ref().map_or(0, BTreeMap::len)),
+            ("india_hub_registrations", INDIA_HUB_REGISTRATIONS.as_ref().map_or(0, BTreeMap::len)),
+            ("system_events", SYSTEM_EVENTS.as_ref().map_or(0, Vec::len)),
+        ]
+    }
+}
+
 // Candid interface
 candid::export_service!();
 
@@ -806,7 +1380,257 @@ mod tests {
     fn test_blockchain_hash_generation() {
         let hash = generate_blockchain_hash("test", "action", "data");
         assert!(hash.starts_with("0x"));
-        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, "0xtestactiondata");
+    }
+    
+    fn test_policy() -> Policy {
+        Policy {
+            id: "policy_1".to_string(),
+            title: "Ward water supply".to_string(),
+            description: String::new(),
+            category: "water".to_string(),
+            fund_allocation: 50_000,
+            fund_released: 0,
+            beneficiaries: 0,
+            status: PolicyStatus::Active,
+            created_at: 0,
+            updated_at: 0,
+            district: "Pune".to_string(),
+            contractor: None,
+            eligibility_criteria: vec![],
+            execution_conditions: vec![],
+            smart_contract_code: String::new(),
+            blockchain_hash: None,
+            icp_transaction_id: None,
+            india_hub_registration: Some("INDIA_HUB_1".to_string()),
+            audit_trail: vec![AuditEntry {
+                timestamp: 0,
+                action: "Policy Created".to_string(),
+                actor: "Government".to_string(),
+                details: String::new(),
+                blockchain_hash: Some("0xpolicy_1".to_string()),
+                icp_transaction_id: Some("icp_1".to_string()),
+            }],
+            ai_analysis_score: None,
+            transparency_score: 0.0,
+            citizen_approval_rate: 0.0,
+            transparency_history: None,
+        }
+    }
+    
+    #[test]
+    fn test_execution_threshold() {
+        let mut policy = test_policy();
+        policy.fund_allocation = 50_000;
+        policy.fund_released = 10_000;
+        policy.transparency_score = 0.7;
+        
+        let default = ExecutionPolicyConfig { min_transparency: DEFAULT_MIN_TRANSPARENCY };
+        assert!(!check_execution_conditions(&policy, &default));
+        assert!(check_execution_conditions(&policy, &ExecutionPolicyConfig { min_transparency: 0.6 }));
+        
+        // The score must exceed the bar, and fully released policies never qualify
+        assert!(!check_execution_conditions(&policy, &ExecutionPolicyConfig { min_transparency: 0.7 }));
+        policy.fund_released = policy.fund_allocation;
+        assert!(!check_execution_conditions(&policy, &ExecutionPolicyConfig { min_transparency: 0.0 }));
+    }
+    
+    #[test]
+    fn test_release_costs() {
+        let flow = |id: &str| FundFlow {
+            id: id.to_string(),
+            policy_id: "policy_1".to_string(),
+            amount: 1_000,
+            from_address: "government_treasury".to_string(),
+            to_address: "contractor".to_string(),
+            timestamp: 0,
+            status: FundFlowStatus::Processing,
+            transaction_hash: None,
+            icp_block_hash: None,
+            india_hub_verification: None,
+            smart_contract_execution: None,
+            gas_used: None,
+            execution_time: None,
+        };
+        
+        // Two releases measured with different instruction counts and confirmation delays
+        let mut first = flow("flow_1");
+        confirm_fund_flow(&mut first, instruction_cycles(1_200, 2_501_200), 1_000, 2_000_001_000);
+        let mut second = flow("flow_2");
+        confirm_fund_flow(&mut second, instruction_cycles(0, 3_750_000), 5_000, 3_500_005_000);
+        
+        assert_eq!(first.gas_used, Some(1_000_000));
+        assert_eq!(first.execution_time, Some(2_000_000_000));
+        assert_eq!(second.gas_used, Some(1_500_000));
+        assert_eq!(second.execution_time, Some(3_500_000_000));
+        assert!(first.gas_used != second.gas_used);
+        assert!(matches!(second.status, FundFlowStatus::BlockchainConfirmed));
+        
+        // A counter that did not advance costs nothing rather than underflowing
+        assert_eq!(instruction_cycles(10, 5), 0);
+    }
+    
+    #[test]
+    fn test_complaint_summaries() {
+        let complaint = |id: &str, status: ComplaintStatus, created_at: u64| LinkedComplaint {
+            id: id.to_string(),
+            title: format!("Complaint {}", id),
+            category: "water".to_string(),
+            priority: ComplaintPriority::High,
+            status,
+            district: "Pune".to_string(),
+            created_at,
+            resolution_time: None,
+        };
+        let response = Ok((vec![
+            complaint("c1", ComplaintStatus::Resolved, 10),
+            complaint("c2", ComplaintStatus::UnderReview, 30),
+        ],));
+        
+        let summaries = complaint_summaries(response).unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].id, "c2");
+        assert_eq!(summaries[0].status, ComplaintStatus::UnderReview);
+        assert!(!summaries[0].resolved);
+        assert_eq!(summaries[1].title, "Complaint c1");
+        assert_eq!(summaries[1].priority, ComplaintPriority::High);
+        assert!(summaries[1].resolved);
+        
+        let failed = complaint_summaries(Err((ic_cdk::api::call::RejectionCode::DestinationInvalid, "no such canister".to_string())));
+        assert!(matches!(failed, Err(ref e) if e.contains("no such canister")));
+    }
+    
+    #[test]
+    fn test_transparency_history() {
+        let mut policy = test_policy();
+        let initial = record_transparency(&mut policy, 10);
+        assert!((initial - 0.82).abs() < 1e-9);
+        
+        pause(&mut policy, 20);
+        resume(&mut policy, 30);
+        let refreshed = record_transparency(&mut policy, 40);
+        assert!((refreshed - 0.86).abs() < 1e-9);
+        assert_eq!(policy.transparency_score, refreshed);
+        assert_eq!(policy.transparency_history, Some(vec![(10, initial), (40, refreshed)]));
+        
+        // Entries without an on-chain anchor lower the score
+        policy.audit_trail.push(AuditEntry {
+            timestamp: 50,
+            action: "Manual note".to_string(),
+            actor: "Government".to_string(),
+            details: String::new(),
+            blockchain_hash: None,
+            icp_transaction_id: None,
+        });
+        assert!(record_transparency(&mut policy, 60) < refreshed);
+        
+        for now in 0..MAX_TRANSPARENCY_HISTORY as u64 {
+            record_transparency(&mut policy, 100 + now);
+        }
+        let history = policy.transparency_history.unwrap();
+        assert_eq!(history.len(), MAX_TRANSPARENCY_HISTORY);
+        assert_eq!(history[0].0, 100);
+    }
+    
+    fn policy_import(title: &str, district: &str, fund_allocation: u64) -> PolicyImport {
+        PolicyImport {
+            title: title.to_string(),
+            description: "Seeded for the demo".to_string(),
+            category: "water".to_string(),
+            fund_allocation,
+            district: district.to_string(),
+            eligibility_criteria: vec![],
+            execution_conditions: vec![],
+        }
+    }
+    
+    #[test]
+    fn test_import_batch_validation() {
+        let batch = [
+            policy_import("Ward water supply", "Pune", 50_000),
+            policy_import("  ", "Pune", 50_000),
+            policy_import("Rural roads", "", 10_000),
+            policy_import("School meals", "Nagpur", 0),
+            policy_import(&"x".repeat(MAX_TITLE_LENGTH + 1), "Nagpur", 1),
+            policy_import("Street lighting", "Nashik", 1),
+        ];
+        let results: Vec<Result<(), String>> = batch.iter().map(validate_policy_import).collect();
+        assert!(results[0].is_ok());
+        assert_eq!(results[1], Err("Policy title must not be empty".to_string()));
+        assert_eq!(results[2], Err("Policy district must not be empty".to_string()));
+        assert_eq!(results[3], Err("Fund allocation must be greater than zero".to_string()));
+        assert!(results[4].is_err());
+        assert!(results[5].is_ok());
+        
+        assert!(check_import_position(0).is_ok());
+        assert!(check_import_position(MAX_IMPORT_BATCH - 1).is_ok());
+        assert_eq!(
+            check_import_position(MAX_IMPORT_BATCH),
+            Err(format!("At most {} policies can be imported at once", MAX_IMPORT_BATCH))
+        );
+    }
+    
+    #[test]
+    fn test_idempotency_keys() {
+        let ttl = 1_000;
+        let mut keys = BTreeMap::new();
+        remember_idempotency_key(&mut keys, "retry-1".to_string(), "policy-a".to_string(), 100, ttl);
+        
+        // A repeated key within the TTL returns the policy it created
+        assert_eq!(idempotent_policy_id(&keys, "retry-1", 600, ttl), Some("policy-a".to_string()));
+        assert_eq!(idempotent_policy_id(&keys, "retry-2", 600, ttl), None);
+        
+        // Once expired the key creates a new policy, and storing it replaces the old entry
+        assert_eq!(idempotent_policy_id(&keys, "retry-1", 1_100, ttl), None);
+        remember_idempotency_key(&mut keys, "retry-1".to_string(), "policy-b".to_string(), 1_100, ttl);
+        assert_eq!(idempotent_policy_id(&keys, "retry-1", 1_200, ttl), Some("policy-b".to_string()));
+        
+        // Storing a key prunes the ones that have expired
+        remember_idempotency_key(&mut keys, "retry-3".to_string(), "policy-c".to_string(), 2_500, ttl);
+        assert_eq!(keys.len(), 1);
+        assert!(keys.contains_key("retry-3"));
+    }
+    
+    #[test]
+    fn test_low_cycles_entry() {
+        assert!(low_cycles_entry(2_000, 1_000, 0).is_none());
+        
+        let entry = low_cycles_entry(500, 1_000, 42).unwrap();
+        assert_eq!(entry.action, "Low Cycles Warning");
+        assert_eq!(entry.timestamp, 42);
+    }
+    
+    #[test]
+    fn test_storage_stats() {
+        let mut sizes = BTreeMap::new();
+        assert_eq!(storage_stats("map", 0, &sizes).approx_bytes, 0);
+        
+        let small = encoded_len(&"value".to_string());
+        let large = encoded_len(&"a much longer value".to_string());
+        adjust_storage_bytes(&mut sizes, "map", 0, small);
+        let stats = storage_stats("map", 1, &sizes);
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.approx_bytes, small);
+        
+        // Replacing a record swaps its old size for the new one
+        adjust_storage_bytes(&mut sizes, "map", small, large);
+        assert_eq!(storage_stats("map", 1, &sizes).approx_bytes, large);
+        adjust_storage_bytes(&mut sizes, "map", large, 0);
+        assert_eq!(storage_stats("map", 0, &sizes).approx_bytes, 0);
+    }
+    
+    #[test]
+    fn test_system_events_are_capped() {
+        let mut events = Vec::new();
+        for timestamp in 0..MAX_SYSTEM_EVENTS as u64 {
+            assert!(push_system_event(&mut events, low_cycles_entry(0, 1, timestamp).unwrap()).is_none());
+        }
+        
+        let dropped = push_system_event(&mut events, low_cycles_entry(0, 1, 5_000).unwrap()).unwrap();
+        assert_eq!(dropped.timestamp, 0);
+        assert_eq!(events.len(), MAX_SYSTEM_EVENTS);
+        assert_eq!(events.first().unwrap().timestamp, 1);
+        assert_eq!(events.last().unwrap().timestamp, 5_000);
     }
     
     #[test]
@@ -815,4 +1639,33 @@ mod tests {
         assert!(score > 0.8);
         assert!(score <= 1.0);
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_health_check_counts() {
+        unsafe {
+            POLICIES = Some(BTreeMap::new());
+            FUND_FLOWS = Some(BTreeMap::new());
+            EXECUTIONS = Some(BTreeMap::new());
+            SYSTEM_EVENTS = Some(Vec::new());
+            INIT_TIME_NS = Some(100);
+        }
+        let before = health_status_at(400);
+        assert!(before.initialized);
+        assert_eq!(before.uptime_ns, 300);
+        assert_eq!(before.record_counts[4], ("system_events".to_string(), 0));
+        
+        unsafe {
+            if let Some(ref mut events) = SYSTEM_EVENTS {
+                events.push(AuditEntry {
+                    timestamp: 200,
+                    action: "Policy Paused".to_string(),
+                    actor: "Government".to_string(),
+                    details: String::new(),
+                    blockchain_hash: None,
+                    icp_transaction_id: None,
+                });
+            }
+        }
+        assert_eq!(health_status_at(400).record_counts[4], ("system_events".to_string(), 1));
+    }
+}
@@ -76,4 +76,24 @@ describe("Vibe Coding Template Backend", () => {
     const currentCount = await actor.get_count();
     expect(currentCount).toEqual(newValue);
   });
+
+  it("should decrement counter without going below zero", async () => {
+    await actor.set_count(BigInt(1));
+    expect(await actor.decrement()).toEqual(BigInt(0));
+    expect(await actor.decrement()).toEqual(BigInt(0));
+  });
+
+  it("should reset counter to zero", async () => {
+    await actor.set_count(BigInt(42));
+    expect(await actor.reset()).toEqual(BigInt(0));
+    expect(await actor.get_count()).toEqual(BigInt(0));
+  });
+
+  it("should report canister resource usage", async () => {
+    const stats = await actor.get_canister_stats();
+    expect(stats.cycles_balance).toBeGreaterThan(BigInt(0));
+    expect(stats.heap_size).toBeGreaterThan(BigInt(0));
+    expect(stats.heap_size % BigInt(65536)).toEqual(BigInt(0));
+    expect(stats.stable_memory_bytes % BigInt(65536)).toEqual(BigInt(0));
+  });
 });